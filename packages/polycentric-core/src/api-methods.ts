@@ -527,6 +527,22 @@ export async function postClaimHandle(
   await checkResponse('postClaimHandle', response);
 }
 
+export async function postReport(
+  server: string,
+  report: Protocol.ReportRequest,
+): Promise<void> {
+  const response = await fetch(server + '/report', {
+    method: 'POST',
+    headers: new Headers({
+      'content-type': 'application/octet-stream',
+      'x-polycentric-user-agent': userAgent,
+    }),
+    body: Protocol.ReportRequest.encode(report).finish(),
+  });
+
+  await checkResponse('postReport', response);
+}
+
 export async function getResolveHandle(
   server: string,
   handle: string,
@@ -54,14 +54,30 @@ async function createHandleWithName(username: string) {
   return s1p1;
 }
 
+async function claimHandle(
+  processHandle: ProcessHandle.ProcessHandle,
+  handle: string,
+) {
+  const challenge = await APIMethods.getChallenge(TEST_SERVER);
+
+  const solvedChallenge = await ProcessHandle.solveChallenge(
+    processHandle,
+    challenge,
+  );
+
+  await APIMethods.postClaimHandle(TEST_SERVER, {
+    handle: handle,
+    system: processHandle.system(),
+    challenge: solvedChallenge.challenge,
+    signature: solvedChallenge.signature,
+  });
+}
+
 async function createHandleWithNameAndIdentityHandle(username: string) {
   const s1p1 = await ProcessHandle.createTestProcessHandle();
   await s1p1.addServer(TEST_SERVER);
   await s1p1.setUsername(username + '@' + TEST_SERVER_ADDRESS);
-  await APIMethods.postClaimHandle(TEST_SERVER, {
-    handle: username,
-    system: s1p1.system(),
-  });
+  await claimHandle(s1p1, username);
   await Synchronization.backFillServers(s1p1, s1p1.system());
   return s1p1;
 }
@@ -508,10 +524,7 @@ describe('integration', () => {
     expect(result_osotnoc).toStrictEqual(osotnoc.system());
 
     // Duplicate entry for system
-    await APIMethods.postClaimHandle(TEST_SERVER, {
-      handle: handleContoso3,
-      system: contoso2.system(),
-    });
+    await claimHandle(contoso2, handleContoso3);
 
     const result_contoso3 = await APIMethods.getResolveHandle(
       TEST_SERVER,
@@ -522,11 +535,10 @@ describe('integration', () => {
     // Name with restricted chars
     let creation_failed = true;
     try {
-      await APIMethods.postClaimHandle(TEST_SERVER, {
-        handle:
-          'This has spaces, dollar $ign$, and an &mpersand. not allowed!!',
-        system: contoso.system(),
-      });
+      await claimHandle(
+        contoso,
+        'This has spaces, dollar $ign$, and an &mpersand. not allowed!!',
+      );
       creation_failed = false;
     } catch {}
     expect(creation_failed).toStrictEqual(true);
@@ -534,11 +546,10 @@ describe('integration', () => {
     // Name that's too long
     creation_failed = true;
     try {
-      await APIMethods.postClaimHandle(TEST_SERVER, {
-        handle:
-          '01234567890123456789012345678901234567890123456789012345678901234',
-        system: contoso.system(),
-      });
+      await claimHandle(
+        contoso,
+        '01234567890123456789012345678901234567890123456789012345678901234',
+      );
       creation_failed = false;
     } catch {}
     expect(creation_failed).toStrictEqual(true);
@@ -546,10 +557,28 @@ describe('integration', () => {
     // Name that's already taken
     const contosoCopycat = await createHandleWithName('contoso-1');
     creation_failed = true;
+    try {
+      await claimHandle(contosoCopycat, handleContoso1);
+      creation_failed = false;
+    } catch {}
+    expect(creation_failed).toStrictEqual(true);
+
+    // Name that only differs by case from one already taken
+    creation_failed = true;
+    try {
+      await claimHandle(contosoCopycat, handleContoso1.toUpperCase());
+      creation_failed = false;
+    } catch {}
+    expect(creation_failed).toStrictEqual(true);
+
+    // Claiming without solving the challenge
+    creation_failed = true;
     try {
       await APIMethods.postClaimHandle(TEST_SERVER, {
-        handle: handleContoso1,
+        handle: 'unsigned' + randomNumberString(),
         system: contosoCopycat.system(),
+        challenge: undefined,
+        signature: new Uint8Array(),
       });
       creation_failed = false;
     } catch {}
@@ -20,6 +20,8 @@ pub mod known_message_types {
     pub const STORE: u64 = 15;
     pub const AUTHORITY: u64 = 16;
     pub const JOIN_TOPIC: u64 = 17;
+    pub const BLOCK: u64 = 18;
+    pub const BLOCK_TOPIC: u64 = 19;
 }
 
 pub fn content_type_to_string(content_type: u64) -> String {
@@ -41,6 +43,8 @@ pub fn content_type_to_string(content_type: u64) -> String {
         known_message_types::STORE => "STORE".to_string(),
         known_message_types::AUTHORITY => "AUTHORITY".to_string(),
         known_message_types::JOIN_TOPIC => "JOIN_TOPIC".to_string(),
+        known_message_types::BLOCK => "BLOCK".to_string(),
+        known_message_types::BLOCK_TOPIC => "BLOCK_TOPIC".to_string(),
         _ => content_type.to_string(),
     }
 }
@@ -422,11 +426,34 @@ impl EventLayers {
             &crate::protocol::Event::parse_from_bytes(signed_event.event())?,
         )?;
 
-        let content = crate::model::content::decode_content(
-            *event.content_type(),
-            event.content(),
+        let content = crate::model::content::from_event(&event)?;
+
+        Ok(EventLayers {
+            raw_event,
+            signed_event,
+            event,
+            content,
+        })
+    }
+
+    // Keeps the content as `Content::Unknown` without decoding it. Only for
+    // stored events, which may have been accepted before content was
+    // validated as strictly as in `new`.
+    pub fn new_undecoded(
+        signed_event: crate::model::signed_event::SignedEvent,
+    ) -> ::anyhow::Result<EventLayers> {
+        let raw_event = crate::model::signed_event::to_proto(&signed_event)
+            .write_to_bytes()?;
+
+        let event = crate::model::event::from_proto(
+            &crate::protocol::Event::parse_from_bytes(signed_event.event())?,
         )?;
 
+        let content = crate::model::content::Content::Unknown(
+            *event.content_type(),
+            event.content().clone(),
+        );
+
         Ok(EventLayers {
             raw_event,
             signed_event,
@@ -1083,45 +1110,468 @@ pub mod claim {
     }
 }
 
+pub mod post {
+    use anyhow::Context;
+
+    #[derive(PartialEq, Clone, Debug)]
+    pub struct Post {
+        content: ::std::option::Option<String>,
+        image: ::std::option::Option<crate::protocol::ImageManifest>,
+    }
+
+    impl Post {
+        pub fn new(
+            content: ::std::option::Option<String>,
+            image: ::std::option::Option<crate::protocol::ImageManifest>,
+        ) -> Post {
+            Post { content, image }
+        }
+
+        pub fn content(&self) -> &::std::option::Option<String> {
+            &self.content
+        }
+
+        pub fn image(
+            &self,
+        ) -> &::std::option::Option<crate::protocol::ImageManifest> {
+            &self.image
+        }
+    }
+
+    pub fn from_proto(proto: &crate::protocol::Post) -> ::anyhow::Result<Post> {
+        if let Some(image) = proto.image.as_ref() {
+            crate::model::process::from_proto(&image.process)
+                .context("POST image manifest had an invalid process")?;
+        }
+
+        Ok(Post::new(
+            proto.content.clone(),
+            proto.image.clone().into_option(),
+        ))
+    }
+
+    pub fn to_proto(post: &Post) -> crate::protocol::Post {
+        let mut proto = crate::protocol::Post::new();
+        proto.content = post.content().clone();
+        proto.image =
+            ::protobuf::MessageField::from_option(post.image().clone());
+        proto
+    }
+}
+
+pub mod opinion {
+    #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+    pub enum Opinion {
+        Like,
+        Dislike,
+        Neutral,
+    }
+
+    pub fn from_value(value: &[u8]) -> ::anyhow::Result<Opinion> {
+        match value {
+            [1] => Ok(Opinion::Like),
+            [2] => Ok(Opinion::Dislike),
+            [3] => Ok(Opinion::Neutral),
+            _ => ::anyhow::bail!("unknown opinion"),
+        }
+    }
+
+    pub fn to_value(opinion: &Opinion) -> ::std::vec::Vec<u8> {
+        match opinion {
+            Opinion::Like => vec![1],
+            Opinion::Dislike => vec![2],
+            Opinion::Neutral => vec![3],
+        }
+    }
+}
+
+pub mod ranges {
+    fn make_range(low: u64, high: u64) -> crate::protocol::Range {
+        let mut range = crate::protocol::Range::new();
+        range.low = low;
+        range.high = high;
+        range
+    }
+
+    fn subtract_range(
+        ranges: ::std::vec::Vec<crate::protocol::Range>,
+        remove: &crate::protocol::Range,
+    ) -> ::std::vec::Vec<crate::protocol::Range> {
+        let mut result = vec![];
+
+        for range in ranges {
+            if remove.high < range.low || remove.low > range.high {
+                result.push(range);
+                continue;
+            }
+
+            if remove.low > range.low {
+                result.push(make_range(range.low, remove.low - 1));
+            }
+
+            if remove.high < range.high {
+                result.push(make_range(remove.high + 1, range.high));
+            }
+        }
+
+        result
+    }
+
+    // Every logical clock covered by `left` but not by `right`.
+    pub fn subtract(
+        left: &[crate::protocol::Range],
+        right: &[crate::protocol::Range],
+    ) -> ::std::vec::Vec<crate::protocol::Range> {
+        right.iter().fold(left.to_vec(), subtract_range)
+    }
+
+    pub fn count(ranges: &[crate::protocol::Range]) -> u64 {
+        ranges.iter().fold(0, |total, range| {
+            total.saturating_add(
+                range.high.saturating_sub(range.low).saturating_add(1),
+            )
+        })
+    }
+
+    // Events a holder of `left` has that a holder of `right` is missing.
+    pub fn subtract_for_system(
+        left: &crate::protocol::RangesForSystem,
+        right: &crate::protocol::RangesForSystem,
+    ) -> crate::protocol::RangesForSystem {
+        let mut result = crate::protocol::RangesForSystem::new();
+
+        for left_for_process in left.ranges_for_processes.iter() {
+            let right_ranges: ::std::vec::Vec<crate::protocol::Range> = right
+                .ranges_for_processes
+                .iter()
+                .filter(|right_for_process| {
+                    right_for_process.process == left_for_process.process
+                })
+                .flat_map(|right_for_process| right_for_process.ranges.clone())
+                .collect();
+
+            let ranges = subtract(&left_for_process.ranges, &right_ranges);
+
+            if !ranges.is_empty() {
+                let mut for_process = crate::protocol::RangesForProcess::new();
+                for_process.process = left_for_process.process.clone();
+                for_process.ranges = ranges;
+                result.ranges_for_processes.push(for_process);
+            }
+        }
+
+        result
+    }
+
+    // A prefix of `ranges` covering at most `max_items` logical clocks, used
+    // to bound the size of a single request.
+    pub fn take_max_items(
+        ranges: &crate::protocol::RangesForSystem,
+        max_items: u64,
+    ) -> crate::protocol::RangesForSystem {
+        let mut result = crate::protocol::RangesForSystem::new();
+        let mut remaining = max_items;
+
+        for for_process in ranges.ranges_for_processes.iter() {
+            if remaining == 0 {
+                break;
+            }
+
+            let mut taken = crate::protocol::RangesForProcess::new();
+            taken.process = for_process.process.clone();
+
+            for range in for_process.ranges.iter() {
+                if remaining == 0 {
+                    break;
+                }
+
+                let items = count(::std::slice::from_ref(range));
+
+                if items <= remaining {
+                    taken.ranges.push(range.clone());
+                    remaining -= items;
+                } else {
+                    taken
+                        .ranges
+                        .push(make_range(range.low, range.low + remaining - 1));
+                    remaining = 0;
+                }
+            }
+
+            if !taken.ranges.is_empty() {
+                result.ranges_for_processes.push(taken);
+            }
+        }
+
+        result
+    }
+}
+
 pub mod content {
+    use anyhow::Context;
     use protobuf::Message;
 
+    use crate::model::known_message_types;
+
+    // Content types that carry their payload in the event body are decoded
+    // from `content`. CRDT content types carry their payload in the value of
+    // the LWWElement or LWWElementSet and are decoded from `lww_value`.
     #[derive(PartialEq, Clone, Debug)]
     pub enum Content {
         Delete(crate::model::delete::Delete),
+        SystemProcesses(::std::vec::Vec<crate::model::process::Process>),
+        Post(crate::model::post::Post),
+        Follow(crate::model::public_key::PublicKey),
+        Username(String),
+        Description(String),
+        BlobMeta(::std::vec::Vec<u8>),
+        BlobSection(::std::vec::Vec<u8>),
+        Avatar(crate::protocol::ImageBundle),
+        Server(String),
+        Vouch,
         Claim(crate::model::claim::Claim),
+        Banner(crate::protocol::ImageBundle),
+        Opinion(crate::model::opinion::Opinion),
+        Store(String),
+        Authority(String),
+        JoinTopic(String),
+        Block(crate::model::public_key::PublicKey),
+        BlockTopic(String),
         Unknown(u64, ::std::vec::Vec<u8>),
     }
 
+    fn is_lww_element_type(content_type: u64) -> bool {
+        matches!(
+            content_type,
+            known_message_types::USERNAME
+                | known_message_types::DESCRIPTION
+                | known_message_types::AVATAR
+                | known_message_types::BANNER
+                | known_message_types::OPINION
+                | known_message_types::STORE
+        )
+    }
+
+    fn is_lww_element_set_type(content_type: u64) -> bool {
+        matches!(
+            content_type,
+            known_message_types::FOLLOW
+                | known_message_types::SERVER
+                | known_message_types::AUTHORITY
+                | known_message_types::JOIN_TOPIC
+                | known_message_types::BLOCK
+                | known_message_types::BLOCK_TOPIC
+        )
+    }
+
+    fn expect_lww_value(
+        content_type: u64,
+        lww_value: ::std::option::Option<&[u8]>,
+    ) -> ::anyhow::Result<&[u8]> {
+        lww_value.with_context(|| {
+            format!(
+                "{} expected lww value",
+                crate::model::content_type_to_string(content_type)
+            )
+        })
+    }
+
+    fn decode_string(
+        content_type: u64,
+        lww_value: ::std::option::Option<&[u8]>,
+    ) -> ::anyhow::Result<String> {
+        String::from_utf8(expect_lww_value(content_type, lww_value)?.to_vec())
+            .with_context(|| {
+                format!(
+                    "{} value was not valid UTF-8",
+                    crate::model::content_type_to_string(content_type)
+                )
+            })
+    }
+
+    fn decode_public_key(
+        content_type: u64,
+        lww_value: ::std::option::Option<&[u8]>,
+    ) -> ::anyhow::Result<crate::model::public_key::PublicKey> {
+        let proto = crate::protocol::PublicKey::parse_from_bytes(
+            expect_lww_value(content_type, lww_value)?,
+        )
+        .map_err(::anyhow::Error::new)?;
+
+        crate::model::public_key::from_proto(&proto).with_context(|| {
+            format!(
+                "{} value was not a valid PublicKey",
+                crate::model::content_type_to_string(content_type)
+            )
+        })
+    }
+
+    fn decode_image_bundle(
+        content_type: u64,
+        lww_value: ::std::option::Option<&[u8]>,
+    ) -> ::anyhow::Result<crate::protocol::ImageBundle> {
+        let bundle = crate::protocol::ImageBundle::parse_from_bytes(
+            expect_lww_value(content_type, lww_value)?,
+        )
+        .map_err(::anyhow::Error::new)?;
+
+        for manifest in bundle.image_manifests.iter() {
+            crate::model::process::from_proto(&manifest.process).with_context(
+                || {
+                    format!(
+                        "{} image manifest had an invalid process",
+                        crate::model::content_type_to_string(content_type)
+                    )
+                },
+            )?;
+        }
+
+        Ok(bundle)
+    }
+
     pub fn decode_content(
         content_type: u64,
         content: &[u8],
+        lww_value: ::std::option::Option<&[u8]>,
     ) -> ::anyhow::Result<Content> {
         match content_type {
-            1 => {
+            known_message_types::DELETE => {
                 let proto = crate::protocol::Delete::parse_from_bytes(content)
                     .map_err(::anyhow::Error::new)?;
 
                 Ok(Content::Delete(crate::model::delete::from_proto(&proto)?))
             }
-            12 => {
+            known_message_types::SYSTEM_PROCESSES => {
+                let proto =
+                    crate::protocol::SystemProcesses::parse_from_bytes(content)
+                        .map_err(::anyhow::Error::new)?;
+
+                Ok(Content::SystemProcesses(
+                    proto
+                        .processes
+                        .iter()
+                        .map(crate::model::process::from_proto)
+                        .collect::<::anyhow::Result<
+                            ::std::vec::Vec<crate::model::process::Process>,
+                        >>()?,
+                ))
+            }
+            known_message_types::POST => {
+                let proto = crate::protocol::Post::parse_from_bytes(content)
+                    .map_err(::anyhow::Error::new)?;
+
+                Ok(Content::Post(crate::model::post::from_proto(&proto)?))
+            }
+            known_message_types::FOLLOW => {
+                Ok(Content::Follow(decode_public_key(content_type, lww_value)?))
+            }
+            known_message_types::USERNAME => {
+                Ok(Content::Username(decode_string(content_type, lww_value)?))
+            }
+            known_message_types::DESCRIPTION => Ok(Content::Description(
+                decode_string(content_type, lww_value)?,
+            )),
+            known_message_types::BLOB_META => {
+                Ok(Content::BlobMeta(content.to_owned()))
+            }
+            known_message_types::BLOB_SECTION => {
+                Ok(Content::BlobSection(content.to_owned()))
+            }
+            known_message_types::AVATAR => Ok(Content::Avatar(
+                decode_image_bundle(content_type, lww_value)?,
+            )),
+            known_message_types::SERVER => {
+                Ok(Content::Server(decode_string(content_type, lww_value)?))
+            }
+            known_message_types::VOUCH => {
+                crate::protocol::Vouch::parse_from_bytes(content)
+                    .map_err(::anyhow::Error::new)?;
+
+                Ok(Content::Vouch)
+            }
+            known_message_types::CLAIM => {
                 let proto = crate::protocol::Claim::parse_from_bytes(content)
                     .map_err(::anyhow::Error::new)?;
 
                 Ok(Content::Claim(crate::model::claim::from_proto(&proto)))
             }
+            known_message_types::BANNER => Ok(Content::Banner(
+                decode_image_bundle(content_type, lww_value)?,
+            )),
+            known_message_types::OPINION => {
+                Ok(Content::Opinion(crate::model::opinion::from_value(
+                    expect_lww_value(content_type, lww_value)?,
+                )?))
+            }
+            known_message_types::STORE => {
+                Ok(Content::Store(decode_string(content_type, lww_value)?))
+            }
+            known_message_types::AUTHORITY => {
+                Ok(Content::Authority(decode_string(content_type, lww_value)?))
+            }
+            known_message_types::JOIN_TOPIC => {
+                Ok(Content::JoinTopic(decode_string(content_type, lww_value)?))
+            }
+            known_message_types::BLOCK => {
+                Ok(Content::Block(decode_public_key(content_type, lww_value)?))
+            }
+            known_message_types::BLOCK_TOPIC => {
+                Ok(Content::BlockTopic(decode_string(content_type, lww_value)?))
+            }
             _ => Ok(Content::Unknown(content_type, content.to_owned())),
         }
     }
 
+    pub fn from_event(
+        event: &crate::model::event::Event,
+    ) -> ::anyhow::Result<Content> {
+        let content_type = *event.content_type();
+
+        let lww_value = if is_lww_element_type(content_type) {
+            event
+                .lww_element()
+                .as_ref()
+                .map(|lww_element| lww_element.value.as_slice())
+        } else if is_lww_element_set_type(content_type) {
+            event
+                .lww_element_set()
+                .as_ref()
+                .map(|lww_element_set| lww_element_set.value.as_slice())
+        } else {
+            None
+        };
+
+        decode_content(content_type, event.content(), lww_value)
+    }
+
     pub fn content_type(content: &Content) -> u64 {
         match content {
-            Content::Delete(_) => 1,
-            Content::Claim(_) => 12,
+            Content::Delete(_) => known_message_types::DELETE,
+            Content::SystemProcesses(_) => {
+                known_message_types::SYSTEM_PROCESSES
+            }
+            Content::Post(_) => known_message_types::POST,
+            Content::Follow(_) => known_message_types::FOLLOW,
+            Content::Username(_) => known_message_types::USERNAME,
+            Content::Description(_) => known_message_types::DESCRIPTION,
+            Content::BlobMeta(_) => known_message_types::BLOB_META,
+            Content::BlobSection(_) => known_message_types::BLOB_SECTION,
+            Content::Avatar(_) => known_message_types::AVATAR,
+            Content::Server(_) => known_message_types::SERVER,
+            Content::Vouch => known_message_types::VOUCH,
+            Content::Claim(_) => known_message_types::CLAIM,
+            Content::Banner(_) => known_message_types::BANNER,
+            Content::Opinion(_) => known_message_types::OPINION,
+            Content::Store(_) => known_message_types::STORE,
+            Content::Authority(_) => known_message_types::AUTHORITY,
+            Content::JoinTopic(_) => known_message_types::JOIN_TOPIC,
+            Content::Block(_) => known_message_types::BLOCK,
+            Content::BlockTopic(_) => known_message_types::BLOCK_TOPIC,
             Content::Unknown(content_type, _) => *content_type,
         }
     }
 
+    // The bytes that belong in `Event.content`.
     pub fn encode_content(
         content: &Content,
     ) -> ::anyhow::Result<::std::vec::Vec<u8>> {
@@ -1129,10 +1579,72 @@ pub mod content {
             Content::Delete(body) => crate::model::delete::to_proto(body)
                 .write_to_bytes()
                 .map_err(::anyhow::Error::new),
+            Content::SystemProcesses(processes) => {
+                let mut proto = crate::protocol::SystemProcesses::new();
+                proto.processes = processes
+                    .iter()
+                    .map(crate::model::process::to_proto)
+                    .collect();
+                proto.write_to_bytes().map_err(::anyhow::Error::new)
+            }
+            Content::Post(body) => crate::model::post::to_proto(body)
+                .write_to_bytes()
+                .map_err(::anyhow::Error::new),
             Content::Claim(body) => crate::model::claim::to_proto(body)
                 .write_to_bytes()
                 .map_err(::anyhow::Error::new),
+            Content::BlobMeta(body) | Content::BlobSection(body) => {
+                Ok(body.clone())
+            }
             Content::Unknown(_, body) => Ok(body.clone()),
+            Content::Vouch
+            | Content::Follow(_)
+            | Content::Username(_)
+            | Content::Description(_)
+            | Content::Avatar(_)
+            | Content::Server(_)
+            | Content::Banner(_)
+            | Content::Opinion(_)
+            | Content::Store(_)
+            | Content::Authority(_)
+            | Content::JoinTopic(_)
+            | Content::Block(_)
+            | Content::BlockTopic(_) => Ok(vec![]),
+        }
+    }
+
+    // The bytes that belong in the value of the `LWWElement` or
+    // `LWWElementSet`, for content types that carry one.
+    pub fn encode_lww_value(
+        content: &Content,
+    ) -> ::anyhow::Result<::std::option::Option<::std::vec::Vec<u8>>> {
+        match content {
+            Content::Follow(system) | Content::Block(system) => Ok(Some(
+                crate::model::public_key::to_proto(system)
+                    .write_to_bytes()
+                    .map_err(::anyhow::Error::new)?,
+            )),
+            Content::Username(value)
+            | Content::Description(value)
+            | Content::Server(value)
+            | Content::Store(value)
+            | Content::Authority(value)
+            | Content::JoinTopic(value)
+            | Content::BlockTopic(value) => Ok(Some(value.as_bytes().to_vec())),
+            Content::Avatar(bundle) | Content::Banner(bundle) => {
+                Ok(Some(bundle.write_to_bytes().map_err(::anyhow::Error::new)?))
+            }
+            Content::Opinion(opinion) => {
+                Ok(Some(crate::model::opinion::to_value(opinion)))
+            }
+            Content::Delete(_)
+            | Content::SystemProcesses(_)
+            | Content::Post(_)
+            | Content::BlobMeta(_)
+            | Content::BlobSection(_)
+            | Content::Vouch
+            | Content::Claim(_)
+            | Content::Unknown(_, _) => Ok(None),
         }
     }
 }
@@ -1159,6 +1671,388 @@ pub mod tests {
 
         assert!(signed_event == parsed_event);
     }
+
+    fn ranges(pairs: &[(u64, u64)]) -> ::std::vec::Vec<crate::protocol::Range> {
+        pairs
+            .iter()
+            .map(|(low, high)| {
+                let mut range = crate::protocol::Range::new();
+                range.low = *low;
+                range.high = *high;
+                range
+            })
+            .collect()
+    }
+
+    fn ranges_for_system(
+        processes: &[(&crate::model::process::Process, &[(u64, u64)])],
+    ) -> crate::protocol::RangesForSystem {
+        let mut result = crate::protocol::RangesForSystem::new();
+
+        for (process, pairs) in processes {
+            let mut for_process = crate::protocol::RangesForProcess::new();
+            for_process.process = ::protobuf::MessageField::some(
+                crate::model::process::to_proto(process),
+            );
+            for_process.ranges = ranges(pairs);
+            result.ranges_for_processes.push(for_process);
+        }
+
+        result
+    }
+
+    #[test]
+    fn subtract_ranges() {
+        assert_eq!(
+            crate::model::ranges::subtract(
+                &ranges(&[(1, 10)]),
+                &ranges(&[(4, 6)])
+            ),
+            ranges(&[(1, 3), (7, 10)])
+        );
+
+        assert_eq!(
+            crate::model::ranges::subtract(
+                &ranges(&[(1, 10), (20, 30)]),
+                &ranges(&[(0, 2), (8, 25)])
+            ),
+            ranges(&[(3, 7), (26, 30)])
+        );
+
+        assert_eq!(
+            crate::model::ranges::subtract(
+                &ranges(&[(5, 5)]),
+                &ranges(&[(1, 10)])
+            ),
+            ranges(&[])
+        );
+
+        assert_eq!(
+            crate::model::ranges::subtract(
+                &ranges(&[(0, u64::MAX)]),
+                &ranges(&[(0, 0), (u64::MAX, u64::MAX)])
+            ),
+            ranges(&[(1, u64::MAX - 1)])
+        );
+    }
+
+    #[test]
+    fn subtract_ranges_for_system() {
+        let p1 = crate::test_utils::make_test_process();
+        let p2 = crate::test_utils::make_test_process();
+
+        let ours = ranges_for_system(&[(&p1, &[(1, 10)]), (&p2, &[(1, 3)])]);
+        let theirs = ranges_for_system(&[(&p1, &[(1, 5)])]);
+
+        assert_eq!(
+            crate::model::ranges::subtract_for_system(&ours, &theirs),
+            ranges_for_system(&[(&p1, &[(6, 10)]), (&p2, &[(1, 3)])])
+        );
+
+        assert!(crate::model::ranges::subtract_for_system(&theirs, &ours)
+            .ranges_for_processes
+            .is_empty());
+    }
+
+    #[test]
+    fn take_max_items_from_ranges() {
+        let p1 = crate::test_utils::make_test_process();
+        let p2 = crate::test_utils::make_test_process();
+
+        let all = ranges_for_system(&[
+            (&p1, &[(1, 3), (10, 12)]),
+            (&p2, &[(1, 100)]),
+        ]);
+
+        assert_eq!(
+            crate::model::ranges::take_max_items(&all, 5),
+            ranges_for_system(&[(&p1, &[(1, 3), (10, 11)])])
+        );
+
+        assert_eq!(
+            crate::model::ranges::take_max_items(&all, 10),
+            ranges_for_system(&[(&p1, &[(1, 3), (10, 12)]), (&p2, &[(1, 4)])])
+        );
+
+        assert_eq!(crate::model::ranges::take_max_items(&all, 1000), all);
+    }
+
+    fn random_string(rng: &mut impl Rng) -> String {
+        let length = rng.gen_range(0..32);
+        (0..length).map(|_| rng.gen::<char>()).collect()
+    }
+
+    fn random_bytes(rng: &mut impl Rng) -> ::std::vec::Vec<u8> {
+        let length = rng.gen_range(0..64);
+        (0..length).map(|_| rng.gen::<u8>()).collect()
+    }
+
+    fn random_system() -> crate::model::public_key::PublicKey {
+        crate::model::public_key::PublicKey::Ed25519(
+            crate::test_utils::make_test_keypair().verifying_key(),
+        )
+    }
+
+    fn random_image_manifest(
+        rng: &mut impl Rng,
+    ) -> crate::protocol::ImageManifest {
+        let mut manifest = crate::protocol::ImageManifest::new();
+        manifest.mime = random_string(rng);
+        manifest.width = rng.gen();
+        manifest.height = rng.gen();
+        manifest.byte_count = rng.gen();
+        manifest.process =
+            ::protobuf::MessageField::some(crate::model::process::to_proto(
+                &crate::test_utils::make_test_process(),
+            ));
+        manifest
+    }
+
+    fn random_image_bundle(rng: &mut impl Rng) -> crate::protocol::ImageBundle {
+        let mut bundle = crate::protocol::ImageBundle::new();
+        bundle.image_manifests = (0..rng.gen_range(0..4))
+            .map(|_| random_image_manifest(rng))
+            .collect();
+        bundle
+    }
+
+    fn random_content(
+        rng: &mut impl Rng,
+        content_type: u64,
+    ) -> crate::model::content::Content {
+        use crate::model::content::Content;
+        use crate::model::known_message_types;
+
+        match content_type {
+            known_message_types::DELETE => {
+                Content::Delete(crate::model::delete::Delete::new(
+                    crate::test_utils::make_test_process(),
+                    rng.gen(),
+                    crate::protocol::Indices::new(),
+                    Some(rng.gen()),
+                    rng.gen(),
+                ))
+            }
+            known_message_types::SYSTEM_PROCESSES => Content::SystemProcesses(
+                (0..rng.gen_range(0..4))
+                    .map(|_| crate::test_utils::make_test_process())
+                    .collect(),
+            ),
+            known_message_types::POST => {
+                Content::Post(crate::model::post::Post::new(
+                    rng.gen::<bool>().then(|| random_string(rng)),
+                    rng.gen::<bool>().then(|| random_image_manifest(rng)),
+                ))
+            }
+            known_message_types::FOLLOW => Content::Follow(random_system()),
+            known_message_types::USERNAME => {
+                Content::Username(random_string(rng))
+            }
+            known_message_types::DESCRIPTION => {
+                Content::Description(random_string(rng))
+            }
+            known_message_types::BLOB_META => {
+                Content::BlobMeta(random_bytes(rng))
+            }
+            known_message_types::BLOB_SECTION => {
+                Content::BlobSection(random_bytes(rng))
+            }
+            known_message_types::AVATAR => {
+                Content::Avatar(random_image_bundle(rng))
+            }
+            known_message_types::SERVER => Content::Server(random_string(rng)),
+            known_message_types::VOUCH => Content::Vouch,
+            known_message_types::CLAIM => {
+                let mut field = crate::protocol::ClaimFieldEntry::new();
+                field.key = rng.gen();
+                field.value = random_string(rng);
+
+                Content::Claim(crate::model::claim::Claim::new(
+                    rng.gen(),
+                    &[field],
+                ))
+            }
+            known_message_types::BANNER => {
+                Content::Banner(random_image_bundle(rng))
+            }
+            known_message_types::OPINION => Content::Opinion(
+                [
+                    crate::model::opinion::Opinion::Like,
+                    crate::model::opinion::Opinion::Dislike,
+                    crate::model::opinion::Opinion::Neutral,
+                ][rng.gen_range(0..3)],
+            ),
+            known_message_types::STORE => Content::Store(random_string(rng)),
+            known_message_types::AUTHORITY => {
+                Content::Authority(random_string(rng))
+            }
+            known_message_types::JOIN_TOPIC => {
+                Content::JoinTopic(random_string(rng))
+            }
+            known_message_types::BLOCK => Content::Block(random_system()),
+            known_message_types::BLOCK_TOPIC => {
+                Content::BlockTopic(random_string(rng))
+            }
+            _ => Content::Unknown(content_type, random_bytes(rng)),
+        }
+    }
+
+    #[test]
+    fn content_encode_decode_is_isomorphic() {
+        let mut rng = ::rand::thread_rng();
+
+        for _ in 0..32 {
+            for content_type in 1..=20 {
+                let content = random_content(&mut rng, content_type);
+
+                assert_eq!(
+                    crate::model::content::content_type(&content),
+                    content_type
+                );
+
+                let encoded =
+                    crate::model::content::encode_content(&content).unwrap();
+
+                let lww_value =
+                    crate::model::content::encode_lww_value(&content).unwrap();
+
+                let decoded = crate::model::content::decode_content(
+                    content_type,
+                    &encoded,
+                    lww_value.as_deref(),
+                )
+                .unwrap();
+
+                assert_eq!(content, decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn event_layers_decode_lww_element_content() {
+        let keypair = crate::test_utils::make_test_keypair();
+
+        let mut lww_element = crate::protocol::LWWElement::new();
+        lww_element.value = "alice".as_bytes().to_vec();
+        lww_element.unix_milliseconds = 5;
+
+        let event = crate::model::event::Event::new(
+            crate::model::public_key::PublicKey::Ed25519(
+                keypair.verifying_key(),
+            ),
+            crate::test_utils::make_test_process(),
+            0,
+            crate::model::known_message_types::USERNAME,
+            vec![],
+            crate::protocol::VectorClock::new(),
+            crate::protocol::Indices::new(),
+            vec![],
+            Some(lww_element),
+            None,
+            None,
+        );
+
+        let signed_event = crate::model::signed_event::SignedEvent::sign(
+            crate::model::event::to_proto(&event)
+                .unwrap()
+                .write_to_bytes()
+                .unwrap(),
+            &keypair,
+        );
+
+        let layers = crate::model::EventLayers::new(signed_event).unwrap();
+
+        assert_eq!(
+            layers.content(),
+            &crate::model::content::Content::Username("alice".to_string())
+        );
+    }
+
+    #[test]
+    fn event_layers_undecoded_keeps_invalid_content() {
+        let signed_event = crate::test_utils::make_test_event_with_content(
+            &crate::test_utils::make_test_keypair(),
+            &crate::test_utils::make_test_process(),
+            1,
+            crate::model::known_message_types::USERNAME,
+            b"alice",
+            vec![],
+        );
+
+        assert!(crate::model::EventLayers::new(signed_event.clone()).is_err());
+
+        let layers =
+            crate::model::EventLayers::new_undecoded(signed_event).unwrap();
+
+        assert_eq!(
+            layers.content(),
+            &crate::model::content::Content::Unknown(
+                crate::model::known_message_types::USERNAME,
+                b"alice".to_vec(),
+            )
+        );
+    }
+
+    #[test]
+    fn decode_content_rejects_invalid_content() {
+        use crate::model::known_message_types;
+
+        let err = crate::model::content::decode_content(
+            known_message_types::USERNAME,
+            &[],
+            None,
+        )
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "USERNAME expected lww value");
+
+        let err = crate::model::content::decode_content(
+            known_message_types::DESCRIPTION,
+            &[],
+            Some(&[0xff, 0xfe]),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "DESCRIPTION value was not valid UTF-8");
+
+        assert!(crate::model::content::decode_content(
+            known_message_types::OPINION,
+            &[],
+            Some(&[4]),
+        )
+        .is_err());
+
+        assert!(crate::model::content::decode_content(
+            known_message_types::POST,
+            &[0, 1, 2, 3],
+            None,
+        )
+        .is_err());
+
+        assert!(crate::model::content::decode_content(
+            known_message_types::FOLLOW,
+            &[],
+            Some(&[1, 2, 3]),
+        )
+        .is_err());
+
+        let mut bundle = crate::protocol::ImageBundle::new();
+        bundle
+            .image_manifests
+            .push(crate::protocol::ImageManifest::new());
+
+        let err = crate::model::content::decode_content(
+            known_message_types::AVATAR,
+            &[],
+            Some(&bundle.write_to_bytes().unwrap()),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "AVATAR image manifest had an invalid process"
+        );
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -13,6 +13,16 @@ pub fn make_test_process_from_number(n: u8) -> crate::model::process::Process {
     crate::model::process::Process::new([n; 16])
 }
 
+pub fn make_test_post_content() -> ::std::vec::Vec<u8> {
+    crate::model::content::encode_content(
+        &crate::model::content::Content::Post(crate::model::post::Post::new(
+            Some("test".to_string()),
+            None,
+        )),
+    )
+    .unwrap()
+}
+
 pub fn make_test_event_with_content(
     keypair: &::ed25519_dalek::SigningKey,
     process: &crate::model::process::Process,
@@ -53,8 +63,8 @@ pub fn make_test_event(
         crate::model::public_key::PublicKey::Ed25519(keypair.verifying_key()),
         process.clone(),
         logical_clock,
-        3,
-        vec![0, 1, 2, 3],
+        crate::model::known_message_types::POST,
+        make_test_post_content(),
         crate::protocol::VectorClock::new(),
         crate::protocol::Indices::new(),
         vec![],
@@ -83,7 +93,7 @@ pub fn make_test_event_with_time(
         process.clone(),
         logical_clock,
         crate::model::known_message_types::POST,
-        vec![0, 1, 2, 3],
+        make_test_post_content(),
         crate::protocol::VectorClock::new(),
         crate::protocol::Indices::new(),
         vec![],
@@ -101,6 +111,41 @@ pub fn make_test_event_with_time(
     )
 }
 
+pub fn make_test_lww_event(
+    keypair: &::ed25519_dalek::SigningKey,
+    process: &crate::model::process::Process,
+    logical_clock: u64,
+    content_type: u64,
+    value: &[u8],
+    unix_milliseconds: u64,
+) -> crate::model::signed_event::SignedEvent {
+    let mut lww_element = crate::protocol::LWWElement::new();
+    lww_element.value = value.to_vec();
+    lww_element.unix_milliseconds = unix_milliseconds;
+
+    let event = crate::model::event::Event::new(
+        crate::model::public_key::PublicKey::Ed25519(keypair.verifying_key()),
+        process.clone(),
+        logical_clock,
+        content_type,
+        vec![],
+        crate::protocol::VectorClock::new(),
+        crate::protocol::Indices::new(),
+        vec![],
+        Some(lww_element),
+        None,
+        Some(unix_milliseconds),
+    );
+
+    crate::model::signed_event::SignedEvent::sign(
+        crate::model::event::to_proto(&event)
+            .unwrap()
+            .write_to_bytes()
+            .unwrap(),
+        keypair,
+    )
+}
+
 pub fn make_delete_event_from_event(
     keypair: &::ed25519_dalek::SigningKey,
     process: &crate::model::process::Process,
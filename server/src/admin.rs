@@ -0,0 +1,315 @@
+// Admin credentials. Named tokens are stored in `admin_tokens` as SHA-256
+// hashes together with the scopes they grant. A token starts with a random
+// prefix that is not secret, which is how it is looked up. The ADMIN_TOKEN
+// from the environment remains valid as a bootstrap credential named "admin"
+// that holds every scope.
+
+pub(crate) const BOOTSTRAP_ADMIN_NAME: &str = "admin";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum AdminScope {
+    Censor,
+    Purge,
+    ModerationReview,
+    Handles,
+    Export,
+    Audit,
+}
+
+pub(crate) const ALL_SCOPES: [AdminScope; 6] = [
+    AdminScope::Censor,
+    AdminScope::Purge,
+    AdminScope::ModerationReview,
+    AdminScope::Handles,
+    AdminScope::Export,
+    AdminScope::Audit,
+];
+
+impl AdminScope {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            AdminScope::Censor => "censor",
+            AdminScope::Purge => "purge",
+            AdminScope::ModerationReview => "moderation_review",
+            AdminScope::Handles => "handles",
+            AdminScope::Export => "export",
+            AdminScope::Audit => "audit",
+        }
+    }
+}
+
+impl ::std::str::FromStr for AdminScope {
+    type Err = ::anyhow::Error;
+
+    fn from_str(s: &str) -> ::anyhow::Result<Self> {
+        ALL_SCOPES
+            .iter()
+            .find(|scope| scope.as_str() == s)
+            .copied()
+            .ok_or_else(|| ::anyhow::anyhow!("unknown admin scope: {}", s))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Admin {
+    pub(crate) name: String,
+    pub(crate) scopes: ::std::vec::Vec<AdminScope>,
+}
+
+impl Admin {
+    pub(crate) fn has_scope(&self, scope: AdminScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+pub(crate) fn hash_token(token: &str) -> [u8; 32] {
+    ::hmac_sha256::Hash::hash(token.as_bytes())
+}
+
+pub(crate) fn parse_scopes(
+    scopes: &str,
+) -> ::anyhow::Result<::std::vec::Vec<AdminScope>> {
+    scopes
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+fn random_string(length: usize) -> String {
+    let mut bytes = vec![0u8; length];
+    ::rand::RngCore::fill_bytes(&mut ::rand::thread_rng(), &mut bytes);
+    ::base64::encode_config(bytes, ::base64::URL_SAFE_NO_PAD)
+}
+
+// Returns the prefix and the whole token
+pub(crate) fn generate_token() -> (String, String) {
+    let prefix = random_string(9);
+    let token = format!("{}.{}", prefix, random_string(32));
+    (prefix, token)
+}
+
+fn token_prefix(authorization: &str) -> Option<&str> {
+    authorization
+        .split_once('.')
+        .map(|(prefix, _)| prefix)
+        .filter(|prefix| !prefix.is_empty())
+}
+
+// `token` is the stored token with the prefix of `authorization`, if any.
+// Hashes are compared in constant time.
+fn find_admin(
+    bootstrap_token: &str,
+    token: Option<&crate::postgres::admin::AdminTokenRow>,
+    authorization: &str,
+) -> Option<Admin> {
+    let mut result = None;
+
+    if !bootstrap_token.is_empty()
+        && ::constant_time_eq::constant_time_eq(
+            authorization.as_bytes(),
+            bootstrap_token.as_bytes(),
+        )
+    {
+        result = Some(Admin {
+            name: BOOTSTRAP_ADMIN_NAME.to_string(),
+            scopes: ALL_SCOPES.to_vec(),
+        });
+    }
+
+    let token_hash = hash_token(authorization);
+
+    if let Some(token) = token {
+        if ::constant_time_eq::constant_time_eq(&token_hash, &token.token_hash)
+        {
+            result = Some(Admin {
+                name: token.name.clone(),
+                scopes: token
+                    .scopes
+                    .iter()
+                    .filter_map(|scope| scope.parse().ok())
+                    .collect(),
+            });
+        }
+    }
+
+    result
+}
+
+// Resolves the authorization header to an admin holding `scope`, otherwise
+// the status to respond with. Tokens are read from the primary so that a
+// revoked token stops working right away.
+pub(crate) async fn authorize(
+    state: &::std::sync::Arc<crate::State>,
+    authorization: &str,
+    scope: AdminScope,
+) -> ::anyhow::Result<Result<Admin, ::warp::http::StatusCode>> {
+    let token = match token_prefix(authorization) {
+        Some(prefix) => {
+            let mut transaction = state.pool.begin().await?;
+            let token = crate::postgres::admin::load_active_admin_token(
+                &mut transaction,
+                prefix,
+            )
+            .await?;
+            transaction.commit().await?;
+            token
+        }
+        None => None,
+    };
+
+    Ok(
+        match find_admin(&state.admin_token, token.as_ref(), authorization) {
+            Some(admin) if admin.has_scope(scope) => Ok(admin),
+            Some(_) => Err(::warp::http::StatusCode::FORBIDDEN),
+            None => Err(::warp::http::StatusCode::UNAUTHORIZED),
+        },
+    )
+}
+
+pub(crate) async fn audit(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    admin: &Admin,
+    action: &str,
+    target: Option<&str>,
+    details: ::serde_json::Value,
+) -> ::anyhow::Result<()> {
+    crate::postgres::admin::insert_audit_log(
+        transaction,
+        &admin.name,
+        action,
+        target,
+        &details,
+    )
+    .await
+}
+
+// Tokens are managed from the command line with the CREATE_ADMIN_TOKEN and
+// REVOKE_ADMIN_TOKEN modes, which are audited under this name.
+const CLI_ADMIN: &str = "cli";
+
+// Returns the new token. Only its hash is stored.
+pub(crate) async fn create_token(
+    pool: &::sqlx::PgPool,
+    name: &str,
+    scopes: &[AdminScope],
+) -> ::anyhow::Result<String> {
+    if name == BOOTSTRAP_ADMIN_NAME || name == CLI_ADMIN {
+        ::anyhow::bail!("admin token name {} is reserved", name);
+    }
+
+    let (prefix, token) = generate_token();
+
+    let scopes = scopes
+        .iter()
+        .map(|scope| scope.as_str().to_string())
+        .collect::<::std::vec::Vec<String>>();
+
+    let mut transaction = pool.begin().await?;
+
+    crate::postgres::admin::insert_admin_token(
+        &mut transaction,
+        name,
+        &prefix,
+        &hash_token(&token),
+        &scopes,
+    )
+    .await?;
+
+    crate::postgres::admin::insert_audit_log(
+        &mut transaction,
+        CLI_ADMIN,
+        "create_admin_token",
+        Some(name),
+        &::serde_json::json!({ "scopes": scopes }),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(token)
+}
+
+pub(crate) async fn revoke_token(
+    pool: &::sqlx::PgPool,
+    name: &str,
+) -> ::anyhow::Result<()> {
+    let mut transaction = pool.begin().await?;
+
+    if !crate::postgres::admin::revoke_admin_token(&mut transaction, name)
+        .await?
+    {
+        ::anyhow::bail!("no active admin token named {}", name);
+    }
+
+    crate::postgres::admin::insert_audit_log(
+        &mut transaction,
+        CLI_ADMIN,
+        "revoke_admin_token",
+        Some(name),
+        &::serde_json::json!({}),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::admin::AdminScope;
+
+    fn make_token(
+        name: &str,
+        token: &str,
+        scopes: &[&str],
+    ) -> crate::postgres::admin::AdminTokenRow {
+        crate::postgres::admin::AdminTokenRow {
+            name: name.to_string(),
+            token_hash: crate::admin::hash_token(token).to_vec(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_scopes() {
+        assert_eq!(
+            crate::admin::parse_scopes("censor, moderation_review,").unwrap(),
+            vec![AdminScope::Censor, AdminScope::ModerationReview]
+        );
+        assert!(crate::admin::parse_scopes("censor,root").is_err());
+    }
+
+    #[test]
+    fn test_find_admin() {
+        let (prefix, token) = crate::admin::generate_token();
+        assert_eq!(crate::admin::token_prefix(&token), Some(prefix.as_str()));
+        assert_eq!(crate::admin::token_prefix("bootstrap"), None);
+        assert_eq!(crate::admin::token_prefix(".secret"), None);
+
+        let bob = make_token("bob", "bob.token", &["audit", "purge"]);
+
+        let admin =
+            crate::admin::find_admin("bootstrap", Some(&bob), "bob.token")
+                .unwrap();
+        assert_eq!(admin.name, "bob");
+        assert!(admin.has_scope(AdminScope::Purge));
+        assert!(!admin.has_scope(AdminScope::Censor));
+
+        // Same prefix, wrong secret
+        assert!(
+            crate::admin::find_admin("bootstrap", Some(&bob), "bob.guess")
+                .is_none()
+        );
+
+        let bootstrap =
+            crate::admin::find_admin("bootstrap", None, "bootstrap").unwrap();
+        assert_eq!(bootstrap.name, crate::admin::BOOTSTRAP_ADMIN_NAME);
+        assert!(bootstrap.has_scope(AdminScope::Censor));
+
+        assert!(crate::admin::find_admin("bootstrap", None, "nope").is_none());
+        assert!(crate::admin::find_admin("", None, "").is_none());
+    }
+}
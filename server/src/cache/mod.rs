@@ -1,2 +1,4 @@
 pub(crate) mod providers;
+pub(crate) mod purge_queue;
+pub(crate) mod routes;
 pub(crate) mod util;
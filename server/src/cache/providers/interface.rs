@@ -5,4 +5,10 @@ pub(crate) trait CacheProvider: Send + Sync {
     async fn purge_tags(&self, tags: &[String]) -> Result<()>;
     fn get_header_name(&self) -> &str;
     fn get_header_value(&self, tags: &[String]) -> String;
+
+    // Set for providers that serve responses from this process, see
+    // `cache::routes`.
+    fn response_cache(&self) -> Option<&super::memory::MemoryProvider> {
+        None
+    }
 }
@@ -0,0 +1,200 @@
+use crate::cache::providers::interface;
+use anyhow::Result;
+
+// Keeps whole responses in process, for deployments without Varnish. Each
+// server process has its own cache, and only sees the purges it sends
+// itself, so this is meant for a single instance. See `cache::routes` for how
+// responses get in and out.
+
+#[derive(Clone)]
+pub(crate) struct CachedResponse {
+    pub(crate) status: ::warp::http::StatusCode,
+    pub(crate) headers: ::warp::http::HeaderMap,
+    pub(crate) body: ::bytes::Bytes,
+    pub(crate) tags: Vec<String>,
+    pub(crate) expires_at: ::std::time::Instant,
+}
+
+impl ::warp::Reply for CachedResponse {
+    fn into_response(self) -> ::warp::reply::Response {
+        let mut response = ::warp::reply::Response::new(self.body.into());
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+struct Entries {
+    responses: ::lru::LruCache<String, CachedResponse>,
+    // tag -> keys of the responses carrying it
+    keys_by_tag: ::std::collections::HashMap<
+        String,
+        ::std::collections::HashSet<String>,
+    >,
+}
+
+impl Entries {
+    fn unindex(&mut self, key: &str, response: &CachedResponse) {
+        for tag in &response.tags {
+            if let Some(keys) = self.keys_by_tag.get_mut(tag) {
+                keys.remove(key);
+
+                if keys.is_empty() {
+                    self.keys_by_tag.remove(tag);
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(response) = self.responses.pop(key) {
+            self.unindex(key, &response);
+        }
+    }
+}
+
+pub(crate) struct MemoryProvider {
+    entries: ::std::sync::Mutex<Entries>,
+}
+
+impl MemoryProvider {
+    pub fn new(capacity: ::std::num::NonZeroUsize) -> Self {
+        Self {
+            entries: ::std::sync::Mutex::new(Entries {
+                responses: ::lru::LruCache::new(capacity),
+                keys_by_tag: ::std::collections::HashMap::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().ok()?;
+
+        match entries.responses.get(key) {
+            Some(response)
+                if response.expires_at > ::std::time::Instant::now() =>
+            {
+                Some(response.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, key: String, response: CachedResponse) {
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        entries.remove(&key);
+
+        for tag in &response.tags {
+            entries
+                .keys_by_tag
+                .entry(tag.clone())
+                .or_default()
+                .insert(key.clone());
+        }
+
+        if let Some((evicted_key, evicted)) =
+            entries.responses.push(key, response)
+        {
+            entries.unindex(&evicted_key, &evicted);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl interface::CacheProvider for MemoryProvider {
+    async fn purge_tags(&self, tags: &[String]) -> Result<()> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow::anyhow!("memory cache poisoned"))?;
+
+        for tag in tags {
+            if let Some(keys) = entries.keys_by_tag.remove(tag) {
+                for key in keys {
+                    entries.remove(&key);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Internal, `cache::routes` strips it before the response goes out
+    fn get_header_name(&self) -> &str {
+        "x-polycentric-cache-tags"
+    }
+
+    fn get_header_value(&self, tags: &[String]) -> String {
+        tags.join(" ")
+    }
+
+    fn response_cache(&self) -> Option<&MemoryProvider> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::providers::interface::CacheProvider;
+
+    fn response(tags: &[&str], ttl: u64) -> CachedResponse {
+        CachedResponse {
+            status: ::warp::http::StatusCode::OK,
+            headers: ::warp::http::HeaderMap::new(),
+            body: ::bytes::Bytes::from_static(b"body"),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            expires_at: ::std::time::Instant::now()
+                + ::std::time::Duration::from_secs(ttl),
+        }
+    }
+
+    fn provider(capacity: usize) -> MemoryProvider {
+        MemoryProvider::new(::std::num::NonZeroUsize::new(capacity).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_purge_by_tag() -> anyhow::Result<()> {
+        let cache = provider(10);
+
+        cache.insert("/a".to_string(), response(&["x", "y"], 60));
+        cache.insert("/b".to_string(), response(&["y"], 60));
+        cache.insert("/c".to_string(), response(&["z"], 60));
+
+        cache.purge_tags(&["y".to_string()]).await?;
+
+        assert!(cache.get("/a").is_none());
+        assert!(cache.get("/b").is_none());
+        assert!(cache.get("/c").is_some());
+
+        // The purged entry no longer answers to its other tags
+        assert!(!cache.entries.lock().unwrap().keys_by_tag.contains_key("x"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eviction_and_expiry() {
+        let cache = provider(2);
+
+        cache.insert("/a".to_string(), response(&["x"], 60));
+        cache.insert("/b".to_string(), response(&["x"], 60));
+        cache.insert("/c".to_string(), response(&["x"], 60));
+
+        assert!(cache.get("/a").is_none());
+        assert!(cache.get("/b").is_some());
+        assert_eq!(cache.entries.lock().unwrap().keys_by_tag["x"].len(), 2);
+
+        cache.insert("/b".to_string(), response(&["x"], 0));
+        assert!(cache.get("/b").is_none());
+        assert_eq!(cache.entries.lock().unwrap().keys_by_tag["x"].len(), 1);
+    }
+}
@@ -1,4 +1,5 @@
 pub(crate) mod interface;
+pub(crate) mod memory;
 pub(crate) mod noop;
 pub(crate) mod varnish;
 
@@ -17,6 +18,17 @@ pub(crate) fn make_provider(
                     Err(anyhow::anyhow!("Missing cache base URL configuration"))
                 }
             }
+            "memory" => {
+                let capacity =
+                    ::std::num::NonZeroUsize::new(config.memory_cache_capacity)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "MEMORY_CACHE_CAPACITY must not be zero"
+                            )
+                        })?;
+
+                Ok(Box::new(memory::MemoryProvider::new(capacity)))
+            }
             "noop" => {
                 ::log::info!("No cache provider selected");
                 Ok(Box::new(noop::NoopProvider))
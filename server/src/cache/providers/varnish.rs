@@ -29,32 +29,26 @@ impl interface::CacheProvider for VarnishProvider {
         let url = format!("{}/", self.base_url);
 
         ::log::debug!("Purging tags: {}", tags_str);
-        let method = Method::from_bytes(b"PURGE").unwrap();
+        let method = Method::from_bytes(b"PURGE")?;
         let response = self
             .client
             .request(method, &url)
             .header("xkey-purge", tags_str)
             .send()
-            .await;
+            .await?;
 
-        if let Err(e) = response {
-            ::log::error!("Error purging tags: {:?}", e);
-            ::log::error!("Error purging tags: {}", e.to_string());
-            return Ok(());
-        }
-
-        let response = response.unwrap();
+        // Failures are returned so that the purge queue retries them
         if !response.status().is_success() {
             // Check if we got a 403 Forbidden, which likely means we're not allowed to access the cache API
             if response.status() == reqwest::StatusCode::FORBIDDEN {
                 ::log::error!("Access to Varnish purge API is forbidden. Please check your Varnish configuration to ensure the server has access to the purge API.");
             }
 
-            ::log::error!(
+            return Err(anyhow::anyhow!(
                 "Varnish purge API returned error: {} - {}",
                 response.status(),
                 response.text().await?
-            );
+            ));
         } else {
             ::log::debug!(
                 "Successfully purged tags: {}",
@@ -0,0 +1,479 @@
+use ::cadence::{Counted, Gauged};
+
+// Cache tags to purge are written to `cache_purges` by the transaction making
+// the change, so nothing is purged before the change is visible. `run` sends
+// the queued tags to the cache provider in batches, a tag queued by several
+// changes is purged once, and batches that fail are retried with exponential
+// backoff.
+//
+// Taken tags are leased rather than removed, and only removed once the
+// provider has purged them. Tags taken by a server that stops before then are
+// taken again when the lease runs out.
+
+// Longer than a purge request can take
+const LEASE: ::std::time::Duration = ::std::time::Duration::from_secs(300);
+
+pub(crate) struct Options {
+    pub(crate) interval: ::std::time::Duration,
+    pub(crate) batch_size: u64,
+    pub(crate) max_backoff: ::std::time::Duration,
+}
+
+#[derive(::sqlx::FromRow, Debug)]
+struct PurgeRow {
+    id: i64,
+    tag: String,
+    attempts: i32,
+}
+
+// Every queued row of one tag
+#[derive(Debug)]
+struct Purge {
+    ids: ::std::vec::Vec<i64>,
+    tag: String,
+    attempts: i32,
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct DrainResult {
+    // Rows, several of which can be for the same tag
+    taken: u64,
+    purged: u64,
+    failed: u64,
+}
+
+#[derive(::sqlx::FromRow, Debug)]
+struct Backlog {
+    pending: i64,
+    // Seconds since the oldest pending tag was queued
+    oldest: f64,
+}
+
+pub(crate) async fn enqueue(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    tags: &[String],
+) -> ::anyhow::Result<()> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+
+    let query = "
+        INSERT INTO cache_purges (tag)
+        SELECT DISTINCT UNNEST($1::TEXT[]);
+    ";
+
+    ::sqlx::query(query)
+        .bind(tags)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn enqueue_events(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    signed_events: &[polycentric_protocol::model::signed_event::SignedEvent],
+) -> ::anyhow::Result<()> {
+    let tags = crate::cache::util::signed_events_to_cache_tags(
+        signed_events,
+        true,
+        true,
+        true,
+        true,
+    );
+
+    enqueue(transaction, &tags).await
+}
+
+// Takes raw events so that it can be given the rows returned by a DELETE.
+pub(crate) async fn enqueue_raw_events(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    raw_events: &[::std::vec::Vec<u8>],
+) -> ::anyhow::Result<()> {
+    let signed_events = raw_events
+        .iter()
+        .map(|raw_event| {
+            polycentric_protocol::model::signed_event::from_vec(raw_event)
+        })
+        .collect::<::anyhow::Result<::std::vec::Vec<_>>>()?;
+
+    enqueue_events(transaction, &signed_events).await
+}
+
+pub(crate) async fn enqueue_event(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: i64,
+) -> ::anyhow::Result<()> {
+    let query = "
+        SELECT raw_event FROM events WHERE id = $1;
+    ";
+
+    let raw_events: ::std::vec::Vec<::std::vec::Vec<u8>> =
+        ::sqlx::query_scalar(query)
+            .bind(event_id)
+            .fetch_all(&mut **transaction)
+            .await?;
+
+    enqueue_raw_events(transaction, &raw_events).await
+}
+
+pub(crate) async fn enqueue_system(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+) -> ::anyhow::Result<()> {
+    enqueue(
+        transaction,
+        &crate::cache::util::system_to_cache_tags(system)?,
+    )
+    .await
+}
+
+// Leases the rows due for an attempt along with every other due row of the
+// same tags. A change made while a tag is being purged queues a new row, which
+// is not part of the lease and is purged again.
+async fn take(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    limit: u64,
+) -> ::anyhow::Result<(u64, ::std::vec::Vec<Purge>)> {
+    let query = "
+        WITH due AS (
+            SELECT tag FROM cache_purges
+            WHERE next_attempt_at <= NOW()
+            ORDER BY next_attempt_at, id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        UPDATE cache_purges
+        SET next_attempt_at = NOW() + make_interval(secs => $2)
+        WHERE tag IN (SELECT tag FROM due)
+        AND next_attempt_at <= NOW()
+        RETURNING id, tag, attempts;
+    ";
+
+    let rows = ::sqlx::query_as::<_, PurgeRow>(query)
+        .bind(i64::try_from(limit)?)
+        .bind(LEASE.as_secs_f64())
+        .fetch_all(&mut **transaction)
+        .await?;
+
+    let taken = u64::try_from(rows.len())?;
+
+    let mut purges = ::std::collections::BTreeMap::<String, Purge>::new();
+
+    for row in rows {
+        let purge = purges.entry(row.tag.clone()).or_insert(Purge {
+            ids: vec![],
+            tag: row.tag,
+            attempts: 0,
+        });
+
+        purge.ids.push(row.id);
+        purge.attempts = purge.attempts.max(row.attempts);
+    }
+
+    Ok((taken, purges.into_values().collect()))
+}
+
+fn ids(purges: &[Purge]) -> ::std::vec::Vec<i64> {
+    purges
+        .iter()
+        .flat_map(|purge| purge.ids.iter().copied())
+        .collect()
+}
+
+async fn complete(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    purges: &[Purge],
+) -> ::anyhow::Result<()> {
+    let query = "
+        DELETE FROM cache_purges WHERE id = ANY($1);
+    ";
+
+    ::sqlx::query(query)
+        .bind(ids(purges))
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+// The next attempt is delayed by 2^attempts seconds, up to `max_backoff`.
+async fn retry(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    purges: &[Purge],
+    max_backoff: ::std::time::Duration,
+) -> ::anyhow::Result<()> {
+    let query = "
+        UPDATE cache_purges
+        SET attempts = attempts + 1,
+            next_attempt_at = NOW() + make_interval(
+                secs => LEAST(POWER(2, LEAST(attempts, 30)), $2)
+            )
+        WHERE id = ANY($1);
+    ";
+
+    ::sqlx::query(query)
+        .bind(ids(purges))
+        .bind(max_backoff.as_secs_f64())
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+async fn backlog(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+) -> ::anyhow::Result<Backlog> {
+    let query = "
+        SELECT
+            COUNT(DISTINCT tag) AS pending,
+            COALESCE(
+                EXTRACT(EPOCH FROM NOW() - MIN(created_on)), 0
+            )::FLOAT8 AS oldest
+        FROM cache_purges;
+    ";
+
+    Ok(::sqlx::query_as::<_, Backlog>(query)
+        .fetch_one(&mut **transaction)
+        .await?)
+}
+
+async fn drain(
+    state: &crate::State,
+    provider: &dyn crate::cache::providers::interface::CacheProvider,
+    options: &Options,
+) -> ::anyhow::Result<DrainResult> {
+    let mut transaction = state.pool.begin().await?;
+    let (taken, purges) = take(&mut transaction, options.batch_size).await?;
+    transaction.commit().await?;
+
+    if purges.is_empty() {
+        return Ok(DrainResult::default());
+    }
+
+    let tags = purges
+        .iter()
+        .map(|purge| purge.tag.clone())
+        .collect::<::std::vec::Vec<_>>();
+
+    match provider.purge_tags(&tags).await {
+        Ok(()) => {
+            let mut transaction = state.pool.begin().await?;
+            complete(&mut transaction, &purges).await?;
+            transaction.commit().await?;
+
+            Ok(DrainResult {
+                taken,
+                purged: u64::try_from(purges.len())?,
+                failed: 0,
+            })
+        }
+        Err(err) => {
+            ::log::warn!("failed to purge {} cache tags: {}", tags.len(), err);
+
+            let mut transaction = state.pool.begin().await?;
+            retry(&mut transaction, &purges, options.max_backoff).await?;
+            transaction.commit().await?;
+
+            Ok(DrainResult {
+                taken,
+                purged: 0,
+                failed: u64::try_from(purges.len())?,
+            })
+        }
+    }
+}
+
+async fn report(
+    state: &crate::State,
+    result: &DrainResult,
+) -> ::anyhow::Result<()> {
+    let mut transaction = state.pool.begin().await?;
+    let backlog = backlog(&mut transaction).await?;
+    transaction.commit().await?;
+
+    state
+        .statsd_client
+        .gauge_with_tags("cache_purge_backlog", u64::try_from(backlog.pending)?)
+        .try_send()?;
+
+    state
+        .statsd_client
+        .gauge_with_tags("cache_purge_oldest_seconds", backlog.oldest)
+        .try_send()?;
+
+    state
+        .statsd_client
+        .count_with_tags("cache_purged", i64::try_from(result.purged)?)
+        .try_send()?;
+
+    state
+        .statsd_client
+        .count_with_tags("cache_purge_failed", i64::try_from(result.failed)?)
+        .try_send()?;
+
+    Ok(())
+}
+
+pub(crate) async fn run(
+    state: ::std::sync::Arc<crate::State>,
+    options: Options,
+) {
+    let provider = match &state.cache_provider {
+        Some(provider) => provider.as_ref(),
+        None => return,
+    };
+
+    let mut interval = ::tokio::time::interval(
+        options
+            .interval
+            .max(::std::time::Duration::from_millis(100)),
+    );
+
+    loop {
+        interval.tick().await;
+
+        // Keep going without waiting for the next tick while there is a full
+        // batch to purge.
+        loop {
+            let result = match drain(&state, provider, &options).await {
+                Ok(result) => result,
+                Err(err) => {
+                    ::log::warn!("failed to drain cache purges: {}", err);
+                    break;
+                }
+            };
+
+            if let Err(err) = report(&state, &result).await {
+                ::log::warn!("failed to report cache purges: {}", err);
+            }
+
+            if result.taken < options.batch_size || result.failed > 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postgres::prepare_database;
+    use sqlx::PgPool;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    fn taken_tags(purges: &[Purge]) -> Vec<&str> {
+        purges.iter().map(|purge| purge.tag.as_str()).collect()
+    }
+
+    #[sqlx::test]
+    async fn test_queue(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        // Coalesced across changes
+        enqueue(&mut transaction, &tags(&["a", "b"])).await?;
+        enqueue(&mut transaction, &tags(&["b", "c"])).await?;
+        assert_eq!(backlog(&mut transaction).await?.pending, 3);
+
+        let (taken, purges) = take(&mut transaction, 2).await?;
+        assert_eq!(taken, 3);
+        assert_eq!(taken_tags(&purges), vec!["a", "b"]);
+        assert_eq!(purges[1].ids.len(), 2);
+
+        // Leased until purged
+        assert_eq!(backlog(&mut transaction).await?.pending, 3);
+        let (_, rest) = take(&mut transaction, 10).await?;
+        assert_eq!(taken_tags(&rest), vec!["c"]);
+
+        complete(&mut transaction, &rest).await?;
+        assert_eq!(backlog(&mut transaction).await?.pending, 2);
+
+        retry(
+            &mut transaction,
+            &purges,
+            ::std::time::Duration::from_secs(60),
+        )
+        .await?;
+
+        // Backing off
+        assert!(take(&mut transaction, 10).await?.1.is_empty());
+
+        sqlx::query("UPDATE cache_purges SET next_attempt_at = NOW();")
+            .execute(&mut *transaction)
+            .await?;
+
+        let (_, retried) = take(&mut transaction, 10).await?;
+        assert_eq!(taken_tags(&retried), vec!["a", "b"]);
+        assert!(retried.iter().all(|purge| purge.attempts == 1));
+
+        // Queued again while being purged
+        enqueue(&mut transaction, &tags(&["a"])).await?;
+        complete(&mut transaction, &retried).await?;
+
+        let (_, requeued) = take(&mut transaction, 10).await?;
+        assert_eq!(taken_tags(&requeued), vec!["a"]);
+        assert_eq!(requeued[0].attempts, 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_expired_lease(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        enqueue(&mut transaction, &tags(&["a"])).await?;
+
+        // Taken by a server that stopped before purging
+        assert_eq!(take(&mut transaction, 10).await?.1.len(), 1);
+        assert!(take(&mut transaction, 10).await?.1.is_empty());
+
+        sqlx::query("UPDATE cache_purges SET next_attempt_at = NOW();")
+            .execute(&mut *transaction)
+            .await?;
+
+        assert_eq!(taken_tags(&take(&mut transaction, 10).await?.1), vec!["a"]);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_ingest_queues_tags(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+        let signed_event = polycentric_protocol::test_utils::make_test_event(
+            &keypair, &process, 1,
+        );
+
+        crate::ingest::ingest_event_postgres(&mut transaction, &signed_event)
+            .await?;
+
+        let mut queued = take(&mut transaction, 100)
+            .await?
+            .1
+            .into_iter()
+            .map(|purge| purge.tag)
+            .collect::<Vec<_>>();
+        queued.sort();
+
+        assert_eq!(
+            queued,
+            crate::cache::util::signed_events_to_cache_tags(
+                &[signed_event],
+                true,
+                true,
+                true,
+                true,
+            )
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+use ::warp::Filter;
+
+// Serves a route from the cache provider's response cache, when it has one,
+// without running the handler. Responses are stored when they carry cache
+// tags and a shared max age, the same things Varnish goes by, and stay until
+// the max age passes or one of their tags is purged.
+
+fn shared_max_age(
+    headers: &::warp::http::HeaderMap,
+) -> Option<::std::time::Duration> {
+    let value = headers
+        .get(::warp::http::header::CACHE_CONTROL)?
+        .to_str()
+        .ok()?;
+
+    let mut max_age = None;
+
+    for directive in value.split(',').map(str::trim) {
+        if directive == "no-store" || directive == "private" {
+            return None;
+        }
+
+        if let Some(seconds) = directive.strip_prefix("s-maxage=") {
+            return seconds.parse().ok().map(::std::time::Duration::from_secs);
+        }
+
+        if let Some(seconds) = directive.strip_prefix("max-age=") {
+            max_age =
+                seconds.parse().ok().map(::std::time::Duration::from_secs);
+        }
+    }
+
+    max_age
+}
+
+// Strong, so it only matches byte for byte identical bodies.
+fn etag(body: &[u8]) -> String {
+    let digest = ::hmac_sha256::Hash::hash(body);
+
+    format!(
+        "\"{}\"",
+        digest
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    )
+}
+
+// `If-None-Match` uses the weak comparison, so `W/` prefixes are ignored.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// Response for a read endpoint, `body` is the serialized protobuf. The tag
+// header is left out when there is no cache provider to purge it.
+pub(crate) fn reply(
+    state: &crate::State,
+    body: ::std::vec::Vec<u8>,
+    cache_control: &'static str,
+    mut tags: ::std::vec::Vec<String>,
+) -> Box<dyn ::warp::Reply> {
+    let etag = etag(&body);
+
+    let response = ::warp::reply::with_header(
+        ::warp::reply::with_header(
+            ::warp::reply::with_status(body, ::warp::http::StatusCode::OK),
+            "Cache-Control",
+            cache_control,
+        ),
+        ::warp::http::header::ETAG,
+        etag,
+    );
+
+    tags.sort();
+    tags.dedup();
+
+    match state.cache_provider.as_ref() {
+        Some(cache_provider) if !tags.is_empty() => {
+            Box::new(::warp::reply::with_header(
+                response,
+                cache_provider.get_header_name(),
+                cache_provider.get_header_value(&tags),
+            ))
+        }
+        _ => Box::new(response),
+    }
+}
+
+// Answers with `304 Not Modified`, keeping the other headers, when the client
+// already has the body the `ETag` was computed from.
+fn conditional(
+    request_headers: ::warp::http::HeaderMap,
+    reply: Box<dyn ::warp::Reply>,
+) -> ::warp::reply::Response {
+    let mut response = ::warp::Reply::into_response(reply);
+
+    let if_none_match = match request_headers
+        .get(::warp::http::header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(if_none_match) => if_none_match,
+        None => return response,
+    };
+
+    let matches = response
+        .headers()
+        .get(::warp::http::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(|etag| etag_matches(if_none_match, etag))
+        .unwrap_or(false);
+
+    if !matches || response.status() != ::warp::http::StatusCode::OK {
+        return response;
+    }
+
+    *response.status_mut() = ::warp::http::StatusCode::NOT_MODIFIED;
+    *response.body_mut() = ::hyper::Body::empty();
+    response
+        .headers_mut()
+        .remove(::warp::http::header::CONTENT_LENGTH);
+    response
+        .headers_mut()
+        .remove(::warp::http::header::CONTENT_TYPE);
+
+    response
+}
+
+async fn lookup(
+    key: String,
+    state: ::std::sync::Arc<crate::State>,
+) -> Result<Box<dyn ::warp::Reply>, ::warp::Rejection> {
+    let response = state
+        .cache_provider
+        .as_ref()
+        .and_then(|provider| provider.response_cache())
+        .and_then(|cache| cache.get(&key));
+
+    match response {
+        Some(response) => Ok(Box::new(response)),
+        None => Err(::warp::reject()),
+    }
+}
+
+async fn store(
+    key: String,
+    state: ::std::sync::Arc<crate::State>,
+    reply: Box<dyn ::warp::Reply>,
+) -> Result<Box<dyn ::warp::Reply>, ::warp::Rejection> {
+    let provider = match state.cache_provider.as_ref() {
+        Some(provider) => provider,
+        None => return Ok(reply),
+    };
+
+    let cache = match provider.response_cache() {
+        Some(cache) => cache,
+        None => return Ok(reply),
+    };
+
+    let mut response = ::warp::Reply::into_response(reply);
+
+    let tags: Vec<String> = response
+        .headers_mut()
+        .remove(provider.get_header_name())
+        .and_then(|value| value.to_str().map(str::to_string).ok())
+        .map(|value| value.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let max_age = shared_max_age(response.headers());
+
+    // Responses without tags could never be purged
+    let max_age = match max_age {
+        Some(max_age)
+            if response.status() == ::warp::http::StatusCode::OK
+                && !tags.is_empty()
+                && !max_age.is_zero() =>
+        {
+            max_age
+        }
+        _ => return Ok(Box::new(response)),
+    };
+
+    let (parts, body) = response.into_parts();
+
+    let body = match ::hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => {
+            ::log::warn!("failed to buffer response for {}: {}", key, err);
+
+            return Ok(Box::new(
+                ::warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+
+    cache.insert(
+        key,
+        crate::cache::providers::memory::CachedResponse {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+            tags,
+            expires_at: ::std::time::Instant::now() + max_age,
+        },
+    );
+
+    Ok(Box::new(::warp::reply::Response::from_parts(
+        parts,
+        body.into(),
+    )))
+}
+
+// `route` is what comes after the path filters, keys are the full path and
+// query string. Cached or not, `If-None-Match` is checked against the `ETag`
+// set by `reply`.
+pub(crate) fn cached<S, F>(
+    state: S,
+    route: F,
+) -> ::warp::filters::BoxedFilter<(Box<dyn ::warp::Reply>,)>
+where
+    S: Filter<
+            Extract = (::std::sync::Arc<crate::State>,),
+            Error = ::std::convert::Infallible,
+        > + Clone
+        + Send
+        + Sync
+        + 'static,
+    F: Filter<Extract = (Box<dyn ::warp::Reply>,), Error = ::warp::Rejection>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    let key = ::warp::path::full()
+        .and(
+            ::warp::query::raw()
+                .or(::warp::any().map(String::new))
+                .unify(),
+        )
+        .map(|path: ::warp::path::FullPath, query: String| {
+            if query.is_empty() {
+                path.as_str().to_string()
+            } else {
+                format!("{}?{}", path.as_str(), query)
+            }
+        });
+
+    let hit = key.and(state.clone()).and_then(lookup);
+    let miss = key.and(state).and(route).and_then(store);
+
+    ::warp::header::headers_cloned()
+        .and(hit.or(miss).unify())
+        .map(
+            |request_headers: ::warp::http::HeaderMap,
+             reply: Box<dyn ::warp::Reply>| {
+                Box::new(conditional(request_headers, reply))
+                    as Box<dyn ::warp::Reply>
+            },
+        )
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(cache_control: &str) -> ::warp::http::HeaderMap {
+        let mut headers = ::warp::http::HeaderMap::new();
+        headers.insert(
+            ::warp::http::header::CACHE_CONTROL,
+            cache_control.parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_shared_max_age() {
+        assert_eq!(
+            shared_max_age(&headers("public, s-maxage=3600, max-age=5")),
+            Some(::std::time::Duration::from_secs(3600))
+        );
+        assert_eq!(
+            shared_max_age(&headers("public, max-age=30")),
+            Some(::std::time::Duration::from_secs(30))
+        );
+        assert_eq!(shared_max_age(&headers("no-store")), None);
+        assert_eq!(shared_max_age(&::warp::http::HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_conditional() {
+        let tag = etag(b"body");
+        assert_eq!(tag, etag(b"body"));
+        assert_ne!(tag, etag(b"other"));
+
+        assert!(etag_matches(&tag, &tag));
+        assert!(etag_matches(&format!("\"x\", W/{}", tag), &tag));
+        assert!(etag_matches("*", &tag));
+        assert!(!etag_matches("\"x\"", &tag));
+
+        let response = || -> Box<dyn ::warp::Reply> {
+            Box::new(::warp::reply::with_header(
+                ::warp::reply::with_status(
+                    b"body".to_vec(),
+                    ::warp::http::StatusCode::OK,
+                ),
+                ::warp::http::header::ETAG,
+                etag(b"body"),
+            ))
+        };
+
+        let mut request_headers = ::warp::http::HeaderMap::new();
+
+        assert_eq!(
+            conditional(request_headers.clone(), response()).status(),
+            ::warp::http::StatusCode::OK
+        );
+
+        request_headers
+            .insert(::warp::http::header::IF_NONE_MATCH, tag.parse().unwrap());
+
+        let not_modified = conditional(request_headers, response());
+        assert_eq!(
+            not_modified.status(),
+            ::warp::http::StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            not_modified.headers()[::warp::http::header::ETAG],
+            tag.as_str()
+        );
+    }
+}
@@ -120,3 +120,53 @@ pub(crate) fn signed_events_to_cache_tags(
 
     tags
 }
+
+// Every per-user tag of the system, for changes that affect all of its
+// content.
+pub(crate) fn system_to_cache_tags(
+    system: &public_key::PublicKey,
+) -> ::anyhow::Result<Vec<String>> {
+    let key_str = public_key::to_base64(system)?;
+
+    let mut tags: Vec<String> = [
+        known_message_types::POST,
+        known_message_types::DELETE,
+        known_message_types::CLAIM,
+        known_message_types::VOUCH,
+        known_message_types::USERNAME,
+        known_message_types::AVATAR,
+        known_message_types::BANNER,
+        known_message_types::DESCRIPTION,
+        known_message_types::SERVER,
+    ]
+    .iter()
+    .map(|content_type| format!("pkey-{}-{}", content_type, key_str))
+    .collect();
+
+    tags.push(format!("pkey-meta-{}", key_str));
+
+    Ok(tags)
+}
+
+// Tag for anything read out of the system's log as a whole, heads, ranges and
+// indices. Every event the system signs and every purge of it invalidates it.
+pub(crate) fn key_to_cache_tags_account_meta(
+    system: &public_key::PublicKey,
+) -> Vec<String> {
+    match public_key::to_base64(system) {
+        Ok(key_str) => vec![format!("pkey-meta-{}", key_str)],
+        Err(_) => Vec::new(),
+    }
+}
+
+// Tags for responses about a reference subject, matching the reference tags
+// of the events pointing at it.
+pub(crate) fn references_to_cache_tags(
+    references: &[reference::Reference],
+) -> Vec<String> {
+    references
+        .iter()
+        .filter_map(|reference| reference::to_base64(reference).ok())
+        .map(|base64| format!("ref-{}", base64))
+        .collect()
+}
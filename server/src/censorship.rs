@@ -0,0 +1,37 @@
+async fn sweep(pool: &::sqlx::PgPool) -> ::anyhow::Result<u64> {
+    let mut transaction = pool.begin().await?;
+
+    let lifted = crate::postgres::censorship::delete_expired_censorship(
+        &mut transaction,
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(lifted)
+}
+
+// Lifts censorships once their expiry has passed.
+pub(crate) async fn run_expiry_sweep(
+    pool: ::sqlx::PgPool,
+    interval: ::std::time::Duration,
+) {
+    let mut interval = ::tokio::time::interval(
+        interval.max(::std::time::Duration::from_secs(1)),
+    );
+
+    loop {
+        interval.tick().await;
+
+        match sweep(&pool).await {
+            Ok(lifted) => {
+                if lifted > 0 {
+                    ::log::info!("lifted {} expired censorships", lifted);
+                }
+            }
+            Err(err) => {
+                ::log::warn!("failed to sweep expired censorships: {}", err);
+            }
+        }
+    }
+}
@@ -4,6 +4,13 @@ pub(crate) enum Mode {
     ServeAPI,
     BackfillSearch,
     BackfillRemoteServer,
+    PullRemoteServer,
+    Reconcile,
+    ReconcileSearch,
+    CreateAdminToken,
+    RevokeAdminToken,
+    Remoderate,
+    StoreModerationPolicy,
 }
 
 impl ::std::str::FromStr for Mode {
@@ -14,14 +21,23 @@ impl ::std::str::FromStr for Mode {
             "SERVE_API" => Ok(Mode::ServeAPI),
             "BACKFILL_SEARCH" => Ok(Mode::BackfillSearch),
             "BACKFILL_REMOTE_SERVER" => Ok(Mode::BackfillRemoteServer),
+            "PULL_REMOTE_SERVER" => Ok(Mode::PullRemoteServer),
+            "RECONCILE" => Ok(Mode::Reconcile),
+            "RECONCILE_SEARCH" => Ok(Mode::ReconcileSearch),
+            "CREATE_ADMIN_TOKEN" => Ok(Mode::CreateAdminToken),
+            "REVOKE_ADMIN_TOKEN" => Ok(Mode::RevokeAdminToken),
+            "REMODERATE" => Ok(Mode::Remoderate),
+            "STORE_MODERATION_POLICY" => Ok(Mode::StoreModerationPolicy),
             _ => Err(()),
         }
     }
 }
 
-#[derive(Clone, Copy, ::sqlx::Type, ::serde::Deserialize)]
+#[derive(Clone, Copy, PartialEq, Debug, ::sqlx::Type)]
 #[sqlx(type_name = "moderation_mode")]
 #[sqlx(rename_all = "lowercase")]
+#[derive(::serde::Deserialize, ::serde::Serialize)]
+#[serde(rename_all = "lowercase")]
 pub(crate) enum ModerationMode {
     Off,
     Lazy,
@@ -64,6 +80,14 @@ pub(crate) struct Config {
     #[envconfig(from = "ADMIN_TOKEN")]
     pub admin_token: String,
 
+    // Used by the CREATE_ADMIN_TOKEN and REVOKE_ADMIN_TOKEN modes
+    #[envconfig(from = "ADMIN_TOKEN_NAME")]
+    pub admin_token_name: Option<String>,
+
+    // Comma separated, for example "censor,purge"
+    #[envconfig(from = "ADMIN_TOKEN_SCOPES", default = "")]
+    pub admin_token_scopes: String,
+
     #[envconfig(from = "STATSD_ADDRESS", default = "telegraf")]
     pub statsd_address: String,
 
@@ -73,6 +97,27 @@ pub(crate) struct Config {
     #[envconfig(from = "CHALLENGE_KEY")]
     pub challenge_key: String,
 
+    #[envconfig(from = "CHALLENGE_TTL_SECONDS", default = "300")]
+    pub challenge_ttl_seconds: u64,
+
+    // Reporters needed to send an event to manual review
+    #[envconfig(from = "REPORT_REVIEW_THRESHOLD", default = "5")]
+    pub report_review_threshold: u64,
+
+    #[envconfig(from = "CENSORSHIP_SWEEP_INTERVAL_SECONDS", default = "60")]
+    pub censorship_sweep_interval_seconds: u64,
+
+    #[envconfig(from = "SEARCH_INDEXER_INTERVAL_SECONDS", default = "1")]
+    pub search_indexer_interval_seconds: u64,
+
+    // Events sent to OpenSearch per _bulk request
+    #[envconfig(from = "SEARCH_INDEXER_BATCH_SIZE", default = "500")]
+    pub search_indexer_batch_size: u64,
+
+    // Cap on the exponential backoff between attempts to index an event
+    #[envconfig(from = "SEARCH_INDEXER_MAX_BACKOFF_SECONDS", default = "600")]
+    pub search_indexer_max_backoff_seconds: u64,
+
     #[envconfig(from = "MODE", default = "SERVE_API")]
     pub mode: Mode,
 
@@ -82,15 +127,40 @@ pub(crate) struct Config {
     #[envconfig(from = "BACKFILL_REMOTE_SERVER_POSITION")]
     pub backfill_remote_server_position: Option<u64>,
 
+    #[envconfig(from = "PULL_REMOTE_SERVER_ADDRESS")]
+    pub pull_remote_server_address: Option<String>,
+
+    #[envconfig(from = "PULL_REMOTE_SERVER_TOKEN")]
+    pub pull_remote_server_token: Option<String>,
+
+    #[envconfig(from = "RECONCILE_SERVERS", default = "")]
+    pub reconcile_servers: String,
+
+    #[envconfig(from = "RECONCILE_SELF_ADDRESS")]
+    pub reconcile_self_address: Option<String>,
+
+    // Hosts that servers listed by systems themselves may be on, comma
+    // separated. Servers in RECONCILE_SERVERS are always used.
+    #[envconfig(from = "RECONCILE_ALLOWED_PEER_HOSTS", default = "")]
+    pub reconcile_allowed_peer_hosts: String,
+
     #[envconfig(from = "MODERATION_MODE", default = "OFF")]
     pub moderation_mode: ModerationMode,
 
+    // Read by STORE_MODERATION_POLICY, other modes use the stored policy
+    #[envconfig(from = "MODERATION_POLICY_PATH")]
+    pub moderation_policy_path: Option<String>,
+
     #[envconfig(from = "CSAM_INTERFACE")]
     pub csam_interface: Option<String>,
 
     #[envconfig(from = "TAG_INTERFACE")]
     pub tag_interface: Option<String>,
 
+    // Runs next to TAG_INTERFACE for comparison, its tags are never applied
+    #[envconfig(from = "SHADOW_TAG_INTERFACE")]
+    pub shadow_tag_interface: Option<String>,
+
     #[envconfig(from = "AZURE_TAGGING_ENDPOINT")]
     pub azure_tagging_endpoint: Option<String>,
 
@@ -103,15 +173,109 @@ pub(crate) struct Config {
     #[envconfig(from = "PHOTODNA_KEY")]
     pub photodna_key: Option<String>,
 
+    #[envconfig(from = "LOCAL_TAG_RULES_PATH")]
+    pub local_tag_rules_path: Option<String>,
+
+    #[envconfig(from = "PHASH_LIST_PATH")]
+    pub phash_list_path: Option<String>,
+
+    #[envconfig(from = "PHASH_MAX_DISTANCE", default = "4")]
+    pub phash_max_distance: u32,
+
+    #[envconfig(from = "TAG_WEBHOOK_URL")]
+    pub tag_webhook_url: Option<String>,
+
+    #[envconfig(from = "TAG_WEBHOOK_AUTHORIZATION")]
+    pub tag_webhook_authorization: Option<String>,
+
+    #[envconfig(from = "CSAM_WEBHOOK_URL")]
+    pub csam_webhook_url: Option<String>,
+
+    #[envconfig(from = "CSAM_WEBHOOK_AUTHORIZATION")]
+    pub csam_webhook_authorization: Option<String>,
+
+    #[envconfig(from = "WEBHOOK_TIMEOUT_SECONDS", default = "30")]
+    pub webhook_timeout_seconds: u64,
+
     #[envconfig(from = "TAGGING_REQUEST_RATE_LIMIT", default = "10")]
     pub tagging_request_rate_limit: u16,
 
+    #[envconfig(from = "SHADOW_TAGGING_REQUEST_RATE_LIMIT", default = "10")]
+    pub shadow_tagging_request_rate_limit: u16,
+
     #[envconfig(from = "CSAM_REQUEST_RATE_LIMIT", default = "4")]
     pub csam_request_rate_limit: u16,
 
+    // How long provider results are reused for identical content, 0 turns
+    // the cache off
+    #[envconfig(from = "MODERATION_CACHE_TTL_SECONDS", default = "604800")]
+    pub moderation_cache_ttl_seconds: u64,
+
+    #[envconfig(from = "MODERATION_WORKERS", default = "1")]
+    pub moderation_workers: u16,
+
+    #[envconfig(from = "MODERATION_LEASE_SECONDS", default = "300")]
+    pub moderation_lease_seconds: u64,
+
+    #[envconfig(from = "MODERATION_MAX_RETRIES", default = "3")]
+    pub moderation_max_retries: u32,
+
+    // Delay before a failed item is retried, doubled on every failure
+    #[envconfig(from = "MODERATION_RETRY_DELAY_SECONDS", default = "30")]
+    pub moderation_retry_delay_seconds: u64,
+
+    // Progress of a REMODERATE run is stored under this name
+    #[envconfig(from = "REMODERATE_JOB", default = "default")]
+    pub remoderate_job: String,
+
+    // Comma separated content type names, for example "post,username"
+    #[envconfig(from = "REMODERATE_CONTENT_TYPES", default = "")]
+    pub remoderate_content_types: String,
+
+    // Unix seconds, compared with the time the server received the event
+    #[envconfig(from = "REMODERATE_SINCE")]
+    pub remoderate_since: Option<u64>,
+
+    #[envconfig(from = "REMODERATE_UNTIL")]
+    pub remoderate_until: Option<u64>,
+
+    #[envconfig(from = "REMODERATE_TAG")]
+    pub remoderate_tag: Option<String>,
+
+    #[envconfig(from = "REMODERATE_MIN_LEVEL")]
+    pub remoderate_min_level: Option<i16>,
+
+    // Starts from this event id instead of the stored position
+    #[envconfig(from = "REMODERATE_POSITION")]
+    pub remoderate_position: Option<i64>,
+
+    #[envconfig(from = "REMODERATE_BATCH_SIZE", default = "100")]
+    pub remoderate_batch_size: u64,
+
     #[envconfig(from = "CACHE_INTERFACE")]
     pub cache_interface: Option<String>,
 
     #[envconfig(from = "CACHE_BASE_URL")]
     pub cache_base_url: Option<String>,
+
+    // Responses kept by the memory cache interface
+    #[envconfig(from = "MEMORY_CACHE_CAPACITY", default = "10000")]
+    pub memory_cache_capacity: usize,
+
+    #[envconfig(from = "CACHE_PURGE_INTERVAL_SECONDS", default = "1")]
+    pub cache_purge_interval_seconds: u64,
+
+    // Tags sent per purge request
+    #[envconfig(from = "CACHE_PURGE_BATCH_SIZE", default = "100")]
+    pub cache_purge_batch_size: u64,
+
+    // Cap on the exponential backoff between attempts to purge a tag
+    #[envconfig(from = "CACHE_PURGE_MAX_BACKOFF_SECONDS", default = "300")]
+    pub cache_purge_max_backoff_seconds: u64,
+
+    #[envconfig(
+        from = "RESERVED_HANDLES",
+        default = "admin,administrator,root,moderator,support,help,polycentric,futo"
+    )]
+    pub reserved_handles: String,
 }
@@ -54,6 +54,10 @@ impl ExploreCursor {
         let bytes = ::base64::decode_config(cursor_str, ::base64::URL_SAFE)
             .context("Cursor base64 decoding failed")?;
 
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
         match bytes.len() {
             Self::FULL_CURSOR_SIZE => {
                 let ts_bytes_slice = bytes
@@ -84,7 +88,7 @@ impl ExploreCursor {
             }
             Self::ID_ONLY_SIZE => {
                 let id_array: [u8; Self::ID_SIZE] =
-                    bytes.as_slice().try_into().map_err(|_| {
+                    bytes.try_into().map_err(|_| {
                         anyhow!(
                             "Invalid cursor: single component not {} bytes",
                             Self::ID_SIZE
@@ -0,0 +1,120 @@
+use crate::handlers::get_censorship::CensorshipKind;
+
+// Lifts a censorship. Events purged by a RefuseStorage censorship are not
+// restored, but may be ingested again afterwards.
+pub(crate) async fn handler(
+    kind: CensorshipKind,
+    id: u64,
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::Censor,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let mut transaction = crate::warp_try_err_500!(state.pool.begin().await);
+
+    let lifted = match kind {
+        CensorshipKind::Systems => {
+            let entry = crate::warp_try_err_500!(
+                crate::postgres::censorship::load_censored_system(
+                    &mut transaction,
+                    id
+                )
+                .await
+            );
+
+            crate::warp_try_err_500!(
+                crate::postgres::censorship::lift_censored_system(
+                    &mut transaction,
+                    id
+                )
+                .await
+            );
+
+            entry
+                .map(|censored| {
+                    crate::handlers::get_censorship::censored_system_to_json(
+                        &censored,
+                    )
+                })
+                .transpose()
+        }
+        CensorshipKind::Events => {
+            let entry = crate::warp_try_err_500!(
+                crate::postgres::censorship::load_censored_event(
+                    &mut transaction,
+                    id
+                )
+                .await
+            );
+
+            crate::warp_try_err_500!(
+                crate::postgres::censorship::lift_censored_event(
+                    &mut transaction,
+                    id
+                )
+                .await
+            );
+
+            entry
+                .map(|censored| {
+                    crate::handlers::get_censorship::censored_event_to_json(
+                        &censored,
+                    )
+                })
+                .transpose()
+        }
+    };
+
+    let lifted = match crate::warp_try_err_500!(lifted) {
+        Some(lifted) => lifted,
+        None => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                ::warp::http::StatusCode::NOT_FOUND,
+            )));
+        }
+    };
+
+    crate::warp_try_err_500!(
+        crate::admin::audit(
+            &mut transaction,
+            &admin,
+            "lift_censorship",
+            Some(
+                format!(
+                    "{}/{}",
+                    match kind {
+                        CensorshipKind::Systems => "systems",
+                        CensorshipKind::Events => "events",
+                    },
+                    id
+                )
+                .as_str(),
+            ),
+            lifted,
+        )
+        .await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    Ok(Box::new(::warp::reply::with_status(
+        String::from(""),
+        ::warp::http::StatusCode::OK,
+    )))
+}
@@ -0,0 +1,68 @@
+// Releases a claimed handle, for example one that impersonates someone, so
+// that it can be claimed again.
+pub(crate) async fn handler(
+    handle: String,
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::Handles,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let mut transaction = crate::warp_try_err_500!(state.pool.begin().await);
+
+    let system = match crate::warp_try_err_500!(
+        crate::postgres::release_handle(&mut transaction, &handle).await
+    ) {
+        Some(system) => system,
+        None => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                ::warp::http::StatusCode::NOT_FOUND,
+            )));
+        }
+    };
+
+    crate::warp_try_err_500!(
+        crate::cache::purge_queue::enqueue(
+            &mut transaction,
+            &crate::cache::util::key_to_cache_tags_account_meta(&system),
+        )
+        .await
+    );
+
+    crate::warp_try_err_500!(
+        crate::admin::audit(
+            &mut transaction,
+            &admin,
+            "release_handle",
+            Some(handle.as_str()),
+            ::serde_json::json!({
+                "system": crate::warp_try_err_500!(
+                    polycentric_protocol::model::public_key::to_base64(&system)
+                ),
+            }),
+        )
+        .await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    Ok(Box::new(::warp::reply::with_status(
+        String::from(""),
+        ::warp::http::StatusCode::OK,
+    )))
+}
@@ -0,0 +1,75 @@
+use ::serde_json::json;
+
+#[derive(::serde::Deserialize)]
+pub(crate) struct Query {
+    before: ::std::option::Option<u64>,
+    limit: ::std::option::Option<u64>,
+    admin: ::std::option::Option<String>,
+    action: ::std::option::Option<String>,
+}
+
+// Pages backwards through admin actions, newest first. The returned cursor is
+// passed back as `before`.
+pub(crate) async fn handler(
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+    query: Query,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::Audit,
+        )
+        .await
+    ) {
+        Ok(_) => {}
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let limit = query.limit.unwrap_or(100).min(1000);
+
+    let mut transaction =
+        crate::warp_try_err_500!(state.pool_read_only.begin().await);
+
+    let entries = crate::warp_try_err_500!(
+        crate::postgres::admin::load_audit_log(
+            &mut transaction,
+            query.before,
+            limit,
+            query.admin.as_deref(),
+            query.action.as_deref(),
+        )
+        .await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    let items = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "id": entry.id,
+                "admin": entry.admin_name,
+                "action": entry.action,
+                "target": entry.target,
+                "details": entry.details,
+                "created_on": entry.created_on,
+            })
+        })
+        .collect::<::std::vec::Vec<_>>();
+
+    Ok(Box::new(::warp::reply::with_header(
+        ::warp::reply::json(&json!({
+            "items": items,
+            "cursor": entries.last().map(|last| last.id),
+        })),
+        "Cache-Control",
+        "no-store",
+    )))
+}
@@ -0,0 +1,151 @@
+use ::serde_json::json;
+
+// The two kinds of censorship entries, selected by the second path segment of
+// the `/censorship` routes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum CensorshipKind {
+    Systems,
+    Events,
+}
+
+impl ::std::str::FromStr for CensorshipKind {
+    type Err = ::anyhow::Error;
+
+    fn from_str(s: &str) -> ::anyhow::Result<Self> {
+        match s {
+            "systems" => Ok(CensorshipKind::Systems),
+            "events" => Ok(CensorshipKind::Events),
+            _ => Err(::anyhow::anyhow!("unknown censorship kind")),
+        }
+    }
+}
+
+#[derive(::serde::Deserialize)]
+pub(crate) struct Query {
+    after: ::std::option::Option<u64>,
+    limit: ::std::option::Option<u64>,
+    censorship_type: ::std::option::Option<crate::postgres::CensorshipType>,
+}
+
+pub(crate) fn censored_system_to_json(
+    censored: &crate::postgres::censorship::CensoredSystem,
+) -> ::anyhow::Result<::serde_json::Value> {
+    Ok(json!({
+        "id": censored.id,
+        "system": polycentric_protocol::model::public_key::to_base64(
+            &censored.system
+        )?,
+        "censorship_type": censored.censorship_type,
+        "reason": censored.reason,
+        "censored_by": censored.censored_by,
+        "expires_at": censored.expires_at,
+        "created_at": censored.created_at,
+        "updated_at": censored.updated_at,
+    }))
+}
+
+pub(crate) fn censored_event_to_json(
+    censored: &crate::postgres::censorship::CensoredEvent,
+) -> ::anyhow::Result<::serde_json::Value> {
+    Ok(json!({
+        "id": censored.id,
+        "system": polycentric_protocol::model::public_key::to_base64(
+            &censored.system
+        )?,
+        "process": ::base64::encode_config(
+            censored.process.bytes(),
+            ::base64::URL_SAFE
+        ),
+        "logical_clock": censored.logical_clock,
+        "censorship_type": censored.censorship_type,
+        "reason": censored.reason,
+        "censored_by": censored.censored_by,
+        "expires_at": censored.expires_at,
+        "created_at": censored.created_at,
+        "updated_at": censored.updated_at,
+    }))
+}
+
+// Pages through censored systems or events in the order they were first
+// censored. The returned cursor is passed back as `after`.
+pub(crate) async fn handler(
+    kind: CensorshipKind,
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+    query: Query,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let _admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::Censor,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let after = query.after.unwrap_or(0);
+    let limit = query.limit.unwrap_or(100).min(1000);
+
+    let mut transaction =
+        crate::warp_try_err_500!(state.pool_read_only.begin().await);
+
+    let (items, cursor) = match kind {
+        CensorshipKind::Systems => {
+            let censored = crate::warp_try_err_500!(
+                crate::postgres::censorship::list_censored_systems(
+                    &mut transaction,
+                    after,
+                    limit,
+                    query.censorship_type,
+                )
+                .await
+            );
+
+            (
+                crate::warp_try_err_500!(censored
+                    .iter()
+                    .map(censored_system_to_json)
+                    .collect::<::anyhow::Result<::std::vec::Vec<_>>>()),
+                censored.last().map(|last| last.id),
+            )
+        }
+        CensorshipKind::Events => {
+            let censored = crate::warp_try_err_500!(
+                crate::postgres::censorship::list_censored_events(
+                    &mut transaction,
+                    after,
+                    limit,
+                    query.censorship_type,
+                )
+                .await
+            );
+
+            (
+                crate::warp_try_err_500!(censored
+                    .iter()
+                    .map(censored_event_to_json)
+                    .collect::<::anyhow::Result<::std::vec::Vec<_>>>()),
+                censored.last().map(|last| last.id),
+            )
+        }
+    };
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    Ok(Box::new(::warp::reply::with_header(
+        ::warp::reply::json(&json!({
+            "items": items,
+            "cursor": cursor,
+        })),
+        "Cache-Control",
+        "no-store",
+    )))
+}
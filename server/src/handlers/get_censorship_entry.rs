@@ -0,0 +1,65 @@
+use crate::handlers::get_censorship::CensorshipKind;
+
+pub(crate) async fn handler(
+    kind: CensorshipKind,
+    id: u64,
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let _admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::Censor,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let mut transaction =
+        crate::warp_try_err_500!(state.pool_read_only.begin().await);
+
+    let entry = match kind {
+        CensorshipKind::Systems => crate::warp_try_err_500!(
+            crate::postgres::censorship::load_censored_system(
+                &mut transaction,
+                id
+            )
+            .await
+        )
+        .map(|censored| {
+            crate::handlers::get_censorship::censored_system_to_json(&censored)
+        }),
+        CensorshipKind::Events => crate::warp_try_err_500!(
+            crate::postgres::censorship::load_censored_event(
+                &mut transaction,
+                id
+            )
+            .await
+        )
+        .map(|censored| {
+            crate::handlers::get_censorship::censored_event_to_json(&censored)
+        }),
+    };
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    match entry {
+        Some(json) => Ok(Box::new(::warp::reply::with_header(
+            ::warp::reply::json(&crate::warp_try_err_500!(json)),
+            "Cache-Control",
+            "no-store",
+        ))),
+        None => Ok(Box::new(::warp::reply::with_status(
+            String::from(""),
+            ::warp::http::StatusCode::NOT_FOUND,
+        ))),
+    }
+}
@@ -117,7 +117,11 @@ pub(crate) async fn handler(
         true,
         false,
     );
-    let cache_tags = [claim_cache_tags, vouch_cache_tags].concat();
+    // New matches need a vouch from the trust root
+    let trust_root_cache_tags =
+        cache::util::key_to_cache_tags_account_meta(&query.query.trust_root);
+    let cache_tags =
+        [claim_cache_tags, vouch_cache_tags, trust_root_cache_tags].concat();
 
     for match_item in matches.iter() {
         let mut item = polycentric_protocol::protocol::QueryClaimToSystemResponseMatch::new();
@@ -139,26 +143,10 @@ pub(crate) async fn handler(
 
     let result_serialized = crate::warp_try_err_500!(result.write_to_bytes());
 
-    let response = ::warp::reply::with_header(
-        ::warp::reply::with_status(
-            result_serialized,
-            ::warp::http::StatusCode::OK,
-        ),
-        "Cache-Control",
+    Ok(crate::cache::routes::reply(
+        &state,
+        result_serialized,
         "public, s-maxage=3600, max-age=5",
-    );
-
-    if !cache_tags.is_empty() {
-        if let Some(cache_provider) = state.cache_provider.as_ref() {
-            Ok(Box::new(::warp::reply::with_header(
-                response,
-                cache_provider.get_header_name(),
-                cache_provider.get_header_value(&cache_tags),
-            )))
-        } else {
-            Ok(Box::new(response))
-        }
-    } else {
-        Ok(Box::new(response))
-    }
+        cache_tags,
+    ))
 }
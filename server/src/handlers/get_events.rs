@@ -62,32 +62,20 @@ async fn handler_inner(
     transaction.commit().await?;
 
     // We want to invalidate the account meta and the events, in case of a new event
-    let tags: Vec<String> = crate::cache::util::signed_events_to_cache_tags(
+    let mut tags: Vec<String> = crate::cache::util::signed_events_to_cache_tags(
         &events, false, true, false, true,
     );
 
-    let response = ::warp::reply::with_header(
-        ::warp::reply::with_status(
-            result.write_to_bytes()?,
-            ::warp::http::StatusCode::OK,
-        ),
-        "Cache-Control",
-        "public, s-maxage=3600, max-age=5",
-    );
+    tags.extend(crate::cache::util::key_to_cache_tags_account_meta(
+        &query.system,
+    ));
 
-    if !tags.is_empty() {
-        if let Some(cache_provider) = state.cache_provider.as_ref() {
-            Ok(Box::new(::warp::reply::with_header(
-                response,
-                cache_provider.get_header_name(),
-                cache_provider.get_header_value(&tags),
-            )))
-        } else {
-            Ok(Box::new(response))
-        }
-    } else {
-        Ok(Box::new(response))
-    }
+    Ok(crate::cache::routes::reply(
+        &state,
+        result.write_to_bytes()?,
+        "public, s-maxage=3600, max-age=5",
+        tags,
+    ))
 }
 
 pub(crate) async fn handler(
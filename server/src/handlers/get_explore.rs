@@ -46,6 +46,16 @@ pub(crate) async fn handler(
         .await
     );
 
+    // New posts only show up once the short max age passes, the tags are
+    // for posts that get deleted or moderated.
+    let tags: Vec<String> = crate::cache::util::signed_events_to_cache_tags(
+        &db_result.events,
+        false,
+        true,
+        false,
+        false,
+    );
+
     let mut events = Events::new();
 
     for event in db_result.events.iter() {
@@ -64,12 +74,10 @@ pub(crate) async fn handler(
 
     let result_serialized = crate::warp_try_err_500!(result.write_to_bytes());
 
-    Ok(Box::new(::warp::reply::with_header(
-        ::warp::reply::with_status(
-            result_serialized,
-            ::warp::http::StatusCode::OK,
-        ),
-        "Cache-Control",
+    Ok(crate::cache::routes::reply(
+        &state,
+        result_serialized,
         "public, s-maxage=5, max-age=5",
-    )))
+        tags,
+    ))
 }
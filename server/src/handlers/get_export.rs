@@ -0,0 +1,78 @@
+use ::protobuf::{Message, MessageField};
+
+// Long enough for any ingest transaction to have committed, see
+// `load_events_for_export`
+const EXPORT_LAG: ::std::time::Duration = ::std::time::Duration::from_secs(30);
+
+#[derive(::serde::Deserialize)]
+pub(crate) struct Query {
+    after: ::std::option::Option<u64>,
+    limit: ::std::option::Option<u64>,
+}
+
+// Unfiltered feed of every stored event in insertion order, consumed by
+// peers running in PULL_REMOTE_SERVER mode. It exposes content that has been
+// moderated away, so it is restricted to the admin token.
+pub(crate) async fn handler(
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+    query: Query,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let _admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::Export,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let limit = query.limit.unwrap_or(1000).min(1000);
+
+    let mut transaction =
+        crate::warp_try_err_500!(state.pool_read_only.begin().await);
+
+    let db_result = crate::warp_try_err_500!(
+        crate::postgres::load_events_for_export(
+            &mut transaction,
+            query.after.unwrap_or(0),
+            limit,
+            EXPORT_LAG,
+        )
+        .await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    let mut events = polycentric_protocol::protocol::Events::new();
+
+    for event in db_result.events.iter() {
+        events
+            .events
+            .push(polycentric_protocol::model::signed_event::to_proto(event));
+    }
+
+    let mut result =
+        polycentric_protocol::protocol::ResultEventsAndRelatedEventsAndCursor::new();
+    result.result_events = MessageField::some(events);
+    result.cursor = db_result.cursor.map(|cursor| cursor.to_bytes());
+
+    let result_serialized = crate::warp_try_err_500!(result.write_to_bytes());
+
+    Ok(Box::new(::warp::reply::with_header(
+        ::warp::reply::with_status(
+            result_serialized,
+            ::warp::http::StatusCode::OK,
+        ),
+        "Cache-Control",
+        "no-store",
+    )))
+}
@@ -87,14 +87,24 @@ pub(crate) async fn handler(
             let result_serialized =
                 crate::warp_try_err_500!(result.write_to_bytes());
 
-            Ok(Box::new(::warp::reply::with_header(
-                ::warp::reply::with_status(
-                    result_serialized,
-                    ::warp::http::StatusCode::OK,
+            // A newer vouch or claim, or deleting either, is signed by one of
+            // the two systems
+            let cache_tags = [
+                crate::cache::util::key_to_cache_tags_account_meta(
+                    &query.query.vouching_system,
                 ),
-                "Cache-Control",
-                "no-cache",
-            )))
+                crate::cache::util::key_to_cache_tags_account_meta(
+                    &query.query.claiming_system,
+                ),
+            ]
+            .concat();
+
+            Ok(crate::cache::routes::reply(
+                &state,
+                result_serialized,
+                "public, s-maxage=3600, max-age=5",
+                cache_tags,
+            ))
         }
         None => Ok(Box::new(::warp::reply::with_status(
             "pair not found".to_string(),
@@ -82,36 +82,17 @@ async fn handler_inner(
         .map(polycentric_protocol::model::signed_event::to_proto)
         .collect();
 
-    let tags: Vec<String> = crate::cache::util::signed_events_to_cache_tags(
-        &result_signed_events,
-        false,
-        false,
-        false,
-        true,
-    );
+    // Any event the system signs moves its head, including when it has none
+    // yet.
+    let tags: Vec<String> =
+        crate::cache::util::key_to_cache_tags_account_meta(&query.system);
 
     let result_serialized = result.write_to_bytes()?;
 
-    let response = ::warp::reply::with_header(
-        ::warp::reply::with_status(
-            result_serialized,
-            ::warp::http::StatusCode::OK,
-        ),
-        "Cache-Control",
+    Ok(crate::cache::routes::reply(
+        &state,
+        result_serialized,
         "public, s-maxage=3600, max-age=5",
-    );
-
-    if !tags.is_empty() {
-        if let Some(cache_provider) = state.cache_provider.as_ref() {
-            Ok(Box::new(::warp::reply::with_header(
-                response,
-                cache_provider.get_header_name(),
-                cache_provider.get_header_value(&tags),
-            )))
-        } else {
-            Ok(Box::new(response))
-        }
-    } else {
-        Ok(Box::new(response))
-    }
+        tags,
+    ))
 }
@@ -0,0 +1,29 @@
+use ::warp::Reply;
+
+// The moderation policy in effect for each moderated content type, so that
+// clients can show the rules of this server.
+pub(crate) async fn handler(
+    state: ::std::sync::Arc<crate::State>,
+) -> ::warp::reply::Response {
+    let content_types = state
+        .moderation_policy
+        .content_types()
+        .into_iter()
+        .map(|policy| {
+            let mut value = ::serde_json::json!(policy);
+            value["mode"] = ::serde_json::json!(
+                policy.effective_mode(state.moderation_mode)
+            );
+            value
+        })
+        .collect::<::std::vec::Vec<_>>();
+
+    ::warp::reply::with_header(
+        ::warp::reply::json(&::serde_json::json!({
+            "content_types": content_types,
+        })),
+        "Cache-Control",
+        "public, max-age=300",
+    )
+    .into_response()
+}
@@ -0,0 +1,92 @@
+use ::serde_json::json;
+
+#[derive(::serde::Deserialize)]
+pub(crate) struct Query {
+    event_id: ::std::option::Option<i64>,
+    after: ::std::option::Option<i64>,
+    limit: ::std::option::Option<u64>,
+}
+
+fn report_to_json(
+    report: &crate::moderation::reports::Report,
+) -> ::anyhow::Result<::serde_json::Value> {
+    let reporter =
+        polycentric_protocol::model::public_key::from_type_and_bytes(
+            u64::try_from(report.reporter_key_type)?,
+            &report.reporter_key,
+        )?;
+
+    let target = polycentric_protocol::model::public_key::from_type_and_bytes(
+        u64::try_from(report.target_key_type)?,
+        &report.target_key,
+    )?;
+
+    Ok(json!({
+        "id": report.id,
+        "reporter": polycentric_protocol::model::public_key::to_base64(
+            &reporter
+        )?,
+        "target_system": polycentric_protocol::model::public_key::to_base64(
+            &target
+        )?,
+        "event_id": report.event_id,
+        "reason": report.reason,
+        "note": report.note,
+        "created_on": report.created_on,
+    }))
+}
+
+// Pages through user reports, optionally those on one event. The returned
+// cursor is passed back as `after`.
+pub(crate) async fn handler(
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+    query: Query,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let _admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::ModerationReview,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let mut transaction =
+        crate::warp_try_err_500!(state.pool_read_only.begin().await);
+
+    let reports = crate::warp_try_err_500!(
+        crate::moderation::reports::list_reports(
+            &mut transaction,
+            query.event_id,
+            query.after.unwrap_or(0),
+            query.limit.unwrap_or(100).min(1000),
+        )
+        .await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    let result = crate::warp_try_err_500!(reports
+        .iter()
+        .map(report_to_json)
+        .collect::<::anyhow::Result<::std::vec::Vec<_>>>(
+    ));
+
+    Ok(Box::new(::warp::reply::with_header(
+        ::warp::reply::json(&json!({
+            "reports": result,
+            "cursor": reports.last().map(|last| last.id),
+        })),
+        "Cache-Control",
+        "no-store",
+    )))
+}
@@ -0,0 +1,122 @@
+use ::serde_json::json;
+
+#[derive(::serde::Deserialize)]
+pub(crate) struct Query {
+    status: ::std::option::Option<
+        crate::moderation::moderation_queue::ModerationStatus,
+    >,
+    tag: ::std::option::Option<String>,
+    min_level: ::std::option::Option<i16>,
+    dead_lettered: ::std::option::Option<bool>,
+    escalated: ::std::option::Option<bool>,
+    after: ::std::option::Option<i64>,
+    limit: ::std::option::Option<u64>,
+}
+
+pub(crate) fn review_item_to_json(
+    item: &crate::moderation::review::ReviewItem,
+) -> ::anyhow::Result<::serde_json::Value> {
+    let event = polycentric_protocol::model::event::from_vec(
+        item.signed_event.event(),
+    )?;
+
+    let text = match polycentric_protocol::model::content::from_event(&event)? {
+        polycentric_protocol::model::content::Content::Post(post) => {
+            post.content().clone()
+        }
+        polycentric_protocol::model::content::Content::Description(text) => {
+            Some(text)
+        }
+        polycentric_protocol::model::content::Content::Username(text) => {
+            Some(text)
+        }
+        _ => None,
+    };
+
+    Ok(json!({
+        "id": item.id,
+        "system": polycentric_protocol::model::public_key::to_base64(
+            event.system()
+        )?,
+        "process": ::base64::encode_config(
+            event.process().bytes(),
+            ::base64::URL_SAFE
+        ),
+        "logical_clock": event.logical_clock(),
+        "content_type": event.content_type(),
+        "text": text,
+        "moderation_status": item.status,
+        "moderation_tags": item.tags,
+        "failure_count": item.failure_count,
+        "dead_lettered": item.dead_lettered,
+        "reports": item.reports,
+        "escalated": item.escalated,
+        "review": item.review.as_ref().map(|review| json!({
+            "reviewer": review.reviewer,
+            "note": review.note,
+            "reviewed_on": review.reviewed_on,
+        })),
+    }))
+}
+
+// Pages through moderated events for manual review. The returned cursor is
+// passed back as `after`.
+pub(crate) async fn handler(
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+    query: Query,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let _admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::ModerationReview,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let filter = crate::moderation::review::ReviewFilter {
+        status: query.status,
+        tag: query.tag,
+        min_level: query.min_level,
+        dead_lettered: query.dead_lettered,
+        escalated: query.escalated,
+    };
+
+    let mut transaction =
+        crate::warp_try_err_500!(state.pool_read_only.begin().await);
+
+    let items = crate::warp_try_err_500!(
+        crate::moderation::review::list_items(
+            &mut transaction,
+            &filter,
+            query.after.unwrap_or(0),
+            query.limit.unwrap_or(100).min(1000),
+        )
+        .await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    let result = crate::warp_try_err_500!(items
+        .iter()
+        .map(review_item_to_json)
+        .collect::<::anyhow::Result<::std::vec::Vec<_>>>());
+
+    Ok(Box::new(::warp::reply::with_header(
+        ::warp::reply::json(&json!({
+            "items": result,
+            "cursor": items.last().map(|last| last.id),
+        })),
+        "Cache-Control",
+        "no-store",
+    )))
+}
@@ -0,0 +1,57 @@
+// A single review item together with the image under review, base64 encoded.
+pub(crate) async fn handler(
+    id: i64,
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let _admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::ModerationReview,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let mut transaction =
+        crate::warp_try_err_500!(state.pool_read_only.begin().await);
+
+    let item = match crate::warp_try_err_500!(
+        crate::moderation::review::load_item(&mut transaction, id).await
+    ) {
+        Some(item) => item,
+        None => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                ::warp::http::StatusCode::NOT_FOUND,
+            )));
+        }
+    };
+
+    let blob = crate::warp_try_err_500!(
+        crate::moderation::review::load_item_blob(&mut transaction, &item)
+            .await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    let mut result = crate::warp_try_err_500!(
+        crate::handlers::get_moderation_review::review_item_to_json(&item)
+    );
+
+    result["blob"] = ::serde_json::json!(blob.map(::base64::encode));
+
+    Ok(Box::new(::warp::reply::with_header(
+        ::warp::reply::json(&result),
+        "Cache-Control",
+        "no-store",
+    )))
+}
@@ -0,0 +1,54 @@
+#[derive(::serde::Deserialize)]
+pub(crate) struct Query {
+    provider: ::std::option::Option<String>,
+    content_type: ::std::option::Option<i64>,
+    since: ::std::option::Option<u64>,
+    until: ::std::option::Option<u64>,
+}
+
+// Where the shadow tagging provider disagrees with the primary one, per tag
+// and pair of levels.
+pub(crate) async fn handler(
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+    query: Query,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let _admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::ModerationReview,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let filter = crate::moderation::shadow::ReportFilter {
+        provider: query.provider,
+        content_type: query.content_type,
+        since: query.since,
+        until: query.until,
+    };
+
+    let mut transaction =
+        crate::warp_try_err_500!(state.pool_read_only.begin().await);
+
+    let report = crate::warp_try_err_500!(
+        crate::moderation::shadow::report(&mut transaction, &filter).await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    Ok(Box::new(::warp::reply::with_header(
+        ::warp::reply::json(&report),
+        "Cache-Control",
+        "no-store",
+    )))
+}
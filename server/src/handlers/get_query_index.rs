@@ -48,7 +48,9 @@ pub(crate) async fn handler(
         .map(polycentric_protocol::model::signed_event::to_proto)
         .collect();
 
-    let tags: Vec<String> = crate::cache::util::signed_events_to_cache_tags(
+    // Not every content type has its own tag, the account meta tag covers the
+    // rest and empty results.
+    let mut tags: Vec<String> = crate::cache::util::signed_events_to_cache_tags(
         &query_result.events,
         true,
         false,
@@ -56,6 +58,10 @@ pub(crate) async fn handler(
         false,
     );
 
+    tags.extend(crate::cache::util::key_to_cache_tags_account_meta(
+        &query.system,
+    ));
+
     result.proof = query_result
         .proof
         .iter()
@@ -64,26 +70,10 @@ pub(crate) async fn handler(
 
     let result_serialized = crate::warp_try_err_500!(result.write_to_bytes());
 
-    let response = ::warp::reply::with_header(
-        ::warp::reply::with_status(
-            result_serialized,
-            ::warp::http::StatusCode::OK,
-        ),
-        "Cache-Control",
+    Ok(crate::cache::routes::reply(
+        &state,
+        result_serialized,
         "public, s-maxage=3600, max-age=5",
-    );
-
-    if !tags.is_empty() {
-        if let Some(cache_provider) = state.cache_provider.as_ref() {
-            Ok(Box::new(::warp::reply::with_header(
-                response,
-                cache_provider.get_header_name(),
-                cache_provider.get_header_value(&tags),
-            )))
-        } else {
-            Ok(Box::new(response))
-        }
-    } else {
-        Ok(Box::new(response))
-    }
+        tags,
+    ))
 }
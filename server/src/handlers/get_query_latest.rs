@@ -33,37 +33,19 @@ async fn handler_inner(
     transaction.commit().await?;
 
     let cache_tags: Vec<String> =
-        crate::cache::util::signed_events_to_cache_tags(
-            &events, false, false, false, true,
-        );
+        crate::cache::util::key_to_cache_tags_account_meta(&query.system);
 
     result.events = events
         .iter()
         .map(polycentric_protocol::model::signed_event::to_proto)
         .collect();
 
-    let response = ::warp::reply::with_header(
-        ::warp::reply::with_status(
-            result.write_to_bytes()?,
-            ::warp::http::StatusCode::OK,
-        ),
-        "Cache-Control",
+    Ok(crate::cache::routes::reply(
+        &state,
+        result.write_to_bytes()?,
         "public, s-maxage=3600, max-age=5",
-    );
-
-    if !cache_tags.is_empty() {
-        if let Some(cache_provider) = state.cache_provider.as_ref() {
-            Ok(Box::new(::warp::reply::with_header(
-                response,
-                cache_provider.get_header_name(),
-                cache_provider.get_header_value(&cache_tags),
-            )))
-        } else {
-            Ok(Box::new(response))
-        }
-    } else {
-        Ok(Box::new(response))
-    }
+        cache_tags,
+    ))
 }
 
 pub(crate) async fn handler(
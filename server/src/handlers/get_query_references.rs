@@ -41,6 +41,8 @@ pub(crate) async fn handler(
         )
     );
 
+    let mut cache_tags = Vec::new();
+
     let subject = match &reference {
         polycentric_protocol::model::reference::Reference::Pointer(pointer) => {
             if !query.query.extra_byte_references.is_empty() {
@@ -51,6 +53,10 @@ pub(crate) async fn handler(
                 )));
             }
 
+            cache_tags.extend(crate::cache::util::references_to_cache_tags(
+                ::std::slice::from_ref(&reference),
+            ));
+
             polycentric_protocol::model::PointerOrByteReferences::Pointer(
                 pointer.clone(),
             )
@@ -60,6 +66,15 @@ pub(crate) async fn handler(
         ) => {
             let mut byte_references = query.query.extra_byte_references.clone();
             byte_references.push(primary_reference.clone());
+
+            cache_tags.extend(crate::cache::util::references_to_cache_tags(
+                &byte_references
+                    .iter()
+                    .cloned()
+                    .map(polycentric_protocol::model::reference::Reference::Bytes)
+                    .collect::<::std::vec::Vec<_>>(),
+            ));
+
             polycentric_protocol::model::PointerOrByteReferences::Bytes(
                 byte_references,
             )
@@ -86,8 +101,6 @@ pub(crate) async fn handler(
     let mut result =
         polycentric_protocol::protocol::QueryReferencesResponse::new();
 
-    let mut cache_tags = Vec::new();
-
     if let Some(request_events) = query.query.request_events.0 {
         let query_result = crate::warp_try_err_500!(
             crate::postgres::query_references::query_references(
@@ -108,13 +121,13 @@ pub(crate) async fn handler(
             result.cursor = Some(query_result_cursor.to_be_bytes().to_vec());
         }
 
-        cache_tags = crate::cache::util::signed_events_to_cache_tags(
+        cache_tags.extend(crate::cache::util::signed_events_to_cache_tags(
             &query_result.events,
             false,
             true,
             true,
             false,
-        );
+        ));
 
         for signed_event in query_result.events.iter() {
             let event = crate::warp_try_err_500!(
@@ -132,6 +145,24 @@ pub(crate) async fn handler(
                 ),
             );
 
+            // Counts of references to the event itself
+            if !request_events.count_lww_element_references.is_empty()
+                || !request_events.count_references.is_empty()
+            {
+                let pointer = crate::warp_try_err_500!(
+                    polycentric_protocol::model::pointer::from_signed_event(
+                        signed_event
+                    )
+                );
+
+                cache_tags
+                    .extend(crate::cache::util::references_to_cache_tags(&[
+                    polycentric_protocol::model::reference::Reference::Pointer(
+                        pointer,
+                    ),
+                ]));
+            }
+
             for params in request_events.count_lww_element_references.iter() {
                 item.counts.push(crate::warp_try_err_500!(
                         crate::postgres::count_lww_element_references::
@@ -190,28 +221,10 @@ pub(crate) async fn handler(
 
     let result_serialized = crate::warp_try_err_500!(result.write_to_bytes());
 
-    let response = ::warp::reply::with_status(
+    Ok(crate::cache::routes::reply(
+        &state,
         result_serialized,
-        ::warp::http::StatusCode::OK,
-    );
-
-    let response = ::warp::reply::with_header(
-        response,
-        "Cache-Control",
         "public, s-maxage=3600, max-age=5",
-    );
-
-    if !cache_tags.is_empty() {
-        if let Some(cache_provider) = state.cache_provider.as_ref() {
-            Ok(Box::new(::warp::reply::with_header(
-                response,
-                cache_provider.get_header_name(),
-                cache_provider.get_header_value(&cache_tags),
-            )))
-        } else {
-            Ok(Box::new(response))
-        }
-    } else {
-        Ok(Box::new(response))
-    }
+        cache_tags,
+    ))
 }
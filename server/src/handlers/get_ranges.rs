@@ -23,24 +23,24 @@ pub(crate) async fn handler(
         .await
     );
 
-    // let tags: Vec<String> = crate::cache::util::key_to_cache_tags_account_meta(
-    //     &query.system,
-    // );
+    // Ranges change with every event the system signs, all of which purge
+    // the account meta tag once committed.
+    //
+    // Caching for ranges is hard because sync needs nearly strong
+    // consistency. Purges run after the commit, so a response read just
+    // before it can be stored after its purge was sent, and only the short
+    // max age bounds how long it stays stale.
+    let tags: Vec<String> =
+        crate::cache::util::key_to_cache_tags_account_meta(&query.system);
 
     crate::warp_try_err_500!(transaction.commit().await);
 
     let result_serialized = crate::warp_try_err_500!(result.write_to_bytes());
 
-    let response = ::warp::reply::with_header(
-        ::warp::reply::with_status(
-            result_serialized,
-            ::warp::http::StatusCode::OK,
-        ),
-        "Cache-Control",
-        "no-cache",
-    );
-
-    // Caching for ranges is hard because we need nearly strong consistency for caching
-    // It's currently not implemented, but should be able to using key_to_cache_tags_account_meta
-    Ok(Box::new(response))
+    Ok(crate::cache::routes::reply(
+        &state,
+        result_serialized,
+        "public, s-maxage=5, max-age=5",
+        tags,
+    ))
 }
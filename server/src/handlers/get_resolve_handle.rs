@@ -23,8 +23,12 @@ pub(crate) async fn handler(
     let result = polycentric_protocol::model::public_key::to_proto(&identity);
     let result_serialized = crate::warp_try_err_500!(result.write_to_bytes());
 
-    Ok(Box::new(::warp::reply::with_status(
+    // Claiming a handle purges the claiming system, which releases its
+    // previous handle
+    Ok(crate::cache::routes::reply(
+        &state,
         result_serialized,
-        ::warp::http::StatusCode::OK,
-    )))
+        "public, s-maxage=3600, max-age=5",
+        crate::cache::util::key_to_cache_tags_account_meta(&identity),
+    ))
 }
@@ -61,25 +61,42 @@ pub(crate) async fn handler_inner(
     search_type: SearchType,
     moderation_filters: &Option<ModerationFilters>,
 ) -> ::anyhow::Result<Box<dyn ::warp::Reply>> {
-    let response = state
-        .search
-        .search(match search_type {
-            SearchType::Messages => SearchParts::Index(&["messages"]),
-            SearchType::Profiles => {
-                SearchParts::Index(&["profile_names", "profile_descriptions"])
-            }
-        })
-        .from(i64::try_from(start_count)?)
-        .size(i64::try_from(limit)?)
-        .body(json!({
-            "query": {
+    let mut transaction = state.pool_read_only.begin().await?;
+
+    let (indices, query) = match search_type {
+        SearchType::Messages => (
+            vec![crate::opensearch::MESSAGES_INDEX],
+            json!({
                 "match": {
                     "message_content": {
                         "query": search,
                         "fuzziness": 2
                     }
                 }
-            }
+            }),
+        ),
+        SearchType::Profiles => (
+            vec![
+                crate::opensearch::PROFILE_NAMES_INDEX,
+                crate::opensearch::PROFILE_DESCRIPTIONS_INDEX,
+            ],
+            json!({
+                "multi_match": {
+                    "query": search,
+                    "fields": ["profile_name", "profile_description"],
+                    "fuzziness": 2
+                }
+            }),
+        ),
+    };
+
+    let response = state
+        .search
+        .search(SearchParts::Index(&indices))
+        .from(i64::try_from(start_count)?)
+        .size(i64::try_from(limit)?)
+        .body(json!({
+            "query": query
         }))
         .send()
         .await?;
@@ -88,14 +105,18 @@ pub(crate) async fn handler_inner(
         .json::<crate::opensearch::OpenSearchSearchL0>()
         .await?;
 
-    let mut transaction = state.pool_read_only.begin().await?;
+    let mut loaded_events = vec![];
 
-    let mut result_events = polycentric_protocol::protocol::Events::new();
+    // Hits that are skipped still advance the cursor, otherwise the next
+    // page would read them again
+    let mut consumed_hit_count = 0;
 
     if let Some(hits) = response_body.hits {
+        consumed_hit_count = u64::try_from(hits.hits.len())?;
+
         for hit in hits.hits {
             let id = hit._id;
-            if hit._index == "messages" {
+            if hit._index == crate::opensearch::MESSAGES_INDEX {
                 let pointer =
                     match polycentric_protocol::model::pointer::from_base64(&id)
                     {
@@ -118,17 +139,15 @@ pub(crate) async fn handler_inner(
                 .await?;
 
                 if let Some(event_result) = event_result {
-                    result_events.events.push(
-                        polycentric_protocol::model::signed_event::to_proto(
-                            &event_result,
-                        ),
-                    );
+                    loaded_events.push(event_result);
                 };
             } else {
                 let system =
                     polycentric_protocol::model::public_key::from_base64(&id)?;
 
-                let content_type = if hit._index == "profile_names" {
+                let content_type = if hit._index
+                    == crate::opensearch::PROFILE_NAMES_INDEX
+                {
                     polycentric_protocol::model::known_message_types::USERNAME
                 } else {
                     polycentric_protocol::model::known_message_types::DESCRIPTION
@@ -139,29 +158,50 @@ pub(crate) async fn handler_inner(
                         &mut transaction,
                         &system,
                         content_type,
+                        &ModerationOptions {
+                            filters: moderation_filters.clone(),
+                            mode: state.moderation_mode,
+                        },
                     )
                     .await?;
 
                 if let Some(event) = potential_event {
-                    result_events.events.push(
-                        polycentric_protocol::model::signed_event::to_proto(
-                            &event,
-                        ),
-                    );
+                    loaded_events.push(event);
                 }
             }
         }
     }
 
+    // Censored documents are removed from the index by `search_indexer`, this
+    // only covers hits censored since the indexer last ran
+    let censored = crate::postgres::are_events_censored(
+        &mut transaction,
+        &loaded_events
+            .iter()
+            .map(|event| {
+                polycentric_protocol::model::event::from_vec(event.event())
+            })
+            .collect::<::anyhow::Result<::std::vec::Vec<_>>>()?,
+    )
+    .await?;
+
+    let mut result_events = polycentric_protocol::protocol::Events::new();
+
+    for (event, censored) in loaded_events.iter().zip(censored) {
+        if !censored {
+            result_events.events.push(
+                polycentric_protocol::model::signed_event::to_proto(event),
+            );
+        }
+    }
+
     let mut result =
         polycentric_protocol::protocol::ResultEventsAndRelatedEventsAndCursor::new();
 
-    let returned_event_count = u64::try_from(result_events.events.len())?;
-
     result.result_events = MessageField::some(result_events);
 
     result.cursor =
-        Some(u64::to_le_bytes(start_count + returned_event_count).to_vec());
+        Some(u64::to_le_bytes(start_count + consumed_hit_count).to_vec());
 
     transaction.commit().await?;
 
@@ -170,3 +210,198 @@ pub(crate) async fn handler_inner(
         ::warp::http::StatusCode::OK,
     )))
 }
+
+#[cfg(test)]
+mod tests {
+    use ::protobuf::Message;
+    use ::warp::Reply;
+
+    #[::sqlx::test]
+    async fn test_search_skips_censored(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+        let system =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                keypair.verifying_key(),
+            );
+
+        let visible = polycentric_protocol::test_utils::make_test_event(
+            &keypair, &process, 1,
+        );
+        let censored = polycentric_protocol::test_utils::make_test_event(
+            &keypair, &process, 2,
+        );
+
+        for event in [&visible, &censored] {
+            crate::ingest::ingest_event_postgres(&mut transaction, event)
+                .await?;
+        }
+
+        crate::postgres::censor_event(
+            &mut transaction,
+            crate::postgres::CensorshipType::DoNotRecommend,
+            &system,
+            &process,
+            2,
+            &crate::postgres::CensorshipDetails::default(),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        let to_hit = |event: &polycentric_protocol::model::signed_event::SignedEvent| -> ::anyhow::Result<::serde_json::Value> {
+            Ok(::serde_json::json!({
+                "_index": crate::opensearch::MESSAGES_INDEX,
+                "_id": polycentric_protocol::model::pointer::to_base64(
+                    &polycentric_protocol::model::pointer::from_signed_event(
+                        event,
+                    )?,
+                )?,
+            }))
+        };
+
+        // The censored document has not been removed from the index yet
+        let (search, requests) =
+            crate::opensearch::test_utils::mock(::serde_json::json!({
+                "hits": {
+                    "hits": [to_hit(&censored)?, to_hit(&visible)?]
+                }
+            }))?;
+
+        let state = crate::make_test_state(&pool, search)?;
+
+        let response = super::handler_inner(
+            state,
+            String::from("hello"),
+            10,
+            0,
+            super::SearchType::Messages,
+            &None,
+        )
+        .await?
+        .into_response();
+
+        assert_eq!(response.status(), ::warp::http::StatusCode::OK);
+
+        let result = polycentric_protocol::protocol::ResultEventsAndRelatedEventsAndCursor::parse_from_bytes(
+            &::hyper::body::to_bytes(response.into_body()).await?,
+        )?;
+
+        assert_eq!(
+            result.result_events.events,
+            vec![polycentric_protocol::model::signed_event::to_proto(
+                &visible
+            )]
+        );
+
+        // Both hits were consumed although only one event is returned
+        assert_eq!(result.cursor, Some(u64::to_le_bytes(2).to_vec()));
+
+        assert_eq!(
+            requests.lock().unwrap().clone(),
+            vec![::serde_json::json!({
+                "query": {
+                    "match": {
+                        "message_content": {
+                            "query": "hello",
+                            "fuzziness": 2
+                        }
+                    }
+                }
+            })]
+        );
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_search_profiles_skips_rejected(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+        let system =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                keypair.verifying_key(),
+            );
+
+        let username = |logical_clock: u64, name: &str| {
+            polycentric_protocol::test_utils::make_test_lww_event(
+                &keypair,
+                &process,
+                logical_clock,
+                polycentric_protocol::model::known_message_types::USERNAME,
+                name.as_bytes(),
+                logical_clock * 1000,
+            )
+        };
+
+        let visible = username(1, "alice");
+        let rejected = username(2, "mallory");
+
+        for event in [&visible, &rejected] {
+            crate::ingest::ingest_event_postgres(&mut transaction, event)
+                .await?;
+        }
+
+        ::sqlx::query(
+            "
+            UPDATE events
+            SET moderation_status = 'flagged_and_rejected'
+            WHERE logical_clock = 2;
+            ",
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        // The rejected username is still in the index
+        let (search, _) = crate::opensearch::test_utils::mock(
+            ::serde_json::json!({
+                "hits": {
+                    "hits": [{
+                        "_index": crate::opensearch::PROFILE_NAMES_INDEX,
+                        "_id": polycentric_protocol::model::public_key::to_base64(
+                            &system,
+                        )?,
+                    }]
+                }
+            }),
+        )?;
+
+        let state = crate::make_test_state(&pool, search)?;
+
+        let response = super::handler_inner(
+            state,
+            String::from("mallory"),
+            10,
+            0,
+            super::SearchType::Profiles,
+            &None,
+        )
+        .await?
+        .into_response();
+
+        let result = polycentric_protocol::protocol::ResultEventsAndRelatedEventsAndCursor::parse_from_bytes(
+            &::hyper::body::to_bytes(response.into_body()).await?,
+        )?;
+
+        assert_eq!(
+            result.result_events.events,
+            vec![polycentric_protocol::model::signed_event::to_proto(
+                &visible
+            )]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use ::futures::{SinkExt, StreamExt};
+use ::protobuf::Message;
+
+#[derive(::serde::Deserialize)]
+pub(crate) struct Query {
+    #[serde(
+        default,
+        deserialize_with = "crate::handlers::util::deserialize_json_string"
+    )]
+    moderation_filters: Option<crate::moderation::ModerationFilters>,
+}
+
+// Clients send a binary StreamSubscription at any time to replace what they
+// are subscribed to, and receive a binary Events message for each matching
+// event as it is ingested.
+pub(crate) async fn handler(
+    state: ::std::sync::Arc<crate::State>,
+    query: Query,
+    ws: ::warp::ws::Ws,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    Ok(Box::new(ws.on_upgrade(move |socket| async move {
+        if let Err(err) = connection(state, query, socket).await {
+            ::log::debug!("stream closed: {}", err);
+        }
+    })))
+}
+
+async fn connection(
+    state: ::std::sync::Arc<crate::State>,
+    query: Query,
+    socket: ::warp::ws::WebSocket,
+) -> ::anyhow::Result<()> {
+    let (mut sender, mut receiver) = socket.split();
+
+    let mut events = state.stream.subscribe();
+
+    let mut subscription = crate::stream::Subscription::default();
+
+    let moderation_options = crate::moderation::ModerationOptions {
+        filters: query.moderation_filters,
+        mode: state.moderation_mode,
+    };
+
+    loop {
+        ::tokio::select! {
+            message = receiver.next() => {
+                let message = match message {
+                    Some(message) => message?,
+                    None => return Ok(()),
+                };
+
+                if message.is_close() {
+                    return Ok(());
+                }
+
+                if message.is_binary() {
+                    subscription = crate::stream::Subscription::from_proto(
+                        &polycentric_protocol::protocol::StreamSubscription::parse_from_bytes(
+                            message.as_bytes(),
+                        )?,
+                    )?;
+                }
+            }
+            streamed_event = events.recv() => {
+                let streamed_event = match streamed_event {
+                    Ok(streamed_event) => streamed_event,
+                    Err(::tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        ::log::warn!("stream subscriber lagged by {}", skipped);
+                        continue;
+                    }
+                    Err(::tokio::sync::broadcast::error::RecvError::Closed) => {
+                        return Ok(());
+                    }
+                };
+
+                if !subscription.matches(&streamed_event) {
+                    continue;
+                }
+
+                // Load through the same moderation filtering as the read
+                // endpoints rather than sending the broadcast copy directly.
+                // The notification is sent by the primary on commit, a
+                // replica may not have the event yet.
+                let mut transaction = state.pool.begin().await?;
+
+                let potential_signed_event = crate::postgres::load_event(
+                    &mut transaction,
+                    streamed_event.event.system(),
+                    streamed_event.event.process(),
+                    *streamed_event.event.logical_clock(),
+                    &moderation_options,
+                )
+                .await?;
+
+                transaction.commit().await?;
+
+                if let Some(signed_event) = potential_signed_event {
+                    let mut result = polycentric_protocol::protocol::Events::new();
+
+                    result.events.push(
+                        polycentric_protocol::model::signed_event::to_proto(
+                            &signed_event,
+                        ),
+                    );
+
+                    sender
+                        .send(::warp::ws::Message::binary(result.write_to_bytes()?))
+                        .await?;
+                }
+            }
+        }
+    }
+}
@@ -148,3 +148,73 @@ pub(crate) async fn handler_inner(
         "public, s-maxage=60, max-age=60",
     )))
 }
+
+#[cfg(test)]
+mod tests {
+    use ::protobuf::Message;
+    use ::warp::Reply;
+
+    #[::sqlx::test]
+    async fn test_top_string_references(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let (search, requests) =
+            crate::opensearch::test_utils::mock(::serde_json::json!({
+                "aggregations": {
+                    "top_byte_references": {
+                        "buckets": [
+                            { "key": "a", "doc_count": 3 },
+                            { "key": "b", "doc_count": 1 }
+                        ]
+                    }
+                }
+            }))?;
+
+        let state = crate::make_test_state(&pool, search)?;
+
+        let response = super::handler_inner(
+            state,
+            Some(String::from("/a")),
+            10,
+            String::from("now-1d/d"),
+        )
+        .await?
+        .into_response();
+
+        assert_eq!(response.status(), ::warp::http::StatusCode::OK);
+
+        let result =
+            polycentric_protocol::protocol::ResultTopStringReferences::parse_from_bytes(
+                &::hyper::body::to_bytes(response.into_body()).await?,
+            )?;
+
+        assert_eq!(
+            result
+                .buckets
+                .iter()
+                .map(|bucket| (bucket.key.clone(), bucket.value))
+                .collect::<::std::vec::Vec<_>>(),
+            vec![(b"a".to_vec(), 3), (b"b".to_vec(), 1)]
+        );
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+
+        // Censored posts are kept out of the index, only the query and time
+        // range filter documents
+        assert_eq!(
+            requests[0]["query"]["bool"]["should"][1],
+            ::serde_json::json!({
+                "wildcard": { "byte_reference": "a*" }
+            })
+        );
+        assert!(requests[0]["query"]["bool"]["must_not"].is_null());
+        assert_eq!(
+            requests[0]["query"]["bool"]["filter"][0]["range"]
+                ["unix_milliseconds"]["gte"],
+            "now-1d/d"
+        );
+
+        Ok(())
+    }
+}
@@ -1,10 +1,21 @@
+pub(crate) mod delete_censorship;
+pub(crate) mod delete_handle;
+pub(crate) mod get_audit_log;
+pub(crate) mod get_censorship;
+pub(crate) mod get_censorship_entry;
 pub(crate) mod get_challenge;
 pub(crate) mod get_claim_to_system;
 pub(crate) mod get_events;
 pub(crate) mod get_explore;
+pub(crate) mod get_export;
 pub(crate) mod get_find_claim_and_vouch;
 pub(crate) mod get_head;
 pub(crate) mod get_health;
+pub(crate) mod get_moderation_policy;
+pub(crate) mod get_moderation_reports;
+pub(crate) mod get_moderation_review;
+pub(crate) mod get_moderation_review_item;
+pub(crate) mod get_moderation_shadow_report;
 pub(crate) mod get_query_index;
 pub(crate) mod get_query_latest;
 pub(crate) mod get_query_references;
@@ -12,10 +23,13 @@ pub(crate) mod get_ranges;
 pub(crate) mod get_recommend_profiles;
 pub(crate) mod get_resolve_handle;
 pub(crate) mod get_search;
+pub(crate) mod get_stream;
 pub(crate) mod get_top_string_references;
 pub(crate) mod get_version;
 pub(crate) mod post_censor;
 pub(crate) mod post_claim_handle;
 pub(crate) mod post_events;
+pub(crate) mod post_moderation_review;
 pub(crate) mod post_purge;
+pub(crate) mod post_report;
 pub(crate) mod util;
@@ -4,18 +4,43 @@ use std::fmt::Error;
 #[derive(::serde::Deserialize)]
 pub(crate) struct Query {
     censorship_type: crate::postgres::CensorshipType,
+    reason: ::std::option::Option<String>,
+    // unix milliseconds
+    expires_at: ::std::option::Option<u64>,
 }
 
+// Censoring a system or event that is already censored replaces the
+// censorship type, reason and expiry.
 pub(crate) async fn handler(
     state: ::std::sync::Arc<crate::State>,
     authorization: String,
     query: Query,
     bytes: ::bytes::Bytes,
 ) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
-    if authorization != state.admin_token {
+    let admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::Censor,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    // RefuseStorage deletes stored events so it also requires the purge scope
+    if query.censorship_type == crate::postgres::CensorshipType::RefuseStorage
+        && !admin.has_scope(crate::admin::AdminScope::Purge)
+    {
         return Ok(Box::new(::warp::reply::with_status(
             String::from(""),
-            ::warp::http::StatusCode::UNAUTHORIZED,
+            ::warp::http::StatusCode::FORBIDDEN,
         )));
     }
 
@@ -36,6 +61,12 @@ pub(crate) async fn handler(
         polycentric_protocol::protocol::URLInfo::parse_from_bytes(&bytes2)
     );
 
+    let details = crate::postgres::CensorshipDetails {
+        reason: query.reason.clone(),
+        censored_by: Some(admin.name.clone()),
+        expires_at: query.expires_at,
+    };
+
     let mut transaction = crate::warp_try_err_500!(state.pool.begin().await);
 
     if url_info.url_type == 1 {
@@ -53,10 +84,19 @@ pub(crate) async fn handler(
             crate::postgres::censor_system(
                 &mut transaction,
                 query.censorship_type,
-                system
+                system.clone(),
+                &details,
             )
             .await
         );
+
+        if query.censorship_type
+            == crate::postgres::CensorshipType::RefuseStorage
+        {
+            crate::warp_try_err_500!(
+                crate::postgres::purge::purge(&mut transaction, &system).await
+            );
+        }
     } else if url_info.url_type == 2 {
         let body_proto = crate::warp_try_err_500!(
             polycentric_protocol::protocol::URLInfoEventLink::parse_from_bytes(
@@ -82,10 +122,25 @@ pub(crate) async fn handler(
                 query.censorship_type,
                 &system,
                 &process,
-                logical_clock
+                logical_clock,
+                &details,
             )
             .await
         );
+
+        if query.censorship_type
+            == crate::postgres::CensorshipType::RefuseStorage
+        {
+            crate::warp_try_err_500!(
+                crate::postgres::purge::purge_event(
+                    &mut transaction,
+                    &system,
+                    &process,
+                    logical_clock
+                )
+                .await
+            );
+        }
     } else {
         return Ok(Box::new(::warp::reply::with_status(
             String::from("Unknown URL type"),
@@ -93,6 +148,25 @@ pub(crate) async fn handler(
         )));
     }
 
+    crate::warp_try_err_500!(
+        crate::admin::audit(
+            &mut transaction,
+            &admin,
+            if url_info.url_type == 1 {
+                "censor_system"
+            } else {
+                "censor_event"
+            },
+            Some(url_str.as_str()),
+            ::serde_json::json!({
+                "censorship_type": query.censorship_type,
+                "reason": query.reason,
+                "expires_at": query.expires_at,
+            }),
+        )
+        .await
+    );
+
     crate::warp_try_err_500!(transaction.commit().await);
 
     Ok(Box::new(::warp::reply::with_status(
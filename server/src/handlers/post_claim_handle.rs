@@ -20,6 +20,12 @@ pub(crate) async fn handler(
         )
     );
 
+    let challenge = crate::warp_try_err_400!(request
+        .challenge
+        .clone()
+        .into_option()
+        .context("expected challenge"));
+
     let handle: String = request.handle.clone();
 
     if handle.len() > 64 || handle.is_empty() {
@@ -37,12 +43,53 @@ pub(crate) async fn handler(
         )));
     }
 
+    if state.reserved_handles.contains(&handle.to_lowercase()) {
+        return Ok(Box::new(::warp::reply::with_status(
+            "Handle is reserved",
+            ::warp::http::StatusCode::FORBIDDEN,
+        )));
+    }
+
     let mut transaction = crate::warp_try_err_500!(state.pool.begin().await);
 
-    crate::warp_try_err_500!(
+    let verification = crate::warp_try_err_400!(
+        crate::harbor::verify_harbor_request(
+            &mut transaction,
+            &state.challenge_key,
+            state.challenge_ttl,
+            &system,
+            &challenge,
+            &request.signature,
+        )
+        .await
+    );
+
+    if verification != crate::harbor::HarborVerification::Verified {
+        return Ok(Box::new(::warp::reply::with_status(
+            verification.reason(),
+            ::warp::http::StatusCode::UNAUTHORIZED,
+        )));
+    }
+
+    let result = crate::warp_try_err_500!(
         crate::postgres::claim_handle(&mut transaction, handle, &system).await
     );
 
+    if result == crate::postgres::ClaimHandleResult::Taken {
+        return Ok(Box::new(::warp::reply::with_status(
+            "Handle is already taken",
+            ::warp::http::StatusCode::CONFLICT,
+        )));
+    }
+
+    crate::warp_try_err_500!(
+        crate::cache::purge_queue::enqueue(
+            &mut transaction,
+            &crate::cache::util::key_to_cache_tags_account_meta(&system),
+        )
+        .await
+    );
+
     crate::warp_try_err_500!(transaction.commit().await);
 
     Ok(Box::new(::warp::reply::with_status(
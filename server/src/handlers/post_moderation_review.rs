@@ -0,0 +1,91 @@
+use ::serde_json::json;
+
+#[derive(::serde::Deserialize, Default)]
+struct Body {
+    note: ::std::option::Option<String>,
+    tags: ::std::option::Option<
+        ::std::vec::Vec<
+            polycentric_protocol::model::moderation_tag::ModerationTag,
+        >,
+    >,
+}
+
+// Records a manual decision for an event. The optional JSON body carries a
+// note and, for approve and retag, the tags to store.
+pub(crate) async fn handler(
+    id: i64,
+    action: crate::moderation::review::ReviewAction,
+    state: ::std::sync::Arc<crate::State>,
+    authorization: String,
+    bytes: ::bytes::Bytes,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let admin = match crate::warp_try_err_500!(
+        crate::admin::authorize(
+            &state,
+            &authorization,
+            crate::admin::AdminScope::ModerationReview,
+        )
+        .await
+    ) {
+        Ok(admin) => admin,
+        Err(status) => {
+            return Ok(Box::new(::warp::reply::with_status(
+                String::from(""),
+                status,
+            )));
+        }
+    };
+
+    let body = if bytes.is_empty() {
+        Body::default()
+    } else {
+        crate::warp_try_err_400!(::serde_json::from_slice::<Body>(&bytes))
+    };
+
+    crate::warp_try_err_400!(crate::moderation::review::validate(
+        action,
+        body.tags.as_deref()
+    ));
+
+    let mut transaction = crate::warp_try_err_500!(state.pool.begin().await);
+
+    let found = crate::warp_try_err_500!(
+        crate::moderation::review::apply_review(
+            &mut transaction,
+            id,
+            action,
+            body.tags.clone(),
+            &admin.name,
+            body.note.as_deref(),
+        )
+        .await
+    );
+
+    if !found {
+        return Ok(Box::new(::warp::reply::with_status(
+            String::from(""),
+            ::warp::http::StatusCode::NOT_FOUND,
+        )));
+    }
+
+    crate::warp_try_err_500!(
+        crate::admin::audit(
+            &mut transaction,
+            &admin,
+            &format!("moderation_{}", action.as_str()),
+            Some(id.to_string().as_str()),
+            json!({
+                "note": body.note,
+                "tags": body.tags,
+            }),
+        )
+        .await
+    );
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    Ok(Box::new(::warp::reply::with_status(
+        String::from(""),
+        ::warp::http::StatusCode::OK,
+    )))
+}
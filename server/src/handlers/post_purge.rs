@@ -19,35 +19,27 @@ pub(crate) async fn handler(
         )
     );
 
-    let request_body = crate::warp_try_err_400!(
-        polycentric_protocol::protocol::HarborChallengeResponseBody::parse_from_bytes(
-            &request.challenge.body
-        )
-    );
+    let mut transaction = crate::warp_try_err_500!(state.pool.begin().await);
 
-    crate::warp_try_err_400!(
-        polycentric_protocol::model::public_key::validate_signature(
+    let verification = crate::warp_try_err_400!(
+        crate::harbor::verify_harbor_request(
+            &mut transaction,
+            &state.challenge_key,
+            state.challenge_ttl,
             &system,
+            &request.challenge,
             &request.signature,
-            &request_body.challenge,
         )
+        .await
     );
 
-    let hmac = ::hmac_sha256::HMAC::mac(
-        request.challenge.body.clone(),
-        state.challenge_key.as_bytes(),
-    )
-    .to_vec();
-
-    if !::constant_time_eq::constant_time_eq(&hmac, &request.challenge.hmac) {
+    if verification != crate::harbor::HarborVerification::Verified {
         return Ok(Box::new(::warp::reply::with_status(
-            "",
+            verification.reason(),
             ::warp::http::StatusCode::UNAUTHORIZED,
         )));
     }
 
-    let mut transaction = crate::warp_try_err_500!(state.pool.begin().await);
-
     crate::warp_try_err_500!(
         crate::postgres::purge::purge(&mut transaction, &system,).await
     );
@@ -0,0 +1,121 @@
+use ::anyhow::Context;
+use ::protobuf::Message;
+
+const MAX_NOTE_LENGTH: usize = 1000;
+
+pub(crate) async fn handler(
+    state: ::std::sync::Arc<crate::State>,
+    bytes: ::bytes::Bytes,
+) -> Result<Box<dyn ::warp::Reply>, ::std::convert::Infallible> {
+    let request = crate::warp_try_err_400!(
+        polycentric_protocol::protocol::ReportRequest::parse_from_tokio_bytes(
+            &bytes
+        )
+    );
+
+    let reporter = crate::warp_try_err_400!(
+        polycentric_protocol::model::public_key::from_proto(
+            crate::warp_try_err_400!(&request
+                .system
+                .clone()
+                .into_option()
+                .context("expected system"))
+        )
+    );
+
+    let challenge = crate::warp_try_err_400!(request
+        .challenge
+        .clone()
+        .into_option()
+        .context("expected challenge"));
+
+    let target = match (
+        request.event.clone().into_option(),
+        request.target_system.clone().into_option(),
+    ) {
+        (Some(event), None) => crate::moderation::reports::ReportTarget::Event(
+            crate::warp_try_err_400!(
+                polycentric_protocol::model::pointer::from_proto(&event)
+            ),
+        ),
+        (None, Some(system)) => {
+            crate::moderation::reports::ReportTarget::System(
+                crate::warp_try_err_400!(
+                    polycentric_protocol::model::public_key::from_proto(
+                        &system
+                    )
+                ),
+            )
+        }
+        _ => {
+            return Ok(Box::new(::warp::reply::with_status(
+                "Expected either an event or a system",
+                ::warp::http::StatusCode::BAD_REQUEST,
+            )));
+        }
+    };
+
+    let reason = crate::warp_try_err_400!(
+        crate::moderation::reports::ReportReason::try_from(request.reason)
+    );
+
+    if request.note.len() > MAX_NOTE_LENGTH {
+        return Ok(Box::new(::warp::reply::with_status(
+            "Note is too long",
+            ::warp::http::StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    let note = if request.note.is_empty() {
+        None
+    } else {
+        Some(request.note.as_str())
+    };
+
+    let mut transaction = crate::warp_try_err_500!(state.pool.begin().await);
+
+    let verification = crate::warp_try_err_400!(
+        crate::harbor::verify_harbor_request(
+            &mut transaction,
+            &state.challenge_key,
+            state.challenge_ttl,
+            &reporter,
+            &challenge,
+            &request.signature,
+        )
+        .await
+    );
+
+    if verification != crate::harbor::HarborVerification::Verified {
+        return Ok(Box::new(::warp::reply::with_status(
+            verification.reason(),
+            ::warp::http::StatusCode::UNAUTHORIZED,
+        )));
+    }
+
+    let result = crate::warp_try_err_500!(
+        crate::moderation::reports::submit(
+            &mut transaction,
+            &reporter,
+            &target,
+            reason,
+            note,
+            state.report_review_threshold,
+        )
+        .await
+    );
+
+    if result == crate::moderation::reports::SubmitResult::UnknownEvent {
+        return Ok(Box::new(::warp::reply::with_status(
+            "Event not found",
+            ::warp::http::StatusCode::NOT_FOUND,
+        )));
+    }
+
+    crate::warp_try_err_500!(transaction.commit().await);
+
+    Ok(Box::new(::warp::reply::with_status(
+        "",
+        ::warp::http::StatusCode::OK,
+    )))
+}
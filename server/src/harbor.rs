@@ -0,0 +1,269 @@
+use ::protobuf::Message;
+use ::rand::Rng;
+
+#[derive(PartialEq, Debug)]
+pub(crate) enum HarborVerification {
+    Verified,
+    InvalidHmac,
+    InvalidSignature,
+    Expired,
+    Replayed,
+}
+
+impl HarborVerification {
+    pub(crate) fn reason(&self) -> &'static str {
+        match self {
+            HarborVerification::Verified => "",
+            HarborVerification::InvalidHmac => "Challenge was not issued here",
+            HarborVerification::InvalidSignature => "Invalid signature",
+            HarborVerification::Expired => "Challenge expired",
+            HarborVerification::Replayed => "Challenge already used",
+        }
+    }
+}
+
+fn now_milliseconds() -> ::anyhow::Result<u64> {
+    Ok(u64::try_from(
+        ::std::time::SystemTime::now()
+            .duration_since(::std::time::UNIX_EPOCH)?
+            .as_millis(),
+    )?)
+}
+
+pub(crate) fn make_challenge(
+    challenge_key: &str,
+) -> ::anyhow::Result<polycentric_protocol::protocol::HarborChallengeResponse> {
+    let mut body =
+        polycentric_protocol::protocol::HarborChallengeResponseBody::new();
+    body.challenge = ::rand::thread_rng().gen::<[u8; 32]>().to_vec();
+    body.created_on = now_milliseconds()?;
+
+    let body_bytes = body.write_to_bytes()?;
+
+    let mut challenge =
+        polycentric_protocol::protocol::HarborChallengeResponse::new();
+    challenge.hmac =
+        ::hmac_sha256::HMAC::mac(body_bytes.clone(), challenge_key.as_bytes())
+            .to_vec();
+    challenge.body = body_bytes;
+
+    Ok(challenge)
+}
+
+// Every endpoint authenticated by a solved challenge must go through this.
+// The challenge is marked as used inside the caller's transaction, so it is
+// only consumed if the caller commits.
+pub(crate) async fn verify_harbor_request(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    challenge_key: &str,
+    challenge_ttl: ::std::time::Duration,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+    challenge: &polycentric_protocol::protocol::HarborChallengeResponse,
+    signature: &[u8],
+) -> ::anyhow::Result<HarborVerification> {
+    let hmac = ::hmac_sha256::HMAC::mac(
+        challenge.body.clone(),
+        challenge_key.as_bytes(),
+    )
+    .to_vec();
+
+    if !::constant_time_eq::constant_time_eq(&hmac, &challenge.hmac) {
+        return Ok(HarborVerification::InvalidHmac);
+    }
+
+    let body =
+        polycentric_protocol::protocol::HarborChallengeResponseBody::parse_from_bytes(
+            &challenge.body,
+        )?;
+
+    if polycentric_protocol::model::public_key::validate_signature(
+        system,
+        signature,
+        &body.challenge,
+    )
+    .is_err()
+    {
+        return Ok(HarborVerification::InvalidSignature);
+    }
+
+    let expires_on = body
+        .created_on
+        .saturating_add(u64::try_from(challenge_ttl.as_millis())?);
+
+    if now_milliseconds()? > expires_on {
+        return Ok(HarborVerification::Expired);
+    }
+
+    if !crate::postgres::insert_used_challenge(
+        &mut *transaction,
+        &body.challenge,
+        body.created_on,
+    )
+    .await?
+    {
+        return Ok(HarborVerification::Replayed);
+    }
+
+    Ok(HarborVerification::Verified)
+}
+
+async fn garbage_collect(
+    pool: &::sqlx::PgPool,
+    challenge_ttl: ::std::time::Duration,
+) -> ::anyhow::Result<u64> {
+    let cutoff = now_milliseconds()?
+        .saturating_sub(u64::try_from(challenge_ttl.as_millis())?);
+
+    let mut transaction = pool.begin().await?;
+
+    let deleted = crate::postgres::delete_used_challenges_before(
+        &mut transaction,
+        cutoff,
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(deleted)
+}
+
+// Used challenges only need to be remembered until they would have expired
+// anyway.
+pub(crate) async fn run_garbage_collection(
+    pool: ::sqlx::PgPool,
+    challenge_ttl: ::std::time::Duration,
+) {
+    let mut interval = ::tokio::time::interval(
+        challenge_ttl.max(::std::time::Duration::from_secs(1)),
+    );
+
+    loop {
+        interval.tick().await;
+
+        match garbage_collect(&pool, challenge_ttl).await {
+            Ok(deleted) => {
+                ::log::debug!("garbage collected {} used challenges", deleted);
+            }
+            Err(err) => {
+                ::log::warn!("failed to garbage collect challenges: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use ::protobuf::Message;
+
+    const CHALLENGE_KEY: &str = "test_challenge_key";
+
+    fn solve(
+        keypair: &::ed25519_dalek::SigningKey,
+        challenge: &polycentric_protocol::protocol::HarborChallengeResponse,
+    ) -> ::anyhow::Result<::std::vec::Vec<u8>> {
+        use ::ed25519_dalek::Signer;
+
+        let body =
+            polycentric_protocol::protocol::HarborChallengeResponseBody::parse_from_bytes(
+                &challenge.body,
+            )?;
+
+        Ok(keypair.sign(&body.challenge).to_bytes().to_vec())
+    }
+
+    #[::sqlx::test]
+    async fn test_verify_harbor_request(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let system =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                keypair.verifying_key(),
+            );
+        let ttl = ::std::time::Duration::from_secs(60);
+
+        let challenge = crate::harbor::make_challenge(CHALLENGE_KEY)?;
+        let signature = solve(&keypair, &challenge)?;
+
+        assert_eq!(
+            crate::harbor::verify_harbor_request(
+                &mut transaction,
+                "wrong_key",
+                ttl,
+                &system,
+                &challenge,
+                &signature,
+            )
+            .await?,
+            crate::harbor::HarborVerification::InvalidHmac
+        );
+
+        assert_eq!(
+            crate::harbor::verify_harbor_request(
+                &mut transaction,
+                CHALLENGE_KEY,
+                ttl,
+                &system,
+                &challenge,
+                &[0; 64],
+            )
+            .await?,
+            crate::harbor::HarborVerification::InvalidSignature
+        );
+
+        assert_eq!(
+            crate::harbor::verify_harbor_request(
+                &mut transaction,
+                CHALLENGE_KEY,
+                ttl,
+                &system,
+                &challenge,
+                &signature,
+            )
+            .await?,
+            crate::harbor::HarborVerification::Verified
+        );
+
+        assert_eq!(
+            crate::harbor::verify_harbor_request(
+                &mut transaction,
+                CHALLENGE_KEY,
+                ttl,
+                &system,
+                &challenge,
+                &signature,
+            )
+            .await?,
+            crate::harbor::HarborVerification::Replayed
+        );
+
+        let mut body =
+            polycentric_protocol::protocol::HarborChallengeResponseBody::new();
+        body.challenge = vec![1; 32];
+        body.created_on = 1;
+        let mut expired =
+            polycentric_protocol::protocol::HarborChallengeResponse::new();
+        expired.body = body.write_to_bytes()?;
+        expired.hmac =
+            ::hmac_sha256::HMAC::mac(expired.body.clone(), CHALLENGE_KEY)
+                .to_vec();
+
+        assert_eq!(
+            crate::harbor::verify_harbor_request(
+                &mut transaction,
+                CHALLENGE_KEY,
+                ttl,
+                &system,
+                &expired,
+                &solve(&keypair, &expired)?,
+            )
+            .await?,
+            crate::harbor::HarborVerification::Expired
+        );
+
+        Ok(())
+    }
+}
@@ -1,14 +1,12 @@
 use ::cadence::Counted;
 use ::log::*;
 use ::opensearch::IndexParts;
-use ::polycentric_protocol::protocol::Post;
-use ::protobuf::Message;
 use ::std::collections::HashMap;
 use ::std::fmt::Error;
 use ::std::ops::Deref;
 use ::std::time::SystemTime;
 use polycentric_protocol::model::{
-    known_message_types, signed_event::SignedEvent,
+    content::Content, opinion, signed_event::SignedEvent,
 };
 
 const MAX_POST_LENGTH: usize = 10_000;
@@ -28,7 +26,7 @@ pub(crate) async fn ingest_event_batch(
     filter_recently_ingested(state, &mut batch);
 
     for layers in batch.values() {
-        trace_event(user_agent, layers.event())?;
+        trace_event(user_agent, layers)?;
     }
 
     for attempt in 1..4 {
@@ -59,10 +57,6 @@ pub(crate) async fn ingest_event_batch(
         }
     }
 
-    for layers in batch.values() {
-        ingest_event_search(&state.search, layers).await?;
-    }
-
     mark_as_recently_ingested(state, &batch);
 
     state
@@ -87,53 +81,23 @@ pub(crate) async fn ingest_event_batch(
 
 fn trace_event(
     user_agent: &Option<String>,
-    event: &polycentric_protocol::model::event::Event,
+    layers: &polycentric_protocol::model::EventLayers,
 ) -> ::anyhow::Result<()> {
-    let mut content_str: String = "unknown".to_string();
-
-    let content_type = *event.content_type();
-
-    if content_type == polycentric_protocol::model::known_message_types::POST {
-        content_str = Post::parse_from_bytes(event.content())?
-            .content
-            .ok_or(Error)?;
-    } else if content_type
-        == polycentric_protocol::model::known_message_types::USERNAME
-        || content_type
-            == polycentric_protocol::model::known_message_types::DESCRIPTION
-        || content_type
-            == polycentric_protocol::model::known_message_types::STORE
-    {
-        let lww_element = event.lww_element().clone().ok_or(Error)?;
+    let event = layers.event();
 
-        content_str = String::from_utf8(lww_element.value)?;
-    } else if content_type
-        == polycentric_protocol::model::known_message_types::SERVER
-        || content_type
-            == polycentric_protocol::model::known_message_types::AUTHORITY
-    {
-        let lww_element_set = event.lww_element_set().clone().ok_or(Error)?;
-
-        content_str = String::from_utf8(lww_element_set.value)?;
-    } else if content_type
-        == polycentric_protocol::model::known_message_types::AVATAR
-        || content_type
-            == polycentric_protocol::model::known_message_types::BANNER
-    {
-        content_str = "updated".to_string();
-    } else if content_type
-        == polycentric_protocol::model::known_message_types::OPINION
-    {
-        let lww_element = event.lww_element().clone().ok_or(Error)?;
-
-        if lww_element.value == vec![1] {
-            content_str = "LIKE".to_string();
-        } else if lww_element.value == vec![2] {
-            content_str = "DISLIKE".to_string();
-        } else if lww_element.value == vec![3] {
-            content_str = "NEUTRAL".to_string();
-        }
-    }
+    let content_str = match layers.content() {
+        Content::Post(post) => post.content().clone().ok_or(Error)?,
+        Content::Username(value)
+        | Content::Description(value)
+        | Content::Store(value)
+        | Content::Server(value)
+        | Content::Authority(value) => value.clone(),
+        Content::Avatar(_) | Content::Banner(_) => "updated".to_string(),
+        Content::Opinion(opinion::Opinion::Like) => "LIKE".to_string(),
+        Content::Opinion(opinion::Opinion::Dislike) => "DISLIKE".to_string(),
+        Content::Opinion(opinion::Opinion::Neutral) => "NEUTRAL".to_string(),
+        _ => "unknown".to_string(),
+    };
 
     debug!(
         "ingesting {:?} {}:{} event_type: {} details: {}",
@@ -158,9 +122,7 @@ fn filter_subjects_of_deletes(
     let mut to_remove = vec![];
 
     for layers in batch.values() {
-        if let polycentric_protocol::model::content::Content::Delete(body) =
-            &layers.content()
-        {
+        if let Content::Delete(body) = &layers.content() {
             to_remove.push(polycentric_protocol::model::InsecurePointer::new(
                 layers.event().system().clone(),
                 body.process().clone(),
@@ -269,16 +231,37 @@ async fn ingest_event_postgres_single(
         return Ok(());
     }
 
+    if crate::postgres::is_event_censored(
+        &mut *transaction,
+        event,
+        Some(crate::postgres::CensorshipType::RefuseStorage),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
     let server_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_secs();
 
     let content = layers.content();
 
+    // Sections of blobs found to be CSAM are refused, see `result_cache`
+    if let Content::BlobSection(bytes) = content {
+        if crate::moderation::result_cache::is_blocked(
+            &mut *transaction,
+            &crate::moderation::result_cache::sha256(bytes),
+        )
+        .await?
+        {
+            return Ok(());
+        }
+    }
+
     // Enforce post character limit
-    if *event.content_type() == known_message_types::POST {
-        let post = Post::parse_from_bytes(event.content())?;
-        if let Some(ref text) = post.content {
+    if let Content::Post(post) = content {
+        if let Some(text) = post.content() {
             if text.len() > MAX_POST_LENGTH {
                 return Err(::anyhow::anyhow!(
                     "post content exceeds maximum length of {} characters",
@@ -303,6 +286,24 @@ async fn ingest_event_postgres_single(
     )
     .await?;
 
+    crate::postgres::notify_event_available(&mut *transaction, event_id)
+        .await?;
+
+    // Indexed by `search_indexer` once this transaction commits
+    if matches!(
+        content,
+        Content::Post(_) | Content::Username(_) | Content::Description(_)
+    ) {
+        crate::search_indexer::enqueue(&mut *transaction, event_id).await?;
+    }
+
+    // Purged once this transaction commits
+    crate::cache::purge_queue::enqueue_events(
+        &mut *transaction,
+        ::std::slice::from_ref(layers.signed_event()),
+    )
+    .await?;
+
     for reference in event.references().iter() {
         match reference {
             polycentric_protocol::model::reference::Reference::Pointer(
@@ -338,9 +339,7 @@ async fn ingest_event_postgres_single(
         .await?;
     }
 
-    if let polycentric_protocol::model::content::Content::Delete(body) =
-        &content
-    {
+    if let Content::Delete(body) = &content {
         crate::postgres::delete_event(
             &mut *transaction,
             event_id,
@@ -348,9 +347,7 @@ async fn ingest_event_postgres_single(
             body,
         )
         .await?;
-    } else if let polycentric_protocol::model::content::Content::Claim(body) =
-        &content
-    {
+    } else if let Content::Claim(body) = &content {
         crate::postgres::insert_claim(&mut *transaction, event_id, body)
             .await?;
     }
@@ -374,102 +371,168 @@ async fn ingest_event_postgres_single(
     Ok(())
 }
 
-pub(crate) async fn ingest_event_search(
-    search: &::opensearch::OpenSearch,
+// What an event contributes to the search indices, if anything.
+pub(crate) fn search_document(
     layers: &polycentric_protocol::model::EventLayers,
-) -> ::anyhow::Result<()> {
+) -> ::anyhow::Result<Option<crate::opensearch::SearchDocument>> {
     let event = layers.event();
 
-    // Use the full event pointer (system + process + logical clock + digest) as the OpenSearch _id so that we can reliably decode it later in `get_search`.
-    let pointer = polycentric_protocol::model::pointer::from_signed_event(
-        layers.signed_event(),
-    )?;
+    match layers.content() {
+        Content::Post(post) => {
+            // Use the full event pointer (system + process + logical clock + digest) as the OpenSearch _id so that we can reliably decode it later in `get_search`.
+            let pointer =
+                polycentric_protocol::model::pointer::from_signed_event(
+                    layers.signed_event(),
+                )?;
+
+            let doc_id =
+                polycentric_protocol::model::pointer::to_base64(&pointer)?;
+
+            let first_byte_reference_b64: Option<String> =
+                    event.references().iter().find_map(|reference| {
+                        if let polycentric_protocol::model::reference::Reference::Bytes(bytes) = reference {
+                            Some(base64::encode_config(bytes, base64::URL_SAFE_NO_PAD))
+                        } else {
+                            None
+                        }
+                    });
 
-    let doc_id = polycentric_protocol::model::pointer::to_base64(&pointer)?;
+            let doc = crate::opensearch::OpenSearchContent {
+                message_content: post.content().clone().unwrap_or_default(),
+                unix_milliseconds: *event.unix_milliseconds(),
+                byte_reference: first_byte_reference_b64,
+            };
+
+            Ok(Some(crate::opensearch::SearchDocument {
+                index: crate::opensearch::MESSAGES_INDEX,
+                id: doc_id,
+                body: ::serde_json::to_value(&doc)?,
+                version: None,
+            }))
+        }
+        Content::Username(value) => profile_search_document(
+            event,
+            crate::opensearch::PROFILE_NAMES_INDEX,
+            Some(value.clone()),
+            None,
+        )
+        .map(Some),
+        Content::Description(value) => profile_search_document(
+            event,
+            crate::opensearch::PROFILE_DESCRIPTIONS_INDEX,
+            None,
+            Some(value.clone()),
+        )
+        .map(Some),
+        _ => Ok(None),
+    }
+}
 
-    let content_type = *event.content_type();
-    let search_doc: Option<crate::opensearch::OpenSearchContent> =
-        if content_type == known_message_types::POST {
-            let post_content = Post::parse_from_bytes(event.content())?;
+fn profile_search_document(
+    event: &polycentric_protocol::model::event::Event,
+    index: &'static str,
+    profile_name: Option<String>,
+    profile_description: Option<String>,
+) -> ::anyhow::Result<crate::opensearch::SearchDocument> {
+    let lww_element = event.lww_element().as_ref().ok_or_else(|| {
+        ::anyhow::anyhow!("LWW Element missing in profile_search_document")
+    })?;
+
+    // Profile documents are keyed by system so that each system has exactly
+    // one document per index, matching what `get_search` expects.
+    let doc_id =
+        polycentric_protocol::model::public_key::to_base64(event.system())?;
+
+    let doc = crate::opensearch::OpenSearchSearchDocumentProfile {
+        author_public_key: doc_id.clone(),
+        writer_id: base64::encode_config(
+            event.process().bytes(),
+            base64::URL_SAFE,
+        ),
+        sequence_number: i64::try_from(*event.logical_clock())?,
+        profile_name,
+        profile_description,
+        unix_milliseconds: lww_element.unix_milliseconds,
+    };
+
+    Ok(crate::opensearch::SearchDocument {
+        index,
+        id: doc_id,
+        body: ::serde_json::to_value(&doc)?,
+        version: Some(lww_element.unix_milliseconds),
+    })
+}
 
-            let first_byte_reference_b64: Option<String> = event.references().iter().find_map(|r_enum| {
-            if let polycentric_protocol::model::reference::Reference::Bytes(bytes_vec) = r_enum {
-                Some(base64::encode_config(bytes_vec, base64::URL_SAFE_NO_PAD))
-            } else {
-                None
-            }
-        });
+// Indexes a single event right away. Ingest goes through the outbox instead,
+// see `search_indexer`.
+pub(crate) async fn ingest_event_search(
+    search: &::opensearch::OpenSearch,
+    layers: &polycentric_protocol::model::EventLayers,
+) -> ::anyhow::Result<()> {
+    match search_document(layers)? {
+        Some(document) => index_search_document(search, &document).await,
+        None => Ok(()),
+    }
+}
 
-            Some(crate::opensearch::OpenSearchContent {
-                message_content: post_content.content.unwrap_or_default(),
-                unix_milliseconds: *event.unix_milliseconds(),
-                byte_reference: first_byte_reference_b64,
-            })
-        } else if content_type == known_message_types::USERNAME {
-            let lww_element = event.lww_element().clone().ok_or_else(|| {
-            ::anyhow::anyhow!("LWW Element missing for USERNAME content type in ingest_event_search")
-        })?;
-            Some(crate::opensearch::OpenSearchContent {
-                message_content: String::from_utf8(lww_element.value)?,
-                unix_milliseconds: Some(lww_element.unix_milliseconds),
-                byte_reference: None,
-            })
-        } else if content_type == known_message_types::DESCRIPTION {
-            let lww_element = event.lww_element().clone().ok_or_else(|| {
-            ::anyhow::anyhow!("LWW Element missing for DESCRIPTION content type in ingest_event_search")
-        })?;
-            Some(crate::opensearch::OpenSearchContent {
-                message_content: String::from_utf8(lww_element.value)?,
-                unix_milliseconds: Some(lww_element.unix_milliseconds),
-                byte_reference: None,
-            })
-        } else {
-            None
-        };
-
-    if let Some(doc) = search_doc {
-        let response_result = search
-            .index(IndexParts::IndexId("messages", &doc_id))
-            .body(&doc)
-            .send()
-            .await;
-
-        match response_result {
-            Ok(response) => {
-                let status = response.status_code();
-                if !status.is_success() {
-                    let response_body =
-                        response.text().await.unwrap_or_else(|e| {
-                            format!("Failed to get response text: {}", e)
-                        });
-                    error!(
-                        "Failed to index document {} into OpenSearch: status: {}, body: {}",
-                        doc_id,
-                        status,
-                        response_body
-                    );
-                    return Err(::anyhow::anyhow!(
-                        "OpenSearch indexing failed for document {}: status {}, body: {}",
-                        doc_id,
-                        status,
-                        response_body
-                    ));
-                } else {
-                    debug!("Indexed document {} into OpenSearch", doc_id);
-                }
-            }
-            Err(e) => {
+// When a version is provided the document is only written if the version is
+// at least as new as the one already stored.
+async fn index_search_document(
+    search: &::opensearch::OpenSearch,
+    document: &crate::opensearch::SearchDocument,
+) -> ::anyhow::Result<()> {
+    let index = document.index;
+    let doc_id = &document.id;
+    let version = document.version;
+
+    let mut request = search
+        .index(IndexParts::IndexId(index, doc_id))
+        .body(&document.body);
+
+    if let Some(version) = version {
+        request = request
+            .version(i64::try_from(version)?)
+            .version_type(::opensearch::params::VersionType::ExternalGte);
+    }
+
+    match request.send().await {
+        Ok(response) => {
+            let status = response.status_code();
+            if version.is_some()
+                && status == ::opensearch::http::StatusCode::CONFLICT
+            {
+                debug!(
+                    "Skipped indexing stale document {} into {}",
+                    doc_id, index
+                );
+            } else if !status.is_success() {
+                let response_body = response.text().await.unwrap_or_else(|e| {
+                    format!("Failed to get response text: {}", e)
+                });
                 error!(
-                    "Error sending document {} to OpenSearch: {}",
-                    doc_id, e
+                    "Failed to index document {} into OpenSearch: status: {}, body: {}",
+                    doc_id,
+                    status,
+                    response_body
                 );
                 return Err(::anyhow::anyhow!(
-                    "OpenSearch communication error for document {}: {}",
+                    "OpenSearch indexing failed for document {}: status {}, body: {}",
                     doc_id,
-                    e
+                    status,
+                    response_body
                 ));
+            } else {
+                debug!("Indexed document {} into {}", doc_id, index);
             }
         }
+        Err(e) => {
+            error!("Error sending document {} to OpenSearch: {}", doc_id, e);
+            return Err(::anyhow::anyhow!(
+                "OpenSearch communication error for document {}: {}",
+                doc_id,
+                e
+            ));
+        }
     }
 
     Ok(())
@@ -488,6 +551,36 @@ async fn ingest_event_postgres_batch_transaction(
     Ok(())
 }
 
+// Events received from a peer server. Invalid events are skipped rather than
+// failing the whole batch, and the peer's moderation tags are not trusted so
+// events are moderated again locally.
+pub(crate) fn verify_remote_events(
+    protos: &[polycentric_protocol::protocol::SignedEvent],
+) -> ::std::vec::Vec<SignedEvent> {
+    let mut result = vec![];
+
+    for proto in protos {
+        let verified =
+            polycentric_protocol::model::signed_event::from_proto(proto)
+                .and_then(|mut signed_event| {
+                    signed_event.set_moderation_tags(vec![]);
+                    polycentric_protocol::model::EventLayers::new(
+                        signed_event.clone(),
+                    )?;
+                    Ok(signed_event)
+                });
+
+        match verified {
+            Ok(signed_event) => result.push(signed_event),
+            Err(err) => {
+                warn!("skipping invalid remote event: {}", err);
+            }
+        }
+    }
+
+    result
+}
+
 fn construct_event_batch(
     signed_events: ::std::vec::Vec<
         polycentric_protocol::model::signed_event::SignedEvent,
@@ -9,15 +9,21 @@ use config::ModerationMode;
 use envconfig::Envconfig;
 use polycentric_protocol::model;
 
+mod admin;
 mod cache;
+mod censorship;
 mod config;
 mod cursor;
 mod handlers;
+mod harbor;
 mod ingest;
 mod migrate;
 mod moderation;
 mod opensearch;
 mod postgres;
+mod reconcile;
+mod search_indexer;
+mod stream;
 mod version;
 use config::{Config, Mode};
 
@@ -60,11 +66,18 @@ struct State {
     admin_token: String,
     statsd_client: ::cadence::StatsdClient,
     challenge_key: String,
+    challenge_ttl: ::std::time::Duration,
+    report_review_threshold: u64,
     ingest_cache: ::std::sync::Mutex<
         ::lru::LruCache<polycentric_protocol::model::InsecurePointer, ()>,
     >,
     moderation_mode: ModerationMode,
+    moderation_policy: crate::moderation::policy::ModerationPolicy,
     cache_provider: Option<Box<dyn cache::providers::interface::CacheProvider>>,
+    reserved_handles: ::std::collections::HashSet<String>,
+    stream: ::tokio::sync::broadcast::Sender<
+        ::std::sync::Arc<crate::stream::StreamedEvent>,
+    >,
 }
 
 async fn handler_404(path: ::warp::path::FullPath) -> ::warp::reply::Response {
@@ -97,10 +110,23 @@ async fn handle_rejection(
     ))
 }
 
-async fn serve_api(
+fn make_statsd_client(
+    config: &Config,
+) -> Result<StatsdClient, Box<dyn ::std::error::Error>> {
+    info!("Connecting to StatsD");
+
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_nonblocking(true)?;
+    let host = (config.statsd_address.to_owned(), config.statsd_port);
+    let sink = UdpMetricSink::from(host, socket)?;
+
+    Ok(StatsdClient::from_sink("polycentric-server", sink))
+}
+
+async fn make_state(
     config: &Config,
     pool: &::sqlx::PgPool,
-) -> Result<(), Box<dyn ::std::error::Error>> {
+) -> Result<::std::sync::Arc<State>, Box<dyn ::std::error::Error>> {
     info!("Connecting to Postgres");
 
     let pool_read_only = ::sqlx::postgres::PgPoolOptions::new()
@@ -123,13 +149,7 @@ async fn serve_api(
     info!("Connecting to OpenSearch");
     crate::opensearch::prepare_indices(&opensearch_client).await?;
 
-    info!("Connecting to StatsD");
-
-    let socket = UdpSocket::bind("0.0.0.0:0")?;
-    socket.set_nonblocking(true)?;
-    let host = (config.statsd_address.to_owned(), config.statsd_port);
-    let sink = UdpMetricSink::from(host, socket)?;
-    let statsd_client = StatsdClient::from_sink("polycentric-server", sink);
+    let statsd_client = make_statsd_client(config)?;
 
     let ingest_cache = ::std::sync::Mutex::new(::lru::LruCache::new(
         core::num::NonZeroUsize::new(1000).context("expected NonZeroUSize")?,
@@ -137,17 +157,75 @@ async fn serve_api(
 
     let cache_provider = cache::providers::make_provider(config)?;
 
-    let state = ::std::sync::Arc::new(State {
+    let (stream_sender, _) = ::tokio::sync::broadcast::channel(1024);
+
+    Ok(::std::sync::Arc::new(State {
         pool: pool.clone(),
         pool_read_only,
         search: opensearch_client,
         admin_token: config.admin_token.clone(),
         challenge_key: config.challenge_key.clone(),
+        challenge_ttl: ::std::time::Duration::from_secs(
+            config.challenge_ttl_seconds,
+        ),
+        report_review_threshold: config.report_review_threshold,
         statsd_client,
         ingest_cache,
         moderation_mode: config.moderation_mode,
+        moderation_policy: crate::moderation::policy::load_stored(pool)
+            .await?,
         cache_provider: Some(cache_provider),
-    });
+        reserved_handles: config
+            .reserved_handles
+            .split(',')
+            .map(|handle| handle.trim().to_lowercase())
+            .filter(|handle| !handle.is_empty())
+            .collect(),
+        stream: stream_sender,
+    }))
+}
+
+#[cfg(test)]
+fn make_test_state(
+    pool: &::sqlx::PgPool,
+    search: ::opensearch::OpenSearch,
+) -> ::anyhow::Result<::std::sync::Arc<State>> {
+    let (stream_sender, _) = ::tokio::sync::broadcast::channel(1024);
+
+    Ok(::std::sync::Arc::new(State {
+        pool: pool.clone(),
+        pool_read_only: pool.clone(),
+        search,
+        admin_token: String::from("admin"),
+        statsd_client: StatsdClient::from_sink(
+            "polycentric-server",
+            ::cadence::NopMetricSink,
+        ),
+        challenge_key: String::from("challenge"),
+        challenge_ttl: ::std::time::Duration::from_secs(60),
+        report_review_threshold: 1,
+        ingest_cache: ::std::sync::Mutex::new(::lru::LruCache::new(
+            core::num::NonZeroUsize::new(1000)
+                .context("expected NonZeroUSize")?,
+        )),
+        moderation_mode: ModerationMode::Off,
+        moderation_policy: Default::default(),
+        cache_provider: None,
+        reserved_handles: ::std::collections::HashSet::new(),
+        stream: stream_sender,
+    }))
+}
+
+async fn serve_api(
+    config: &Config,
+    pool: &::sqlx::PgPool,
+) -> Result<(), Box<dyn ::std::error::Error>> {
+    let state = make_state(config, pool).await?;
+
+    ::tokio::spawn(crate::stream::run_listener(
+        pool.clone(),
+        state.stream.clone(),
+    ));
 
     let cors = ::warp::cors()
         .allow_any_origin()
@@ -156,8 +234,12 @@ async fn serve_api(
         .allow_methods(&[
             ::warp::http::Method::POST,
             ::warp::http::Method::GET,
+            ::warp::http::Method::DELETE,
         ]);
 
+    let indexer_state = state.clone();
+    let purge_state = state.clone();
+
     let state_filter = ::warp::any().map(move || state.clone());
 
     let route_post_events = ::warp::post()
@@ -174,57 +256,85 @@ async fn serve_api(
     let route_get_head = ::warp::get()
         .and(::warp::path("head"))
         .and(::warp::path::end())
-        .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_head::Query>())
-        .and_then(crate::handlers::get_head::handler)
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_head::Query>())
+                .and_then(crate::handlers::get_head::handler),
+        ))
         .with(cors.clone());
 
     let route_get_query_latest = ::warp::get()
         .and(::warp::path("query_latest"))
         .and(::warp::path::end())
-        .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_query_latest::Query>())
-        .and_then(crate::handlers::get_query_latest::handler)
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_query_latest::Query>())
+                .and_then(crate::handlers::get_query_latest::handler),
+        ))
         .with(cors.clone());
 
     let route_get_query_index = ::warp::get()
         .and(::warp::path("query_index"))
         .and(::warp::path::end())
-        .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_query_index::Query>())
-        .and_then(crate::handlers::get_query_index::handler)
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_query_index::Query>())
+                .and_then(crate::handlers::get_query_index::handler),
+        ))
         .with(cors.clone());
 
     let route_get_query_references = ::warp::get()
         .and(::warp::path("query_references"))
         .and(::warp::path::end())
-        .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_query_references::Query>())
-        .and_then(crate::handlers::get_query_references::handler)
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_query_references::Query>())
+                .and_then(crate::handlers::get_query_references::handler),
+        ))
         .with(cors.clone());
 
     let route_get_events = ::warp::get()
         .and(::warp::path("events"))
         .and(::warp::path::end())
-        .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_events::Query>())
-        .and_then(crate::handlers::get_events::handler)
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_events::Query>())
+                .and_then(crate::handlers::get_events::handler),
+        ))
         .with(cors.clone());
 
     let route_get_claim_to_system = ::warp::get()
         .and(::warp::path("resolve_claim"))
         .and(::warp::path::end())
-        .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_claim_to_system::Query>())
-        .and_then(crate::handlers::get_claim_to_system::handler)
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_claim_to_system::Query>())
+                .and_then(crate::handlers::get_claim_to_system::handler),
+        ))
         .with(cors.clone());
 
     let route_get_ranges = ::warp::get()
         .and(::warp::path("ranges"))
         .and(::warp::path::end())
-        .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_ranges::Query>())
-        .and_then(crate::handlers::get_ranges::handler)
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_ranges::Query>())
+                .and_then(crate::handlers::get_ranges::handler),
+        ))
         .with(cors.clone());
 
     let route_get_search = ::warp::get()
@@ -248,9 +358,13 @@ async fn serve_api(
     let route_get_explore = ::warp::get()
         .and(::warp::path("explore"))
         .and(::warp::path::end())
-        .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_explore::Query>())
-        .and_then(crate::handlers::get_explore::handler)
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_explore::Query>())
+                .and_then(crate::handlers::get_explore::handler),
+        ))
         .with(cors.clone());
 
     let route_get_recommended_profiles = ::warp::get()
@@ -276,6 +390,14 @@ async fn serve_api(
         .then(crate::handlers::get_version::handler)
         .with(cors.clone());
 
+    let route_get_moderation_policy = ::warp::get()
+        .and(::warp::path("moderation"))
+        .and(::warp::path("policy"))
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .then(crate::handlers::get_moderation_policy::handler)
+        .with(cors.clone());
+
     let route_post_censor = ::warp::post()
         .and(::warp::path("censor"))
         .and(::warp::path::end())
@@ -286,14 +408,139 @@ async fn serve_api(
         .and_then(crate::handlers::post_censor::handler)
         .with(cors.clone());
 
-    let route_get_find_claim_and_vouch = ::warp::get()
-        .and(::warp::path("find_claim_and_vouch"))
+    let route_get_censorship = ::warp::get()
+        .and(::warp::path("censorship"))
+        .and(::warp::path::param::<
+            crate::handlers::get_censorship::CensorshipKind,
+        >())
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and(::warp::query::<crate::handlers::get_censorship::Query>())
+        .and_then(crate::handlers::get_censorship::handler)
+        .with(cors.clone());
+
+    let route_get_censorship_entry = ::warp::get()
+        .and(::warp::path("censorship"))
+        .and(::warp::path::param::<
+            crate::handlers::get_censorship::CensorshipKind,
+        >())
+        .and(::warp::path::param::<u64>())
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and_then(crate::handlers::get_censorship_entry::handler)
+        .with(cors.clone());
+
+    let route_delete_censorship = ::warp::delete()
+        .and(::warp::path("censorship"))
+        .and(::warp::path::param::<
+            crate::handlers::get_censorship::CensorshipKind,
+        >())
+        .and(::warp::path::param::<u64>())
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and_then(crate::handlers::delete_censorship::handler)
+        .with(cors.clone());
+
+    let route_delete_handle = ::warp::delete()
+        .and(::warp::path("handles"))
+        .and(::warp::path::param::<String>())
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and_then(crate::handlers::delete_handle::handler)
+        .with(cors.clone());
+
+    let route_get_audit_log = ::warp::get()
+        .and(::warp::path("admin"))
+        .and(::warp::path("audit_log"))
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and(::warp::query::<crate::handlers::get_audit_log::Query>())
+        .and_then(crate::handlers::get_audit_log::handler)
+        .with(cors.clone());
+
+    let route_get_moderation_review = ::warp::get()
+        .and(::warp::path("moderation"))
+        .and(::warp::path("review"))
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and(::warp::query::<crate::handlers::get_moderation_review::Query>())
+        .and_then(crate::handlers::get_moderation_review::handler)
+        .with(cors.clone());
+
+    let route_get_moderation_reports = ::warp::get()
+        .and(::warp::path("moderation"))
+        .and(::warp::path("reports"))
         .and(::warp::path::end())
         .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and(::warp::query::<crate::handlers::get_moderation_reports::Query>())
+        .and_then(crate::handlers::get_moderation_reports::handler)
+        .with(cors.clone());
+
+    let route_get_moderation_shadow_report = ::warp::get()
+        .and(::warp::path("moderation"))
+        .and(::warp::path("shadow"))
+        .and(::warp::path("report"))
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
         .and(::warp::query::<
-            crate::handlers::get_find_claim_and_vouch::Query,
+            crate::handlers::get_moderation_shadow_report::Query,
+        >())
+        .and_then(crate::handlers::get_moderation_shadow_report::handler)
+        .with(cors.clone());
+
+    let route_get_moderation_review_item = ::warp::get()
+        .and(::warp::path("moderation"))
+        .and(::warp::path("review"))
+        .and(::warp::path::param::<i64>())
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and_then(crate::handlers::get_moderation_review_item::handler)
+        .with(cors.clone());
+
+    let route_post_moderation_review = ::warp::post()
+        .and(::warp::path("moderation"))
+        .and(::warp::path("review"))
+        .and(::warp::path::param::<i64>())
+        .and(::warp::path::param::<
+            crate::moderation::review::ReviewAction,
         >())
-        .and_then(crate::handlers::get_find_claim_and_vouch::handler)
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and(::warp::body::bytes())
+        .and_then(crate::handlers::post_moderation_review::handler)
+        .with(cors.clone());
+
+    let route_get_export = ::warp::get()
+        .and(::warp::path("export"))
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::header::<String>("authorization"))
+        .and(::warp::query::<crate::handlers::get_export::Query>())
+        .and_then(crate::handlers::get_export::handler)
+        .with(cors.clone());
+
+    let route_get_find_claim_and_vouch = ::warp::get()
+        .and(::warp::path("find_claim_and_vouch"))
+        .and(::warp::path::end())
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<
+                    crate::handlers::get_find_claim_and_vouch::Query,
+                >())
+                .and_then(crate::handlers::get_find_claim_and_vouch::handler),
+        ))
         .with(cors.clone());
 
     let route_get_challenge = ::warp::get()
@@ -319,12 +566,33 @@ async fn serve_api(
         .and_then(crate::handlers::post_claim_handle::handler)
         .with(cors.clone());
 
+    let route_post_report = ::warp::post()
+        .and(::warp::path("report"))
+        .and(::warp::path::end())
+        .and(state_filter.clone())
+        .and(::warp::body::bytes())
+        .and_then(crate::handlers::post_report::handler)
+        .with(cors.clone());
+
     let route_get_resolve_handle = ::warp::get()
         .and(::warp::path("resolve_handle"))
         .and(::warp::path::end())
+        .and(crate::cache::routes::cached(
+            state_filter.clone(),
+            state_filter
+                .clone()
+                .and(::warp::query::<crate::handlers::get_resolve_handle::Query>())
+                .and_then(crate::handlers::get_resolve_handle::handler),
+        ))
+        .with(cors.clone());
+
+    let route_get_stream = ::warp::get()
+        .and(::warp::path("stream"))
+        .and(::warp::path::end())
         .and(state_filter.clone())
-        .and(::warp::query::<crate::handlers::get_resolve_handle::Query>())
-        .and_then(crate::handlers::get_resolve_handle::handler)
+        .and(::warp::query::<crate::handlers::get_stream::Query>())
+        .and(::warp::ws())
+        .and_then(crate::handlers::get_stream::handler)
         .with(cors.clone());
 
     let route_404 = ::warp::any()
@@ -346,15 +614,67 @@ async fn serve_api(
         .or(route_get_recommended_profiles)
         .or(route_get_health)
         .or(route_get_version)
+        .or(route_get_moderation_policy)
         .or(route_post_censor)
+        .or(route_get_censorship)
+        .or(route_get_censorship_entry)
+        .or(route_delete_censorship)
+        .or(route_delete_handle)
+        .or(route_get_audit_log)
+        .or(route_get_moderation_review)
+        .or(route_get_moderation_review_item)
+        .or(route_post_moderation_review)
+        .or(route_get_moderation_shadow_report)
+        .or(route_get_moderation_reports)
+        .or(route_get_export)
         .or(route_get_find_claim_and_vouch)
         .or(route_get_challenge)
         .or(route_post_purge)
         .or(route_post_claim_handle)
+        .or(route_post_report)
         .or(route_get_resolve_handle)
+        .or(route_get_stream)
         .or(route_404)
         .recover(handle_rejection);
 
+    ::tokio::spawn(crate::harbor::run_garbage_collection(
+        pool.clone(),
+        ::std::time::Duration::from_secs(config.challenge_ttl_seconds),
+    ));
+
+    ::tokio::spawn(crate::censorship::run_expiry_sweep(
+        pool.clone(),
+        ::std::time::Duration::from_secs(
+            config.censorship_sweep_interval_seconds,
+        ),
+    ));
+
+    ::tokio::spawn(crate::search_indexer::run(
+        indexer_state,
+        crate::search_indexer::Options {
+            interval: ::std::time::Duration::from_secs(
+                config.search_indexer_interval_seconds,
+            ),
+            batch_size: config.search_indexer_batch_size,
+            max_backoff: ::std::time::Duration::from_secs(
+                config.search_indexer_max_backoff_seconds,
+            ),
+        },
+    ));
+
+    ::tokio::spawn(crate::cache::purge_queue::run(
+        purge_state,
+        crate::cache::purge_queue::Options {
+            interval: ::std::time::Duration::from_secs(
+                config.cache_purge_interval_seconds,
+            ),
+            batch_size: config.cache_purge_batch_size,
+            max_backoff: ::std::time::Duration::from_secs(
+                config.cache_purge_max_backoff_seconds,
+            ),
+        },
+    ));
+
     info!("API server listening on {}", config.http_port_api);
     ::warp::serve(routes)
         .run(([0, 0, 0, 0], config.http_port_api))
@@ -381,6 +701,24 @@ async fn run_moderation_queue(
     } else {
         Some(moderation::providers::tags::make_provider(config).await?)
     };
+    let shadow_tag_provider =
+        moderation::providers::tags::make_shadow_provider(config).await?;
+
+    let result_cache = if config.moderation_cache_ttl_seconds == 0 {
+        None
+    } else {
+        let result_cache = moderation::result_cache::ResultCache::new(
+            pool.clone(),
+            ::std::time::Duration::from_secs(
+                config.moderation_cache_ttl_seconds,
+            ),
+            config.tag_interface.clone(),
+            config.csam_interface.clone(),
+        );
+        let invalidated = result_cache.invalidate().await?;
+        info!("Dropped {} stale moderation cache entries", invalidated);
+        Some(result_cache)
+    };
 
     if tag_provider.is_none() {
         error!(
@@ -394,15 +732,41 @@ async fn run_moderation_queue(
 
     let pool_clone = pool.clone();
     let tagging_request_rate_limit = config.tagging_request_rate_limit;
+    let shadow_tag_interface = config.shadow_tag_interface.clone();
+    let shadow_tagging_request_rate_limit =
+        config.shadow_tagging_request_rate_limit;
     let csam_request_rate_limiter = config.csam_request_rate_limit;
+    let workers = config.moderation_workers;
+    let lease_duration =
+        ::std::time::Duration::from_secs(config.moderation_lease_seconds);
+    let max_retries = config.moderation_max_retries;
+    let retry_delay =
+        ::std::time::Duration::from_secs(config.moderation_retry_delay_seconds);
     let task = tokio::task::spawn({
         async move {
+            let shadow = match (&shadow_tag_interface, &shadow_tag_provider) {
+                (Some(name), Some(provider)) => {
+                    Some(moderation::shadow::ShadowProvider {
+                        name,
+                        provider: provider.as_ref(),
+                    })
+                }
+                _ => None,
+            };
+
             moderation::moderation_queue::run(
                 pool_clone,
                 csam_provider.as_deref(),
                 tag_provider.as_deref(),
+                result_cache.as_ref(),
+                shadow,
                 tagging_request_rate_limit,
+                shadow_tagging_request_rate_limit,
                 csam_request_rate_limiter,
+                workers,
+                lease_duration,
+                max_retries,
+                retry_delay,
             )
             .await
         }
@@ -449,6 +813,7 @@ async fn main() -> Result<(), Box<dyn ::std::error::Error>> {
             crate::postgres::prepare_database(&mut transaction).await?;
 
             crate::migrate::migrate(&mut transaction).await?;
+
             transaction.commit().await?;
 
             let no_interface = config.csam_interface.is_none()
@@ -493,6 +858,7 @@ async fn main() -> Result<(), Box<dyn ::std::error::Error>> {
                 ::opensearch::OpenSearch::new(opensearch_transport);
 
             info!("Connecting to OpenSearch");
+            crate::opensearch::prepare_indices(&opensearch_client).await?;
 
             crate::migrate::backfill_search(pool, opensearch_client).await?;
         }
@@ -518,6 +884,211 @@ async fn main() -> Result<(), Box<dyn ::std::error::Error>> {
             )
             .await?;
         }
+        Mode::PullRemoteServer => {
+            info!("mode: PullRemoteServer");
+
+            let address = config
+                .pull_remote_server_address
+                .clone()
+                .context("PULL_REMOTE_SERVER_ADDRESS required")?;
+
+            let token = config
+                .pull_remote_server_token
+                .clone()
+                .context("PULL_REMOTE_SERVER_TOKEN required")?;
+
+            let pool = ::sqlx::postgres::PgPoolOptions::new()
+                .max_connections(10)
+                .connect(&config.postgres_string)
+                .await?;
+
+            let mut transaction = pool.begin().await?;
+
+            crate::postgres::prepare_database(&mut transaction).await?;
+
+            crate::migrate::migrate(&mut transaction).await?;
+            transaction.commit().await?;
+
+            let state = make_state(&config, &pool).await?;
+
+            crate::migrate::pull_remote_server(state, address, token).await?;
+        }
+        Mode::Reconcile => {
+            info!("mode: Reconcile");
+
+            let pool = ::sqlx::postgres::PgPoolOptions::new()
+                .max_connections(10)
+                .connect(&config.postgres_string)
+                .await?;
+
+            let mut transaction = pool.begin().await?;
+
+            crate::postgres::prepare_database(&mut transaction).await?;
+
+            crate::migrate::migrate(&mut transaction).await?;
+            transaction.commit().await?;
+
+            let state = make_state(&config, &pool).await?;
+
+            let servers = config
+                .reconcile_servers
+                .split(',')
+                .map(|server| server.trim().to_string())
+                .filter(|server| !server.is_empty())
+                .collect();
+
+            let allowed_peer_hosts = config
+                .reconcile_allowed_peer_hosts
+                .split(',')
+                .map(|host| host.trim().to_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect();
+
+            crate::reconcile::run(
+                state,
+                servers,
+                allowed_peer_hosts,
+                config.reconcile_self_address.clone(),
+            )
+            .await?;
+        }
+        Mode::ReconcileSearch => {
+            info!("mode: ReconcileSearch");
+
+            info!("Connecting to Postgres");
+            let pool = ::sqlx::postgres::PgPoolOptions::new()
+                .max_connections(10)
+                .connect(&config.postgres_string)
+                .await?;
+
+            let opensearch_transport =
+                ::opensearch::http::transport::Transport::single_node(
+                    &config.opensearch_string,
+                )?;
+
+            let opensearch_client =
+                ::opensearch::OpenSearch::new(opensearch_transport);
+
+            crate::search_indexer::reconcile(
+                pool,
+                opensearch_client,
+                config.search_indexer_batch_size,
+            )
+            .await?;
+        }
+        Mode::CreateAdminToken | Mode::RevokeAdminToken => {
+            let name = config
+                .admin_token_name
+                .clone()
+                .context("ADMIN_TOKEN_NAME required")?;
+
+            let pool = ::sqlx::postgres::PgPoolOptions::new()
+                .max_connections(1)
+                .connect(&config.postgres_string)
+                .await?;
+
+            let mut transaction = pool.begin().await?;
+
+            crate::postgres::prepare_database(&mut transaction).await?;
+
+            crate::migrate::migrate(&mut transaction).await?;
+            transaction.commit().await?;
+
+            if let Mode::CreateAdminToken = config.mode {
+                info!("mode: CreateAdminToken");
+
+                let scopes =
+                    crate::admin::parse_scopes(&config.admin_token_scopes)?;
+
+                let token =
+                    crate::admin::create_token(&pool, &name, &scopes).await?;
+
+                println!("{}", token);
+            } else {
+                info!("mode: RevokeAdminToken");
+
+                crate::admin::revoke_token(&pool, &name).await?;
+            }
+        }
+        Mode::Remoderate => {
+            info!("mode: Remoderate");
+
+            let content_types = config
+                .remoderate_content_types
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    crate::moderation::policy::content_type_from_name(name)
+                        .map(i64::try_from)
+                        .context(format!("unknown content type {}", name))?
+                        .context("content type out of range")
+                })
+                .collect::<::anyhow::Result<::std::vec::Vec<i64>>>()?;
+
+            let tag_provider =
+                moderation::providers::tags::make_provider(&config).await?;
+
+            let pool = ::sqlx::postgres::PgPoolOptions::new()
+                .max_connections(10)
+                .connect(&config.postgres_string)
+                .await?;
+
+            let mut transaction = pool.begin().await?;
+
+            crate::postgres::prepare_database(&mut transaction).await?;
+
+            crate::migrate::migrate(&mut transaction).await?;
+            transaction.commit().await?;
+
+            crate::moderation::remoderate::run(
+                pool,
+                tag_provider.as_ref(),
+                config.tagging_request_rate_limit,
+                make_statsd_client(&config)?,
+                crate::moderation::remoderate::RemoderateOptions {
+                    job: config.remoderate_job.clone(),
+                    content_types,
+                    since: config.remoderate_since,
+                    until: config.remoderate_until,
+                    tag: config.remoderate_tag.clone(),
+                    min_level: config.remoderate_min_level,
+                    position: config.remoderate_position,
+                    batch_size: config.remoderate_batch_size,
+                },
+            )
+            .await?;
+        }
+        Mode::StoreModerationPolicy => {
+            info!("mode: StoreModerationPolicy");
+
+            let policy = crate::moderation::policy::ModerationPolicy::load(
+                Some(
+                    config
+                        .moderation_policy_path
+                        .as_deref()
+                        .context("MODERATION_POLICY_PATH required")?,
+                ),
+            )?;
+
+            let pool = ::sqlx::postgres::PgPoolOptions::new()
+                .max_connections(1)
+                .connect(&config.postgres_string)
+                .await?;
+
+            let mut transaction = pool.begin().await?;
+
+            crate::postgres::prepare_database(&mut transaction).await?;
+
+            crate::migrate::migrate(&mut transaction).await?;
+
+            crate::moderation::policy::store(&mut transaction, &policy)
+                .await?;
+
+            transaction.commit().await?;
+
+            info!("stored moderation policy version {}", policy.version());
+        }
     }
 
     Ok(())
@@ -4,6 +4,7 @@ use ::log::*;
 use ::protobuf::Message;
 
 const PAGE_SIZE: u64 = 20;
+const PULL_PAGE_SIZE: u64 = 500;
 
 async fn load_version(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
@@ -77,10 +78,8 @@ async fn migration_1_compute_reference_counts(
                 signed_event.event(),
             )?;
 
-            let content = polycentric_protocol::model::content::decode_content(
-                *event.content_type(),
-                event.content(),
-            )?;
+            let content =
+                polycentric_protocol::model::content::from_event(&event)?;
 
             crate::postgres::update_counts::update_counts(
                 transaction,
@@ -116,6 +115,181 @@ async fn migration_2_add_moderation_tags_cols(
     Ok(())
 }
 
+#[derive(::sqlx::FromRow)]
+struct HandleRow {
+    handle: String,
+    system_key_type: i64,
+    system_key: ::std::vec::Vec<u8>,
+}
+
+async fn migration_3_case_insensitive_handles(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+) -> ::anyhow::Result<()> {
+    ::log::info!("running migration_3_case_insensitive_handles");
+
+    // Handles that only differ by case were previously allowed. Which one
+    // to keep is not recorded, so an operator has to decide and release the
+    // others before uniqueness can be enforced.
+    let collisions = ::sqlx::query_as::<_, HandleRow>(
+        "
+        SELECT handle, system_key_type, system_key
+        FROM identity_handles
+        WHERE LOWER(handle) IN (
+            SELECT LOWER(handle)
+            FROM identity_handles
+            GROUP BY LOWER(handle)
+            HAVING COUNT(*) > 1
+        )
+        ORDER BY LOWER(handle), handle;
+        ",
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+
+    for collision in collisions.iter() {
+        ::log::error!(
+            "handle {} of {} differs from another only by case",
+            collision.handle,
+            polycentric_protocol::model::public_key::to_base64(
+                &polycentric_protocol::model::public_key::from_type_and_bytes(
+                    u64::try_from(collision.system_key_type)?,
+                    &collision.system_key,
+                )?,
+            )?,
+        );
+    }
+
+    if !collisions.is_empty() {
+        ::anyhow::bail!(
+            "{} handles differ from another only by case, release all but \
+             one of each before upgrading",
+            collisions.len()
+        );
+    }
+
+    ::sqlx::query(
+        "
+        CREATE UNIQUE INDEX IF NOT EXISTS identity_handles_lower_handle_idx
+        ON identity_handles (LOWER(handle));
+        ",
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+async fn migration_4_moderation_queue_leases(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+) -> ::anyhow::Result<()> {
+    ::log::info!("running migration_4_moderation_queue_leases");
+
+    ::sqlx::query(
+        "
+        ALTER TABLE event_processing_status
+        ADD COLUMN IF NOT EXISTS lease_owner TEXT,
+        ADD COLUMN IF NOT EXISTS lease_expires_at TIMESTAMP WITH TIME ZONE,
+        ADD COLUMN IF NOT EXISTS dead_lettered_at TIMESTAMP WITH TIME ZONE;
+        ",
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    ::sqlx::query(
+        "
+        CREATE INDEX IF NOT EXISTS event_processing_status_lease_expires_at_idx
+        ON event_processing_status (lease_expires_at);
+        ",
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    ::sqlx::query(
+        "
+        CREATE INDEX IF NOT EXISTS event_processing_status_dead_lettered_at_idx
+        ON event_processing_status (dead_lettered_at)
+        WHERE dead_lettered_at IS NOT NULL;
+        ",
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+async fn migration_5_censorship_metadata(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+) -> ::anyhow::Result<()> {
+    ::log::info!("running migration_5_censorship_metadata");
+
+    for table in ["censored_events", "censored_systems"] {
+        ::sqlx::query(&format!(
+            "
+            ALTER TABLE {table}
+            ADD COLUMN IF NOT EXISTS reason TEXT,
+            ADD COLUMN IF NOT EXISTS censored_by TEXT,
+            ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ,
+            ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ
+                NOT NULL DEFAULT NOW(),
+            ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ
+                NOT NULL DEFAULT NOW();
+            "
+        ))
+        .execute(&mut **transaction)
+        .await?;
+
+        ::sqlx::query(&format!(
+            "
+            CREATE INDEX IF NOT EXISTS {table}_expires_at_idx
+            ON {table} (expires_at)
+            WHERE expires_at IS NOT NULL;
+            "
+        ))
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// The moderation queue index was replaced by idx_moderation_queue_pending when
+// usernames, claims and banners became moderated. Those stored before then
+// are approved as they are, rather than hidden in strong moderation mode
+// until the queue gets through all of them.
+async fn migration_6_moderate_profile_content(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+) -> ::anyhow::Result<()> {
+    ::log::info!("running migration_6_moderate_profile_content");
+
+    ::sqlx::query("DROP INDEX IF EXISTS idx_moderation_queue_filter;")
+        .execute(&mut **transaction)
+        .await?;
+
+    ::sqlx::query(
+        "
+        UPDATE events
+        SET moderation_status = 'approved',
+            moderation_tags = COALESCE(moderation_tags, '{}')
+        WHERE content_type = ANY($1)
+        AND moderation_status = 'unprocessed';
+        ",
+    )
+    .bind(
+        [
+            polycentric_protocol::model::known_message_types::USERNAME,
+            polycentric_protocol::model::known_message_types::CLAIM,
+            polycentric_protocol::model::known_message_types::BANNER,
+        ]
+        .iter()
+        .map(|content_type| i64::try_from(*content_type))
+        .collect::<Result<::std::vec::Vec<i64>, _>>()?,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
 pub(crate) async fn migrate(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
 ) -> ::anyhow::Result<()> {
@@ -131,7 +305,15 @@ pub(crate) async fn migrate(
             1 => {
                 migration_2_add_moderation_tags_cols(&mut *transaction).await?
             }
-            2 => break,
+            2 => {
+                migration_3_case_insensitive_handles(&mut *transaction).await?
+            }
+            3 => migration_4_moderation_queue_leases(&mut *transaction).await?,
+            4 => migration_5_censorship_metadata(&mut *transaction).await?,
+            5 => {
+                migration_6_moderate_profile_content(&mut *transaction).await?
+            }
+            6 => break,
             _ => ::anyhow::bail!("schema too new for this server version"),
         }
 
@@ -178,11 +360,11 @@ pub(crate) async fn backfill_search(
 
         let num_events_in_batch = db_result.events.len() as u64;
         for signed_event in db_result.events {
-            crate::ingest::ingest_event_search(
-                &search,
-                &polycentric_protocol::model::EventLayers::new(signed_event)?,
-            )
-            .await?;
+            let layers = crate::postgres::stored_event_layers(signed_event)?;
+
+            remove_profile_from_messages_index(&search, &layers).await?;
+
+            crate::ingest::ingest_event_search(&search, &layers).await?;
         }
         total_processed += num_events_in_batch;
     }
@@ -194,6 +376,49 @@ pub(crate) async fn backfill_search(
     Ok(())
 }
 
+// Older servers indexed USERNAME and DESCRIPTION events into the messages
+// index. Those now live in the profile indices.
+async fn remove_profile_from_messages_index(
+    search: &::opensearch::OpenSearch,
+    layers: &polycentric_protocol::model::EventLayers,
+) -> ::anyhow::Result<()> {
+    if !matches!(
+        layers.content(),
+        polycentric_protocol::model::content::Content::Username(_)
+            | polycentric_protocol::model::content::Content::Description(_)
+    ) {
+        return Ok(());
+    }
+
+    let pointer = polycentric_protocol::model::pointer::from_signed_event(
+        layers.signed_event(),
+    )?;
+
+    let doc_id = polycentric_protocol::model::pointer::to_base64(&pointer)?;
+
+    let response = search
+        .delete(::opensearch::DeleteParts::IndexId(
+            crate::opensearch::MESSAGES_INDEX,
+            &doc_id,
+        ))
+        .send()
+        .await?;
+
+    let status = response.status_code();
+
+    if !status.is_success()
+        && status != ::opensearch::http::StatusCode::NOT_FOUND
+    {
+        ::anyhow::bail!(
+            "failed to remove profile document {} from messages: {}",
+            doc_id,
+            status
+        );
+    }
+
+    Ok(())
+}
+
 pub(crate) async fn backfill_remote_server(
     pool: ::sqlx::PgPool,
     address: String,
@@ -333,3 +558,278 @@ pub(crate) async fn backfill_remote_server(
 
     Ok(())
 }
+
+async fn fetch_export_page(
+    client: &::reqwest::Client,
+    address: &str,
+    token: &str,
+    after: u64,
+) -> ::anyhow::Result<
+    polycentric_protocol::protocol::ResultEventsAndRelatedEventsAndCursor,
+> {
+    let op = || async {
+        let result = client
+            .get(format!("{}/export", address))
+            .query(&[("after", after), ("limit", PULL_PAGE_SIZE)])
+            .header("authorization", token)
+            .send()
+            .await;
+
+        match result {
+            Ok(response) => {
+                if response.status() == ::reqwest::StatusCode::UNAUTHORIZED
+                    || response.status() == ::reqwest::StatusCode::BAD_REQUEST
+                {
+                    Err(::backoff::Error::permanent(::anyhow::Error::msg(
+                        response.status().to_string(),
+                    )))
+                } else if response.status() != ::reqwest::StatusCode::OK {
+                    ::log::warn!(
+                        "temporary failure with status {:?}",
+                        response.status(),
+                    );
+
+                    Err(::backoff::Error::transient(::anyhow::Error::msg(
+                        "bad code",
+                    )))
+                } else {
+                    response.bytes().await.map_err(|err| {
+                        ::backoff::Error::transient(::anyhow::Error::from(err))
+                    })
+                }
+            }
+            Err(err) => {
+                Err(::backoff::Error::transient(::anyhow::Error::from(err)))
+            }
+        }
+    };
+
+    let bytes =
+        ::backoff::future::retry(::backoff::ExponentialBackoff::default(), op)
+            .await?;
+
+    Ok(
+        polycentric_protocol::protocol::ResultEventsAndRelatedEventsAndCursor::parse_from_tokio_bytes(
+            &bytes,
+        )?,
+    )
+}
+
+// Continuously mirrors a peer by paging through its export feed. The peer's
+// position is stored after each page so a restart resumes where it left off.
+// A page that fails to ingest as a whole is ingested one event at a time, and
+// the events that still fail are skipped so that the cursor moves on.
+async fn ingest_pulled_events(
+    state: &::std::sync::Arc<crate::State>,
+    user_agent: &Option<String>,
+    events: ::std::vec::Vec<
+        polycentric_protocol::model::signed_event::SignedEvent,
+    >,
+) {
+    let err = match crate::ingest::ingest_event_batch(
+        state,
+        user_agent,
+        events.clone(),
+    )
+    .await
+    {
+        Ok(_) => return,
+        Err(err) => err,
+    };
+
+    warn!("failed to ingest page, retrying event by event: {}", err);
+
+    for event in events {
+        let pointer =
+            polycentric_protocol::model::pointer::from_signed_event(&event)
+                .ok()
+                .and_then(|pointer| {
+                    polycentric_protocol::model::pointer::to_base64(&pointer)
+                        .ok()
+                });
+
+        if let Err(err) =
+            crate::ingest::ingest_event_batch(state, user_agent, vec![event])
+                .await
+        {
+            warn!("skipping event {:?}: {}", pointer, err);
+        }
+    }
+}
+
+pub(crate) async fn pull_remote_server(
+    state: ::std::sync::Arc<crate::State>,
+    address: String,
+    token: String,
+) -> ::anyhow::Result<()> {
+    let client = ::reqwest::Client::new();
+
+    {
+        let response = client.get(address.clone() + "/version").send().await?;
+
+        if response.status() != ::reqwest::StatusCode::OK {
+            ::anyhow::bail!("invalid server");
+        }
+    }
+
+    let mut position = {
+        let mut transaction = state.pool.begin().await?;
+
+        let position = crate::postgres::load_remote_server_cursor(
+            &mut transaction,
+            &address,
+        )
+        .await?
+        .unwrap_or(0);
+
+        transaction.commit().await?;
+
+        position
+    };
+
+    info!("Pulling from {} at position {}", address, position);
+
+    let user_agent = Some(String::from("pull_remote_server"));
+
+    loop {
+        let page =
+            fetch_export_page(&client, &address, &token, position).await?;
+
+        let next_position = match page.cursor {
+            Some(ref cursor) => {
+                u64::try_from(ExploreCursor::from_bytes(cursor)?.id)?
+            }
+            None => {
+                ::log::debug!("caught up with {}, waiting", address);
+
+                ::tokio::time::sleep(::tokio::time::Duration::from_millis(
+                    5000,
+                ))
+                .await;
+
+                continue;
+            }
+        };
+
+        let events = crate::ingest::verify_remote_events(
+            page.result_events
+                .as_ref()
+                .map(|result_events| result_events.events.as_slice())
+                .unwrap_or_default(),
+        );
+
+        let count = events.len();
+
+        if !events.is_empty() {
+            ingest_pulled_events(&state, &user_agent, events).await;
+        }
+
+        let mut transaction = state.pool.begin().await?;
+        crate::postgres::upsert_remote_server_cursor(
+            &mut transaction,
+            &address,
+            next_position,
+        )
+        .await?;
+        transaction.commit().await?;
+
+        position = next_position;
+
+        info!("Pulled {} events, new position: {}", count, position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[::sqlx::test]
+    async fn test_handle_collisions_stop_migration(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        // As left by a server that compared handles case sensitively
+        ::sqlx::query("DROP INDEX identity_handles_lower_handle_idx;")
+            .execute(&mut *transaction)
+            .await?;
+
+        let mut systems = vec![];
+        for handle in ["alice", "Alice"] {
+            let system =
+                polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                    polycentric_protocol::test_utils::make_test_keypair()
+                        .verifying_key(),
+                );
+
+            ::sqlx::query(
+                "
+                INSERT INTO identity_handles (
+                    handle,
+                    system_key_type,
+                    system_key
+                )
+                VALUES ($1, 1, $2);
+                ",
+            )
+            .bind(handle)
+            .bind(polycentric_protocol::model::public_key::get_key_bytes(
+                &system,
+            ))
+            .execute(&mut *transaction)
+            .await?;
+
+            systems.push(system);
+        }
+
+        ::sqlx::query("UPDATE schema_version SET version = 2;")
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        let mut transaction = pool.begin().await?;
+        assert!(super::migrate(&mut transaction).await.is_err());
+        transaction.rollback().await?;
+
+        // Both are kept until an operator decides
+        let mut transaction = pool.begin().await?;
+        assert_eq!(
+            ::sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM identity_handles;"
+            )
+            .fetch_one(&mut *transaction)
+            .await?,
+            2
+        );
+
+        ::sqlx::query("DELETE FROM identity_handles WHERE handle = 'Alice';")
+            .execute(&mut *transaction)
+            .await?;
+
+        super::migrate(&mut transaction).await?;
+
+        assert_eq!(
+            crate::postgres::resolve_handle(
+                &mut transaction,
+                String::from("ALICE")
+            )
+            .await?,
+            systems[0]
+        );
+
+        assert!(::sqlx::query(
+            "
+            INSERT INTO identity_handles (handle, system_key_type, system_key)
+            VALUES ('ALICE', 1, $1);
+            ",
+        )
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            &systems[1],
+        ))
+        .execute(&mut *transaction)
+        .await
+        .is_err());
+
+        Ok(())
+    }
+}
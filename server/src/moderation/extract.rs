@@ -0,0 +1,152 @@
+use polycentric_protocol::model::{content::Content, known_message_types};
+
+// The content types `extract` handles, the only ones that are moderated, by
+// the names moderation policies use for them. Queries and schema.sql take
+// the list from `moderated_content_types_sql`.
+pub(crate) const MODERATED_CONTENT_TYPES: [(&str, u64); 6] = [
+    ("post", known_message_types::POST),
+    ("username", known_message_types::USERNAME),
+    ("description", known_message_types::DESCRIPTION),
+    ("avatar", known_message_types::AVATAR),
+    ("claim", known_message_types::CLAIM),
+    ("banner", known_message_types::BANNER),
+];
+
+// For `content_type IN (...)`, as a literal so that partial indexes on it
+// are used.
+pub(crate) fn moderated_content_types_sql() -> String {
+    MODERATED_CONTENT_TYPES
+        .iter()
+        .map(|(_, content_type)| content_type.to_string())
+        .collect::<::std::vec::Vec<_>>()
+        .join(", ")
+}
+
+// What a moderation provider needs to see of an event.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Moderatable {
+    pub(crate) text: Option<String>,
+    pub(crate) images:
+        ::std::vec::Vec<polycentric_protocol::protocol::ImageManifest>,
+}
+
+impl Moderatable {
+    fn text(text: &str) -> Self {
+        Moderatable {
+            text: non_empty(text),
+            images: vec![],
+        }
+    }
+
+    fn images(bundle: &polycentric_protocol::protocol::ImageBundle) -> Self {
+        Moderatable {
+            text: None,
+            images: bundle.image_manifests.clone(),
+        }
+    }
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+// Every resolution of an image bundle is returned, the image size limits of
+// the queue decide which ones are sent to providers.
+pub(crate) fn extract(content: &Content) -> Moderatable {
+    match content {
+        Content::Post(post) => Moderatable {
+            text: post.content().as_deref().and_then(non_empty),
+            images: post.image().iter().cloned().collect(),
+        },
+        Content::Username(text) | Content::Description(text) => {
+            Moderatable::text(text)
+        }
+        Content::Avatar(bundle) | Content::Banner(bundle) => {
+            Moderatable::images(bundle)
+        }
+        Content::Claim(claim) => Moderatable::text(
+            &claim
+                .claim_fields()
+                .iter()
+                .map(|field| field.value.as_str())
+                .collect::<::std::vec::Vec<&str>>()
+                .join("\n"),
+        ),
+        _ => Moderatable::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moderated_content_types_sql() {
+        assert_eq!(moderated_content_types_sql(), "3, 5, 6, 9, 12, 13");
+    }
+
+    fn manifest(width: u64) -> polycentric_protocol::protocol::ImageManifest {
+        let mut manifest = polycentric_protocol::protocol::ImageManifest::new();
+        manifest.width = width;
+        manifest.height = width;
+        manifest.process = ::protobuf::MessageField::some(
+            polycentric_protocol::model::process::to_proto(
+                &polycentric_protocol::model::process::Process::new([1; 16]),
+            ),
+        );
+        manifest
+    }
+
+    #[test]
+    fn test_extract_text() {
+        assert_eq!(
+            extract(&Content::Username("  ".to_string())),
+            Moderatable::default()
+        );
+        assert_eq!(
+            extract(&Content::Description("hello".to_string())).text,
+            Some("hello".to_string())
+        );
+
+        let mut first = polycentric_protocol::protocol::ClaimFieldEntry::new();
+        first.key = 1;
+        first.value = "alice".to_string();
+        let mut second = polycentric_protocol::protocol::ClaimFieldEntry::new();
+        second.key = 2;
+        second.value = "example.com".to_string();
+
+        let claim =
+            polycentric_protocol::model::claim::Claim::new(1, &[first, second]);
+
+        assert_eq!(
+            extract(&Content::Claim(claim)).text,
+            Some("alice\nexample.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_images() {
+        let mut bundle = polycentric_protocol::protocol::ImageBundle::new();
+        bundle.image_manifests = vec![manifest(32), manifest(256)];
+
+        assert_eq!(extract(&Content::Banner(bundle.clone())).images.len(), 2);
+        assert_eq!(extract(&Content::Avatar(bundle)).images.len(), 2);
+
+        let mut post = polycentric_protocol::protocol::Post::new();
+        post.content = Some("caption".to_string());
+        post.image = Some(manifest(512)).into();
+
+        let moderatable = extract(&Content::Post(
+            polycentric_protocol::model::post::from_proto(&post).unwrap(),
+        ));
+
+        assert_eq!(moderatable.text, Some("caption".to_string()));
+        assert_eq!(moderatable.images, vec![manifest(512)]);
+
+        assert_eq!(extract(&Content::Vouch), Moderatable::default());
+    }
+}
@@ -1,11 +1,18 @@
 use crate::{config::ModerationMode, model::moderation_tag::ModerationTagName};
 
+pub mod extract;
 pub mod moderation_queue;
+pub mod policy;
 pub mod providers;
+pub mod remoderate;
+pub mod reports;
+pub mod result_cache;
+pub mod review;
+pub mod shadow;
 
 #[derive(::sqlx::Type)]
 #[sqlx(type_name = "moderation_filter_type")]
-#[derive(::serde::Deserialize, ::serde::Serialize, Debug, Clone)]
+#[derive(::serde::Deserialize, ::serde::Serialize, Debug, Clone, PartialEq)]
 pub(crate) struct ModerationFilter {
     name: ModerationTagName,
     max_level: i16,
@@ -44,28 +51,6 @@ where
     }
 }
 
-impl Default for ModerationFilters {
-    fn default() -> Self {
-        ModerationFilters(vec![
-            ModerationFilter {
-                name: ModerationTagName::new(String::from("violence")),
-                max_level: 1,
-                strict_mode: true,
-            },
-            ModerationFilter {
-                name: ModerationTagName::new(String::from("hate")),
-                max_level: 2,
-                strict_mode: true,
-            },
-            ModerationFilter {
-                name: ModerationTagName::new(String::from("sexual")),
-                max_level: 1,
-                strict_mode: true,
-            },
-        ])
-    }
-}
-
 impl ModerationFilters {
     pub fn empty() -> Self {
         ModerationFilters(vec![])
@@ -78,19 +63,11 @@ pub(crate) struct ModerationOptions {
 }
 
 impl ModerationOptions {
-    pub fn get_filters_with_defaults(&self) -> ModerationFilters {
-        match self.filters.clone() {
-            Some(mut filters) => {
-                // Add in missing filters from default
-                for default_filter in ModerationFilters::default().0 {
-                    if !filters.0.iter().any(|f| f.name == default_filter.name)
-                    {
-                        filters.0.push(default_filter);
-                    }
-                }
-                filters
-            }
-            None => ModerationFilters::default(),
-        }
+    // Filters for tags the client did not mention come from the moderation
+    // policy of each content type, see `policy`.
+    pub fn get_filters(&self) -> ModerationFilters {
+        self.filters
+            .clone()
+            .unwrap_or_else(ModerationFilters::empty)
     }
 }
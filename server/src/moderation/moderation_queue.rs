@@ -1,7 +1,6 @@
 use crate::moderation::providers;
 use futures::stream::{self, StreamExt};
-use log::debug;
-use protobuf::Message;
+use log::{debug, warn};
 use std::sync::Arc;
 use tokio::sync::Semaphore;
 use tokio::time::{self, Duration};
@@ -10,13 +9,13 @@ use super::providers::csam::interface::ModerationCSAMResult;
 use super::providers::tags::interface::ModerationTaggingResult;
 
 #[allow(dead_code)]
-struct RateLimiter {
+pub(super) struct RateLimiter {
     semaphore: Arc<Semaphore>,
     tokens_per_second: u16,
 }
 
 impl RateLimiter {
-    fn new(max_tokens: u16, tokens_per_second: u16) -> Self {
+    pub(super) fn new(max_tokens: u16, tokens_per_second: u16) -> Self {
         debug!("Creating new RateLimiter with max_tokens: {}, tokens_per_second: {}", max_tokens, tokens_per_second);
         let semaphore = Arc::new(Semaphore::new(max_tokens as usize));
         let rate_limiter = RateLimiter {
@@ -51,9 +50,11 @@ struct ModerationQueueRawRow {
     raw_event: Vec<u8>,
 }
 
-#[derive(sqlx::Type, Debug, PartialEq)]
+#[derive(sqlx::Type, Debug, PartialEq, Clone, Copy)]
 #[sqlx(type_name = "moderation_status_enum", rename_all = "snake_case")]
-enum ModerationStatus {
+#[derive(::serde::Deserialize, ::serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ModerationStatus {
     Unprocessed,
     Processing,
     Approved,
@@ -67,74 +68,115 @@ pub struct ModerationQueueItem {
     pub content: Option<String>,
     pub blob: Option<Vec<u8>>,
     pub blob_db_ids: Option<Vec<i64>>,
+    // Images after the first, such as the other resolutions of an avatar or
+    // banner. Providers see one image at a time, see `parts`.
+    pub extra_images: Vec<ModerationImage>,
+    // An assembled image is on `blocked_content_hashes`, the item is rejected
+    // as CSAM without asking the providers
+    pub blocked: bool,
 }
 
-async fn get_blob(
+#[derive(Clone)]
+pub struct ModerationImage {
+    pub blob: Vec<u8>,
+    pub blob_db_ids: Vec<i64>,
+}
+
+impl ModerationQueueItem {
+    // Splits an item into one item per image. The text is moderated once,
+    // along with the first image.
+    fn parts(&self) -> Vec<ModerationQueueItem> {
+        let mut parts = vec![ModerationQueueItem {
+            id: self.id,
+            content: self.content.clone(),
+            blob: self.blob.clone(),
+            blob_db_ids: self.blob_db_ids.clone(),
+            extra_images: vec![],
+            blocked: self.blocked,
+        }];
+
+        for image in self.extra_images.iter() {
+            parts.push(ModerationQueueItem {
+                id: self.id,
+                content: None,
+                blob: Some(image.blob.clone()),
+                blob_db_ids: Some(image.blob_db_ids.clone()),
+                extra_images: vec![],
+                blocked: self.blocked,
+            });
+        }
+
+        parts
+    }
+}
+
+// Loads the blob behind an image manifest if it is worth sending to the
+// providers.
+async fn load_image(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: i64,
     event: &crate::model::event::Event,
-    post: &polycentric_protocol::protocol::Post,
-) -> ::anyhow::Result<(Vec<u8>, Vec<i64>)> {
-    debug!("Getting blob for event");
-    let mut logical_clocks = vec![];
-    // Check if post has image field (single image support)
-    if let Some(image_manifest) = post.image.as_ref() {
-        for range in image_manifest.sections.iter() {
-            let start = range.low;
-            let end = range.high;
-            for i in start..=end {
-                logical_clocks.push(i);
-            }
-        }
+    manifest: &polycentric_protocol::protocol::ImageManifest,
+) -> ::anyhow::Result<Option<ModerationImage>> {
+    if manifest.width < 50 || manifest.height < 50 {
+        debug!(
+            "Skipping image of event {} - image too small ({}x{} pixels)",
+            event_id, manifest.width, manifest.height
+        );
+        return Ok(None);
     }
 
-    let query = "
-    SELECT content, id
-    FROM events
-    WHERE system_key_type = $1
-    AND system_key = $2
-    AND process = $3
-    AND logical_clock = ANY($4)
-    ORDER BY logical_clock ASC
-    ";
+    // Frontend should downscale to 1000px, but allow some buffer for safety
+    if manifest.width > 2000 || manifest.height > 2000 {
+        debug!(
+            "Skipping image of event {} - image too large ({}x{} pixels)",
+            event_id, manifest.width, manifest.height
+        );
+        return Ok(None);
+    }
 
-    let system = event.system();
-    let system_key_type =
-        i64::try_from(crate::model::public_key::get_key_type(system))?;
-    let system_key_bytes = crate::model::public_key::get_key_bytes(system);
-    let process_bytes = event.process().bytes();
-    let logical_clock_array: Vec<i64> = logical_clocks
-        .into_iter()
-        .map(|lc| i64::try_from(lc).unwrap())
-        .collect();
+    if manifest.sections.is_empty() {
+        debug!("Skipping image of event {} - no image sections", event_id);
+        return Ok(None);
+    }
 
-    let rows: Vec<(Vec<u8>, i64)> = sqlx::query_as(query)
-        .bind(system_key_type)
-        .bind(system_key_bytes)
-        .bind(process_bytes)
-        .bind(&logical_clock_array)
-        .fetch_all(&mut **transaction)
-        .await?;
+    let (blob, blob_db_ids) =
+        get_blob_by_logical_clocks(transaction, event, manifest).await?;
 
-    // concat the sorted event.content() into a single buffer
-    let mut blob = Vec::new();
-    let mut blob_db_ids = Vec::new();
-    for (row, id) in rows.iter() {
-        blob.extend_from_slice(row);
-        blob_db_ids.push(*id);
+    // Skip if the blob is empty or too large for Azure
+    if blob.is_empty() || blob.len() > 4 * 1024 * 1024 {
+        debug!(
+            "Skipping image of event {} - blob empty or too large ({} bytes)",
+            event_id,
+            blob.len()
+        );
+        return Ok(None);
     }
 
     debug!(
-        "Blob retrieved successfully for event: {:?}",
-        event.logical_clock()
+        "Event {} has valid blob: {} bytes ({}x{} pixels)",
+        event_id,
+        blob.len(),
+        manifest.width,
+        manifest.height
     );
-    Ok((blob, blob_db_ids))
+
+    Ok(Some(ModerationImage { blob, blob_db_ids }))
 }
 
-async fn get_blob_by_logical_clocks(
+// Blob sections are published by the process named in the image manifest,
+// which is usually but not necessarily the process of the referencing event.
+pub(super) async fn get_blob_by_logical_clocks(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
     event: &crate::model::event::Event,
-    logical_clocks: &[u64],
+    manifest: &polycentric_protocol::protocol::ImageManifest,
 ) -> ::anyhow::Result<(Vec<u8>, Vec<i64>)> {
+    let logical_clocks: Vec<u64> = manifest
+        .sections
+        .iter()
+        .flat_map(|range| range.low..=range.high)
+        .collect();
+
     if logical_clocks.is_empty() {
         return Ok((Vec::new(), Vec::new()));
     }
@@ -149,11 +191,16 @@ async fn get_blob_by_logical_clocks(
         ORDER BY logical_clock ASC
     ";
 
+    let process = match manifest.process.as_ref() {
+        Some(process) => crate::model::process::from_proto(process)?,
+        None => event.process().clone(),
+    };
+
     let system = event.system();
     let system_key_type =
         i64::try_from(crate::model::public_key::get_key_type(system))?;
     let system_key_bytes = crate::model::public_key::get_key_bytes(system);
-    let process_bytes = event.process().bytes();
+    let process_bytes = process.bytes();
     let logical_clock_array: Vec<i64> = logical_clocks
         .iter()
         .map(|lc| i64::try_from(*lc).unwrap())
@@ -176,34 +223,165 @@ async fn get_blob_by_logical_clocks(
     Ok((blob, blob_db_ids))
 }
 
-async fn pull_queue_events(
+// Identifies one worker's claim on queue items. Several workers, in this
+// process or others, can share a database because each item is leased to a
+// single worker until its results are applied or the lease expires.
+pub struct QueueOptions {
+    pub worker_id: String,
+    pub lease_duration: Duration,
+    pub max_retries: u32,
+    // A failed item waits this long before its first retry, doubling with
+    // every further failure
+    pub retry_delay: Duration,
+}
+
+impl QueueOptions {
+    pub fn new(
+        lease_duration: Duration,
+        max_retries: u32,
+        retry_delay: Duration,
+    ) -> Self {
+        QueueOptions {
+            worker_id: format!(
+                "{}-{:016x}",
+                std::process::id(),
+                rand::random::<u64>()
+            ),
+            lease_duration,
+            max_retries,
+            retry_delay,
+        }
+    }
+}
+
+// Gathers the text and images of an event for the providers.
+pub(super) async fn make_queue_item(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: i64,
+    raw_event: &[u8],
+) -> ::anyhow::Result<ModerationQueueItem> {
+    let signed_event = crate::model::signed_event::from_vec(raw_event)?;
+    let event = crate::model::event::from_vec(signed_event.event())?;
+
+    use polycentric_protocol::model::content::Content;
+
+    let content = match crate::model::content::from_event(&event) {
+        Ok(content) => content,
+        Err(err) => {
+            debug!("Failed to decode content for event {}: {}", id, err);
+            Content::Unknown(*event.content_type(), vec![])
+        }
+    };
+
+    let moderatable = super::extract::extract(&content);
+
+    let mut images = vec![];
+    for manifest in moderatable.images.iter() {
+        if let Some(image) =
+            load_image(transaction, id, &event, manifest).await?
+        {
+            images.push(image);
+        }
+    }
+
+    // Ingest only refuses blocked sections, the same image split into other
+    // sections is caught once it is assembled
+    let mut blocked = false;
+    for image in images.iter() {
+        blocked |= super::result_cache::is_blocked(
+            transaction,
+            &super::result_cache::sha256(&image.blob),
+        )
+        .await?;
+    }
+
+    // Events with nothing to moderate still produce an item so that they
+    // are approved rather than left leased
+    debug!(
+        "Creating queue item: id={}, content_type={}, has_content={}, images={}",
+        id,
+        event.content_type(),
+        moderatable.text.is_some(),
+        images.len()
+    );
+
+    let mut images = images.into_iter();
+    let first = images.next();
+
+    Ok(ModerationQueueItem {
+        id,
+        content: moderatable.text,
+        blob_db_ids: first.as_ref().map(|image| image.blob_db_ids.clone()),
+        blob: first.map(|image| image.blob),
+        extra_images: images.collect(),
+        blocked,
+    })
+}
+
+pub(super) async fn pull_queue_events(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    options: &QueueOptions,
 ) -> ::anyhow::Result<Vec<ModerationQueueItem>> {
     debug!("Pulling queue events");
 
-    let query = "
-    SELECT 
+    // Items held by another worker are either locked right now, which SKIP
+    // LOCKED passes over, or in processing with a lease that has not expired.
+    // A processing item whose lease has lapsed belonged to a worker that
+    // crashed or stalled and is reclaimed. Failed items back off so that a
+    // provider that is briefly unavailable does not use up their retries.
+    // Events reported by users, or whose system was reported, go first.
+    // Approved events escalated by reports are tagged again without leaving
+    // the approved state.
+    let query = format!(
+        "
+    SELECT
         e.id,
-        e.raw_event,
-        COALESCE(eps.failure_count, 0) as failure_count,
-        EXTRACT(EPOCH FROM (CURRENT_TIMESTAMP - COALESCE(eps.last_failure_at, '1970-01-01'::timestamp))) as time_since_last_failure
+        e.raw_event
     FROM events e
-    LEFT JOIN LATERAL (
-        SELECT event_id, failure_count, last_failure_at
-        FROM event_processing_status
-        WHERE event_id = e.id
-        AND failure_count < 3
-    ) eps ON true
-    WHERE e.content_type IN (3, 6, 9)
-    AND e.moderation_status IN ('unprocessed'::moderation_status_enum, 'error'::moderation_status_enum)
-    ORDER BY 
-        CASE WHEN e.moderation_status = 'unprocessed'::moderation_status_enum THEN 0 ELSE 1 END,
-        COALESCE(eps.failure_count, 0) DESC
+    LEFT JOIN event_processing_status eps ON eps.event_id = e.id
+    LEFT JOIN moderation_escalations esc ON esc.event_id = e.id
+    LEFT JOIN moderation_event_report_counts er ON er.event_id = e.id
+    LEFT JOIN moderation_system_report_counts sr
+        ON sr.system_key_type = e.system_key_type
+        AND sr.system_key = e.system_key
+    WHERE e.content_type IN ({content_types})
+    AND (
+        e.moderation_status IN ('unprocessed'::moderation_status_enum, 'error'::moderation_status_enum)
+        OR (
+            e.moderation_status = 'processing'::moderation_status_enum
+            AND (eps.lease_expires_at IS NULL OR eps.lease_expires_at < CURRENT_TIMESTAMP)
+        )
+        OR (
+            e.moderation_status = 'approved'::moderation_status_enum
+            AND esc.recheck
+            AND (eps.lease_expires_at IS NULL OR eps.lease_expires_at < CURRENT_TIMESTAMP)
+        )
+    )
+    AND eps.dead_lettered_at IS NULL
+    AND (
+        eps.last_failure_at IS NULL
+        OR eps.last_failure_at < CURRENT_TIMESTAMP - make_interval(
+            secs => $1 * POWER(2, LEAST(GREATEST(eps.failure_count - 1, 0), 16))
+        )
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM moderation_reviews r WHERE r.event_id = e.id
+    )
+    ORDER BY
+        CASE WHEN e.moderation_status = 'error'::moderation_status_enum THEN 1 ELSE 0 END,
+        COALESCE(er.reports, 0) + COALESCE(sr.reports, 0) DESC,
+        COALESCE(eps.failure_count, 0) DESC,
+        e.id ASC
     LIMIT 20
-    ";
+    FOR UPDATE OF e SKIP LOCKED
+    ",
+        content_types = super::extract::moderated_content_types_sql(),
+    );
 
-    let candidate_rows: Vec<ModerationQueueRawRow> =
-        sqlx::query_as(query).fetch_all(&mut **transaction).await?;
+    let candidate_rows: Vec<ModerationQueueRawRow> = sqlx::query_as(&query)
+        .bind(options.retry_delay.as_secs_f64())
+        .fetch_all(&mut **transaction)
+        .await?;
 
     let candidate_ids: Vec<i64> =
         candidate_rows.iter().map(|row| row.id).collect();
@@ -212,243 +390,41 @@ async fn pull_queue_events(
         UPDATE events
         SET moderation_status = 'processing'
         WHERE id = ANY($1)
+        AND moderation_status != 'approved'
     ";
     sqlx::query(update_events_query)
         .bind(&candidate_ids)
         .execute(&mut **transaction)
         .await?;
 
-    let upsert_processing_status_query = "
-        INSERT INTO event_processing_status (event_id, processing_started_at)
-        SELECT unnest($1::bigint[]), CURRENT_TIMESTAMP
+    let lease_query = "
+        INSERT INTO event_processing_status (
+            event_id,
+            processing_started_at,
+            lease_owner,
+            lease_expires_at
+        )
+        SELECT
+            unnest($1::bigint[]),
+            CURRENT_TIMESTAMP,
+            $2,
+            CURRENT_TIMESTAMP + make_interval(secs => $3)
         ON CONFLICT (event_id) DO UPDATE
-        SET processing_started_at = CURRENT_TIMESTAMP
+        SET processing_started_at = CURRENT_TIMESTAMP,
+            lease_owner = EXCLUDED.lease_owner,
+            lease_expires_at = EXCLUDED.lease_expires_at
     ";
-    sqlx::query(upsert_processing_status_query)
+    sqlx::query(lease_query)
         .bind(&candidate_ids)
+        .bind(&options.worker_id)
+        .bind(options.lease_duration.as_secs_f64())
         .execute(&mut **transaction)
         .await?;
 
     let mut result_set = vec![];
     for row in candidate_rows.iter() {
-        let signed_event =
-            crate::model::signed_event::from_vec(&row.raw_event)?;
-        let event = crate::model::event::from_vec(signed_event.event())?;
-
-        use polycentric_protocol::model::known_message_types as ct;
-
-        match *event.content_type() {
-            ct::POST => {
-                // Standard post parsing (existing behaviour)
-                let post =
-                    polycentric_protocol::protocol::Post::parse_from_bytes(
-                        event.content(),
-                    )?;
-
-                // Retrieve the blob bytes for this post (if any), but only
-                // include them in the queue item when we actually have data.
-                let (blob, blob_db_ids) = if let Some(image_manifest) =
-                    post.image.as_ref()
-                {
-                    // Check image dimensions before processing
-                    if image_manifest.width < 50 || image_manifest.height < 50 {
-                        debug!("Skipping POST event {} - image too small ({}x{} pixels)", 
-                            row.id, image_manifest.width, image_manifest.height);
-                        (None, None)
-                    } else if image_manifest.width > 2000
-                        || image_manifest.height > 2000
-                    {
-                        // Frontend should downscale to 1000px, but allow some buffer for safety
-                        debug!("Skipping POST event {} - image too large ({}x{} pixels), frontend should downscale", 
-                            row.id, image_manifest.width, image_manifest.height);
-                        (None, None)
-                    } else if image_manifest.sections.is_empty() {
-                        debug!(
-                            "Skipping POST event {} - no image sections",
-                            row.id
-                        );
-                        (None, None)
-                    } else {
-                        let (blob, blob_db_ids) =
-                            get_blob(transaction, &event, &post).await?;
-
-                        // Skip if the blob is empty or too large for Azure
-                        if blob.is_empty() || blob.len() > 4 * 1024 * 1024 {
-                            debug!("Skipping POST event {} - blob empty or too large ({} bytes)", row.id, blob.len());
-                            (None, None)
-                        } else {
-                            debug!(
-                                "POST event {} has valid blob: {} bytes ({}x{} pixels)",
-                                row.id,
-                                blob.len(),
-                                image_manifest.width,
-                                image_manifest.height
-                            );
-                            (Some(blob), Some(blob_db_ids))
-                        }
-                    }
-                } else {
-                    (None, None)
-                };
-
-                // Only create queue item if we have content or a valid blob
-                let has_content = post.content.is_some()
-                    && !post.content.as_ref().unwrap().trim().is_empty();
-                let has_blob = blob.is_some();
-
-                if has_content || has_blob {
-                    debug!("Creating POST queue item: id={}, has_content={}, has_blob={}", row.id, has_content, has_blob);
-                    result_set.push(ModerationQueueItem {
-                        id: row.id,
-                        content: post.content,
-                        blob,
-                        blob_db_ids,
-                    });
-                } else {
-                    debug!(
-                        "Skipping POST event {} - no content or blob",
-                        row.id
-                    );
-                }
-            }
-            ct::DESCRIPTION => {
-                // DESCRIPTION uses LWW element for text
-                let text_content = event
-                    .lww_element()
-                    .as_ref()
-                    .and_then(|lww| String::from_utf8(lww.value.clone()).ok());
-
-                // Only create queue item if we have valid text content
-                if let Some(content) = text_content {
-                    if !content.trim().is_empty() {
-                        debug!("Creating DESCRIPTION queue item: id={}, content_len={}", row.id, content.len());
-                        result_set.push(ModerationQueueItem {
-                            id: row.id,
-                            content: Some(content),
-                            blob: None,
-                            blob_db_ids: None,
-                        });
-                    } else {
-                        debug!("Skipping DESCRIPTION event {} - empty content after trim", row.id);
-                    }
-                } else {
-                    debug!("Skipping DESCRIPTION event {} - no valid UTF-8 content", row.id);
-                }
-            }
-            ct::AVATAR => {
-                // Avatar references blob sections via indices with index_type == BLOB_SECTION
-                // For avatars, we need to get the ImageBundle first to find the largest resolution
-                let avatar_bundle = if let Some(lww) = event.lww_element() {
-                    match polycentric_protocol::protocol::ImageBundle::parse_from_bytes(&lww.value) {
-                        Ok(bundle) => Some(bundle),
-                        Err(e) => {
-                            debug!("Failed to parse avatar bundle for event {}: {}", row.id, e);
-                            None
-                        }
-                    }
-                } else {
-                    debug!("AVATAR event {} has no LWW element", row.id);
-                    None
-                };
-
-                let (blob, blob_db_ids) = if let Some(bundle) = avatar_bundle {
-                    // Find the best available avatar resolution (prefer 256x256, then 64x64, never 32x32)
-                    let best_manifest = bundle
-                        .image_manifests
-                        .iter()
-                        .filter(|manifest| manifest.width >= 64) // Filter out 32x32 and smaller
-                        .max_by_key(|manifest| manifest.width);
-
-                    if let Some(manifest) = best_manifest {
-                        // Check image dimensions before processing
-                        if manifest.width < 50 || manifest.height < 50 {
-                            debug!("Skipping AVATAR event {} - image too small ({}x{} pixels)", 
-                                row.id, manifest.width, manifest.height);
-                            (None, None)
-                        } else if manifest.width > 2000
-                            || manifest.height > 2000
-                        {
-                            debug!("Skipping AVATAR event {} - image too large ({}x{} pixels)", 
-                                row.id, manifest.width, manifest.height);
-                            (None, None)
-                        } else if manifest.process.as_ref().is_some() {
-                            let logical_clocks: Vec<u64> = manifest
-                                .sections
-                                .iter()
-                                .flat_map(|range| range.low..=range.high)
-                                .collect();
-
-                            if logical_clocks.is_empty() {
-                                debug!("AVATAR event {} - no logical clocks in manifest", row.id);
-                                (None, None)
-                            } else {
-                                let (blob, blob_db_ids) =
-                                    get_blob_by_logical_clocks(
-                                        transaction,
-                                        &event,
-                                        &logical_clocks,
-                                    )
-                                    .await?;
-
-                                // Skip if the blob is empty or too large for Azure
-                                if blob.is_empty()
-                                    || blob.len() > 4 * 1024 * 1024
-                                {
-                                    debug!("Skipping AVATAR event {} - blob empty or too large ({} bytes)", row.id, blob.len());
-                                    (None, None)
-                                } else {
-                                    debug!(
-                                        "AVATAR event {} has valid blob: {} bytes ({}x{} resolution)",
-                                        row.id,
-                                        blob.len(),
-                                        manifest.width,
-                                        manifest.height
-                                    );
-                                    (Some(blob), Some(blob_db_ids))
-                                }
-                            }
-                        } else {
-                            debug!(
-                                "AVATAR event {} - manifest has no process",
-                                row.id
-                            );
-                            (None, None)
-                        }
-                    } else {
-                        debug!(
-                            "AVATAR event {} - no suitable image manifests found (only 32x32 or smaller available)",
-                            row.id
-                        );
-                        (None, None)
-                    }
-                } else {
-                    debug!("AVATAR event {} - no valid avatar bundle", row.id);
-                    (None, None)
-                };
-
-                // Only create queue item if we have a valid blob
-                if blob.is_some() {
-                    debug!("Creating AVATAR queue item: id={}", row.id);
-                    result_set.push(ModerationQueueItem {
-                        id: row.id,
-                        content: None,
-                        blob,
-                        blob_db_ids,
-                    });
-                } else {
-                    debug!("Skipping AVATAR event {} - no valid blob", row.id);
-                }
-            }
-            _ => {
-                // Unsupported type – skip moderation
-                result_set.push(ModerationQueueItem {
-                    id: row.id,
-                    content: None,
-                    blob: None,
-                    blob_db_ids: None,
-                });
-            }
-        }
+        result_set
+            .push(make_queue_item(transaction, row.id, &row.raw_event).await?);
     }
 
     debug!("Queue events pulled successfully");
@@ -456,38 +432,73 @@ async fn pull_queue_events(
 }
 
 #[derive(Clone)]
-struct ModerationResult {
-    event_id: i64,
-    has_error: bool,
-    is_permanent_error: bool,
-    is_csam: bool,
-    tags: Vec<crate::model::moderation_tag::ModerationTag>,
-    blob_db_ids: Option<Vec<i64>>,
+pub(super) struct ModerationResult {
+    pub(super) event_id: i64,
+    pub(super) has_error: bool,
+    pub(super) is_permanent_error: bool,
+    pub(super) is_csam: bool,
+    pub(super) tags: Vec<crate::model::moderation_tag::ModerationTag>,
+    pub(super) blob_db_ids: Option<Vec<i64>>,
 }
 
 async fn tag_event(
     tag: &dyn providers::tags::interface::ModerationTaggingProvider,
+    cache: Option<&super::result_cache::ResultCache>,
     event: &ModerationQueueItem,
     request_rate_limiter: &RateLimiter,
 ) -> anyhow::Result<ModerationTaggingResult> {
     debug!("Tagging event: {:?}", event.id);
+
+    let digest = cache.map(|_| super::result_cache::tag_digest(event));
+
+    if let (Some(cache), Some(digest)) = (cache, &digest) {
+        if let Some(tags) = cache.get_tags(digest).await {
+            debug!("Event {}: Tags found in result cache", event.id);
+            return Ok(ModerationTaggingResult { tags });
+        }
+    }
+
     request_rate_limiter.acquire().await;
-    tag.moderate(event).await
+    let result = tag.moderate(event).await?;
+
+    if let (Some(cache), Some(digest)) = (cache, &digest) {
+        cache.put_tags(digest, &result.tags).await;
+    }
+
+    Ok(result)
 }
 
 async fn csam_detect_event(
     csam: &dyn providers::csam::interface::ModerationCSAMProvider,
+    cache: Option<&super::result_cache::ResultCache>,
     event: &ModerationQueueItem,
     request_rate_limiter: &RateLimiter,
 ) -> anyhow::Result<ModerationCSAMResult> {
     debug!("Detecting CSAM for event: {:?}", event.id);
+
+    let digest = cache.and_then(|_| super::result_cache::csam_digest(event));
+
+    if let (Some(cache), Some(digest)) = (cache, &digest) {
+        if let Some(is_csam) = cache.get_csam(digest).await {
+            debug!("Event {}: CSAM result found in result cache", event.id);
+            return Ok(ModerationCSAMResult { is_csam });
+        }
+    }
+
     request_rate_limiter.acquire().await;
-    csam.moderate(event).await
+    let result = csam.moderate(event).await?;
+
+    if let (Some(cache), Some(digest)) = (cache, &digest) {
+        cache.put_csam(digest, result.is_csam).await;
+    }
+
+    Ok(result)
 }
 
-async fn process_event(
+async fn process_part(
     csam: Option<&dyn providers::csam::interface::ModerationCSAMProvider>,
     tag: Option<&dyn providers::tags::interface::ModerationTaggingProvider>,
+    cache: Option<&super::result_cache::ResultCache>,
     event: &ModerationQueueItem,
     request_rate_limiter: &RateLimiter,
     csam_request_rate_limiter: &RateLimiter,
@@ -508,9 +519,11 @@ async fn process_event(
     let (tagging_result, csam_result) = match (tag, should_csam) {
         (Some(tag), true) => {
             debug!("Event {}: Running both tagging and CSAM", event.id);
-            let tagging_future = tag_event(tag, event, request_rate_limiter);
+            let tagging_future =
+                tag_event(tag, cache, event, request_rate_limiter);
             let csam_future = csam_detect_event(
                 csam.unwrap(),
+                cache,
                 event,
                 csam_request_rate_limiter,
             );
@@ -521,7 +534,7 @@ async fn process_event(
         (Some(tag), false) => {
             debug!("Event {}: Running tagging only", event.id);
             (
-                Some(tag_event(tag, event, request_rate_limiter).await),
+                Some(tag_event(tag, cache, event, request_rate_limiter).await),
                 None,
             )
         }
@@ -532,6 +545,7 @@ async fn process_event(
                 Some(
                     csam_detect_event(
                         csam.unwrap(),
+                        cache,
                         event,
                         csam_request_rate_limiter,
                     )
@@ -547,6 +561,7 @@ async fn process_event(
 
     let mut has_error = false;
     let mut is_permanent_error = false;
+    let mut has_transient_error = false;
 
     let is_csam = match csam_result {
         Some(ref result) => match result {
@@ -554,6 +569,11 @@ async fn process_event(
             Err(e) => {
                 debug!("CSAM error for event: {:?}, error: {:?}", event.id, e);
                 has_error = true;
+                if providers::is_permanent_error(e) {
+                    is_permanent_error = true;
+                } else {
+                    has_transient_error = true;
+                }
                 false
             }
         },
@@ -578,18 +598,11 @@ async fn process_event(
                     );
                     has_error = true;
 
-                    // Check if this is a permanent error (Azure InvalidRequestBody, etc.)
-                    let error_msg = e.to_string().to_lowercase();
-                    if error_msg.contains("permanent azure error")
-                        || error_msg.contains("invalidrequestbody")
-                        || error_msg.contains("invalidimageformat")
-                        || error_msg.contains("invalidimagesize")
-                        || error_msg.contains("notsupportedimage")
-                        || error_msg.contains("width of given image is")
-                        || error_msg.contains("height of given image is")
-                    {
+                    if providers::is_permanent_error(e) {
                         is_permanent_error = true;
                         debug!("Event {}: Permanent error detected, will not retry", event.id);
+                    } else {
+                        has_transient_error = true;
                     }
 
                     Vec::new()
@@ -599,6 +612,9 @@ async fn process_event(
         None => Vec::new(),
     };
 
+    // Only give up on the item when no failure could succeed on a retry
+    let is_permanent_error = is_permanent_error && !has_transient_error;
+
     debug!(
         "Event {} processed: has_error={}, is_permanent_error={}, is_csam={}, tags_count={}",
         event.id,
@@ -617,27 +633,229 @@ async fn process_event(
     }
 }
 
+pub(super) async fn process_event(
+    csam: Option<&dyn providers::csam::interface::ModerationCSAMProvider>,
+    tag: Option<&dyn providers::tags::interface::ModerationTaggingProvider>,
+    cache: Option<&super::result_cache::ResultCache>,
+    event: &ModerationQueueItem,
+    request_rate_limiter: &RateLimiter,
+    csam_request_rate_limiter: &RateLimiter,
+) -> ModerationResult {
+    if event.blocked {
+        debug!("Event {}: Image is a blocked hash, rejecting", event.id);
+
+        return merge_results(
+            event.id,
+            event
+                .parts()
+                .into_iter()
+                .map(|part| ModerationResult {
+                    event_id: event.id,
+                    has_error: false,
+                    is_permanent_error: false,
+                    is_csam: true,
+                    tags: vec![],
+                    blob_db_ids: part.blob_db_ids,
+                })
+                .collect(),
+        );
+    }
+
+    let mut results = vec![];
+    for part in event.parts() {
+        results.push(
+            process_part(
+                csam,
+                tag,
+                cache,
+                &part,
+                request_rate_limiter,
+                csam_request_rate_limiter,
+            )
+            .await,
+        );
+    }
+
+    merge_results(event.id, results)
+}
+
+// Events are handed to the shadow provider once the primary results are
+// applied. The queue is bounded and events are dropped while it is full, so
+// a slow shadow provider never holds up moderation.
+const SHADOW_QUEUE_SIZE: usize = 100;
+
+struct ShadowJob {
+    event: ModerationQueueItem,
+    primary_error: bool,
+    primary_tags: Vec<crate::model::moderation_tag::ModerationTag>,
+}
+
+fn submit_shadow_jobs(
+    sender: &tokio::sync::mpsc::Sender<ShadowJob>,
+    events: Vec<ModerationQueueItem>,
+    results: &[ModerationResult],
+) {
+    for (event, result) in events.into_iter().zip(results.iter()) {
+        let job = ShadowJob {
+            event,
+            primary_error: result.has_error,
+            primary_tags: result.tags.clone(),
+        };
+
+        if sender.try_send(job).is_err() {
+            debug!("Shadow queue full, skipping event {}", result.event_id);
+        }
+    }
+}
+
+// The shadow provider sees the same parts as the primary one, under its own
+// rate limit, and never uses the result cache. Its result is only recorded,
+// see `shadow`.
+async fn shadow_event(
+    pool: &::sqlx::PgPool,
+    shadow: super::shadow::ShadowProvider<'_>,
+    job: ShadowJob,
+    shadow_request_rate_limiter: &RateLimiter,
+) -> ::anyhow::Result<()> {
+    let mut results = vec![];
+    for part in job.event.parts() {
+        results.push(
+            process_part(
+                None,
+                Some(shadow.provider),
+                None,
+                &part,
+                shadow_request_rate_limiter,
+                shadow_request_rate_limiter,
+            )
+            .await,
+        );
+    }
+
+    let merged = merge_results(job.event.id, results);
+
+    let mut transaction = pool.begin().await?;
+    super::shadow::record(
+        &mut transaction,
+        job.event.id,
+        job.primary_error,
+        &job.primary_tags,
+        &super::shadow::ShadowResult {
+            provider: shadow.name.to_string(),
+            has_error: merged.has_error,
+            tags: merged.tags,
+        },
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn run_shadow_worker(
+    pool: &::sqlx::PgPool,
+    shadow: Option<super::shadow::ShadowProvider<'_>>,
+    mut receiver: tokio::sync::mpsc::Receiver<ShadowJob>,
+    shadow_request_rate_limiter: &RateLimiter,
+) -> ::anyhow::Result<()> {
+    let shadow = match shadow {
+        Some(shadow) => shadow,
+        None => return Ok(()),
+    };
+
+    while let Some(job) = receiver.recv().await {
+        let event_id = job.event.id;
+
+        if let Err(err) =
+            shadow_event(pool, shadow, job, shadow_request_rate_limiter).await
+        {
+            warn!(
+                "Event {}: failed to record shadow result: {:?}",
+                event_id, err
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Combines the results for the parts of an event. The highest level wins for
+// each tag, and the error is only permanent if every failed part failed
+// permanently.
+fn merge_results(
+    event_id: i64,
+    results: Vec<ModerationResult>,
+) -> ModerationResult {
+    let mut merged = ModerationResult {
+        event_id,
+        has_error: false,
+        is_permanent_error: true,
+        is_csam: false,
+        tags: vec![],
+        blob_db_ids: None,
+    };
+
+    for result in results {
+        if result.has_error {
+            merged.has_error = true;
+            merged.is_permanent_error &= result.is_permanent_error;
+        }
+
+        merged.is_csam |= result.is_csam;
+
+        for tag in result.tags {
+            match merged
+                .tags
+                .iter_mut()
+                .find(|existing| existing.name() == tag.name())
+            {
+                Some(existing) => {
+                    if tag.level() > existing.level() {
+                        *existing = tag;
+                    }
+                }
+                None => merged.tags.push(tag),
+            }
+        }
+
+        if let Some(blob_db_ids) = result.blob_db_ids {
+            merged
+                .blob_db_ids
+                .get_or_insert_with(Vec::new)
+                .extend(blob_db_ids);
+        }
+    }
+
+    merged.is_permanent_error &= merged.has_error;
+
+    merged
+}
+
 async fn process(
     csam: Option<&dyn providers::csam::interface::ModerationCSAMProvider>,
     tag: Option<&dyn providers::tags::interface::ModerationTaggingProvider>,
+    cache: Option<&super::result_cache::ResultCache>,
     events: Vec<ModerationQueueItem>,
     request_rate_limiter: &RateLimiter,
     csam_request_rate_limiter: &RateLimiter,
-) -> ::anyhow::Result<Vec<ModerationResult>> {
+) -> ::anyhow::Result<Vec<(ModerationQueueItem, ModerationResult)>> {
     debug!("Starting process for events");
     // Define the maximum concurrency based on the rate limiter's tokens per second
     let max_concurrency = 10;
 
     let results = stream::iter(events.into_iter())
         .map(|event| async move {
-            process_event(
+            let result = process_event(
                 csam,
                 tag,
+                cache,
                 &event,
                 request_rate_limiter,
                 csam_request_rate_limiter,
             )
-            .await
+            .await;
+
+            (event, result)
         })
         .buffer_unordered(max_concurrency)
         .collect::<Vec<_>>()
@@ -647,203 +865,923 @@ async fn process(
     Ok(results)
 }
 
+// Returns true if the event was an escalated event checked again, which was
+// approved, and visible, all along.
+async fn clear_recheck(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: i64,
+) -> ::anyhow::Result<bool> {
+    let query = "
+        UPDATE moderation_escalations
+        SET recheck = FALSE
+        WHERE event_id = $1
+        AND recheck
+    ";
+
+    let cleared = ::sqlx::query(query)
+        .bind(event_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(cleared.rows_affected() > 0)
+}
+
 async fn apply_moderation_results(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    options: &QueueOptions,
     results: &[ModerationResult],
 ) -> ::anyhow::Result<()> {
     debug!("Applying moderation results");
+
+    // Results are only applied while this worker still holds the lease. If it
+    // expired and another worker reclaimed the item, that worker's results
+    // win.
+    let leased_query = "
+        SELECT event_id
+        FROM event_processing_status
+        WHERE event_id = ANY($1)
+        AND lease_owner = $2
+        FOR UPDATE
+    ";
+
+    let leased: std::collections::HashSet<i64> =
+        sqlx::query_scalar(leased_query)
+            .bind(
+                results
+                    .iter()
+                    .map(|result| result.event_id)
+                    .collect::<Vec<i64>>(),
+            )
+            .bind(&options.worker_id)
+            .fetch_all(&mut **transaction)
+            .await?
+            .into_iter()
+            .collect();
+
     for result in results.iter() {
         let event_id = result.event_id;
+
+        if !leased.contains(&event_id) {
+            warn!(
+                "Event {}: lease lost before results were applied, skipping",
+                event_id
+            );
+            continue;
+        }
+
         let has_error = result.has_error;
         let is_permanent_error = result.is_permanent_error;
         let is_csam = result.is_csam;
 
-        let new_moderation_status = match (
-            has_error,
-            is_permanent_error,
-            is_csam,
-        ) {
-            (false, _, true) => ModerationStatus::FlaggedAndRejected,
-            (true, true, _) => {
-                // Permanent errors should be marked as approved to prevent retries
-                // but with no tags (empty moderation result)
-                debug!("Event {}: Permanent error, marking as approved to prevent retries", event_id);
-                ModerationStatus::Approved
-            }
-            (true, false, _) => ModerationStatus::Error,
-            (false, _, false) => ModerationStatus::Approved,
-        };
+        let new_moderation_status = match (
+            has_error,
+            is_permanent_error,
+            is_csam,
+        ) {
+            // CSAM found in one part outweighs errors in the others
+            (_, _, true) => ModerationStatus::FlaggedAndRejected,
+            (true, true, _) => {
+                // Permanent errors should be marked as approved to prevent retries
+                // but with no tags (empty moderation result)
+                debug!("Event {}: Permanent error, marking as approved to prevent retries", event_id);
+                ModerationStatus::Approved
+            }
+            (true, false, _) => ModerationStatus::Error,
+            (false, _, false) => ModerationStatus::Approved,
+        };
+
+        match new_moderation_status {
+            ModerationStatus::FlaggedAndRejected => {
+                // Purge the really bad blobs, the events holding their
+                // sections
+                if let Some(blob_db_ids) = &result.blob_db_ids {
+                    // Refused at ingest from now on
+                    super::result_cache::block_blob_sections(
+                        transaction,
+                        blob_db_ids,
+                        "csam",
+                    )
+                    .await?;
+
+                    let delete_blob_query = "
+                        DELETE FROM events
+                        WHERE id = ANY($1)
+                        AND id != $2
+                    ";
+                    ::sqlx::query(delete_blob_query)
+                        .bind(blob_db_ids)
+                        .bind(event_id)
+                        .execute(&mut **transaction)
+                        .await?;
+                }
+
+                // The event itself is kept, hidden, so that it can be
+                // reviewed
+                let update_query = "
+                    UPDATE events
+                    SET moderation_status = $1
+                    WHERE id = $2
+                ";
+
+                ::sqlx::query(update_query)
+                    .bind(ModerationStatus::FlaggedAndRejected)
+                    .bind(event_id)
+                    .execute(&mut **transaction)
+                    .await?;
+
+                crate::search_indexer::remove_event(transaction, event_id)
+                    .await?;
+                crate::cache::purge_queue::enqueue_event(transaction, event_id)
+                    .await?;
+
+                clear_recheck(transaction, event_id).await?;
+
+                let delete_processing_status_query = "
+                    DELETE FROM event_processing_status
+                    WHERE event_id = $1
+                ";
+
+                ::sqlx::query(delete_processing_status_query)
+                    .bind(event_id)
+                    .execute(&mut **transaction)
+                    .await?;
+            }
+            ModerationStatus::Error => {
+                // Items that keep failing are dead lettered rather than
+                // retried forever, they stay in the error state until
+                // handled manually.
+                let increment_failure_query = "
+                    UPDATE event_processing_status
+                    SET failure_count = failure_count + 1,
+                        last_failure_at = CURRENT_TIMESTAMP,
+                        lease_owner = NULL,
+                        lease_expires_at = NULL,
+                        dead_lettered_at = CASE
+                            WHEN failure_count + 1 >= $2 THEN CURRENT_TIMESTAMP
+                            ELSE NULL
+                        END
+                    WHERE event_id = $1
+                    RETURNING dead_lettered_at IS NOT NULL
+                ";
+
+                let dead_lettered: bool =
+                    ::sqlx::query_scalar(increment_failure_query)
+                        .bind(event_id)
+                        .bind(i32::try_from(options.max_retries)?)
+                        .fetch_one(&mut **transaction)
+                        .await?;
+
+                if dead_lettered {
+                    warn!(
+                        "Event {}: failed {} times, moved to dead letter",
+                        event_id, options.max_retries
+                    );
+                }
+
+                // Escalated events being checked again stay approved
+                let update_query = "
+                    UPDATE events
+                    SET moderation_status = $1
+                    WHERE id = $2
+                    AND moderation_status != 'approved'
+                ";
+
+                ::sqlx::query(update_query)
+                    .bind(ModerationStatus::Error)
+                    .bind(event_id)
+                    .execute(&mut **transaction)
+                    .await?;
+            }
+            ModerationStatus::Approved => {
+                // Tags the moderation policy rejects are kept for review
+                // like any other rejected event
+                let update_query = "
+                    UPDATE events
+                    SET moderation_status = CASE
+                            WHEN moderation_policy_rejects(content_type, $2::moderation_tag_type[])
+                            THEN $4
+                            ELSE $1
+                        END,
+                        moderation_tags = $2::moderation_tag_type[]
+                    WHERE id = $3
+                    RETURNING moderation_status
+                ";
+
+                let status: ModerationStatus =
+                    ::sqlx::query_scalar(update_query)
+                        .bind(ModerationStatus::Approved)
+                        .bind(&result.tags)
+                        .bind(event_id)
+                        .bind(ModerationStatus::FlaggedAndRejected)
+                        .fetch_one(&mut **transaction)
+                        .await?;
+
+                let delete_processing_status_query = "
+                    DELETE FROM event_processing_status
+                    WHERE event_id = $1
+                ";
+
+                ::sqlx::query(delete_processing_status_query)
+                    .bind(event_id)
+                    .execute(&mut **transaction)
+                    .await?;
+
+                let rechecked = clear_recheck(transaction, event_id).await?;
+
+                if status == ModerationStatus::FlaggedAndRejected {
+                    crate::search_indexer::remove_event(transaction, event_id)
+                        .await?;
+                }
+
+                // Tags and status are part of the cached responses
+                crate::cache::purge_queue::enqueue_event(transaction, event_id)
+                    .await?;
+
+                // Events hidden until approved are only now visible to
+                // stream subscribers.
+                if status == ModerationStatus::Approved && !rechecked {
+                    crate::postgres::notify_event_available(
+                        &mut *transaction,
+                        u64::try_from(event_id)?,
+                    )
+                    .await?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    debug!("Moderation results applied successfully");
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_worker(
+    pool: &::sqlx::PgPool,
+    csam: Option<&dyn providers::csam::interface::ModerationCSAMProvider>,
+    tag: Option<&dyn providers::tags::interface::ModerationTaggingProvider>,
+    cache: Option<&super::result_cache::ResultCache>,
+    shadow_sender: Option<&tokio::sync::mpsc::Sender<ShadowJob>>,
+    options: QueueOptions,
+    request_rate_limiter: &RateLimiter,
+    csam_request_rate_limiter: &RateLimiter,
+) -> ::anyhow::Result<()> {
+    debug!("Starting moderation worker {}", options.worker_id);
+
+    // loop until task is cancelled
+    loop {
+        let mut transaction = pool.begin().await?;
+        let events = pull_queue_events(&mut transaction, &options).await?;
+        transaction.commit().await?;
+
+        if events.is_empty() {
+            debug!("No events to moderate, sleeping for 1 second");
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            continue;
+        }
+
+        let (events, results): (Vec<_>, Vec<_>) = process(
+            csam,
+            tag,
+            cache,
+            events,
+            request_rate_limiter,
+            csam_request_rate_limiter,
+        )
+        .await?
+        .into_iter()
+        .unzip();
+
+        // separate transaction because this can take a while and we want to
+        // avoid blocking other writes
+        let mut transaction = pool.begin().await?;
+        apply_moderation_results(&mut transaction, &options, &results).await?;
+        transaction.commit().await?;
+
+        if let Some(shadow_sender) = shadow_sender {
+            submit_shadow_jobs(shadow_sender, events, &results);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    pool: ::sqlx::PgPool,
+    csam: Option<&dyn providers::csam::interface::ModerationCSAMProvider>,
+    tag: Option<&dyn providers::tags::interface::ModerationTaggingProvider>,
+    cache: Option<&super::result_cache::ResultCache>,
+    shadow: Option<super::shadow::ShadowProvider<'_>>,
+    tagging_request_rate_limit: u16,
+    shadow_tagging_request_rate_limit: u16,
+    csam_request_rate_limit: u16,
+    workers: u16,
+    lease_duration: Duration,
+    max_retries: u32,
+    retry_delay: Duration,
+) -> ::anyhow::Result<()> {
+    debug!("Starting run function");
+
+    // Rate limits apply to the provider, so they are shared by all workers
+    let request_rate_limiter = RateLimiter::new(
+        tagging_request_rate_limit,
+        tagging_request_rate_limit,
+    );
+
+    let shadow_request_rate_limiter = RateLimiter::new(
+        shadow_tagging_request_rate_limit,
+        shadow_tagging_request_rate_limit,
+    );
+
+    let csam_request_rate_limiter =
+        RateLimiter::new(csam_request_rate_limit, csam_request_rate_limit);
+
+    let (shadow_sender, shadow_receiver) =
+        tokio::sync::mpsc::channel(SHADOW_QUEUE_SIZE);
+
+    let shadow_worker = run_shadow_worker(
+        &pool,
+        shadow,
+        shadow_receiver,
+        &shadow_request_rate_limiter,
+    );
+
+    let workers =
+        futures::future::try_join_all((0..workers.max(1)).map(|_| {
+            run_worker(
+                &pool,
+                csam,
+                tag,
+                cache,
+                shadow.map(|_| &shadow_sender),
+                QueueOptions::new(lease_duration, max_retries, retry_delay),
+                &request_rate_limiter,
+                &csam_request_rate_limiter,
+            )
+        }));
+
+    futures::future::try_join(workers, shadow_worker).await?;
+
+    Ok(())
+}
+
+// Approval by a reviewer, see `review`. The decision is recorded so that it
+// takes precedence over automated moderation, and the event is streamed,
+// indexed and purged from caches again.
+pub(crate) async fn approve_event(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: i64,
+    tags: Option<&[polycentric_protocol::model::moderation_tag::ModerationTag]>,
+    reviewer: &str,
+    note: Option<&str>,
+) -> ::anyhow::Result<()> {
+    debug!("Approving event {}", event_id);
+
+    super::review::record_decision(
+        transaction,
+        event_id,
+        ModerationStatus::Approved,
+        tags,
+        reviewer,
+        note,
+    )
+    .await?;
+
+    crate::postgres::notify_event_available(
+        &mut *transaction,
+        u64::try_from(event_id)?,
+    )
+    .await?;
+
+    // Back in search if it was rejected before, a later rejection removes it
+    // again
+    crate::search_indexer::enqueue(&mut *transaction, u64::try_from(event_id)?)
+        .await?;
+
+    crate::cache::purge_queue::enqueue_event(transaction, event_id).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::ModerationMode,
+        model::moderation_tag::ModerationTagName,
+        moderation::{ModerationFilter, ModerationFilters, ModerationOptions},
+        postgres::prepare_database,
+    };
+    use protobuf::Message;
+    use sqlx::PgPool;
+    use std::time::Instant;
+
+    fn test_options() -> QueueOptions {
+        QueueOptions {
+            worker_id: "test".to_string(),
+            lease_duration: Duration::from_secs(300),
+            max_retries: 3,
+            retry_delay: Duration::ZERO,
+        }
+    }
+
+    async fn ingest_test_post(pool: &PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        crate::ingest::ingest_event_postgres(
+            &mut transaction,
+            &polycentric_protocol::test_utils::make_test_event(
+                &keypair, &process, 1,
+            ),
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    fn result_for(event_id: i64, has_error: bool) -> ModerationResult {
+        ModerationResult {
+            event_id,
+            has_error,
+            is_permanent_error: false,
+            is_csam: false,
+            tags: vec![],
+            blob_db_ids: None,
+        }
+    }
+
+    async fn load_status(
+        pool: &PgPool,
+        event_id: i64,
+    ) -> anyhow::Result<String> {
+        Ok(sqlx::query_scalar(
+            "SELECT moderation_status::text FROM events WHERE id = $1",
+        )
+        .bind(event_id)
+        .fetch_one(pool)
+        .await?)
+    }
+
+    #[sqlx::test]
+    async fn test_leased_events_are_not_pulled_twice(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        ingest_test_post(&pool).await?;
+
+        let first =
+            QueueOptions::new(Duration::from_secs(300), 3, Duration::ZERO);
+        let second =
+            QueueOptions::new(Duration::from_secs(300), 3, Duration::ZERO);
+
+        let mut transaction = pool.begin().await?;
+        let events = pull_queue_events(&mut transaction, &first).await?;
+        transaction.commit().await?;
+
+        assert_eq!(events.len(), 1);
+
+        let mut transaction = pool.begin().await?;
+        assert!(pull_queue_events(&mut transaction, &second)
+            .await?
+            .is_empty());
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_expired_lease_is_reclaimed(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        ingest_test_post(&pool).await?;
+
+        let stalled = QueueOptions::new(Duration::ZERO, 3, Duration::ZERO);
+        let reclaimer =
+            QueueOptions::new(Duration::from_secs(300), 3, Duration::ZERO);
+
+        let mut transaction = pool.begin().await?;
+        let stalled_events =
+            pull_queue_events(&mut transaction, &stalled).await?;
+        transaction.commit().await?;
+
+        let mut transaction = pool.begin().await?;
+        let reclaimed_events =
+            pull_queue_events(&mut transaction, &reclaimer).await?;
+        transaction.commit().await?;
+
+        assert_eq!(stalled_events.len(), 1);
+        assert_eq!(reclaimed_events.len(), 1);
+        assert_eq!(stalled_events[0].id, reclaimed_events[0].id);
+
+        let event_id = reclaimed_events[0].id;
+
+        // The stalled worker no longer holds the lease
+        let mut transaction = pool.begin().await?;
+        apply_moderation_results(
+            &mut transaction,
+            &stalled,
+            &[result_for(event_id, false)],
+        )
+        .await?;
+        transaction.commit().await?;
+
+        assert_eq!(load_status(&pool, event_id).await?, "processing");
+
+        let mut transaction = pool.begin().await?;
+        apply_moderation_results(
+            &mut transaction,
+            &reclaimer,
+            &[result_for(event_id, false)],
+        )
+        .await?;
+        transaction.commit().await?;
+
+        assert_eq!(load_status(&pool, event_id).await?, "approved");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_dead_letter_after_max_retries(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        ingest_test_post(&pool).await?;
+
+        let options =
+            QueueOptions::new(Duration::from_secs(300), 2, Duration::ZERO);
+
+        for _ in 0..2 {
+            let mut transaction = pool.begin().await?;
+            let events = pull_queue_events(&mut transaction, &options).await?;
+            assert_eq!(events.len(), 1);
+            apply_moderation_results(
+                &mut transaction,
+                &options,
+                &[result_for(events[0].id, true)],
+            )
+            .await?;
+            transaction.commit().await?;
+        }
+
+        let mut transaction = pool.begin().await?;
+        assert!(pull_queue_events(&mut transaction, &options)
+            .await?
+            .is_empty());
+        transaction.commit().await?;
+
+        let dead_lettered: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM event_processing_status
+             WHERE dead_lettered_at IS NOT NULL AND failure_count = 2",
+        )
+        .fetch_one(&pool)
+        .await?;
+
+        assert_eq!(dead_lettered, 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_failed_items_back_off(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        ingest_test_post(&pool).await?;
+
+        let options = QueueOptions::new(
+            Duration::from_secs(300),
+            3,
+            Duration::from_secs(60),
+        );
+
+        let mut transaction = pool.begin().await?;
+        let events = pull_queue_events(&mut transaction, &options).await?;
+        assert_eq!(events.len(), 1);
+        apply_moderation_results(
+            &mut transaction,
+            &options,
+            &[result_for(events[0].id, true)],
+        )
+        .await?;
+        transaction.commit().await?;
+
+        let mut transaction = pool.begin().await?;
+        assert!(pull_queue_events(&mut transaction, &options)
+            .await?
+            .is_empty());
+        transaction.commit().await?;
+
+        sqlx::query(
+            "UPDATE event_processing_status
+             SET last_failure_at = NOW() - INTERVAL '61 seconds'",
+        )
+        .execute(&pool)
+        .await?;
+
+        let mut transaction = pool.begin().await?;
+        let events = pull_queue_events(&mut transaction, &options).await?;
+        assert_eq!(events.len(), 1);
+        apply_moderation_results(
+            &mut transaction,
+            &options,
+            &[result_for(events[0].id, true)],
+        )
+        .await?;
+        transaction.commit().await?;
+
+        // The second retry waits twice as long
+        sqlx::query(
+            "UPDATE event_processing_status
+             SET last_failure_at = NOW() - INTERVAL '61 seconds'",
+        )
+        .execute(&pool)
+        .await?;
+
+        let mut transaction = pool.begin().await?;
+        assert!(pull_queue_events(&mut transaction, &options)
+            .await?
+            .is_empty());
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_csam_outweighs_permanent_error(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        ingest_test_post(&pool).await?;
+
+        let options = test_options();
+
+        let mut transaction = pool.begin().await?;
+        let events = pull_queue_events(&mut transaction, &options).await?;
+        let event_id = events[0].id;
+
+        // An undecodable image in one part, CSAM in another
+        apply_moderation_results(
+            &mut transaction,
+            &options,
+            &[ModerationResult {
+                is_permanent_error: true,
+                is_csam: true,
+                ..result_for(event_id, true)
+            }],
+        )
+        .await?;
+        transaction.commit().await?;
+
+        assert_eq!(load_status(&pool, event_id).await?, "flagged_and_rejected");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_blocked_assembled_image(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        // Neither section is blocked on its own
+        for (logical_clock, section) in
+            [(1, &b"first"[..]), (2, &b"second"[..])]
+        {
+            crate::ingest::ingest_event_postgres(
+                &mut transaction,
+                &polycentric_protocol::test_utils::make_test_event_with_content(
+                    &keypair,
+                    &process,
+                    logical_clock,
+                    polycentric_protocol::model::known_message_types::BLOB_SECTION,
+                    section,
+                    vec![],
+                ),
+            )
+            .await?;
+        }
+
+        super::super::result_cache::block(
+            &mut transaction,
+            &[super::super::result_cache::sha256(b"firstsecond").to_vec()],
+            "csam",
+        )
+        .await?;
 
-        match new_moderation_status {
-            ModerationStatus::FlaggedAndRejected => {
-                // Purge the really bad blobs
-                if let Some(blob_db_ids) = &result.blob_db_ids {
-                    let delete_blob_query = "
-                        DELETE FROM blobs
-                        WHERE id = ANY($1)
-                    ";
-                    ::sqlx::query(delete_blob_query)
-                        .bind(blob_db_ids)
-                        .execute(&mut **transaction)
-                        .await?;
-                }
+        let mut range = polycentric_protocol::protocol::Range::new();
+        range.low = 1;
+        range.high = 2;
 
-                let delete_event_query = "
-                    DELETE FROM events
-                    WHERE id = $1
-                ";
+        let mut manifest = polycentric_protocol::protocol::ImageManifest::new();
+        manifest.width = 100;
+        manifest.height = 100;
+        manifest.process = ::protobuf::MessageField::some(
+            polycentric_protocol::model::process::to_proto(&process),
+        );
+        manifest.sections = vec![range];
 
-                ::sqlx::query(delete_event_query)
-                    .bind(event_id)
-                    .execute(&mut **transaction)
-                    .await?;
-            }
-            ModerationStatus::Error => {
-                let increment_failure_query = "
-                    UPDATE event_processing_status
-                    SET failure_count = failure_count + 1, last_failure_at = CURRENT_TIMESTAMP
-                    WHERE event_id = $1
-                ";
+        let mut post = polycentric_protocol::protocol::Post::new();
+        post.image = Some(manifest).into();
 
-                ::sqlx::query(increment_failure_query)
-                    .bind(event_id)
-                    .execute(&mut **transaction)
-                    .await?;
+        crate::ingest::ingest_event_postgres(
+            &mut transaction,
+            &polycentric_protocol::test_utils::make_test_event_with_content(
+                &keypair,
+                &process,
+                3,
+                polycentric_protocol::model::known_message_types::POST,
+                &post.write_to_bytes()?,
+                vec![],
+            ),
+        )
+        .await?;
 
-                let update_query = "
-                    UPDATE events
-                    SET moderation_status = $1
-                    WHERE id = $2
-                ";
+        let options = test_options();
+        let events = pull_queue_events(&mut transaction, &options).await?;
+        assert_eq!(events.len(), 1);
+        assert!(events[0].blocked);
 
-                ::sqlx::query(update_query)
-                    .bind(ModerationStatus::Error)
-                    .bind(event_id)
-                    .execute(&mut **transaction)
-                    .await?;
-            }
-            ModerationStatus::Approved => {
-                let update_query = "
-                    UPDATE events
-                    SET moderation_status = $1, moderation_tags = $2::moderation_tag_type[]
-                    WHERE id = $3
-                ";
+        // No providers are needed to reject it
+        let limiter = RateLimiter::new(1, 1);
+        let result =
+            process_event(None, None, None, &events[0], &limiter, &limiter)
+                .await;
+        assert!(result.is_csam);
+        assert_eq!(result.blob_db_ids.as_ref().map(Vec::len), Some(2));
 
-                ::sqlx::query(update_query)
-                    .bind(ModerationStatus::Approved)
-                    .bind(&result.tags)
-                    .bind(event_id)
-                    .execute(&mut **transaction)
-                    .await?;
+        apply_moderation_results(&mut transaction, &options, &[result]).await?;
+        transaction.commit().await?;
 
-                let delete_processing_status_query = "
-                    DELETE FROM event_processing_status
-                    WHERE event_id = $1
-                ";
+        assert_eq!(
+            load_status(&pool, events[0].id).await?,
+            "flagged_and_rejected"
+        );
 
-                ::sqlx::query(delete_processing_status_query)
-                    .bind(event_id)
-                    .execute(&mut **transaction)
-                    .await?;
-            }
-            _ => {}
-        }
+        let remaining: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM events WHERE content_type = $1",
+        )
+        .bind(i64::try_from(
+            polycentric_protocol::model::known_message_types::BLOB_SECTION,
+        )?)
+        .fetch_one(&pool)
+        .await?;
+        assert_eq!(remaining, 0);
+
+        Ok(())
     }
 
-    debug!("Moderation results applied successfully");
-    Ok(())
-}
+    #[sqlx::test]
+    async fn test_shadow_worker(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
 
-pub async fn run(
-    pool: ::sqlx::PgPool,
-    csam: Option<&dyn providers::csam::interface::ModerationCSAMProvider>,
-    tag: Option<&dyn providers::tags::interface::ModerationTaggingProvider>,
-    tagging_request_rate_limit: u16,
-    csam_request_rate_limit: u16,
-) -> ::anyhow::Result<()> {
-    debug!("Starting run function");
-    // loop until task is cancelled
-    let request_rate_limiter = RateLimiter::new(
-        tagging_request_rate_limit,
-        tagging_request_rate_limit,
-    );
+        ingest_test_post(&pool).await?;
+        ingest_test_post(&pool).await?;
 
-    let csam_request_rate_limiter =
-        RateLimiter::new(csam_request_rate_limit, csam_request_rate_limit);
+        let mut transaction = pool.begin().await?;
+        let events =
+            pull_queue_events(&mut transaction, &test_options()).await?;
+        transaction.commit().await?;
 
-    loop {
+        let results: Vec<ModerationResult> = events
+            .iter()
+            .map(|event| result_for(event.id, false))
+            .collect();
+
+        // Submitting never waits, the job that does not fit is dropped
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        submit_shadow_jobs(&sender, events, &results);
+        drop(sender);
+
+        let mut local =
+            crate::moderation::providers::tags::local::LocalTagProvider::new();
+        local.load_rules(serde_json::from_str(
+            r#"[{ "tag": "violence", "level": 2, "keywords": ["test"] }]"#,
+        )?)?;
+
+        let limiter = RateLimiter::new(10, 10);
+        run_shadow_worker(
+            &pool,
+            Some(crate::moderation::shadow::ShadowProvider {
+                name: "local",
+                provider: &local,
+            }),
+            receiver,
+            &limiter,
+        )
+        .await?;
+
+        let recorded: Vec<(
+            i64,
+            Vec<crate::model::moderation_tag::ModerationTag>,
+        )> = sqlx::query_as(
+            "SELECT event_id, shadow_tags FROM moderation_shadow_results;",
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        assert_eq!(
+            recorded,
+            vec![(
+                results[0].event_id,
+                vec![crate::model::moderation_tag::ModerationTag::new(
+                    "violence".to_string(),
+                    2
+                )]
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_escalated_event_stays_visible(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
         let mut transaction = pool.begin().await?;
-        let events = pull_queue_events(&mut transaction).await?;
+        prepare_database(&mut transaction).await?;
         transaction.commit().await?;
 
-        if events.is_empty() {
-            debug!("No events to moderate, sleeping for 1 second");
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            continue;
-        }
+        ingest_test_post(&pool).await?;
 
-        let results = process(
-            csam,
-            tag,
-            events,
-            &request_rate_limiter,
-            &csam_request_rate_limiter,
+        let options = test_options();
+
+        let mut transaction = pool.begin().await?;
+        let events = pull_queue_events(&mut transaction, &options).await?;
+        let event_id = events[0].id;
+        apply_moderation_results(
+            &mut transaction,
+            &options,
+            &[result_for(event_id, false)],
         )
         .await?;
 
-        // separate transaction because this can take a while and we want to
-        // avoid blocking other writes
+        sqlx::query(
+            "INSERT INTO moderation_escalations (event_id, reports, recheck)
+             VALUES ($1, 5, TRUE)",
+        )
+        .bind(event_id)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        // Pulled again, and failing, without leaving the approved state
         let mut transaction = pool.begin().await?;
-        apply_moderation_results(&mut transaction, &results).await?;
+        let events = pull_queue_events(&mut transaction, &options).await?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(load_status(&pool, event_id).await?, "approved");
+
+        apply_moderation_results(
+            &mut transaction,
+            &options,
+            &[result_for(event_id, true)],
+        )
+        .await?;
         transaction.commit().await?;
-    }
-}
 
-#[allow(dead_code)]
-pub async fn approve_event(
-    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
-    system: &crate::model::public_key::PublicKey,
-    process: &crate::model::process::Process,
-    logical_clock: u64,
-) -> ::anyhow::Result<()> {
-    debug!("Approving event with logical_clock: {}", logical_clock);
-    let query = "
-        UPDATE events
-        SET moderation_status = 'approved'
-        WHERE system_key_type = $1
-        AND   system_key      = $2
-        AND   process         = $3
-        AND   logical_clock   = $4;
-    ";
+        assert_eq!(load_status(&pool, event_id).await?, "approved");
 
-    ::sqlx::query(query)
-        .bind(i64::try_from(crate::model::public_key::get_key_type(
-            system,
-        ))?)
-        .bind(crate::model::public_key::get_key_bytes(system))
-        .bind(process.bytes())
-        .bind(i64::try_from(logical_clock)?)
-        .fetch_optional(&mut **transaction)
+        let mut transaction = pool.begin().await?;
+        let events = pull_queue_events(&mut transaction, &options).await?;
+        assert_eq!(events.len(), 1);
+        apply_moderation_results(
+            &mut transaction,
+            &options,
+            &[result_for(event_id, false)],
+        )
         .await?;
+        transaction.commit().await?;
 
-    debug!(
-        "Event approved successfully with logical_clock: {}",
-        logical_clock
-    );
-    Ok(())
-}
+        let mut transaction = pool.begin().await?;
+        assert!(pull_queue_events(&mut transaction, &options)
+            .await?
+            .is_empty());
+        transaction.commit().await?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{
-        config::ModerationMode,
-        model::moderation_tag::ModerationTagName,
-        moderation::{ModerationFilter, ModerationFilters, ModerationOptions},
-        postgres::prepare_database,
-    };
-    use sqlx::PgPool;
-    use std::time::Instant;
+        Ok(())
+    }
 
     #[sqlx::test]
     async fn test_pull_queue_events(pool: PgPool) -> anyhow::Result<()> {
@@ -885,7 +1823,8 @@ mod tests {
         )
         .await?;
 
-        let events = pull_queue_events(&mut transaction).await?;
+        let events =
+            pull_queue_events(&mut transaction, &test_options()).await?;
 
         transaction.commit().await?;
 
@@ -895,6 +1834,106 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn test_pull_claim_text(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        let mut field = polycentric_protocol::protocol::ClaimFieldEntry::new();
+        field.key = 1;
+        field.value = "abusive handle".to_string();
+
+        let mut claim = polycentric_protocol::protocol::Claim::new();
+        claim.claim_type = 1;
+        claim.claim_fields = vec![field];
+
+        crate::ingest::ingest_event_postgres(
+            &mut transaction,
+            &polycentric_protocol::test_utils::make_test_event_with_content(
+                &keypair,
+                &process,
+                1,
+                polycentric_protocol::model::known_message_types::CLAIM,
+                &claim.write_to_bytes()?,
+                vec![],
+            ),
+        )
+        .await?;
+
+        let events =
+            pull_queue_events(&mut transaction, &test_options()).await?;
+
+        transaction.commit().await?;
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].content, Some("abusive handle".to_string()));
+        assert!(events[0].blob.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_results() {
+        let part = |tags: Vec<(&str, i16)>, has_error, is_permanent_error| {
+            ModerationResult {
+                event_id: 1,
+                has_error,
+                is_permanent_error,
+                is_csam: false,
+                tags: tags
+                    .into_iter()
+                    .map(|(name, level)| {
+                        crate::model::moderation_tag::ModerationTag::new(
+                            name.to_string(),
+                            level,
+                        )
+                    })
+                    .collect(),
+                blob_db_ids: Some(vec![]),
+            }
+        };
+
+        let merged = merge_results(
+            1,
+            vec![
+                part(vec![("violence", 1), ("hate", 0)], false, false),
+                part(vec![("violence", 2)], true, true),
+            ],
+        );
+
+        assert!(merged.has_error);
+        assert!(merged.is_permanent_error);
+        assert_eq!(
+            merged.tags,
+            vec![
+                crate::model::moderation_tag::ModerationTag::new(
+                    "violence".to_string(),
+                    2
+                ),
+                crate::model::moderation_tag::ModerationTag::new(
+                    "hate".to_string(),
+                    0
+                ),
+            ]
+        );
+
+        let merged = merge_results(
+            1,
+            vec![part(vec![], true, true), part(vec![], true, false)],
+        );
+
+        assert!(merged.has_error);
+        assert!(!merged.is_permanent_error);
+
+        assert!(
+            !merge_results(1, vec![part(vec![], false, false)])
+                .is_permanent_error
+        );
+    }
+
     #[sqlx::test]
     async fn test_apply_moderation_results(pool: PgPool) -> anyhow::Result<()> {
         let mut transaction = pool.begin().await?;
@@ -928,7 +1967,8 @@ mod tests {
         }
 
         // Pull events from the moderation queue
-        let events = pull_queue_events(&mut transaction).await?;
+        let events =
+            pull_queue_events(&mut transaction, &test_options()).await?;
 
         // Mock moderation results
         let moderation_results = vec![
@@ -988,7 +2028,12 @@ mod tests {
         ];
 
         // Apply moderation results
-        apply_moderation_results(&mut transaction, &moderation_results).await?;
+        apply_moderation_results(
+            &mut transaction,
+            &test_options(),
+            &moderation_results,
+        )
+        .await?;
 
         transaction.commit().await?;
         transaction = pool.begin().await?;
@@ -1189,7 +2234,8 @@ mod tests {
 
         assert_eq!(loaded_events.events.len(), 0);
 
-        let events = pull_queue_events(&mut transaction).await?;
+        let events =
+            pull_queue_events(&mut transaction, &test_options()).await?;
 
         assert_eq!(events.len(), 1);
 
@@ -1205,7 +2251,12 @@ mod tests {
             blob_db_ids: None,
         }];
 
-        apply_moderation_results(&mut transaction, &moderation_results).await?;
+        apply_moderation_results(
+            &mut transaction,
+            &test_options(),
+            &moderation_results,
+        )
+        .await?;
 
         transaction.commit().await?;
         transaction = pool.begin().await?;
@@ -1299,7 +2350,8 @@ mod tests {
         transaction.commit().await?;
         transaction = pool.begin().await?;
 
-        let events = pull_queue_events(&mut transaction).await?;
+        let events =
+            pull_queue_events(&mut transaction, &test_options()).await?;
         assert_eq!(events.len(), 1);
 
         let moderation_results = vec![ModerationResult {
@@ -1324,7 +2376,12 @@ mod tests {
             blob_db_ids: None,
         }];
 
-        apply_moderation_results(&mut transaction, &moderation_results).await?;
+        apply_moderation_results(
+            &mut transaction,
+            &test_options(),
+            &moderation_results,
+        )
+        .await?;
         transaction.commit().await?;
         transaction = pool.begin().await?;
 
@@ -1468,12 +2525,13 @@ mod tests {
         println!("\nAdding performance improvements...");
 
         // Add efficient index for events
-        sqlx::query(
+        sqlx::query(&format!(
             "CREATE INDEX IF NOT EXISTS idx_events_moderation_efficient ON events 
              (moderation_status, content_type) 
              INCLUDE (id, raw_event)
-             WHERE content_type IN (3, 6, 9)"
-        )
+             WHERE content_type IN ({})",
+            crate::moderation::extract::moderated_content_types_sql()
+        ))
         .execute(&mut *transaction)
         .await?;
 
@@ -1557,7 +2615,8 @@ mod tests {
             println!("Data insertion took: {:?}", start.elapsed());
 
             // Test the optimized query
-            let query = "
+            let query = format!(
+                "
                 SELECT 
                     e.id,
                     e.raw_event,
@@ -1570,17 +2629,19 @@ mod tests {
                     WHERE event_id = e.id
                     AND failure_count < 3
                 ) eps ON true
-                WHERE e.content_type IN (3, 6, 9)
+                WHERE e.content_type IN ({})
                 AND e.moderation_status IN ('unprocessed'::moderation_status_enum, 'error'::moderation_status_enum)
                 ORDER BY 
                     CASE WHEN e.moderation_status = 'unprocessed'::moderation_status_enum THEN 0 ELSE 1 END,
                     COALESCE(eps.failure_count, 0) DESC
                 LIMIT 20
-            ";
+            ",
+                crate::moderation::extract::moderated_content_types_sql()
+            );
 
             let start = Instant::now();
             let results =
-                sqlx::query(query).fetch_all(&mut *transaction).await?;
+                sqlx::query(&query).fetch_all(&mut *transaction).await?;
             println!("Query execution took: {:?}", start.elapsed());
             assert_eq!(results.len(), 20);
 
@@ -1625,7 +2686,8 @@ mod tests {
         transaction.commit().await?;
         transaction = pool.begin().await?;
 
-        let events = pull_queue_events(&mut transaction).await?;
+        let events =
+            pull_queue_events(&mut transaction, &test_options()).await?;
         assert_eq!(events.len(), 1);
 
         // Test permanent error handling
@@ -1638,7 +2700,12 @@ mod tests {
             blob_db_ids: None,
         }];
 
-        apply_moderation_results(&mut transaction, &moderation_results).await?;
+        apply_moderation_results(
+            &mut transaction,
+            &test_options(),
+            &moderation_results,
+        )
+        .await?;
         transaction.commit().await?;
         transaction = pool.begin().await?;
 
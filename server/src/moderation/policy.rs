@@ -0,0 +1,592 @@
+use polycentric_protocol::model::moderation_tag::{
+    ModerationTag, ModerationTagName,
+};
+
+use super::extract::MODERATED_CONTENT_TYPES;
+use super::ModerationFilter;
+use crate::config::ModerationMode;
+
+// The moderation policy of a server, stored from the JSON file at
+// MODERATION_POLICY_PATH by the STORE_MODERATION_POLICY mode. `defaults`
+// applies to every moderated content type and each entry of `content_types`
+// refines it for one type:
+//
+// {
+//     "version": 2,
+//     "defaults": {
+//         "filters": [
+//             { "name": "violence", "max_level": 1, "strict_mode": true }
+//         ],
+//         "actions": [
+//             { "tag": "sexual", "min_level": 3, "action": "reject" }
+//         ]
+//     },
+//     "content_types": {
+//         "avatar": { "mode": "strong" },
+//         "post": {
+//             "mode": "lazy",
+//             "actions": [
+//                 { "tag": "violence", "min_level": 2, "action": "blur_label" }
+//             ]
+//         }
+//     }
+// }
+//
+// Filters are the defaults for tags a client does not send a filter for.
+// Actions apply regardless of client filters: `hide` withholds the event,
+// `reject` marks it rejected when moderation results arrive, and
+// `blur_label` is only published for clients to act on.
+//
+// `version` must be raised for every change, a policy is never stored over
+// one with the same or a higher version.
+
+pub(crate) fn content_type_from_name(name: &str) -> Option<u64> {
+    MODERATED_CONTENT_TYPES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, content_type)| *content_type)
+}
+
+// Tag names are stored as VARCHAR(20)
+const MAX_TAG_NAME_LENGTH: usize = 20;
+
+const MAX_TAG_LEVEL: i16 = 3;
+
+#[derive(
+    ::serde::Deserialize,
+    ::serde::Serialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Debug,
+)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PolicyAction {
+    Hide,
+    BlurLabel,
+    Reject,
+}
+
+#[derive(::serde::Deserialize, ::serde::Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ActionRule {
+    tag: String,
+    min_level: i16,
+    action: PolicyAction,
+}
+
+#[derive(::serde::Deserialize, ::serde::Serialize, Clone, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RuleSet {
+    #[serde(default)]
+    mode: Option<ModerationMode>,
+    #[serde(default)]
+    filters: ::std::vec::Vec<ModerationFilter>,
+    #[serde(default)]
+    actions: ::std::vec::Vec<ActionRule>,
+}
+
+#[derive(::serde::Deserialize, ::serde::Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ModerationPolicy {
+    // The built-in policy is version 0
+    version: u64,
+    #[serde(default)]
+    defaults: RuleSet,
+    #[serde(default)]
+    content_types: ::std::collections::BTreeMap<String, RuleSet>,
+}
+
+// The policy in effect for one content type, as stored and published.
+#[derive(::serde::Serialize, Clone, PartialEq, Debug)]
+pub(crate) struct ContentTypePolicy {
+    name: &'static str,
+    content_type: u64,
+    // Falls back to the server moderation mode when not set
+    mode: Option<ModerationMode>,
+    filters: ::std::vec::Vec<ModerationFilter>,
+    actions: ::std::vec::Vec<ActionRule>,
+}
+
+impl ContentTypePolicy {
+    pub(crate) fn effective_mode(
+        &self,
+        server_mode: ModerationMode,
+    ) -> ModerationMode {
+        self.mode.unwrap_or(server_mode)
+    }
+
+    fn tags_for(&self, action: PolicyAction) -> ::std::vec::Vec<ModerationTag> {
+        self.actions
+            .iter()
+            .filter(|rule| rule.action == action)
+            .map(|rule| ModerationTag::new(rule.tag.clone(), rule.min_level))
+            .collect()
+    }
+}
+
+impl Default for ModerationPolicy {
+    fn default() -> Self {
+        let filter = |name: &str, max_level| ModerationFilter {
+            name: ModerationTagName::new(name.to_string()),
+            max_level,
+            strict_mode: true,
+        };
+
+        ModerationPolicy {
+            version: 0,
+            defaults: RuleSet {
+                mode: None,
+                filters: vec![
+                    filter("violence", 1),
+                    filter("hate", 2),
+                    filter("sexual", 1),
+                ],
+                actions: vec![],
+            },
+            content_types: ::std::collections::BTreeMap::new(),
+        }
+    }
+}
+
+fn validate_tag_name(name: &str) -> ::anyhow::Result<()> {
+    if name.is_empty() || name.len() > MAX_TAG_NAME_LENGTH {
+        ::anyhow::bail!(
+            "tag name {:?} must be between 1 and {} bytes",
+            name,
+            MAX_TAG_NAME_LENGTH
+        );
+    }
+
+    Ok(())
+}
+
+fn validate_level(name: &str, level: i16) -> ::anyhow::Result<()> {
+    if !(0..=MAX_TAG_LEVEL).contains(&level) {
+        ::anyhow::bail!(
+            "level {} for tag {:?} must be between 0 and {}",
+            level,
+            name,
+            MAX_TAG_LEVEL
+        );
+    }
+
+    Ok(())
+}
+
+impl RuleSet {
+    fn validate(&self) -> ::anyhow::Result<()> {
+        let mut names = ::std::collections::HashSet::new();
+
+        for filter in self.filters.iter() {
+            let name = filter.name.to_string();
+            validate_tag_name(&name)?;
+            validate_level(&name, filter.max_level)?;
+
+            if !names.insert(name.clone()) {
+                ::anyhow::bail!("duplicate filter for tag {:?}", name);
+            }
+        }
+
+        let mut rules = ::std::collections::HashSet::new();
+
+        for rule in self.actions.iter() {
+            validate_tag_name(&rule.tag)?;
+            validate_level(&rule.tag, rule.min_level)?;
+
+            if !rules.insert((rule.tag.clone(), rule.action)) {
+                ::anyhow::bail!(
+                    "duplicate {:?} action for tag {:?}",
+                    rule.action,
+                    rule.tag
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ModerationPolicy {
+    // Without a path the built-in policy is used.
+    pub(crate) fn load(path: Option<&str>) -> ::anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(ModerationPolicy::default()),
+        };
+
+        let contents = ::std::fs::read_to_string(path).map_err(|err| {
+            ::anyhow::anyhow!(
+                "failed to read moderation policy {}: {}",
+                path,
+                err
+            )
+        })?;
+
+        Self::parse(&contents).map_err(|err| {
+            ::anyhow::anyhow!("invalid moderation policy {}: {}", path, err)
+        })
+    }
+
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub(crate) fn parse(contents: &str) -> ::anyhow::Result<Self> {
+        let policy: ModerationPolicy = ::serde_json::from_str(contents)?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    fn validate(&self) -> ::anyhow::Result<()> {
+        if self.version == 0 {
+            ::anyhow::bail!("version must be at least 1");
+        }
+
+        self.defaults
+            .validate()
+            .map_err(|err| ::anyhow::anyhow!("defaults: {}", err))?;
+
+        for (name, rules) in self.content_types.iter() {
+            if content_type_from_name(name).is_none() {
+                ::anyhow::bail!("unknown content type {:?}", name);
+            }
+
+            rules
+                .validate()
+                .map_err(|err| ::anyhow::anyhow!("{}: {}", name, err))?;
+        }
+
+        Ok(())
+    }
+
+    // Content type settings take precedence over the defaults, filters and
+    // actions are overridden tag by tag.
+    pub(crate) fn content_types(&self) -> ::std::vec::Vec<ContentTypePolicy> {
+        MODERATED_CONTENT_TYPES
+            .iter()
+            .map(|(name, content_type)| {
+                let specific = self.content_types.get(*name);
+
+                let mut filters = self.defaults.filters.clone();
+                let mut actions = self.defaults.actions.clone();
+
+                if let Some(specific) = specific {
+                    for filter in specific.filters.iter() {
+                        filters.retain(|existing| existing.name != filter.name);
+                        filters.push(filter.clone());
+                    }
+
+                    for rule in specific.actions.iter() {
+                        actions.retain(|existing| {
+                            existing.tag != rule.tag
+                                || existing.action != rule.action
+                        });
+                        actions.push(rule.clone());
+                    }
+                }
+
+                ContentTypePolicy {
+                    name,
+                    content_type: *content_type,
+                    mode: specific
+                        .and_then(|specific| specific.mode)
+                        .or(self.defaults.mode),
+                    filters,
+                    actions,
+                }
+            })
+            .collect()
+    }
+}
+
+// The latest stored policy, or the built-in one when none has been stored.
+pub(crate) async fn load_stored(
+    pool: &::sqlx::PgPool,
+) -> ::anyhow::Result<ModerationPolicy> {
+    let query = "
+        SELECT document::TEXT
+        FROM moderation_policy_versions
+        ORDER BY version DESC
+        LIMIT 1;
+    ";
+
+    let document = ::sqlx::query_scalar::<_, String>(query)
+        .fetch_optional(pool)
+        .await?;
+
+    match document {
+        Some(document) => ModerationPolicy::parse(&document).map_err(|err| {
+            ::anyhow::anyhow!("invalid stored moderation policy: {}", err)
+        }),
+        None => Ok(ModerationPolicy::default()),
+    }
+}
+
+// Replaces the policy applied by `filter_events_by_moderation` and the
+// moderation queue for every server sharing the database. Fails when the
+// stored policy has the same or a higher version.
+pub(crate) async fn store(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    policy: &ModerationPolicy,
+) -> ::anyhow::Result<()> {
+    ::sqlx::query("LOCK TABLE moderation_policy_versions IN EXCLUSIVE MODE;")
+        .execute(&mut **transaction)
+        .await?;
+
+    let stored = ::sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MAX(version) FROM moderation_policy_versions;",
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    let version = i64::try_from(policy.version)?;
+
+    if let Some(stored) = stored {
+        if stored >= version {
+            ::anyhow::bail!(
+                "moderation policy version {} is already stored, refusing \
+                to store version {}",
+                stored,
+                version
+            );
+        }
+    }
+
+    let query = "
+        INSERT INTO moderation_policy_versions (version, document)
+        VALUES ($1, $2::TEXT::JSONB);
+    ";
+
+    ::sqlx::query(query)
+        .bind(version)
+        .bind(::serde_json::to_string(policy)?)
+        .execute(&mut **transaction)
+        .await?;
+
+    ::sqlx::query("DELETE FROM moderation_policy;")
+        .execute(&mut **transaction)
+        .await?;
+
+    let query = "
+        INSERT INTO moderation_policy (
+            content_type,
+            moderation_mode,
+            filters,
+            hide,
+            reject
+        )
+        VALUES ($1, $2, $3, $4, $5);
+    ";
+
+    for content_type in policy.content_types() {
+        ::sqlx::query(query)
+            .bind(i64::try_from(content_type.content_type)?)
+            .bind(content_type.mode)
+            .bind(&content_type.filters)
+            .bind(content_type.tags_for(PolicyAction::Hide))
+            .bind(content_type.tags_for(PolicyAction::Reject))
+            .execute(&mut **transaction)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postgres::prepare_database;
+    use protobuf::Message;
+    use sqlx::PgPool;
+
+    const POLICY: &str = r#"
+        {
+            "version": 2,
+            "defaults": {
+                "filters": [
+                    { "name": "violence", "max_level": 1, "strict_mode": true }
+                ],
+                "actions": [
+                    { "tag": "sexual", "min_level": 3, "action": "reject" }
+                ]
+            },
+            "content_types": {
+                "avatar": { "mode": "strong" },
+                "post": {
+                    "mode": "lazy",
+                    "filters": [
+                        { "name": "violence", "max_level": 2, "strict_mode": false }
+                    ],
+                    "actions": [
+                        { "tag": "hate", "min_level": 2, "action": "hide" }
+                    ]
+                }
+            }
+        }
+    "#;
+
+    fn find<'a>(
+        policies: &'a [ContentTypePolicy],
+        name: &str,
+    ) -> &'a ContentTypePolicy {
+        policies.iter().find(|policy| policy.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_content_types() {
+        let policies = ModerationPolicy::parse(POLICY).unwrap().content_types();
+
+        assert_eq!(policies.len(), MODERATED_CONTENT_TYPES.len());
+
+        let post = find(&policies, "post");
+        assert!(matches!(post.mode, Some(ModerationMode::Lazy)));
+        assert_eq!(post.filters.len(), 1);
+        assert_eq!(post.filters[0].max_level, 2);
+        assert_eq!(post.actions.len(), 2);
+
+        let avatar = find(&policies, "avatar");
+        assert!(matches!(avatar.mode, Some(ModerationMode::Strong)));
+        assert_eq!(avatar.filters[0].max_level, 1);
+        assert_eq!(
+            avatar.tags_for(PolicyAction::Reject),
+            vec![ModerationTag::new("sexual".to_string(), 3)]
+        );
+
+        assert!(find(&policies, "username").mode.is_none());
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        for policy in [
+            r#"{ "version": 1, "content_types": { "follow": {} } }"#,
+            r#"{ "version": 1, "defaults": { "filters": [
+                { "name": "violence", "max_level": 4, "strict_mode": true }
+            ] } }"#,
+            r#"{ "version": 1, "defaults": { "actions": [
+                { "tag": "hate", "min_level": 1, "action": "hide" },
+                { "tag": "hate", "min_level": 2, "action": "hide" }
+            ] } }"#,
+            r#"{ "version": 1, "defaults": { "actions": [
+                { "tag": "hate", "min_level": 1, "action": "delete" }
+            ] } }"#,
+            r#"{ "version": 1, "default": {} }"#,
+            r#"{ "version": 0 }"#,
+            "{}",
+        ] {
+            assert!(ModerationPolicy::parse(policy).is_err(), "{}", policy);
+        }
+
+        assert!(ModerationPolicy::parse(r#"{ "version": 1 }"#).is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_policy_applies(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        store(&mut transaction, &ModerationPolicy::parse(POLICY)?).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        let mut post = polycentric_protocol::protocol::Post::new();
+        post.content = Some("test".to_string());
+
+        for logical_clock in 1..=3 {
+            crate::ingest::ingest_event_postgres(
+                &mut transaction,
+                &polycentric_protocol::test_utils::make_test_event_with_content(
+                    &keypair,
+                    &process,
+                    logical_clock,
+                    polycentric_protocol::model::known_message_types::POST,
+                    &post.write_to_bytes()?,
+                    vec![],
+                ),
+            )
+            .await?;
+        }
+
+        let tags = |name: &str, level| {
+            vec![ModerationTag::new(name.to_string(), level)]
+        };
+
+        for (logical_clock, tags) in
+            [(2, tags("hate", 2)), (3, tags("sexual", 3))]
+        {
+            ::sqlx::query(
+                "
+                UPDATE events
+                SET moderation_status = CASE
+                        WHEN moderation_policy_rejects(content_type, $2)
+                        THEN 'flagged_and_rejected'::moderation_status_enum
+                        ELSE 'approved'::moderation_status_enum
+                    END,
+                    moderation_tags = $2
+                WHERE logical_clock = $1;
+                ",
+            )
+            .bind(logical_clock)
+            .bind(tags)
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        // The unprocessed post is still shown because posts are lazy, the
+        // hidden and rejected ones are not shown even with permissive filters
+        let loaded = crate::postgres::load_posts_before_id(
+            &mut transaction,
+            None,
+            10,
+            &crate::moderation::ModerationOptions {
+                filters: None,
+                mode: ModerationMode::Strong,
+            },
+        )
+        .await?;
+
+        assert_eq!(loaded.events.len(), 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_store_refuses_older_version(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        store(&mut transaction, &ModerationPolicy::parse(POLICY)?).await?;
+        transaction.commit().await?;
+
+        let stored = load_stored(&pool).await?;
+        assert_eq!(stored.version, 2);
+        assert_eq!(
+            stored.content_types(),
+            ModerationPolicy::parse(POLICY)?.content_types()
+        );
+
+        for version in [1, 2] {
+            let mut transaction = pool.begin().await?;
+            let older = ModerationPolicy::parse(&format!(
+                r#"{{ "version": {} }}"#,
+                version
+            ))?;
+            assert!(store(&mut transaction, &older).await.is_err());
+        }
+
+        let mut transaction = pool.begin().await?;
+        store(
+            &mut transaction,
+            &ModerationPolicy::parse(r#"{ "version": 3 }"#)?,
+        )
+        .await?;
+        transaction.commit().await?;
+
+        assert_eq!(load_stored(&pool).await?.version, 3);
+
+        Ok(())
+    }
+}
@@ -3,22 +3,27 @@ use crate::config::Config;
 use self::interface::ModerationCSAMProvider;
 pub mod interface;
 
+pub mod phash;
 pub mod photodna;
+pub mod webhook;
 
 pub async fn make_provider(
     config: &Config,
 ) -> ::anyhow::Result<Box<dyn ModerationCSAMProvider>> {
     match &config.csam_interface {
         Some(interface) => {
-            let mut provider = match interface.as_str() {
-                "photodna" => Box::new(photodna::PhotoDNAProvider::new()),
-                _ => {
-                    return Err(anyhow::anyhow!(
-                        "Unknown provider: {}",
-                        interface
-                    ))
-                }
-            };
+            let mut provider: Box<dyn ModerationCSAMProvider> =
+                match interface.as_str() {
+                    "photodna" => Box::new(photodna::PhotoDNAProvider::new()),
+                    "phash" => Box::new(phash::PerceptualHashProvider::new()),
+                    "webhook" => Box::new(webhook::WebhookCSAMProvider::new()),
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "Unknown provider: {}",
+                            interface
+                        ))
+                    }
+                };
             provider.init(config).await?;
             Ok(provider)
         }
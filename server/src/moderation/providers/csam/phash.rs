@@ -0,0 +1,160 @@
+use super::interface::{ModerationCSAMProvider, ModerationCSAMResult};
+use crate::{
+    config::Config,
+    moderation::{
+        moderation_queue::ModerationQueueItem, providers::PermanentError,
+    },
+};
+use async_trait::async_trait;
+
+// Difference hash: the image is reduced to 9x8 grayscale pixels and each bit
+// records whether a pixel is brighter than its right neighbour. Re-encoded,
+// resized or slightly altered copies of an image hash within a small Hamming
+// distance of each other.
+pub fn difference_hash(image_blob: &[u8]) -> anyhow::Result<u64> {
+    let pixels = image::load_from_memory(image_blob)?
+        .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+        .to_luma8();
+
+    let mut hash = 0u64;
+
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if pixels.get_pixel(x, y)[0] > pixels.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+
+    Ok(hash)
+}
+
+// One 64 bit hash per line in hexadecimal, blank lines and lines starting
+// with # are ignored.
+pub fn parse_hash_list(list: &str) -> anyhow::Result<Vec<u64>> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            u64::from_str_radix(line, 16).map_err(|err| {
+                anyhow::anyhow!("Invalid hash {}: {}", line, err)
+            })
+        })
+        .collect()
+}
+
+pub struct PerceptualHashProvider {
+    hashes: Vec<u64>,
+    max_distance: u32,
+}
+
+impl PerceptualHashProvider {
+    pub fn new() -> Self {
+        PerceptualHashProvider {
+            hashes: vec![],
+            max_distance: 0,
+        }
+    }
+
+    pub fn is_match(&self, hash: u64) -> bool {
+        self.hashes
+            .iter()
+            .any(|known| (known ^ hash).count_ones() <= self.max_distance)
+    }
+}
+
+#[async_trait]
+impl ModerationCSAMProvider for PerceptualHashProvider {
+    async fn init(&mut self, config: &Config) -> anyhow::Result<()> {
+        let path = config
+            .phash_list_path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Perceptual hash list not set"))?;
+
+        self.hashes = parse_hash_list(&tokio::fs::read_to_string(path).await?)?;
+        self.max_distance = config.phash_max_distance;
+        Ok(())
+    }
+
+    async fn moderate(
+        &self,
+        event: &ModerationQueueItem,
+    ) -> anyhow::Result<ModerationCSAMResult> {
+        let image_blob = event.blob.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("No image blob provided in the event")
+        })?;
+
+        // An image that cannot be decoded never will be
+        let hash = difference_hash(image_blob).map_err(|err| {
+            PermanentError(format!("Perceptual hash: {}", err))
+        })?;
+
+        Ok(ModerationCSAMResult {
+            is_csam: self.is_match(hash),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_png(pixel: impl Fn(u32, u32) -> u8) -> Vec<u8> {
+        let buffer = image::ImageBuffer::from_fn(64, 64, |x, y| {
+            image::Luma([pixel(x, y)])
+        });
+
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageLuma8(buffer)
+            .write_to(&mut bytes, image::ImageOutputFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn test_hash_matching() -> anyhow::Result<()> {
+        let gradient = encode_png(|x, _| (x * 4) as u8);
+        let brighter = encode_png(|x, _| (x * 3 + 20) as u8);
+        let reversed = encode_png(|x, _| 255 - (x * 4) as u8);
+
+        let mut provider = PerceptualHashProvider::new();
+        provider.hashes = parse_hash_list(&format!(
+            "# known images\n\n{:016x}\n",
+            difference_hash(&gradient)?
+        ))?;
+        provider.max_distance = 4;
+
+        assert!(provider.is_match(difference_hash(&gradient)?));
+        assert!(provider.is_match(difference_hash(&brighter)?));
+        assert!(!provider.is_match(difference_hash(&reversed)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_hash_list() {
+        assert!(parse_hash_list("not a hash").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_image_is_permanent() {
+        let provider = PerceptualHashProvider::new();
+
+        let item = ModerationQueueItem {
+            id: 1,
+            content: None,
+            blob: Some(b"not an image".to_vec()),
+            blob_db_ids: Some(vec![1]),
+            extra_images: vec![],
+            blocked: false,
+        };
+
+        match provider.moderate(&item).await {
+            Err(err) => {
+                assert!(crate::moderation::providers::is_permanent_error(&err))
+            }
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use super::interface::{ModerationCSAMProvider, ModerationCSAMResult};
+use crate::{
+    config::Config,
+    moderation::{
+        moderation_queue::ModerationQueueItem, providers::webhook::Webhook,
+    },
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct WebhookCSAMResponse {
+    is_csam: bool,
+}
+
+pub struct WebhookCSAMProvider {
+    webhook: Option<Webhook>,
+}
+
+impl WebhookCSAMProvider {
+    pub fn new() -> Self {
+        WebhookCSAMProvider { webhook: None }
+    }
+}
+
+#[async_trait]
+impl ModerationCSAMProvider for WebhookCSAMProvider {
+    async fn init(&mut self, config: &Config) -> anyhow::Result<()> {
+        let url = config
+            .csam_webhook_url
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("CSAM webhook URL not set"))?;
+
+        self.webhook = Some(Webhook::new(
+            url,
+            config.csam_webhook_authorization.clone(),
+            std::time::Duration::from_secs(config.webhook_timeout_seconds),
+        )?);
+        Ok(())
+    }
+
+    async fn moderate(
+        &self,
+        event: &ModerationQueueItem,
+    ) -> anyhow::Result<ModerationCSAMResult> {
+        let webhook = self
+            .webhook
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("CSAM webhook not initialized"))?;
+
+        let response: WebhookCSAMResponse = webhook.call(event).await?;
+
+        Ok(ModerationCSAMResult {
+            is_csam: response.is_csam,
+        })
+    }
+}
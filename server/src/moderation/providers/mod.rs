@@ -1,2 +1,21 @@
 pub mod csam;
 pub mod tags;
+pub mod webhook;
+
+// Providers return this when retrying the same item can never succeed, for
+// example when the classifier rejects the request itself. Any other error is
+// treated as transient and the item is retried.
+#[derive(Debug)]
+pub struct PermanentError(pub String);
+
+impl std::fmt::Display for PermanentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Permanent provider error: {}", self.0)
+    }
+}
+
+impl std::error::Error for PermanentError {}
+
+pub fn is_permanent_error(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<PermanentError>().is_some()
+}
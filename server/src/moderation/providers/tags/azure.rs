@@ -1,4 +1,5 @@
 use super::interface::{ModerationTaggingProvider, ModerationTaggingResult};
+use crate::moderation::providers::PermanentError;
 use crate::{
     config::Config, model::moderation_tag::ModerationTag,
     moderation::moderation_queue::ModerationQueueItem,
@@ -330,11 +331,12 @@ impl ContentSafety {
                             error_code,
                             error.error.message.as_deref().unwrap_or("Unknown")
                         );
-                        Err(anyhow::anyhow!(
-                            "Permanent Azure error: {} - {}",
+                        Err(PermanentError(format!(
+                            "Azure: {} - {}",
                             error_code,
                             error.error.message.as_deref().unwrap_or("Unknown")
                         ))
+                        .into())
                     }
                     "InvalidRequest" => {
                         warn!(
@@ -347,11 +349,26 @@ impl ContentSafety {
                         ))
                     }
                     _ => {
+                        let message =
+                            error.error.message.as_deref().unwrap_or("Unknown");
+
+                        // Images outside the supported dimensions are
+                        // reported with a generic code
+                        if message.contains("width of given image is")
+                            || message.contains("height of given image is")
+                        {
+                            return Err(PermanentError(format!(
+                                "Azure: {} - {}",
+                                error_code, message
+                            ))
+                            .into());
+                        }
+
                         // Other errors might be transient
                         Err(anyhow::anyhow!(
                             "Azure error: {} - {}",
                             error_code,
-                            error.error.message.as_deref().unwrap_or("Unknown")
+                            message
                         ))
                     }
                 }
@@ -0,0 +1,214 @@
+use super::interface::{ModerationTaggingProvider, ModerationTaggingResult};
+use crate::{
+    config::Config, model::moderation_tag::ModerationTag,
+    moderation::moderation_queue::ModerationQueueItem,
+};
+use async_trait::async_trait;
+use regex::Regex;
+use serde::Deserialize;
+
+// A rules file is a JSON list of rules such as
+//
+// [
+//   { "tag": "hate", "level": 2, "keywords": ["..."], "patterns": ["..."] }
+// ]
+//
+// Keywords match whole words regardless of case, patterns are regular
+// expressions matched as written. Every tag named in the file is reported on
+// each event, at the highest level of any matching rule or at level 0, so
+// strict moderation filters treat the event as having been checked.
+#[derive(Debug, Deserialize)]
+pub struct Rule {
+    pub tag: String,
+    pub level: i16,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+struct CompiledRule {
+    tag: String,
+    level: i16,
+    expressions: Vec<Regex>,
+}
+
+pub struct LocalTagProvider {
+    rules: Vec<CompiledRule>,
+    tags: Vec<String>,
+}
+
+impl LocalTagProvider {
+    pub fn new() -> Self {
+        LocalTagProvider {
+            rules: vec![],
+            tags: vec![],
+        }
+    }
+
+    pub fn load_rules(&mut self, rules: Vec<Rule>) -> anyhow::Result<()> {
+        let mut compiled = vec![];
+        let mut tags: Vec<String> = vec![];
+
+        for rule in rules {
+            if !(0..=3).contains(&rule.level) {
+                return Err(anyhow::anyhow!(
+                    "Rule for tag {} has level {}, expected 0 to 3",
+                    rule.tag,
+                    rule.level
+                ));
+            }
+
+            if rule.tag.is_empty()
+                || rule.tag.chars().count() > super::MAX_TAG_NAME_LENGTH
+            {
+                return Err(anyhow::anyhow!(
+                    "Rule tag {:?} must be 1 to {} characters",
+                    rule.tag,
+                    super::MAX_TAG_NAME_LENGTH
+                ));
+            }
+
+            let mut expressions = vec![];
+
+            for keyword in rule.keywords.iter() {
+                expressions.push(Regex::new(&format!(
+                    r"(?i)\b{}\b",
+                    regex::escape(keyword)
+                ))?);
+            }
+
+            for pattern in rule.patterns.iter() {
+                expressions.push(Regex::new(pattern)?);
+            }
+
+            if !tags.contains(&rule.tag) {
+                tags.push(rule.tag.clone());
+            }
+
+            compiled.push(CompiledRule {
+                tag: rule.tag,
+                level: rule.level,
+                expressions,
+            });
+        }
+
+        self.rules = compiled;
+        self.tags = tags;
+        Ok(())
+    }
+
+    pub fn tag_text(&self, text: &str) -> Vec<ModerationTag> {
+        self.tags
+            .iter()
+            .map(|tag| {
+                let level = self
+                    .rules
+                    .iter()
+                    .filter(|rule| &rule.tag == tag)
+                    .filter(|rule| {
+                        rule.expressions
+                            .iter()
+                            .any(|expression| expression.is_match(text))
+                    })
+                    .map(|rule| rule.level)
+                    .max()
+                    .unwrap_or(0);
+
+                ModerationTag::new(tag.clone(), level)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ModerationTaggingProvider for LocalTagProvider {
+    async fn init(&mut self, config: &Config) -> anyhow::Result<()> {
+        let path = config
+            .local_tag_rules_path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Local tag rules path not set"))?;
+
+        let rules: Vec<Rule> =
+            serde_json::from_str(&tokio::fs::read_to_string(path).await?)?;
+
+        self.load_rules(rules)
+    }
+
+    async fn moderate(
+        &self,
+        event: &ModerationQueueItem,
+    ) -> anyhow::Result<ModerationTaggingResult> {
+        // Images are not inspected, only the text of an event
+        Ok(ModerationTaggingResult {
+            tags: self.tag_text(event.content.as_deref().unwrap_or("")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moderation::providers::tags::MAX_TAG_NAME_LENGTH;
+
+    fn level_of(tags: &[ModerationTag], name: &str) -> Option<i16> {
+        tags.iter()
+            .find(|tag| tag.name().to_string() == name)
+            .map(|tag| *tag.level())
+    }
+
+    #[test]
+    fn test_tag_text() -> anyhow::Result<()> {
+        let mut provider = LocalTagProvider::new();
+
+        provider.load_rules(serde_json::from_str(
+            r#"[
+                { "tag": "violence", "level": 1, "keywords": ["fight"] },
+                { "tag": "violence", "level": 2, "patterns": ["k+i+l+l"] },
+                { "tag": "hate", "level": 2, "keywords": ["slur"] }
+            ]"#,
+        )?)?;
+
+        let tags = provider.tag_text("Let's FIGHT");
+        assert_eq!(tags.len(), 2);
+        assert_eq!(level_of(&tags, "violence"), Some(1));
+        assert_eq!(level_of(&tags, "hate"), Some(0));
+
+        let tags = provider.tag_text("fight to kiiill");
+        assert_eq!(level_of(&tags, "violence"), Some(2));
+
+        // Keywords only match whole words
+        let tags = provider.tag_text("fighter");
+        assert_eq!(level_of(&tags, "violence"), Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_level_rejected() {
+        let mut provider = LocalTagProvider::new();
+
+        assert!(provider
+            .load_rules(vec![Rule {
+                tag: "hate".to_string(),
+                level: 4,
+                keywords: vec![],
+                patterns: vec![],
+            }])
+            .is_err());
+    }
+
+    #[test]
+    fn test_long_tag_rejected() {
+        let mut provider = LocalTagProvider::new();
+
+        assert!(provider
+            .load_rules(vec![Rule {
+                tag: "a".repeat(MAX_TAG_NAME_LENGTH + 1),
+                level: 1,
+                keywords: vec![],
+                patterns: vec![],
+            }])
+            .is_err());
+    }
+}
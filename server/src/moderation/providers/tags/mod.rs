@@ -4,24 +4,50 @@ use self::interface::ModerationTaggingProvider;
 pub mod interface;
 
 pub mod azure;
+pub mod local;
+pub mod webhook;
+
+// Tag names are stored as VARCHAR(20) in moderation_tag_type
+pub const MAX_TAG_NAME_LENGTH: usize = 20;
+
+async fn make_provider_for(
+    config: &Config,
+    interface: &str,
+) -> ::anyhow::Result<Box<dyn ModerationTaggingProvider>> {
+    let mut provider: Box<dyn ModerationTaggingProvider> = match interface {
+        "azure" => Box::new(azure::AzureTagProvider::new()),
+        "local" => Box::new(local::LocalTagProvider::new()),
+        "webhook" => Box::new(webhook::WebhookTagProvider::new()),
+        _ => return Err(anyhow::anyhow!("Unknown provider: {}", interface)),
+    };
+    provider.init(config).await?;
+    Ok(provider)
+}
 
 pub async fn make_provider(
     config: &Config,
 ) -> ::anyhow::Result<Box<dyn ModerationTaggingProvider>> {
     match &config.tag_interface {
+        Some(interface) => make_provider_for(config, interface).await,
+        None => Err(anyhow::anyhow!("Tagging interface not set")),
+    }
+}
+
+// The shadow provider reads the same settings as the primary one, so it has
+// to be a different interface to be of any use.
+pub async fn make_shadow_provider(
+    config: &Config,
+) -> ::anyhow::Result<Option<Box<dyn ModerationTaggingProvider>>> {
+    match &config.shadow_tag_interface {
         Some(interface) => {
-            let mut provider = match interface.as_str() {
-                "azure" => Box::new(azure::AzureTagProvider::new()),
-                _ => {
-                    return Err(anyhow::anyhow!(
-                        "Unknown provider: {}",
-                        interface
-                    ))
-                }
-            };
-            provider.init(config).await?;
-            Ok(provider)
+            if config.tag_interface.as_ref() == Some(interface) {
+                return Err(anyhow::anyhow!(
+                    "Shadow tagging interface is the same as the primary: {}",
+                    interface
+                ));
+            }
+            Ok(Some(make_provider_for(config, interface).await?))
         }
-        None => Err(anyhow::anyhow!("Tagging interface not set")),
+        None => Ok(None),
     }
 }
@@ -0,0 +1,110 @@
+use super::interface::{ModerationTaggingProvider, ModerationTaggingResult};
+use crate::{
+    config::Config,
+    model::moderation_tag::ModerationTag,
+    moderation::{
+        moderation_queue::ModerationQueueItem,
+        providers::{webhook::Webhook, PermanentError},
+    },
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct WebhookTaggingResponse {
+    tags: Vec<ModerationTag>,
+}
+
+// Tags the database could not store are rejected, the webhook would return
+// them again on a retry
+fn check_tags(tags: &[ModerationTag]) -> anyhow::Result<()> {
+    if let Some(tag) = tags.iter().find(|tag| !(0..=3).contains(tag.level())) {
+        return Err(PermanentError(format!(
+            "Webhook returned tag {} with level {}",
+            tag.name(),
+            tag.level()
+        ))
+        .into());
+    }
+
+    if let Some(tag) = tags.iter().find(|tag| {
+        let length = tag.name().to_string().chars().count();
+        length == 0 || length > super::MAX_TAG_NAME_LENGTH
+    }) {
+        return Err(PermanentError(format!(
+            "Webhook returned tag {:?}, expected 1 to {} characters",
+            tag.name().to_string(),
+            super::MAX_TAG_NAME_LENGTH
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+pub struct WebhookTagProvider {
+    webhook: Option<Webhook>,
+}
+
+impl WebhookTagProvider {
+    pub fn new() -> Self {
+        WebhookTagProvider { webhook: None }
+    }
+}
+
+#[async_trait]
+impl ModerationTaggingProvider for WebhookTagProvider {
+    async fn init(&mut self, config: &Config) -> anyhow::Result<()> {
+        let url = config
+            .tag_webhook_url
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Tag webhook URL not set"))?;
+
+        self.webhook = Some(Webhook::new(
+            url,
+            config.tag_webhook_authorization.clone(),
+            std::time::Duration::from_secs(config.webhook_timeout_seconds),
+        )?);
+        Ok(())
+    }
+
+    async fn moderate(
+        &self,
+        event: &ModerationQueueItem,
+    ) -> anyhow::Result<ModerationTaggingResult> {
+        let webhook = self
+            .webhook
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Tag webhook not initialized"))?;
+
+        let response: WebhookTaggingResponse = webhook.call(event).await?;
+
+        check_tags(&response.tags)?;
+
+        Ok(ModerationTaggingResult {
+            tags: response.tags,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moderation::providers::is_permanent_error;
+
+    #[test]
+    fn test_check_tags() {
+        let tag =
+            |name: &str, level| ModerationTag::new(name.to_string(), level);
+
+        assert!(check_tags(&[tag("violence", 3), tag("hate", 0)]).is_ok());
+
+        for tags in [
+            vec![tag("violence", 4)],
+            vec![tag("", 1)],
+            vec![tag("hate", 1), tag(&"a".repeat(21), 1)],
+        ] {
+            assert!(is_permanent_error(&check_tags(&tags).unwrap_err()));
+        }
+    }
+}
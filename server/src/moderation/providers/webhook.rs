@@ -0,0 +1,185 @@
+use super::PermanentError;
+use crate::moderation::moderation_queue::ModerationQueueItem;
+use reqwest::{Client, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+
+// Webhook providers POST each queue item to an operator configured URL as
+//
+// { "id": 1, "text": "..." | null, "image": "<base64>" | null }
+//
+// Tagging webhooks respond with
+//
+// { "tags": [{ "name": "hate", "level": 0 }, ...] }
+//
+// and CSAM webhooks respond with
+//
+// { "is_csam": false }
+//
+// 408, 429 and 5xx responses or failing to connect are retried later. Any
+// other non 2xx response, or a 2xx response that does not parse, means the
+// item can never be classified.
+#[derive(Debug, Serialize)]
+pub struct WebhookRequest<'a> {
+    pub id: i64,
+    pub text: Option<&'a str>,
+    pub image: Option<String>,
+}
+
+impl<'a> WebhookRequest<'a> {
+    pub fn from_item(item: &'a ModerationQueueItem) -> Self {
+        WebhookRequest {
+            id: item.id,
+            text: item.content.as_deref(),
+            image: item.blob.as_ref().map(base64::encode),
+        }
+    }
+}
+
+pub fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
+pub struct Webhook {
+    url: String,
+    authorization: Option<String>,
+    client: Client,
+}
+
+impl Webhook {
+    pub fn new(
+        url: String,
+        authorization: Option<String>,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<Self> {
+        Ok(Webhook {
+            url,
+            authorization,
+            client: Client::builder().timeout(timeout).build()?,
+        })
+    }
+
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        item: &ModerationQueueItem,
+    ) -> anyhow::Result<T> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&WebhookRequest::from_item(item))?);
+
+        if let Some(authorization) = &self.authorization {
+            request = request.header("authorization", authorization);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            let body = response.text().await?;
+
+            return serde_json::from_str(&body).map_err(|err| {
+                PermanentError(format!(
+                    "Webhook: invalid response {} - {}",
+                    err, body
+                ))
+                .into()
+            });
+        }
+
+        let body = response.text().await.unwrap_or_default();
+
+        if is_transient_status(status) {
+            Err(anyhow::anyhow!("Webhook error: {} - {}", status, body))
+        } else {
+            Err(PermanentError(format!("Webhook: {} - {}", status, body))
+                .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moderation::providers::is_permanent_error;
+    use warp::Filter;
+
+    // Stub classifier answering every request with the given status and body
+    fn serve_stub(
+        status: warp::http::StatusCode,
+        body: &'static str,
+    ) -> std::net::SocketAddr {
+        let route = warp::post().and(warp::body::json()).map(
+            move |request: serde_json::Value| {
+                assert!(request.get("id").is_some());
+                warp::reply::with_status(body, status)
+            },
+        );
+
+        let (address, server) =
+            warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        address
+    }
+
+    fn item() -> ModerationQueueItem {
+        ModerationQueueItem {
+            id: 1,
+            content: Some("text".to_string()),
+            blob: Some(vec![1, 2, 3]),
+            blob_db_ids: None,
+            extra_images: vec![],
+            blocked: false,
+        }
+    }
+
+    fn webhook(address: std::net::SocketAddr) -> Webhook {
+        Webhook::new(
+            format!("http://{}/", address),
+            None,
+            std::time::Duration::from_secs(5),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_webhook_success() -> anyhow::Result<()> {
+        let address =
+            serve_stub(warp::http::StatusCode::OK, r#"{ "is_csam": true }"#);
+
+        let response: serde_json::Value =
+            webhook(address).call(&item()).await?;
+
+        assert_eq!(response["is_csam"], true);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_webhook_error_classification() {
+        let transient =
+            serve_stub(warp::http::StatusCode::SERVICE_UNAVAILABLE, "");
+        let permanent = serve_stub(warp::http::StatusCode::BAD_REQUEST, "");
+        let unparsable = serve_stub(warp::http::StatusCode::OK, "not json");
+
+        let error = webhook(transient)
+            .call::<serde_json::Value>(&item())
+            .await
+            .unwrap_err();
+        assert!(!is_permanent_error(&error));
+
+        let error = webhook(permanent)
+            .call::<serde_json::Value>(&item())
+            .await
+            .unwrap_err();
+        assert!(is_permanent_error(&error));
+
+        let error = webhook(unparsable)
+            .call::<serde_json::Value>(&item())
+            .await
+            .unwrap_err();
+        assert!(is_permanent_error(&error));
+    }
+}
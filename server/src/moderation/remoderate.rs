@@ -0,0 +1,662 @@
+use ::cadence::{Counted, Gauged};
+use log::{info, warn};
+
+use super::moderation_queue::{
+    make_queue_item, process_event, ModerationResult, ModerationStatus,
+    RateLimiter,
+};
+use super::providers;
+
+// Runs events that were already approved through the tagging provider again,
+// for when the provider or its thresholds change. Progress is stored per job
+// in `remoderation_cursors` so that an interrupted run resumes where it
+// stopped. Events the provider failed on are recorded in
+// `remoderation_failures` and retried by the next run of the job. Events with
+// a manual review are left alone.
+
+pub(crate) struct RemoderateOptions {
+    pub(crate) job: String,
+    // Every moderated content type when empty
+    pub(crate) content_types: ::std::vec::Vec<i64>,
+    // Bounds on the server time of the event, in unix seconds
+    pub(crate) since: Option<u64>,
+    pub(crate) until: Option<u64>,
+    // Only events that currently have this tag at `min_level` or above
+    pub(crate) tag: Option<String>,
+    pub(crate) min_level: Option<i16>,
+    // Overrides the stored cursor
+    pub(crate) position: Option<i64>,
+    pub(crate) batch_size: u64,
+}
+
+#[derive(::sqlx::FromRow)]
+struct CandidateRow {
+    id: i64,
+    raw_event: ::std::vec::Vec<u8>,
+}
+
+async fn load_position(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    job: &str,
+) -> ::anyhow::Result<i64> {
+    let position: Option<i64> = ::sqlx::query_scalar(
+        "SELECT position FROM remoderation_cursors WHERE job = $1;",
+    )
+    .bind(job)
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    Ok(position.unwrap_or(0))
+}
+
+async fn store_position(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    job: &str,
+    position: i64,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO remoderation_cursors (job, position)
+        VALUES ($1, $2)
+        ON CONFLICT (job) DO UPDATE
+        SET position = EXCLUDED.position,
+            updated_on = NOW();
+    ";
+
+    ::sqlx::query(query)
+        .bind(job)
+        .bind(position)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+async fn record_failure(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    job: &str,
+    event_id: i64,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO remoderation_failures (job, event_id)
+        VALUES ($1, $2)
+        ON CONFLICT (job, event_id) DO UPDATE
+        SET attempts = remoderation_failures.attempts + 1,
+            failed_on = NOW();
+    ";
+
+    ::sqlx::query(query)
+        .bind(job)
+        .bind(event_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+async fn clear_failure(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    job: &str,
+    event_id: i64,
+) -> ::anyhow::Result<()> {
+    let query = "
+        DELETE FROM remoderation_failures
+        WHERE job = $1
+        AND event_id = $2;
+    ";
+
+    ::sqlx::query(query)
+        .bind(job)
+        .bind(event_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+// Events that are no longer approved, or were reviewed since, are still
+// returned. `update_tags` skips them and their failure is cleared.
+async fn load_failures(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    options: &RemoderateOptions,
+    position: i64,
+) -> ::anyhow::Result<::std::vec::Vec<CandidateRow>> {
+    let query = "
+        SELECT e.id, e.raw_event
+        FROM remoderation_failures f
+        JOIN events e ON e.id = f.event_id
+        WHERE f.job = $1
+        AND f.event_id > $2
+        ORDER BY f.event_id ASC
+        LIMIT $3;
+    ";
+
+    Ok(::sqlx::query_as::<_, CandidateRow>(query)
+        .bind(&options.job)
+        .bind(position)
+        .bind(i64::try_from(options.batch_size)?)
+        .fetch_all(&mut **transaction)
+        .await?)
+}
+
+async fn load_candidates(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    options: &RemoderateOptions,
+    position: i64,
+) -> ::anyhow::Result<::std::vec::Vec<CandidateRow>> {
+    let query = format!(
+        "
+        SELECT e.id, e.raw_event
+        FROM events e
+        WHERE e.id > $1
+        AND e.content_type IN ({})
+        AND (cardinality($3::INT8[]) = 0 OR e.content_type = ANY($3))
+        AND e.moderation_status = 'approved'
+        AND ($4::INT8 IS NULL OR e.server_time >= $4)
+        AND ($5::INT8 IS NULL OR e.server_time < $5)
+        AND (
+            $6::TEXT IS NULL
+            OR EXISTS (
+                SELECT 1 FROM unnest(e.moderation_tags) AS tag
+                WHERE tag.name = $6
+                AND tag.level >= COALESCE($7::INT2, 0)
+            )
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM moderation_reviews r WHERE r.event_id = e.id
+        )
+        ORDER BY e.id ASC
+        LIMIT $2;
+    ",
+        super::extract::moderated_content_types_sql()
+    );
+
+    Ok(::sqlx::query_as::<_, CandidateRow>(&query)
+        .bind(position)
+        .bind(i64::try_from(options.batch_size)?)
+        .bind(&options.content_types)
+        .bind(options.since.map(i64::try_from).transpose()?)
+        .bind(options.until.map(i64::try_from).transpose()?)
+        .bind(&options.tag)
+        .bind(options.min_level)
+        .fetch_all(&mut **transaction)
+        .await?)
+}
+
+// Returns whether the event was updated. An event reviewed or changed while
+// it was being tagged is skipped.
+async fn update_tags(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: i64,
+    tags: &[crate::model::moderation_tag::ModerationTag],
+) -> ::anyhow::Result<bool> {
+    let query = "
+        UPDATE events
+        SET moderation_status = CASE
+                WHEN moderation_policy_rejects(content_type, $2::moderation_tag_type[])
+                THEN $3
+                ELSE moderation_status
+            END,
+            moderation_tags = $2::moderation_tag_type[]
+        WHERE id = $1
+        AND moderation_status = $4
+        AND NOT EXISTS (
+            SELECT 1 FROM moderation_reviews r WHERE r.event_id = events.id
+        )
+        RETURNING moderation_status;
+    ";
+
+    let status: Option<ModerationStatus> = ::sqlx::query_scalar(query)
+        .bind(id)
+        .bind(tags)
+        .bind(ModerationStatus::FlaggedAndRejected)
+        .bind(ModerationStatus::Approved)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    if status == Some(ModerationStatus::FlaggedAndRejected) {
+        crate::search_indexer::remove_event(transaction, id).await?;
+    }
+
+    if status.is_some() {
+        crate::cache::purge_queue::enqueue_event(transaction, id).await?;
+    }
+
+    Ok(status.is_some())
+}
+
+struct Limiters {
+    request: RateLimiter,
+    csam_request: RateLimiter,
+}
+
+async fn tag_candidates(
+    pool: &::sqlx::PgPool,
+    tag: &dyn providers::tags::interface::ModerationTaggingProvider,
+    limiters: &Limiters,
+    candidates: &[CandidateRow],
+) -> ::anyhow::Result<::std::vec::Vec<ModerationResult>> {
+    let mut transaction = pool.begin().await?;
+
+    let mut items = vec![];
+    for candidate in candidates.iter() {
+        items.push(
+            make_queue_item(
+                &mut transaction,
+                candidate.id,
+                &candidate.raw_event,
+            )
+            .await?,
+        );
+    }
+    transaction.commit().await?;
+
+    let mut results = vec![];
+    for item in items.iter() {
+        results.push(
+            process_event(
+                None,
+                Some(tag),
+                None,
+                item,
+                &limiters.request,
+                &limiters.csam_request,
+            )
+            .await,
+        );
+    }
+
+    Ok(results)
+}
+
+// Returns the number of events updated and failed
+async fn apply_results(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    job: &str,
+    results: &[ModerationResult],
+) -> ::anyhow::Result<(i64, i64)> {
+    let mut updated = 0;
+    let mut failed = 0;
+
+    for result in results.iter() {
+        if result.has_error {
+            warn!("remoderation of event {} failed", result.event_id);
+            record_failure(transaction, job, result.event_id).await?;
+            failed += 1;
+            continue;
+        }
+
+        clear_failure(transaction, job, result.event_id).await?;
+
+        if update_tags(transaction, result.event_id, &result.tags).await? {
+            updated += 1;
+        }
+    }
+
+    Ok((updated, failed))
+}
+
+fn send_counts(
+    statsd_client: &::cadence::StatsdClient,
+    job: &str,
+    processed: usize,
+    updated: i64,
+    failed: i64,
+) -> ::anyhow::Result<()> {
+    statsd_client
+        .count_with_tags("remoderate_processed", i64::try_from(processed)?)
+        .with_tag("job", job)
+        .try_send()?;
+    statsd_client
+        .count_with_tags("remoderate_updated", updated)
+        .with_tag("job", job)
+        .try_send()?;
+    statsd_client
+        .count_with_tags("remoderate_failed", failed)
+        .with_tag("job", job)
+        .try_send()?;
+
+    Ok(())
+}
+
+pub(crate) async fn run(
+    pool: ::sqlx::PgPool,
+    tag: &dyn providers::tags::interface::ModerationTaggingProvider,
+    tagging_request_rate_limit: u16,
+    statsd_client: ::cadence::StatsdClient,
+    options: RemoderateOptions,
+) -> ::anyhow::Result<()> {
+    // CSAM detection is not repeated, so its limiter is never used. The
+    // result cache is bypassed too, the point is to ask the provider again.
+    let limiters = Limiters {
+        request: RateLimiter::new(
+            tagging_request_rate_limit,
+            tagging_request_rate_limit,
+        ),
+        csam_request: RateLimiter::new(1, 1),
+    };
+
+    // Failures of earlier runs go first, those failing again stay recorded
+    let mut retry_position = 0;
+
+    loop {
+        let mut transaction = pool.begin().await?;
+        let candidates =
+            load_failures(&mut transaction, &options, retry_position).await?;
+        transaction.commit().await?;
+
+        let last = match candidates.last() {
+            Some(last) => last.id,
+            None => break,
+        };
+
+        let results =
+            tag_candidates(&pool, tag, &limiters, &candidates).await?;
+
+        let mut transaction = pool.begin().await?;
+        let (updated, failed) =
+            apply_results(&mut transaction, &options.job, &results).await?;
+        transaction.commit().await?;
+
+        retry_position = last;
+
+        send_counts(
+            &statsd_client,
+            &options.job,
+            results.len(),
+            updated,
+            failed,
+        )?;
+
+        info!(
+            "retried {} failed events, {} updated, {} failed again",
+            results.len(),
+            updated,
+            failed
+        );
+    }
+
+    let mut position = match options.position {
+        Some(position) => position,
+        None => {
+            let mut transaction = pool.begin().await?;
+            let position =
+                load_position(&mut transaction, &options.job).await?;
+            transaction.commit().await?;
+            position
+        }
+    };
+
+    info!(
+        "remoderating job {} from position {}",
+        options.job, position
+    );
+
+    loop {
+        let mut transaction = pool.begin().await?;
+        let candidates =
+            load_candidates(&mut transaction, &options, position).await?;
+        transaction.commit().await?;
+
+        let last = match candidates.last() {
+            Some(last) => last.id,
+            None => break,
+        };
+
+        let results =
+            tag_candidates(&pool, tag, &limiters, &candidates).await?;
+
+        // Failures are recorded along with the new position, so the cursor
+        // can move past them
+        let mut transaction = pool.begin().await?;
+        let (updated, failed) =
+            apply_results(&mut transaction, &options.job, &results).await?;
+        store_position(&mut transaction, &options.job, last).await?;
+        transaction.commit().await?;
+
+        position = last;
+
+        send_counts(
+            &statsd_client,
+            &options.job,
+            results.len(),
+            updated,
+            failed,
+        )?;
+        statsd_client
+            .gauge_with_tags("remoderate_position", u64::try_from(position)?)
+            .with_tag("job", &options.job)
+            .try_send()?;
+
+        info!(
+            "remoderated {} events up to {}, {} updated, {} failed",
+            results.len(),
+            position,
+            updated,
+            failed
+        );
+    }
+
+    info!("remoderation job {} complete at {}", options.job, position);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::moderation_tag::ModerationTag;
+    use crate::postgres::prepare_database;
+    use sqlx::PgPool;
+
+    async fn ingest_approved(
+        transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+        content_type: u64,
+        tags: &[ModerationTag],
+    ) -> anyhow::Result<i64> {
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        crate::ingest::ingest_event_postgres(
+            transaction,
+            &polycentric_protocol::test_utils::make_test_event_with_content(
+                &keypair,
+                &process,
+                1,
+                content_type,
+                &polycentric_protocol::test_utils::make_test_post_content(),
+                vec![],
+            ),
+        )
+        .await?;
+
+        Ok(sqlx::query_scalar(
+            "
+            UPDATE events
+            SET moderation_status = 'approved', moderation_tags = $1
+            WHERE id = (SELECT MAX(id) FROM events)
+            RETURNING id;
+            ",
+        )
+        .bind(tags)
+        .fetch_one(&mut **transaction)
+        .await?)
+    }
+
+    fn options() -> RemoderateOptions {
+        RemoderateOptions {
+            job: "test".to_string(),
+            content_types: vec![],
+            since: None,
+            until: None,
+            tag: None,
+            min_level: None,
+            position: None,
+            batch_size: 10,
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_candidates(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let violent = ingest_approved(
+            &mut transaction,
+            3,
+            &[ModerationTag::new("violence".to_string(), 2)],
+        )
+        .await?;
+        let plain = ingest_approved(&mut transaction, 3, &[]).await?;
+        let reviewed = ingest_approved(&mut transaction, 3, &[]).await?;
+
+        assert!(
+            crate::moderation::review::apply_review(
+                &mut transaction,
+                reviewed,
+                crate::moderation::review::ReviewAction::Approve,
+                None,
+                "alice",
+                None,
+            )
+            .await?
+        );
+
+        let ids = |candidates: ::std::vec::Vec<CandidateRow>| {
+            candidates.iter().map(|row| row.id).collect::<Vec<i64>>()
+        };
+
+        assert_eq!(
+            ids(load_candidates(&mut transaction, &options(), 0).await?),
+            vec![violent, plain]
+        );
+        assert_eq!(
+            ids(load_candidates(&mut transaction, &options(), violent).await?),
+            vec![plain]
+        );
+        assert_eq!(
+            ids(load_candidates(
+                &mut transaction,
+                &RemoderateOptions {
+                    tag: Some("violence".to_string()),
+                    min_level: Some(2),
+                    ..options()
+                },
+                0
+            )
+            .await?),
+            vec![violent]
+        );
+        assert!(load_candidates(
+            &mut transaction,
+            &RemoderateOptions {
+                content_types: vec![6],
+                ..options()
+            },
+            0
+        )
+        .await?
+        .is_empty());
+
+        assert!(update_tags(&mut transaction, plain, &[]).await?);
+        assert!(!update_tags(&mut transaction, reviewed, &[]).await?);
+
+        store_position(&mut transaction, "test", plain).await?;
+        assert_eq!(load_position(&mut transaction, "test").await?, plain);
+        assert_eq!(load_position(&mut transaction, "other").await?, 0);
+
+        Ok(())
+    }
+    struct FixedProvider {
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl providers::tags::interface::ModerationTaggingProvider for FixedProvider {
+        async fn init(
+            &mut self,
+            _config: &crate::config::Config,
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn moderate(
+            &self,
+            _event: &crate::moderation::moderation_queue::ModerationQueueItem,
+        ) -> anyhow::Result<providers::tags::interface::ModerationTaggingResult>
+        {
+            if self.fail {
+                return Err(anyhow::anyhow!("unavailable"));
+            }
+
+            Ok(providers::tags::interface::ModerationTaggingResult {
+                tags: vec![ModerationTag::new("violence".to_string(), 1)],
+            })
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_failures_are_retried(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        let first = ingest_approved(&mut transaction, 3, &[]).await?;
+        let second = ingest_approved(&mut transaction, 3, &[]).await?;
+        transaction.commit().await?;
+
+        let statsd_client = || {
+            ::cadence::StatsdClient::from_sink(
+                "polycentric-server",
+                ::cadence::NopMetricSink,
+            )
+        };
+
+        let failures = || async {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT event_id FROM remoderation_failures ORDER BY event_id;",
+            )
+            .fetch_all(&pool)
+            .await
+        };
+
+        // The cursor moves past the failures, which are kept
+        run(
+            pool.clone(),
+            &FixedProvider { fail: true },
+            100,
+            statsd_client(),
+            options(),
+        )
+        .await?;
+
+        assert_eq!(failures().await?, vec![first, second]);
+
+        let mut transaction = pool.begin().await?;
+        assert_eq!(load_position(&mut transaction, "test").await?, second);
+        transaction.commit().await?;
+
+        run(
+            pool.clone(),
+            &FixedProvider { fail: false },
+            100,
+            statsd_client(),
+            options(),
+        )
+        .await?;
+
+        assert!(failures().await?.is_empty());
+
+        let tags: ::std::vec::Vec<::std::vec::Vec<ModerationTag>> =
+            sqlx::query_scalar(
+                "SELECT moderation_tags FROM events WHERE id = ANY($1);",
+            )
+            .bind(vec![first, second])
+            .fetch_all(&pool)
+            .await?;
+
+        assert_eq!(
+            tags,
+            vec![vec![ModerationTag::new("violence".to_string(), 1)]; 2]
+        );
+
+        Ok(())
+    }
+}
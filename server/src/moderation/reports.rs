@@ -0,0 +1,521 @@
+use super::moderation_queue::ModerationStatus;
+
+// Reports sent by users through `POST /report`, one per reporter and target.
+// Reports raise the priority of the reported events in the moderation queue.
+// Once enough people report an event since its last review it is escalated:
+// it shows up as such in the review API and, unless a reviewer already
+// decided on it, goes back through the tagging provider. Reporters are free
+// keys, so reports alone never hide an event, it stays visible until the
+// provider or a reviewer rejects it.
+
+#[derive(::sqlx::Type, Clone, Copy, PartialEq, Debug)]
+#[sqlx(type_name = "report_reason_enum", rename_all = "snake_case")]
+#[derive(::serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReportReason {
+    Spam,
+    Harassment,
+    Hate,
+    Violence,
+    Sexual,
+    Csam,
+    Impersonation,
+    Other,
+}
+
+impl TryFrom<u64> for ReportReason {
+    type Error = ::anyhow::Error;
+
+    fn try_from(reason: u64) -> ::anyhow::Result<Self> {
+        match reason {
+            1 => Ok(ReportReason::Spam),
+            2 => Ok(ReportReason::Harassment),
+            3 => Ok(ReportReason::Hate),
+            4 => Ok(ReportReason::Violence),
+            5 => Ok(ReportReason::Sexual),
+            6 => Ok(ReportReason::Csam),
+            7 => Ok(ReportReason::Impersonation),
+            8 => Ok(ReportReason::Other),
+            _ => Err(::anyhow::anyhow!("unknown report reason {}", reason)),
+        }
+    }
+}
+
+pub(crate) enum ReportTarget {
+    Event(polycentric_protocol::model::pointer::Pointer),
+    System(polycentric_protocol::model::public_key::PublicKey),
+}
+
+#[derive(PartialEq, Debug)]
+pub(crate) enum SubmitResult {
+    Stored,
+    Escalated,
+    UnknownEvent,
+}
+
+#[derive(::sqlx::FromRow, Debug)]
+pub(crate) struct Report {
+    pub(crate) id: i64,
+    pub(crate) reporter_key_type: i64,
+    pub(crate) reporter_key: ::std::vec::Vec<u8>,
+    pub(crate) target_key_type: i64,
+    pub(crate) target_key: ::std::vec::Vec<u8>,
+    pub(crate) event_id: Option<i64>,
+    pub(crate) reason: ReportReason,
+    pub(crate) note: Option<String>,
+    // unix milliseconds
+    pub(crate) created_on: i64,
+}
+
+async fn find_event(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    pointer: &polycentric_protocol::model::pointer::Pointer,
+) -> ::anyhow::Result<Option<i64>> {
+    let query = "
+        SELECT id FROM events
+        WHERE system_key_type = $1
+        AND system_key = $2
+        AND process = $3
+        AND logical_clock = $4;
+    ";
+
+    Ok(::sqlx::query_scalar(query)
+        .bind(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(
+                pointer.system(),
+            ),
+        )?)
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            pointer.system(),
+        ))
+        .bind(pointer.process().bytes())
+        .bind(i64::try_from(*pointer.logical_clock())?)
+        .fetch_optional(&mut **transaction)
+        .await?)
+}
+
+// Escalates the event once `threshold` people reported it since it was last
+// reviewed. Returns true only the first time.
+async fn escalate(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: i64,
+    threshold: u64,
+) -> ::anyhow::Result<bool> {
+    let query = "
+        WITH counted AS (
+            SELECT COUNT(*) AS reports
+            FROM moderation_reports r
+            WHERE r.event_id = $1
+            AND r.created_on > COALESCE((
+                SELECT reviewed_on FROM moderation_reviews
+                WHERE event_id = $1
+            ), '-infinity')
+        )
+        INSERT INTO moderation_escalations (event_id, reports)
+        SELECT $1, reports FROM counted WHERE reports >= $2
+        ON CONFLICT (event_id) DO UPDATE
+        SET reports = EXCLUDED.reports
+        RETURNING (xmax = 0) AS inserted;
+    ";
+
+    let inserted: Option<bool> = ::sqlx::query_scalar(query)
+        .bind(event_id)
+        .bind(i64::try_from(threshold)?)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    if inserted != Some(true) {
+        return Ok(false);
+    }
+
+    // Tagged again with the priority its reports give it, see
+    // `pull_queue_events`. Events a reviewer decided on are left to the
+    // reviewers.
+    let recheck_query = "
+        UPDATE moderation_escalations
+        SET recheck = TRUE
+        WHERE event_id = $1
+        AND EXISTS (
+            SELECT 1 FROM events
+            WHERE id = $1
+            AND moderation_status = $2
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM moderation_reviews r WHERE r.event_id = $1
+        );
+    ";
+
+    ::sqlx::query(recheck_query)
+        .bind(event_id)
+        .bind(ModerationStatus::Approved)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(true)
+}
+
+// Counts a new report towards the priority of the target in the moderation
+// queue, see `pull_queue_events`.
+async fn count_report(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+    event_id: Option<i64>,
+) -> ::anyhow::Result<()> {
+    match event_id {
+        Some(event_id) => {
+            let query = "
+                INSERT INTO moderation_event_report_counts (event_id, reports)
+                VALUES ($1, 1)
+                ON CONFLICT (event_id) DO UPDATE
+                SET reports = moderation_event_report_counts.reports + 1;
+            ";
+
+            ::sqlx::query(query)
+                .bind(event_id)
+                .execute(&mut **transaction)
+                .await?;
+        }
+        None => {
+            let query = "
+                INSERT INTO moderation_system_report_counts (
+                    system_key_type,
+                    system_key,
+                    reports
+                )
+                VALUES ($1, $2, 1)
+                ON CONFLICT (system_key_type, system_key) DO UPDATE
+                SET reports = moderation_system_report_counts.reports + 1;
+            ";
+
+            ::sqlx::query(query)
+                .bind(i64::try_from(
+                    polycentric_protocol::model::public_key::get_key_type(
+                        system,
+                    ),
+                )?)
+                .bind(polycentric_protocol::model::public_key::get_key_bytes(
+                    system,
+                ))
+                .execute(&mut **transaction)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// A reporter reporting the same target again replaces their reason and note.
+pub(crate) async fn submit(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    reporter: &polycentric_protocol::model::public_key::PublicKey,
+    target: &ReportTarget,
+    reason: ReportReason,
+    note: Option<&str>,
+    threshold: u64,
+) -> ::anyhow::Result<SubmitResult> {
+    let (target_system, event_id) = match target {
+        ReportTarget::Event(pointer) => {
+            match find_event(transaction, pointer).await? {
+                Some(event_id) => (pointer.system(), Some(event_id)),
+                None => return Ok(SubmitResult::UnknownEvent),
+            }
+        }
+        ReportTarget::System(system) => (system, None),
+    };
+
+    let query = "
+        INSERT INTO moderation_reports (
+            reporter_key_type,
+            reporter_key,
+            target_key_type,
+            target_key,
+            event_id,
+            reason,
+            note
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (
+            reporter_key_type,
+            reporter_key,
+            target_key_type,
+            target_key,
+            COALESCE(event_id, 0)
+        ) DO UPDATE
+        SET reason = EXCLUDED.reason,
+            note = EXCLUDED.note
+        RETURNING (xmax = 0) AS inserted;
+    ";
+
+    let inserted: bool = ::sqlx::query_scalar(query)
+        .bind(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(reporter),
+        )?)
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            reporter,
+        ))
+        .bind(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(
+                target_system,
+            ),
+        )?)
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            target_system,
+        ))
+        .bind(event_id)
+        .bind(reason)
+        .bind(note)
+        .fetch_one(&mut **transaction)
+        .await?;
+
+    if inserted {
+        count_report(transaction, target_system, event_id).await?;
+    }
+
+    if let Some(event_id) = event_id {
+        if escalate(transaction, event_id, threshold).await? {
+            return Ok(SubmitResult::Escalated);
+        }
+    }
+
+    Ok(SubmitResult::Stored)
+}
+
+pub(crate) async fn list_reports(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: Option<i64>,
+    after: i64,
+    limit: u64,
+) -> ::anyhow::Result<::std::vec::Vec<Report>> {
+    let query = "
+        SELECT
+            id,
+            reporter_key_type,
+            reporter_key,
+            target_key_type,
+            target_key,
+            event_id,
+            reason,
+            note,
+            (EXTRACT(EPOCH FROM created_on) * 1000)::INT8 AS created_on
+        FROM moderation_reports
+        WHERE id > $1
+        AND ($3::INT8 IS NULL OR event_id = $3)
+        ORDER BY id ASC
+        LIMIT $2;
+    ";
+
+    Ok(::sqlx::query_as::<_, Report>(query)
+        .bind(after)
+        .bind(i64::try_from(limit)?)
+        .bind(event_id)
+        .fetch_all(&mut **transaction)
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postgres::prepare_database;
+    use sqlx::PgPool;
+
+    async fn ingest_approved(
+        transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    ) -> anyhow::Result<polycentric_protocol::model::pointer::Pointer> {
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+        let signed_event = polycentric_protocol::test_utils::make_test_event(
+            &keypair, &process, 1,
+        );
+
+        crate::ingest::ingest_event_postgres(transaction, &signed_event)
+            .await?;
+
+        sqlx::query("UPDATE events SET moderation_status = 'approved';")
+            .execute(&mut **transaction)
+            .await?;
+
+        polycentric_protocol::model::pointer::from_signed_event(&signed_event)
+    }
+
+    async fn report(
+        transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+        target: &ReportTarget,
+    ) -> anyhow::Result<SubmitResult> {
+        let reporter =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        submit(transaction, &reporter, target, ReportReason::Spam, None, 2)
+            .await
+    }
+
+    async fn status(
+        transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    ) -> anyhow::Result<ModerationStatus> {
+        Ok(sqlx::query_scalar("SELECT moderation_status FROM events;")
+            .fetch_one(&mut **transaction)
+            .await?)
+    }
+
+    #[test]
+    fn test_reason() {
+        assert_eq!(ReportReason::try_from(1).unwrap(), ReportReason::Spam);
+        assert_eq!(ReportReason::try_from(8).unwrap(), ReportReason::Other);
+        assert!(ReportReason::try_from(0).is_err());
+        assert!(ReportReason::try_from(9).is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_submit(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let pointer = ingest_approved(&mut transaction).await?;
+        let target = ReportTarget::Event(pointer.clone());
+
+        let reporter =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        // The same reporter only counts once
+        for reason in [ReportReason::Spam, ReportReason::Hate] {
+            assert_eq!(
+                submit(
+                    &mut transaction,
+                    &reporter,
+                    &target,
+                    reason,
+                    Some("note"),
+                    2,
+                )
+                .await?,
+                SubmitResult::Stored
+            );
+        }
+
+        let reports = list_reports(&mut transaction, None, 0, 10).await?;
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].reason, ReportReason::Hate);
+        assert_eq!(status(&mut transaction).await?, ModerationStatus::Approved);
+
+        assert_eq!(
+            report(&mut transaction, &target).await?,
+            SubmitResult::Escalated
+        );
+
+        // Still visible while it is tagged again
+        assert_eq!(status(&mut transaction).await?, ModerationStatus::Approved);
+        assert!(
+            sqlx::query_scalar::<_, bool>(
+                "SELECT recheck FROM moderation_escalations;"
+            )
+            .fetch_one(&mut *transaction)
+            .await?
+        );
+        assert_eq!(
+            report(&mut transaction, &target).await?,
+            SubmitResult::Stored
+        );
+
+        assert_eq!(
+            report(
+                &mut transaction,
+                &ReportTarget::System(pointer.system().clone())
+            )
+            .await?,
+            SubmitResult::Stored
+        );
+
+        let unknown = polycentric_protocol::model::pointer::Pointer::new(
+            pointer.system().clone(),
+            pointer.process().clone(),
+            2,
+            pointer.event_digest().clone(),
+        );
+        assert_eq!(
+            report(&mut transaction, &ReportTarget::Event(unknown)).await?,
+            SubmitResult::UnknownEvent
+        );
+
+        assert_eq!(
+            sqlx::query_scalar::<_, i64>(
+                "SELECT reports FROM moderation_event_report_counts;"
+            )
+            .fetch_one(&mut *transaction)
+            .await?,
+            3
+        );
+        assert_eq!(
+            sqlx::query_scalar::<_, i64>(
+                "SELECT reports FROM moderation_system_report_counts;"
+            )
+            .fetch_one(&mut *transaction)
+            .await?,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_reported_first(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        let mut pointers = vec![];
+        for logical_clock in 1..=3 {
+            let signed_event =
+                polycentric_protocol::test_utils::make_test_event(
+                    &keypair,
+                    &process,
+                    logical_clock,
+                );
+
+            crate::ingest::ingest_event_postgres(
+                &mut transaction,
+                &signed_event,
+            )
+            .await?;
+
+            pointers.push(
+                polycentric_protocol::model::pointer::from_signed_event(
+                    &signed_event,
+                )?,
+            );
+        }
+
+        report(&mut transaction, &ReportTarget::Event(pointers[2].clone()))
+            .await?;
+
+        let events = super::super::moderation_queue::pull_queue_events(
+            &mut transaction,
+            &super::super::moderation_queue::QueueOptions::new(
+                ::std::time::Duration::from_secs(300),
+                3,
+                ::std::time::Duration::ZERO,
+            ),
+        )
+        .await?;
+
+        let ids = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM events ORDER BY logical_clock;",
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        // Then in the order they arrived
+        assert_eq!(
+            events.iter().map(|event| event.id).collect::<Vec<_>>(),
+            vec![ids[2], ids[0], ids[1]]
+        );
+
+        Ok(())
+    }
+}
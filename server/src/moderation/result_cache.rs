@@ -0,0 +1,455 @@
+use log::warn;
+
+use super::moderation_queue::ModerationQueueItem;
+use crate::model::moderation_tag::ModerationTag;
+
+// Provider results keyed by the SHA-256 of what was sent to the provider, so
+// that reposted images and copied text are not paid for twice. A result only
+// counts while it is younger than the TTL and came from the provider that is
+// configured now.
+//
+// Content found to be CSAM is also added to `blocked_content_hashes`, which
+// never expires. Ingest refuses blob sections whose hash is listed, and the
+// queue rejects events whose assembled images are listed before calling any
+// provider.
+
+const KIND_TAG: &str = "tag";
+const KIND_CSAM: &str = "csam";
+
+pub struct ResultCache {
+    pool: ::sqlx::PgPool,
+    ttl: ::std::time::Duration,
+    tag_provider: Option<String>,
+    csam_provider: Option<String>,
+}
+
+#[derive(::sqlx::FromRow)]
+struct CachedRow {
+    tags: ::std::vec::Vec<ModerationTag>,
+    is_csam: bool,
+}
+
+pub(crate) fn sha256(bytes: &[u8]) -> [u8; 32] {
+    ::hmac_sha256::Hash::hash(bytes)
+}
+
+// Covers both the text and the image, as the tagging provider sees both
+pub(super) fn tag_digest(event: &ModerationQueueItem) -> [u8; 32] {
+    let mut hash = ::hmac_sha256::Hash::new();
+
+    match &event.content {
+        Some(content) => {
+            hash.update([1u8]);
+            hash.update(u64::try_from(content.len()).unwrap().to_be_bytes());
+            hash.update(content.as_bytes());
+        }
+        None => hash.update([0u8]),
+    }
+
+    match &event.blob {
+        Some(blob) => {
+            hash.update([1u8]);
+            hash.update(blob);
+        }
+        None => hash.update([0u8]),
+    }
+
+    hash.finalize()
+}
+
+pub(super) fn csam_digest(event: &ModerationQueueItem) -> Option<[u8; 32]> {
+    event.blob.as_deref().map(sha256)
+}
+
+impl ResultCache {
+    pub fn new(
+        pool: ::sqlx::PgPool,
+        ttl: ::std::time::Duration,
+        tag_provider: Option<String>,
+        csam_provider: Option<String>,
+    ) -> Self {
+        ResultCache {
+            pool,
+            ttl,
+            tag_provider,
+            csam_provider,
+        }
+    }
+
+    fn provider(&self, kind: &str) -> Option<&str> {
+        match kind {
+            KIND_TAG => self.tag_provider.as_deref(),
+            _ => self.csam_provider.as_deref(),
+        }
+    }
+
+    // Drops results that expired or came from another provider
+    pub async fn invalidate(&self) -> ::anyhow::Result<u64> {
+        let query = "
+            DELETE FROM moderation_result_cache
+            WHERE created_on < NOW() - make_interval(secs => $1)
+            OR (kind = $2 AND provider IS DISTINCT FROM $3)
+            OR (kind = $4 AND provider IS DISTINCT FROM $5);
+        ";
+
+        Ok(::sqlx::query(query)
+            .bind(self.ttl.as_secs_f64())
+            .bind(KIND_TAG)
+            .bind(&self.tag_provider)
+            .bind(KIND_CSAM)
+            .bind(&self.csam_provider)
+            .execute(&self.pool)
+            .await?
+            .rows_affected())
+    }
+
+    async fn lookup(
+        &self,
+        kind: &str,
+        digest: &[u8; 32],
+    ) -> ::anyhow::Result<Option<CachedRow>> {
+        let provider = match self.provider(kind) {
+            Some(provider) => provider,
+            None => return Ok(None),
+        };
+
+        let query = "
+            SELECT tags, is_csam
+            FROM moderation_result_cache
+            WHERE digest = $1
+            AND kind = $2
+            AND provider = $3
+            AND created_on >= NOW() - make_interval(secs => $4);
+        ";
+
+        Ok(::sqlx::query_as::<_, CachedRow>(query)
+            .bind(&digest[..])
+            .bind(kind)
+            .bind(provider)
+            .bind(self.ttl.as_secs_f64())
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn store(
+        &self,
+        kind: &str,
+        digest: &[u8; 32],
+        tags: &[ModerationTag],
+        is_csam: bool,
+    ) -> ::anyhow::Result<()> {
+        let provider = match self.provider(kind) {
+            Some(provider) => provider,
+            None => return Ok(()),
+        };
+
+        let mut transaction = self.pool.begin().await?;
+
+        let query = "
+            INSERT INTO moderation_result_cache (
+                digest,
+                kind,
+                provider,
+                tags,
+                is_csam
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (digest, kind) DO UPDATE
+            SET provider = EXCLUDED.provider,
+                tags = EXCLUDED.tags,
+                is_csam = EXCLUDED.is_csam,
+                created_on = NOW();
+        ";
+
+        ::sqlx::query(query)
+            .bind(&digest[..])
+            .bind(kind)
+            .bind(provider)
+            .bind(tags)
+            .bind(is_csam)
+            .execute(&mut *transaction)
+            .await?;
+
+        if is_csam {
+            block(&mut transaction, &[digest.to_vec()], "csam").await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    // Failures to read or write the cache are logged and otherwise ignored,
+    // the provider is asked instead.
+    pub(super) async fn get_tags(
+        &self,
+        digest: &[u8; 32],
+    ) -> Option<::std::vec::Vec<ModerationTag>> {
+        match self.lookup(KIND_TAG, digest).await {
+            Ok(row) => row.map(|row| row.tags),
+            Err(err) => {
+                warn!("failed to read moderation result cache: {:?}", err);
+                None
+            }
+        }
+    }
+
+    pub(super) async fn put_tags(
+        &self,
+        digest: &[u8; 32],
+        tags: &[ModerationTag],
+    ) {
+        if let Err(err) = self.store(KIND_TAG, digest, tags, false).await {
+            warn!("failed to write moderation result cache: {:?}", err);
+        }
+    }
+
+    pub(super) async fn get_csam(&self, digest: &[u8; 32]) -> Option<bool> {
+        let result = async {
+            let mut transaction = self.pool.begin().await?;
+            let blocked = is_blocked(&mut transaction, digest).await?;
+            transaction.commit().await?;
+
+            if blocked {
+                return Ok(Some(true));
+            }
+
+            Ok::<_, ::anyhow::Error>(
+                self.lookup(KIND_CSAM, digest).await?.map(|row| row.is_csam),
+            )
+        }
+        .await;
+
+        match result {
+            Ok(is_csam) => is_csam,
+            Err(err) => {
+                warn!("failed to read moderation result cache: {:?}", err);
+                None
+            }
+        }
+    }
+
+    pub(super) async fn put_csam(&self, digest: &[u8; 32], is_csam: bool) {
+        if let Err(err) = self.store(KIND_CSAM, digest, &[], is_csam).await {
+            warn!("failed to write moderation result cache: {:?}", err);
+        }
+    }
+}
+
+pub(crate) async fn block(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    digests: &[::std::vec::Vec<u8>],
+    reason: &str,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO blocked_content_hashes (digest, reason)
+        SELECT unnest($1::BYTEA[]), $2
+        ON CONFLICT (digest) DO NOTHING;
+    ";
+
+    ::sqlx::query(query)
+        .bind(digests)
+        .bind(reason)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+// Blocks the sections of a blob so that they cannot be uploaded again.
+// Must run before the section events are deleted.
+pub(crate) async fn block_blob_sections(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    blob_db_ids: &[i64],
+    reason: &str,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO blocked_content_hashes (digest, reason)
+        SELECT sha256(content), $2
+        FROM events
+        WHERE id = ANY($1)
+        AND content_type = $3
+        ON CONFLICT (digest) DO NOTHING;
+    ";
+
+    ::sqlx::query(query)
+        .bind(blob_db_ids)
+        .bind(reason)
+        .bind(i64::try_from(
+            polycentric_protocol::model::known_message_types::BLOB_SECTION,
+        )?)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn is_blocked(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    digest: &[u8; 32],
+) -> ::anyhow::Result<bool> {
+    let query = "
+        SELECT EXISTS (
+            SELECT 1 FROM blocked_content_hashes WHERE digest = $1
+        );
+    ";
+
+    Ok(::sqlx::query_scalar::<_, bool>(query)
+        .bind(&digest[..])
+        .fetch_one(&mut **transaction)
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postgres::prepare_database;
+    use sqlx::PgPool;
+
+    fn item(content: Option<&str>, blob: Option<&[u8]>) -> ModerationQueueItem {
+        ModerationQueueItem {
+            id: 1,
+            content: content.map(str::to_string),
+            blob: blob.map(<[u8]>::to_vec),
+            blob_db_ids: None,
+            extra_images: vec![],
+            blocked: false,
+        }
+    }
+
+    fn cache(pool: &PgPool, tag_provider: &str) -> ResultCache {
+        ResultCache::new(
+            pool.clone(),
+            ::std::time::Duration::from_secs(3600),
+            Some(tag_provider.to_string()),
+            Some("photodna".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_digests() {
+        assert_eq!(
+            tag_digest(&item(Some("hello"), None)),
+            tag_digest(&item(Some("hello"), None))
+        );
+        assert_ne!(
+            tag_digest(&item(Some("hello"), None)),
+            tag_digest(&item(None, Some(b"hello")))
+        );
+        assert_ne!(
+            tag_digest(&item(Some(""), None)),
+            tag_digest(&item(None, None))
+        );
+        assert_eq!(csam_digest(&item(Some("hello"), None)), None);
+        assert_eq!(
+            csam_digest(&item(Some("hello"), Some(b"image"))),
+            Some(sha256(b"image"))
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_cache(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let azure = cache(&pool, "azure");
+        let digest = tag_digest(&item(Some("hello"), None));
+        let tags = vec![ModerationTag::new("violence".to_string(), 2)];
+
+        assert_eq!(azure.get_tags(&digest).await, None);
+        azure.put_tags(&digest, &tags).await;
+        assert_eq!(azure.get_tags(&digest).await, Some(tags.clone()));
+
+        // Results of another provider do not count, and are dropped when
+        // the provider changes
+        let local = cache(&pool, "local");
+        assert_eq!(local.get_tags(&digest).await, None);
+        assert_eq!(local.invalidate().await?, 1);
+        assert_eq!(azure.get_tags(&digest).await, None);
+
+        azure.put_tags(&digest, &tags).await;
+        sqlx::query(
+            "
+            UPDATE moderation_result_cache
+            SET created_on = NOW() - INTERVAL '2 hours';
+            ",
+        )
+        .execute(&pool)
+        .await?;
+        assert_eq!(azure.get_tags(&digest).await, None);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_csam_block(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let clean = sha256(b"clean");
+        let bad = sha256(b"bad");
+
+        let provider = cache(&pool, "azure");
+        provider.put_csam(&clean, false).await;
+        provider.put_csam(&bad, true).await;
+
+        assert_eq!(provider.get_csam(&clean).await, Some(false));
+        assert_eq!(provider.get_csam(&bad).await, Some(true));
+
+        // A hit is permanent, whatever the provider or TTL
+        let other = ResultCache::new(
+            pool.clone(),
+            ::std::time::Duration::from_secs(0),
+            None,
+            Some("phash".to_string()),
+        );
+        other.invalidate().await?;
+        assert_eq!(other.get_csam(&clean).await, None);
+        assert_eq!(other.get_csam(&bad).await, Some(true));
+
+        let mut transaction = pool.begin().await?;
+        assert!(is_blocked(&mut transaction, &bad).await?);
+        assert!(!is_blocked(&mut transaction, &clean).await?);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_ingest_refuses_blocked_section(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        block(&mut transaction, &[sha256(b"bad").to_vec()], "csam").await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        for (logical_clock, section) in [(1, &b"bad"[..]), (2, &b"fine"[..])] {
+            crate::ingest::ingest_event_postgres(
+                &mut transaction,
+                &polycentric_protocol::test_utils::make_test_event_with_content(
+                    &keypair,
+                    &process,
+                    logical_clock,
+                    polycentric_protocol::model::known_message_types::BLOB_SECTION,
+                    section,
+                    vec![],
+                ),
+            )
+            .await?;
+        }
+
+        let stored: ::std::vec::Vec<::std::vec::Vec<u8>> =
+            sqlx::query_scalar("SELECT content FROM events ORDER BY id;")
+                .fetch_all(&mut *transaction)
+                .await?;
+
+        assert_eq!(stored, vec![b"fine".to_vec()]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,668 @@
+use polycentric_protocol::model::moderation_tag::ModerationTag;
+
+use super::moderation_queue::ModerationStatus;
+
+// Manual review of moderated events. A decision is recorded in
+// `moderation_reviews` and takes precedence over automated moderation until
+// the event is requeued.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum ReviewAction {
+    Approve,
+    Reject,
+    Retag,
+    Requeue,
+}
+
+impl ReviewAction {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ReviewAction::Approve => "approve",
+            ReviewAction::Reject => "reject",
+            ReviewAction::Retag => "retag",
+            ReviewAction::Requeue => "requeue",
+        }
+    }
+}
+
+impl ::std::str::FromStr for ReviewAction {
+    type Err = ::anyhow::Error;
+
+    fn from_str(s: &str) -> ::anyhow::Result<Self> {
+        match s {
+            "approve" => Ok(ReviewAction::Approve),
+            "reject" => Ok(ReviewAction::Reject),
+            "retag" => Ok(ReviewAction::Retag),
+            "requeue" => Ok(ReviewAction::Requeue),
+            _ => Err(::anyhow::anyhow!("unknown review action")),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ReviewFilter {
+    pub(crate) status: Option<ModerationStatus>,
+    // Matches events with a tag of this name, or any tag when absent, at
+    // `min_level` or above
+    pub(crate) tag: Option<String>,
+    pub(crate) min_level: Option<i16>,
+    pub(crate) dead_lettered: Option<bool>,
+    // Events reported often enough to need a reviewer, see `reports`
+    pub(crate) escalated: Option<bool>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Review {
+    pub(crate) reviewer: String,
+    pub(crate) note: Option<String>,
+    // unix milliseconds
+    pub(crate) reviewed_on: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct ReviewItem {
+    pub(crate) id: i64,
+    pub(crate) signed_event:
+        polycentric_protocol::model::signed_event::SignedEvent,
+    pub(crate) status: ModerationStatus,
+    pub(crate) tags: ::std::vec::Vec<ModerationTag>,
+    pub(crate) failure_count: i32,
+    pub(crate) dead_lettered: bool,
+    pub(crate) reports: i64,
+    pub(crate) escalated: bool,
+    pub(crate) review: Option<Review>,
+}
+
+#[derive(::sqlx::FromRow)]
+struct ReviewItemRow {
+    id: i64,
+    raw_event: ::std::vec::Vec<u8>,
+    moderation_status: ModerationStatus,
+    moderation_tags: Option<::std::vec::Vec<ModerationTag>>,
+    failure_count: i32,
+    dead_lettered: bool,
+    reports: i64,
+    escalated: bool,
+    reviewer: Option<String>,
+    note: Option<String>,
+    reviewed_on: Option<i64>,
+}
+
+impl TryFrom<ReviewItemRow> for ReviewItem {
+    type Error = ::anyhow::Error;
+
+    fn try_from(row: ReviewItemRow) -> ::anyhow::Result<Self> {
+        let review = match (row.reviewer, row.reviewed_on) {
+            (Some(reviewer), Some(reviewed_on)) => Some(Review {
+                reviewer,
+                note: row.note,
+                reviewed_on: u64::try_from(reviewed_on)?,
+            }),
+            _ => None,
+        };
+
+        Ok(ReviewItem {
+            id: row.id,
+            signed_event: polycentric_protocol::model::signed_event::from_vec(
+                &row.raw_event,
+            )?,
+            status: row.moderation_status,
+            tags: row.moderation_tags.unwrap_or_default(),
+            failure_count: row.failure_count,
+            dead_lettered: row.dead_lettered,
+            reports: row.reports,
+            escalated: row.escalated,
+            review,
+        })
+    }
+}
+
+fn item_query() -> String {
+    format!(
+        "
+    SELECT
+        e.id,
+        e.raw_event,
+        e.moderation_status,
+        e.moderation_tags,
+        COALESCE(eps.failure_count, 0) AS failure_count,
+        eps.dead_lettered_at IS NOT NULL AS dead_lettered,
+        (
+            SELECT COUNT(*) FROM moderation_reports mr
+            WHERE mr.event_id = e.id
+        ) AS reports,
+        esc.event_id IS NOT NULL AS escalated,
+        r.reviewer,
+        r.note,
+        (EXTRACT(EPOCH FROM r.reviewed_on) * 1000)::INT8 AS reviewed_on
+    FROM events e
+    LEFT JOIN event_processing_status eps ON eps.event_id = e.id
+    LEFT JOIN moderation_reviews r ON r.event_id = e.id
+    LEFT JOIN moderation_escalations esc ON esc.event_id = e.id
+    WHERE e.content_type IN ({})
+",
+        super::extract::moderated_content_types_sql()
+    )
+}
+
+pub(crate) async fn list_items(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    filter: &ReviewFilter,
+    after: i64,
+    limit: u64,
+) -> ::anyhow::Result<::std::vec::Vec<ReviewItem>> {
+    let query = format!(
+        "
+        {}
+        AND e.id > $1
+        AND ($3::moderation_status_enum IS NULL OR e.moderation_status = $3)
+        AND (
+            ($4::TEXT IS NULL AND $5::INT2 IS NULL)
+            OR EXISTS (
+                SELECT 1 FROM unnest(e.moderation_tags) AS tag
+                WHERE ($4::TEXT IS NULL OR tag.name = $4)
+                AND tag.level >= COALESCE($5::INT2, 0)
+            )
+        )
+        AND ($6::BOOLEAN IS NULL OR (eps.dead_lettered_at IS NOT NULL) = $6)
+        AND ($7::BOOLEAN IS NULL OR (esc.event_id IS NOT NULL) = $7)
+        ORDER BY e.id ASC
+        LIMIT $2;
+        ",
+        item_query()
+    );
+
+    ::sqlx::query_as::<_, ReviewItemRow>(&query)
+        .bind(after)
+        .bind(i64::try_from(limit)?)
+        .bind(filter.status)
+        .bind(&filter.tag)
+        .bind(filter.min_level)
+        .bind(filter.dead_lettered)
+        .bind(filter.escalated)
+        .fetch_all(&mut **transaction)
+        .await?
+        .into_iter()
+        .map(ReviewItem::try_from)
+        .collect()
+}
+
+pub(crate) async fn load_item(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: i64,
+) -> ::anyhow::Result<Option<ReviewItem>> {
+    let query = format!(
+        "
+        {}
+        AND e.id = $1;
+        ",
+        item_query()
+    );
+
+    ::sqlx::query_as::<_, ReviewItemRow>(&query)
+        .bind(id)
+        .fetch_optional(&mut **transaction)
+        .await?
+        .map(ReviewItem::try_from)
+        .transpose()
+}
+
+// The image a reviewer needs to see, the largest one the event references.
+pub(crate) async fn load_item_blob(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    item: &ReviewItem,
+) -> ::anyhow::Result<Option<::std::vec::Vec<u8>>> {
+    let event = polycentric_protocol::model::event::from_vec(
+        item.signed_event.event(),
+    )?;
+
+    let content = polycentric_protocol::model::content::from_event(&event)?;
+
+    let manifest = match super::extract::extract(&content)
+        .images
+        .into_iter()
+        .max_by_key(|manifest| manifest.width)
+    {
+        Some(manifest) => manifest,
+        None => return Ok(None),
+    };
+
+    let (blob, _) = super::moderation_queue::get_blob_by_logical_clocks(
+        transaction,
+        &event,
+        &manifest,
+    )
+    .await?;
+
+    Ok(if blob.is_empty() { None } else { Some(blob) })
+}
+
+pub(super) async fn record_decision(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: i64,
+    status: ModerationStatus,
+    tags: Option<&[ModerationTag]>,
+    reviewer: &str,
+    note: Option<&str>,
+) -> ::anyhow::Result<()> {
+    // Events approved before they were ever tagged get no tags rather than
+    // NULL ones
+    let update_query = "
+        UPDATE events
+        SET moderation_status = $2,
+            moderation_tags = COALESCE(
+                $3::moderation_tag_type[],
+                moderation_tags,
+                '{}'
+            )
+        WHERE id = $1;
+    ";
+
+    ::sqlx::query(update_query)
+        .bind(id)
+        .bind(status)
+        .bind(tags)
+        .execute(&mut **transaction)
+        .await?;
+
+    let review_query = "
+        INSERT INTO moderation_reviews (
+            event_id,
+            moderation_status,
+            moderation_tags,
+            reviewer,
+            note
+        )
+        SELECT id, moderation_status, moderation_tags, $2, $3
+        FROM events
+        WHERE id = $1
+        ON CONFLICT (event_id) DO UPDATE
+        SET moderation_status = EXCLUDED.moderation_status,
+            moderation_tags = EXCLUDED.moderation_tags,
+            reviewer = EXCLUDED.reviewer,
+            note = EXCLUDED.note,
+            reviewed_on = NOW();
+    ";
+
+    ::sqlx::query(review_query)
+        .bind(id)
+        .bind(reviewer)
+        .bind(note)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+pub(crate) fn validate(
+    action: ReviewAction,
+    tags: Option<&[ModerationTag]>,
+) -> ::anyhow::Result<()> {
+    if action == ReviewAction::Retag && tags.is_none() {
+        ::anyhow::bail!("retag requires tags");
+    }
+
+    if let Some(tags) = tags {
+        if let Some(tag) =
+            tags.iter().find(|tag| !(0..=3).contains(tag.level()))
+        {
+            ::anyhow::bail!(
+                "invalid level {} for tag {:?}",
+                tag.level(),
+                tag.name()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Returns false when there is no moderated event with this id. Retagging
+// replaces the tags and approves the event, so the tags decide who sees it.
+pub(crate) async fn apply_review(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: i64,
+    action: ReviewAction,
+    tags: Option<::std::vec::Vec<ModerationTag>>,
+    reviewer: &str,
+    note: Option<&str>,
+) -> ::anyhow::Result<bool> {
+    validate(action, tags.as_deref())?;
+
+    let exists: Option<i64> = ::sqlx::query_scalar(&format!(
+        "
+        SELECT id FROM events
+        WHERE id = $1
+        AND content_type IN ({})
+        FOR UPDATE;
+        ",
+        super::extract::moderated_content_types_sql()
+    ))
+    .bind(id)
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    if exists.is_none() {
+        return Ok(false);
+    }
+
+    // Any lease is dropped so that results from an automated run in flight
+    // are discarded
+    ::sqlx::query("DELETE FROM event_processing_status WHERE event_id = $1;")
+        .bind(id)
+        .execute(&mut **transaction)
+        .await?;
+
+    // Only reports made after this review can escalate the event again
+    ::sqlx::query("DELETE FROM moderation_escalations WHERE event_id = $1;")
+        .bind(id)
+        .execute(&mut **transaction)
+        .await?;
+
+    match action {
+        ReviewAction::Approve | ReviewAction::Retag => {
+            super::moderation_queue::approve_event(
+                transaction,
+                id,
+                tags.as_deref(),
+                reviewer,
+                note,
+            )
+            .await?;
+        }
+        ReviewAction::Reject => {
+            record_decision(
+                transaction,
+                id,
+                ModerationStatus::FlaggedAndRejected,
+                tags.as_deref(),
+                reviewer,
+                note,
+            )
+            .await?;
+
+            crate::search_indexer::remove_event(transaction, id).await?;
+            crate::cache::purge_queue::enqueue_event(transaction, id).await?;
+        }
+        ReviewAction::Requeue => {
+            ::sqlx::query(
+                "DELETE FROM moderation_reviews WHERE event_id = $1;",
+            )
+            .bind(id)
+            .execute(&mut **transaction)
+            .await?;
+
+            ::sqlx::query(
+                "
+                UPDATE events
+                SET moderation_status = 'unprocessed'
+                WHERE id = $1;
+                ",
+            )
+            .bind(id)
+            .execute(&mut **transaction)
+            .await?;
+
+            crate::search_indexer::enqueue(
+                &mut *transaction,
+                u64::try_from(id)?,
+            )
+            .await?;
+            crate::cache::purge_queue::enqueue_event(transaction, id).await?;
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moderation::moderation_queue::{
+        pull_queue_events, QueueOptions,
+    };
+    use crate::postgres::prepare_database;
+    use sqlx::PgPool;
+    use tokio::time::Duration;
+
+    fn test_options() -> QueueOptions {
+        QueueOptions {
+            worker_id: "review-test".to_string(),
+            lease_duration: Duration::from_secs(300),
+            max_retries: 3,
+            retry_delay: Duration::ZERO,
+        }
+    }
+
+    async fn ingest_test_post(pool: &PgPool) -> anyhow::Result<i64> {
+        let mut transaction = pool.begin().await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        crate::ingest::ingest_event_postgres(
+            &mut transaction,
+            &polycentric_protocol::test_utils::make_test_event(
+                &keypair, &process, 1,
+            ),
+        )
+        .await?;
+
+        let id: i64 = sqlx::query_scalar("SELECT MAX(id) FROM events")
+            .fetch_one(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(id)
+    }
+
+    #[sqlx::test]
+    async fn test_review_decisions(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let approved = ingest_test_post(&pool).await?;
+        let rejected = ingest_test_post(&pool).await?;
+        let retagged = ingest_test_post(&pool).await?;
+
+        let mut transaction = pool.begin().await?;
+
+        assert!(
+            apply_review(
+                &mut transaction,
+                approved,
+                ReviewAction::Approve,
+                None,
+                "alice",
+                None,
+            )
+            .await?
+        );
+        assert!(
+            apply_review(
+                &mut transaction,
+                rejected,
+                ReviewAction::Reject,
+                None,
+                "alice",
+                Some("spam"),
+            )
+            .await?
+        );
+        assert!(
+            apply_review(
+                &mut transaction,
+                retagged,
+                ReviewAction::Retag,
+                Some(vec![ModerationTag::new("violence".to_string(), 2)]),
+                "bob",
+                None,
+            )
+            .await?
+        );
+        assert!(
+            !apply_review(
+                &mut transaction,
+                retagged + 1000,
+                ReviewAction::Approve,
+                None,
+                "bob",
+                None,
+            )
+            .await?
+        );
+
+        let rejected_items = list_items(
+            &mut transaction,
+            &ReviewFilter {
+                status: Some(ModerationStatus::FlaggedAndRejected),
+                ..Default::default()
+            },
+            0,
+            10,
+        )
+        .await?;
+
+        assert_eq!(rejected_items.len(), 1);
+        assert_eq!(rejected_items[0].id, rejected);
+        assert_eq!(
+            rejected_items[0].review.as_ref().map(|r| r.note.clone()),
+            Some(Some("spam".to_string()))
+        );
+
+        let violent = list_items(
+            &mut transaction,
+            &ReviewFilter {
+                tag: Some("violence".to_string()),
+                min_level: Some(2),
+                ..Default::default()
+            },
+            0,
+            10,
+        )
+        .await?;
+
+        assert_eq!(violent.len(), 1);
+        assert_eq!(violent[0].id, retagged);
+        assert_eq!(violent[0].status, ModerationStatus::Approved);
+
+        // Approved without ever being tagged, and still readable through
+        // filters that do not require tags
+        let filters = crate::moderation::ModerationFilters(
+            ["violence", "hate", "sexual"]
+                .iter()
+                .map(|name| crate::moderation::ModerationFilter {
+                    name: crate::model::moderation_tag::ModerationTagName::new(
+                        name.to_string(),
+                    ),
+                    max_level: 1,
+                    strict_mode: false,
+                })
+                .collect(),
+        );
+
+        let visible: Vec<i64> = sqlx::query_scalar(
+            "
+            SELECT id FROM events
+            WHERE id = $1
+            AND filter_events_by_moderation(
+                events,
+                $2::moderation_filter_type[],
+                $3::moderation_mode,
+                (SELECT moderation_policies())
+            );
+            ",
+        )
+        .bind(approved)
+        .bind(filters)
+        .bind(crate::config::ModerationMode::Strong)
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        assert_eq!(visible, vec![approved]);
+        assert!(
+            sqlx::query_scalar::<_, bool>(
+                "SELECT moderation_tags IS NOT NULL FROM events WHERE id = $1;"
+            )
+            .bind(approved)
+            .fetch_one(&mut *transaction)
+            .await?
+        );
+
+        transaction.commit().await?;
+
+        // Manual decisions are not picked up by automated moderation
+        let mut transaction = pool.begin().await?;
+        assert!(pull_queue_events(&mut transaction, &test_options())
+            .await?
+            .is_empty());
+        transaction.commit().await?;
+
+        let mut transaction = pool.begin().await?;
+        assert!(
+            apply_review(
+                &mut transaction,
+                rejected,
+                ReviewAction::Requeue,
+                None,
+                "alice",
+                None,
+            )
+            .await?
+        );
+        let item = load_item(&mut transaction, rejected).await?.unwrap();
+        assert_eq!(item.status, ModerationStatus::Unprocessed);
+        assert!(item.review.is_none());
+        transaction.commit().await?;
+
+        let mut transaction = pool.begin().await?;
+        let pulled = pull_queue_events(&mut transaction, &test_options())
+            .await?
+            .into_iter()
+            .map(|item| item.id)
+            .collect::<Vec<i64>>();
+        assert_eq!(pulled, vec![rejected]);
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_retag_requires_valid_tags(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let id = ingest_test_post(&pool).await?;
+
+        let mut transaction = pool.begin().await?;
+
+        assert!(apply_review(
+            &mut transaction,
+            id,
+            ReviewAction::Retag,
+            None,
+            "alice",
+            None
+        )
+        .await
+        .is_err());
+
+        assert!(apply_review(
+            &mut transaction,
+            id,
+            ReviewAction::Retag,
+            Some(vec![ModerationTag::new("hate".to_string(), 4)]),
+            "alice",
+            None
+        )
+        .await
+        .is_err());
+
+        Ok(())
+    }
+}
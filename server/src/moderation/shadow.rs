@@ -0,0 +1,289 @@
+use crate::model::moderation_tag::ModerationTag;
+
+// A second tagging provider can run next to the primary one, configured with
+// SHADOW_TAG_INTERFACE, to see how it would moderate before switching to it.
+// It runs after the primary results are applied, on its own worker, and its
+// tags are stored in `moderation_shadow_results` and never applied.
+
+#[derive(Clone, Copy)]
+pub struct ShadowProvider<'a> {
+    pub name: &'a str,
+    pub provider:
+        &'a dyn super::providers::tags::interface::ModerationTaggingProvider,
+}
+
+pub(crate) struct ShadowResult {
+    pub(crate) provider: String,
+    pub(crate) has_error: bool,
+    pub(crate) tags: ::std::vec::Vec<ModerationTag>,
+}
+
+pub(crate) async fn record(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: i64,
+    primary_error: bool,
+    primary_tags: &[ModerationTag],
+    shadow: &ShadowResult,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO moderation_shadow_results (
+            event_id,
+            provider,
+            primary_error,
+            primary_tags,
+            shadow_error,
+            shadow_tags
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (event_id) DO UPDATE
+        SET provider = EXCLUDED.provider,
+            primary_error = EXCLUDED.primary_error,
+            primary_tags = EXCLUDED.primary_tags,
+            shadow_error = EXCLUDED.shadow_error,
+            shadow_tags = EXCLUDED.shadow_tags,
+            created_on = NOW();
+    ";
+
+    ::sqlx::query(query)
+        .bind(event_id)
+        .bind(&shadow.provider)
+        .bind(primary_error)
+        .bind(primary_tags)
+        .bind(shadow.has_error)
+        .bind(&shadow.tags)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Default)]
+pub(crate) struct ReportFilter {
+    pub(crate) provider: Option<String>,
+    pub(crate) content_type: Option<i64>,
+    // Bounds on when the results were recorded, in unix seconds
+    pub(crate) since: Option<u64>,
+    pub(crate) until: Option<u64>,
+}
+
+// Events where the providers gave a tag different levels, a missing tag
+// counting as level 0.
+#[derive(::sqlx::FromRow, ::serde::Serialize, Debug, PartialEq)]
+pub(crate) struct Disagreement {
+    pub(crate) tag: String,
+    pub(crate) primary_level: i16,
+    pub(crate) shadow_level: i16,
+    pub(crate) events: i64,
+}
+
+#[derive(::sqlx::FromRow, ::serde::Serialize, Debug, PartialEq)]
+pub(crate) struct ReportTotals {
+    // Events both providers tagged without error
+    pub(crate) compared: i64,
+    pub(crate) disagreeing: i64,
+    pub(crate) primary_errors: i64,
+    pub(crate) shadow_errors: i64,
+}
+
+#[derive(::serde::Serialize, Debug)]
+pub(crate) struct Report {
+    #[serde(flatten)]
+    pub(crate) totals: ReportTotals,
+    pub(crate) disagreements: ::std::vec::Vec<Disagreement>,
+}
+
+pub(crate) async fn report(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    filter: &ReportFilter,
+) -> ::anyhow::Result<Report> {
+    let disagreements_query = "
+        SELECT
+            d.name::TEXT AS tag,
+            d.a_level AS primary_level,
+            d.b_level AS shadow_level,
+            COUNT(*) AS events
+        FROM moderation_shadow_results s
+        JOIN events e ON e.id = s.event_id
+        CROSS JOIN LATERAL moderation_tag_disagreements(
+            s.primary_tags,
+            s.shadow_tags
+        ) AS d
+        WHERE NOT s.primary_error
+        AND NOT s.shadow_error
+        AND ($1::TEXT IS NULL OR s.provider = $1)
+        AND ($2::INT8 IS NULL OR e.content_type = $2)
+        AND ($3::INT8 IS NULL OR s.created_on >= to_timestamp($3))
+        AND ($4::INT8 IS NULL OR s.created_on < to_timestamp($4))
+        GROUP BY d.name, d.a_level, d.b_level
+        ORDER BY d.name, d.a_level, d.b_level;
+    ";
+
+    let totals_query = "
+        SELECT
+            COUNT(*) FILTER (
+                WHERE NOT s.primary_error AND NOT s.shadow_error
+            ) AS compared,
+            COUNT(*) FILTER (
+                WHERE NOT s.primary_error
+                AND NOT s.shadow_error
+                AND EXISTS (
+                    SELECT 1 FROM moderation_tag_disagreements(
+                        s.primary_tags,
+                        s.shadow_tags
+                    )
+                )
+            ) AS disagreeing,
+            COUNT(*) FILTER (WHERE s.primary_error) AS primary_errors,
+            COUNT(*) FILTER (WHERE s.shadow_error) AS shadow_errors
+        FROM moderation_shadow_results s
+        JOIN events e ON e.id = s.event_id
+        WHERE ($1::TEXT IS NULL OR s.provider = $1)
+        AND ($2::INT8 IS NULL OR e.content_type = $2)
+        AND ($3::INT8 IS NULL OR s.created_on >= to_timestamp($3))
+        AND ($4::INT8 IS NULL OR s.created_on < to_timestamp($4));
+    ";
+
+    let since = filter.since.map(i64::try_from).transpose()?;
+    let until = filter.until.map(i64::try_from).transpose()?;
+
+    let disagreements =
+        ::sqlx::query_as::<_, Disagreement>(disagreements_query)
+            .bind(&filter.provider)
+            .bind(filter.content_type)
+            .bind(since)
+            .bind(until)
+            .fetch_all(&mut **transaction)
+            .await?;
+
+    let totals = ::sqlx::query_as::<_, ReportTotals>(totals_query)
+        .bind(&filter.provider)
+        .bind(filter.content_type)
+        .bind(since)
+        .bind(until)
+        .fetch_one(&mut **transaction)
+        .await?;
+
+    Ok(Report {
+        totals,
+        disagreements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postgres::prepare_database;
+    use sqlx::PgPool;
+
+    async fn ingest(
+        transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    ) -> anyhow::Result<i64> {
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        crate::ingest::ingest_event_postgres(
+            transaction,
+            &polycentric_protocol::test_utils::make_test_event(
+                &keypair, &process, 1,
+            ),
+        )
+        .await?;
+
+        Ok(sqlx::query_scalar("SELECT MAX(id) FROM events;")
+            .fetch_one(&mut **transaction)
+            .await?)
+    }
+
+    fn tags(tags: &[(&str, i16)]) -> Vec<ModerationTag> {
+        tags.iter()
+            .map(|(name, level)| ModerationTag::new(name.to_string(), *level))
+            .collect()
+    }
+
+    fn shadow(has_error: bool, shadow_tags: &[(&str, i16)]) -> ShadowResult {
+        ShadowResult {
+            provider: "local".to_string(),
+            has_error,
+            tags: tags(shadow_tags),
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_report(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let agreeing = ingest(&mut transaction).await?;
+        record(
+            &mut transaction,
+            agreeing,
+            false,
+            &tags(&[("violence", 1), ("hate", 0)]),
+            &shadow(false, &[("violence", 1)]),
+        )
+        .await?;
+
+        let disagreeing = ingest(&mut transaction).await?;
+        record(
+            &mut transaction,
+            disagreeing,
+            false,
+            &tags(&[("violence", 2)]),
+            &shadow(false, &[("violence", 1), ("sexual", 3)]),
+        )
+        .await?;
+
+        let failed = ingest(&mut transaction).await?;
+        record(
+            &mut transaction,
+            failed,
+            false,
+            &tags(&[("violence", 3)]),
+            &shadow(true, &[]),
+        )
+        .await?;
+
+        let result = report(&mut transaction, &ReportFilter::default()).await?;
+
+        assert_eq!(
+            result.totals,
+            ReportTotals {
+                compared: 2,
+                disagreeing: 1,
+                primary_errors: 0,
+                shadow_errors: 1,
+            }
+        );
+        assert_eq!(
+            result.disagreements,
+            vec![
+                Disagreement {
+                    tag: "sexual".to_string(),
+                    primary_level: 0,
+                    shadow_level: 3,
+                    events: 1,
+                },
+                Disagreement {
+                    tag: "violence".to_string(),
+                    primary_level: 2,
+                    shadow_level: 1,
+                    events: 1,
+                },
+            ]
+        );
+
+        let other = report(
+            &mut transaction,
+            &ReportFilter {
+                provider: Some("azure".to_string()),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        assert_eq!(other.totals.compared, 0);
+        assert!(other.disagreements.is_empty());
+
+        Ok(())
+    }
+}
@@ -11,12 +11,21 @@ struct OpenSearchSearchDocumentMessage {
     message: Option<String>,
 }
 
+pub(crate) const MESSAGES_INDEX: &str = "messages";
+pub(crate) const PROFILE_NAMES_INDEX: &str = "profile_names";
+pub(crate) const PROFILE_DESCRIPTIONS_INDEX: &str = "profile_descriptions";
+
+// One document per system in each profile index. The document _id is the
+// base64 public key of the system, and the document version is the
+// unix_milliseconds of the LWW element so that only newer values replace it.
 #[derive(::serde::Deserialize, ::serde::Serialize)]
-struct OpenSearchSearchDocumentProfile {
+pub(crate) struct OpenSearchSearchDocumentProfile {
     pub(crate) author_public_key: String,
     pub(crate) writer_id: String,
     pub(crate) sequence_number: i64,
-    pub(crate) profile_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) profile_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) profile_description: Option<String>,
     pub(crate) unix_milliseconds: u64,
 }
@@ -31,9 +40,19 @@ pub(crate) struct OpenSearchContent {
     pub(crate) byte_reference: Option<String>,
 }
 
+// A document ready to be written to one of the indices, see
+// `ingest::search_document`.
+pub(crate) struct SearchDocument {
+    pub(crate) index: &'static str,
+    pub(crate) id: String,
+    pub(crate) body: ::serde_json::Value,
+    // When set the document is only written if the version is at least as
+    // new as the one already stored.
+    pub(crate) version: Option<u64>,
+}
+
 #[derive(::serde::Deserialize)]
 pub(crate) struct OpenSearchSearchHitsL2 {
-    pub(crate) _source: OpenSearchContent,
     pub(crate) _id: String,
     pub(crate) _index: String,
 }
@@ -65,13 +84,52 @@ pub(crate) struct OpenSearchSearchL0 {
     pub(crate) aggregations: Option<OpenSearchAggregationsL1>,
 }
 
-pub(crate) async fn prepare_indices(
+#[derive(::serde::Deserialize)]
+pub(crate) struct OpenSearchScrollL0 {
+    pub(crate) _scroll_id: Option<String>,
+    pub(crate) hits: Option<OpenSearchSearchHitsL1>,
+}
+
+fn text_with_keyword() -> ::serde_json::Value {
+    serde_json::json!({
+        "type": "text",
+        "fields": {
+          "keyword": {
+            "type": "keyword",
+            "ignore_above" : 256
+          }
+        }
+    })
+}
+
+fn messages_properties() -> ::serde_json::Value {
+    serde_json::json!({
+        "message_content": text_with_keyword(),
+        "byte_reference": text_with_keyword(),
+        "unix_milliseconds": { "type": "date", "format": "epoch_millis" }
+    })
+}
+
+fn profile_properties() -> ::serde_json::Value {
+    serde_json::json!({
+        "author_public_key": { "type": "keyword" },
+        "writer_id": { "type": "keyword" },
+        "sequence_number": { "type": "long" },
+        "profile_name": text_with_keyword(),
+        "profile_description": text_with_keyword(),
+        "unix_milliseconds": { "type": "date", "format": "epoch_millis" }
+    })
+}
+
+async fn prepare_index(
     opensearch_client: &OpenSearch,
+    index: &str,
+    properties: ::serde_json::Value,
 ) -> Result<opensearch::http::response::Response, opensearch::Error> {
     // Check if the index exists
     let response = opensearch_client
         .indices()
-        .exists(IndicesExistsParts::Index(&["messages"]))
+        .exists(IndicesExistsParts::Index(&[index]))
         .send()
         .await;
 
@@ -81,63 +139,80 @@ pub(crate) async fn prepare_indices(
     };
 
     if !index_exists {
-        let mappings = serde_json::json!({
-            "mappings": {
-                "properties": {
-                    "message_content": { "type": "text",
-                    "fields": {
-                      "keyword": {
-                        "type": "keyword",
-                        "ignore_above" : 256
-                      }
-                    } },
-                    "byte_reference": { "type": "text",
-                    "fields": {
-                      "keyword": {
-                        "type": "keyword",
-                        "ignore_above" : 256
-                      }
-                    } },
-                    "unix_milliseconds": { "type": "date", "format": "epoch_millis" }
-                }
-            }
-        });
-
         opensearch_client
             .indices()
-            .create(IndicesCreateParts::Index("messages"))
-            .body(mappings)
+            .create(IndicesCreateParts::Index(index))
+            .body(serde_json::json!({
+                "mappings": {
+                    "properties": properties
+                }
+            }))
             .send()
             .await
     } else {
-        // If the index already exists, we need to update the mappingsa
-        let mappings = serde_json::json!({
-            "properties": {
-                "message_content": { "type": "text",
-                "fields": {
-                  "keyword": {
-                    "type": "keyword",
-                    "ignore_above" : 256
-                  }
-                } },
-                "byte_reference": {
-                    "type": "text",
-                    "fields": {
-                    "keyword": {
-                        "type": "keyword",
-                        "ignore_above" : 256
-                    }
-                }
-            },
-                "unix_milliseconds": { "type": "date", "format": "epoch_millis" }
-            }
-        });
-
+        // If the index already exists, we need to update the mappings
         opensearch_client
             .indices()
-            .put_mapping(IndicesPutMappingParts::Index(&["messages"]))
-            .body(mappings)
+            .put_mapping(IndicesPutMappingParts::Index(&[index]))
+            .body(serde_json::json!({
+                "properties": properties
+            }))
             .send()
             .await
     }
 }
+
+pub(crate) async fn prepare_indices(
+    opensearch_client: &OpenSearch,
+) -> Result<(), opensearch::Error> {
+    prepare_index(opensearch_client, MESSAGES_INDEX, messages_properties())
+        .await?;
+
+    prepare_index(opensearch_client, PROFILE_NAMES_INDEX, profile_properties())
+        .await?;
+
+    prepare_index(
+        opensearch_client,
+        PROFILE_DESCRIPTIONS_INDEX,
+        profile_properties(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    pub(crate) type Requests = ::std::sync::Arc<
+        ::std::sync::Mutex<::std::vec::Vec<::serde_json::Value>>,
+    >;
+
+    // A client for a local server that answers every request with `response`
+    // and records the request bodies.
+    pub(crate) fn mock(
+        response: ::serde_json::Value,
+    ) -> ::anyhow::Result<(super::OpenSearch, Requests)> {
+        use ::warp::Filter;
+
+        let requests = Requests::default();
+
+        let route = ::warp::body::json().map({
+            let requests = requests.clone();
+            move |body: ::serde_json::Value| {
+                requests.lock().unwrap().push(body);
+                ::warp::reply::json(&response)
+            }
+        });
+
+        let (address, server) =
+            ::warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+
+        ::tokio::spawn(server);
+
+        let transport = ::opensearch::http::transport::Transport::single_node(
+            &format!("http://{}", address),
+        )?;
+
+        Ok((super::OpenSearch::new(transport), requests))
+    }
+}
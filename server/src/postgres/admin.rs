@@ -0,0 +1,296 @@
+#[derive(::sqlx::FromRow)]
+pub(crate) struct AdminTokenRow {
+    pub(crate) name: String,
+    pub(crate) token_hash: ::std::vec::Vec<u8>,
+    pub(crate) scopes: ::std::vec::Vec<String>,
+}
+
+#[derive(::sqlx::FromRow, Debug, PartialEq)]
+pub(crate) struct AuditLogEntry {
+    pub(crate) id: i64,
+    pub(crate) admin_name: String,
+    pub(crate) action: String,
+    pub(crate) target: Option<String>,
+    pub(crate) details: ::serde_json::Value,
+    // unix milliseconds
+    pub(crate) created_on: i64,
+}
+
+pub(crate) async fn insert_admin_token(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    name: &str,
+    token_prefix: &str,
+    token_hash: &[u8],
+    scopes: &[String],
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO admin_tokens (name, token_prefix, token_hash, scopes)
+        VALUES ($1, $2, $3, $4);
+    ";
+
+    ::sqlx::query(query)
+        .bind(name)
+        .bind(token_prefix)
+        .bind(token_hash)
+        .bind(scopes)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+// Returns whether an active token with this name existed.
+pub(crate) async fn revoke_admin_token(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    name: &str,
+) -> ::anyhow::Result<bool> {
+    let query = "
+        UPDATE admin_tokens
+        SET revoked_on = NOW()
+        WHERE name = $1
+        AND revoked_on IS NULL;
+    ";
+
+    let result = ::sqlx::query(query)
+        .bind(name)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub(crate) async fn load_active_admin_token(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    token_prefix: &str,
+) -> ::anyhow::Result<Option<AdminTokenRow>> {
+    let query = "
+        SELECT name, token_hash, scopes
+        FROM admin_tokens
+        WHERE token_prefix = $1
+        AND revoked_on IS NULL;
+    ";
+
+    Ok(::sqlx::query_as::<_, AdminTokenRow>(query)
+        .bind(token_prefix)
+        .fetch_optional(&mut **transaction)
+        .await?)
+}
+
+pub(crate) async fn insert_audit_log(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    admin_name: &str,
+    action: &str,
+    target: Option<&str>,
+    details: &::serde_json::Value,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO admin_audit_log (admin_name, action, target, details)
+        VALUES ($1, $2, $3, $4);
+    ";
+
+    ::sqlx::query(query)
+        .bind(admin_name)
+        .bind(action)
+        .bind(target)
+        .bind(details)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+// Newest entries first. `before` is the id of the last entry of the previous
+// page.
+pub(crate) async fn load_audit_log(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    before: Option<u64>,
+    limit: u64,
+    admin_name: Option<&str>,
+    action: Option<&str>,
+) -> ::anyhow::Result<::std::vec::Vec<AuditLogEntry>> {
+    let query = "
+        SELECT
+            id,
+            admin_name,
+            action,
+            target,
+            details,
+            (EXTRACT(EPOCH FROM created_on) * 1000)::INT8 AS created_on
+        FROM admin_audit_log
+        WHERE ($1::INT8 IS NULL OR id < $1)
+        AND ($3::TEXT IS NULL OR admin_name = $3)
+        AND ($4::TEXT IS NULL OR action = $4)
+        ORDER BY id DESC
+        LIMIT $2;
+    ";
+
+    Ok(::sqlx::query_as::<_, AuditLogEntry>(query)
+        .bind(before.map(i64::try_from).transpose()?)
+        .bind(i64::try_from(limit)?)
+        .bind(admin_name)
+        .bind(action)
+        .fetch_all(&mut **transaction)
+        .await?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use ::serde_json::json;
+
+    #[::sqlx::test]
+    async fn test_admin_tokens(pool: ::sqlx::PgPool) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        crate::postgres::admin::insert_admin_token(
+            &mut transaction,
+            "alice",
+            "first",
+            &crate::admin::hash_token("first.secret"),
+            &[String::from("censor")],
+        )
+        .await?;
+
+        let token = crate::postgres::admin::load_active_admin_token(
+            &mut transaction,
+            "first",
+        )
+        .await?
+        .unwrap();
+
+        assert_eq!(token.name, "alice");
+        assert_eq!(token.scopes, vec![String::from("censor")]);
+
+        assert!(crate::postgres::admin::load_active_admin_token(
+            &mut transaction,
+            "second"
+        )
+        .await?
+        .is_none());
+
+        assert!(
+            crate::postgres::admin::revoke_admin_token(
+                &mut transaction,
+                "alice"
+            )
+            .await?
+        );
+        assert!(
+            !crate::postgres::admin::revoke_admin_token(
+                &mut transaction,
+                "alice"
+            )
+            .await?
+        );
+
+        assert!(crate::postgres::admin::load_active_admin_token(
+            &mut transaction,
+            "first"
+        )
+        .await?
+        .is_none());
+
+        // The name is free again once revoked, but only one active token
+        // can hold it
+        crate::postgres::admin::insert_admin_token(
+            &mut transaction,
+            "alice",
+            "second",
+            &crate::admin::hash_token("second.secret"),
+            &[String::from("censor")],
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        let mut transaction = pool.begin().await?;
+
+        assert!(crate::postgres::admin::insert_admin_token(
+            &mut transaction,
+            "alice",
+            "third",
+            &crate::admin::hash_token("third.secret"),
+            &[String::from("censor")],
+        )
+        .await
+        .is_err());
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_audit_log(pool: ::sqlx::PgPool) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let mut transaction = pool.begin().await?;
+
+        for (admin_name, action) in [
+            ("alice", "censor_system"),
+            ("bob", "censor_event"),
+            ("alice", "lift_censorship"),
+        ] {
+            crate::postgres::admin::insert_audit_log(
+                &mut transaction,
+                admin_name,
+                action,
+                Some("target"),
+                &json!({ "reason": "spam" }),
+            )
+            .await?;
+        }
+
+        let all = crate::postgres::admin::load_audit_log(
+            &mut transaction,
+            None,
+            2,
+            None,
+            None,
+        )
+        .await?;
+
+        assert_eq!(
+            all.iter()
+                .map(|entry| entry.action.as_str())
+                .collect::<::std::vec::Vec<_>>(),
+            vec!["lift_censorship", "censor_event"]
+        );
+        assert_eq!(all[0].details, json!({ "reason": "spam" }));
+
+        let older = crate::postgres::admin::load_audit_log(
+            &mut transaction,
+            Some(u64::try_from(all[1].id)?),
+            10,
+            None,
+            None,
+        )
+        .await?;
+
+        assert_eq!(older.len(), 1);
+        assert_eq!(older[0].action, "censor_system");
+
+        let alice = crate::postgres::admin::load_audit_log(
+            &mut transaction,
+            None,
+            10,
+            Some("alice"),
+            Some("censor_system"),
+        )
+        .await?;
+
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].admin_name, "alice");
+
+        transaction.commit().await?;
+
+        let mut transaction = pool.begin().await?;
+
+        assert!(::sqlx::query("DELETE FROM admin_audit_log;")
+            .execute(&mut *transaction)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+}
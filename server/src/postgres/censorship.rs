@@ -0,0 +1,540 @@
+use crate::postgres::CensorshipType;
+
+#[derive(::sqlx::FromRow)]
+struct CensoredSystemRow {
+    id: i64,
+    #[sqlx(try_from = "i64")]
+    system_key_type: u64,
+    system_key: ::std::vec::Vec<u8>,
+    censorship_type: CensorshipType,
+    reason: Option<String>,
+    censored_by: Option<String>,
+    expires_at: Option<i64>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+#[derive(::sqlx::FromRow)]
+struct CensoredEventRow {
+    id: i64,
+    #[sqlx(try_from = "i64")]
+    system_key_type: u64,
+    system_key: ::std::vec::Vec<u8>,
+    process: ::std::vec::Vec<u8>,
+    #[sqlx(try_from = "i64")]
+    logical_clock: u64,
+    censorship_type: CensorshipType,
+    reason: Option<String>,
+    censored_by: Option<String>,
+    expires_at: Option<i64>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+// Timestamps are unix milliseconds.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CensoredSystem {
+    pub(crate) id: u64,
+    pub(crate) system: polycentric_protocol::model::public_key::PublicKey,
+    pub(crate) censorship_type: CensorshipType,
+    pub(crate) reason: Option<String>,
+    pub(crate) censored_by: Option<String>,
+    pub(crate) expires_at: Option<u64>,
+    pub(crate) created_at: u64,
+    pub(crate) updated_at: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct CensoredEvent {
+    pub(crate) id: u64,
+    pub(crate) system: polycentric_protocol::model::public_key::PublicKey,
+    pub(crate) process: polycentric_protocol::model::process::Process,
+    pub(crate) logical_clock: u64,
+    pub(crate) censorship_type: CensorshipType,
+    pub(crate) reason: Option<String>,
+    pub(crate) censored_by: Option<String>,
+    pub(crate) expires_at: Option<u64>,
+    pub(crate) created_at: u64,
+    pub(crate) updated_at: u64,
+}
+
+impl TryFrom<CensoredSystemRow> for CensoredSystem {
+    type Error = ::anyhow::Error;
+
+    fn try_from(row: CensoredSystemRow) -> ::anyhow::Result<Self> {
+        Ok(CensoredSystem {
+            id: u64::try_from(row.id)?,
+            system:
+                polycentric_protocol::model::public_key::from_type_and_bytes(
+                    row.system_key_type,
+                    &row.system_key,
+                )?,
+            censorship_type: row.censorship_type,
+            reason: row.reason,
+            censored_by: row.censored_by,
+            expires_at: row.expires_at.map(u64::try_from).transpose()?,
+            created_at: u64::try_from(row.created_at)?,
+            updated_at: u64::try_from(row.updated_at)?,
+        })
+    }
+}
+
+impl TryFrom<CensoredEventRow> for CensoredEvent {
+    type Error = ::anyhow::Error;
+
+    fn try_from(row: CensoredEventRow) -> ::anyhow::Result<Self> {
+        Ok(CensoredEvent {
+            id: u64::try_from(row.id)?,
+            system:
+                polycentric_protocol::model::public_key::from_type_and_bytes(
+                    row.system_key_type,
+                    &row.system_key,
+                )?,
+            process: polycentric_protocol::model::process::from_vec(
+                &row.process,
+            )?,
+            logical_clock: row.logical_clock,
+            censorship_type: row.censorship_type,
+            reason: row.reason,
+            censored_by: row.censored_by,
+            expires_at: row.expires_at.map(u64::try_from).transpose()?,
+            created_at: u64::try_from(row.created_at)?,
+            updated_at: u64::try_from(row.updated_at)?,
+        })
+    }
+}
+
+const SYSTEM_COLUMNS: &str = "
+    id,
+    system_key_type,
+    system_key,
+    censorship_type,
+    reason,
+    censored_by,
+    (EXTRACT(EPOCH FROM expires_at) * 1000)::INT8 AS expires_at,
+    (EXTRACT(EPOCH FROM created_at) * 1000)::INT8 AS created_at,
+    (EXTRACT(EPOCH FROM updated_at) * 1000)::INT8 AS updated_at
+";
+
+const EVENT_COLUMNS: &str = "
+    id,
+    system_key_type,
+    system_key,
+    process,
+    logical_clock,
+    censorship_type,
+    reason,
+    censored_by,
+    (EXTRACT(EPOCH FROM expires_at) * 1000)::INT8 AS expires_at,
+    (EXTRACT(EPOCH FROM created_at) * 1000)::INT8 AS created_at,
+    (EXTRACT(EPOCH FROM updated_at) * 1000)::INT8 AS updated_at
+";
+
+pub(crate) async fn list_censored_systems(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    after: u64,
+    limit: u64,
+    censorship_type: Option<CensorshipType>,
+) -> ::anyhow::Result<::std::vec::Vec<CensoredSystem>> {
+    let query = format!(
+        "
+        SELECT {SYSTEM_COLUMNS} FROM censored_systems
+        WHERE id > $1
+        AND ($3::censorship_type IS NULL OR censorship_type = $3)
+        ORDER BY id ASC
+        LIMIT $2;
+        "
+    );
+
+    ::sqlx::query_as::<_, CensoredSystemRow>(&query)
+        .bind(i64::try_from(after)?)
+        .bind(i64::try_from(limit)?)
+        .bind(censorship_type)
+        .fetch_all(&mut **transaction)
+        .await?
+        .into_iter()
+        .map(CensoredSystem::try_from)
+        .collect()
+}
+
+pub(crate) async fn list_censored_events(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    after: u64,
+    limit: u64,
+    censorship_type: Option<CensorshipType>,
+) -> ::anyhow::Result<::std::vec::Vec<CensoredEvent>> {
+    let query = format!(
+        "
+        SELECT {EVENT_COLUMNS} FROM censored_events
+        WHERE id > $1
+        AND ($3::censorship_type IS NULL OR censorship_type = $3)
+        ORDER BY id ASC
+        LIMIT $2;
+        "
+    );
+
+    ::sqlx::query_as::<_, CensoredEventRow>(&query)
+        .bind(i64::try_from(after)?)
+        .bind(i64::try_from(limit)?)
+        .bind(censorship_type)
+        .fetch_all(&mut **transaction)
+        .await?
+        .into_iter()
+        .map(CensoredEvent::try_from)
+        .collect()
+}
+
+pub(crate) async fn load_censored_system(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: u64,
+) -> ::anyhow::Result<Option<CensoredSystem>> {
+    let query = format!(
+        "
+        SELECT {SYSTEM_COLUMNS} FROM censored_systems
+        WHERE id = $1;
+        "
+    );
+
+    ::sqlx::query_as::<_, CensoredSystemRow>(&query)
+        .bind(i64::try_from(id)?)
+        .fetch_optional(&mut **transaction)
+        .await?
+        .map(CensoredSystem::try_from)
+        .transpose()
+}
+
+pub(crate) async fn load_censored_event(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: u64,
+) -> ::anyhow::Result<Option<CensoredEvent>> {
+    let query = format!(
+        "
+        SELECT {EVENT_COLUMNS} FROM censored_events
+        WHERE id = $1;
+        "
+    );
+
+    ::sqlx::query_as::<_, CensoredEventRow>(&query)
+        .bind(i64::try_from(id)?)
+        .fetch_optional(&mut **transaction)
+        .await?
+        .map(CensoredEvent::try_from)
+        .transpose()
+}
+
+// Censored events are removed from the search indices, so lifting a
+// censorship queues the searchable events it covered to be indexed again.
+// `search_indexer` skips any that are still censored some other way.
+fn lift_systems_query(condition: &str) -> String {
+    format!(
+        "
+        WITH lifted AS (
+            DELETE FROM censored_systems
+            WHERE {condition}
+            RETURNING system_key_type, system_key
+        ), reindexed AS (
+            INSERT INTO search_outbox (event_id)
+            SELECT events.id FROM events
+            JOIN lifted
+            ON events.system_key_type = lifted.system_key_type
+            AND events.system_key = lifted.system_key
+            WHERE events.content_type = ANY($1)
+        )
+        SELECT COUNT(*) FROM lifted;
+        "
+    )
+}
+
+fn lift_events_query(condition: &str) -> String {
+    format!(
+        "
+        WITH lifted AS (
+            DELETE FROM censored_events
+            WHERE {condition}
+            RETURNING system_key_type, system_key, process, logical_clock
+        ), reindexed AS (
+            INSERT INTO search_outbox (event_id)
+            SELECT events.id FROM events
+            JOIN lifted
+            ON events.system_key_type = lifted.system_key_type
+            AND events.system_key = lifted.system_key
+            AND events.process = lifted.process
+            AND events.logical_clock = lifted.logical_clock
+            WHERE events.content_type = ANY($1)
+        )
+        SELECT COUNT(*) FROM lifted;
+        "
+    )
+}
+
+// Returns whether there was a censorship to lift.
+pub(crate) async fn lift_censored_system(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: u64,
+) -> ::anyhow::Result<bool> {
+    let lifted: i64 = ::sqlx::query_scalar(&lift_systems_query("id = $2"))
+        .bind(crate::search_indexer::searchable_content_types()?)
+        .bind(i64::try_from(id)?)
+        .fetch_one(&mut **transaction)
+        .await?;
+
+    Ok(lifted > 0)
+}
+
+pub(crate) async fn lift_censored_event(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: u64,
+) -> ::anyhow::Result<bool> {
+    let lifted: i64 = ::sqlx::query_scalar(&lift_events_query("id = $2"))
+        .bind(crate::search_indexer::searchable_content_types()?)
+        .bind(i64::try_from(id)?)
+        .fetch_one(&mut **transaction)
+        .await?;
+
+    Ok(lifted > 0)
+}
+
+// Returns the number of censored systems and events that were lifted.
+pub(crate) async fn delete_expired_censorship(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+) -> ::anyhow::Result<u64> {
+    let mut deleted = 0;
+
+    for query in [
+        lift_systems_query("expires_at <= NOW()"),
+        lift_events_query("expires_at <= NOW()"),
+    ] {
+        let lifted: i64 = ::sqlx::query_scalar(&query)
+            .bind(crate::search_indexer::searchable_content_types()?)
+            .fetch_one(&mut **transaction)
+            .await?;
+
+        deleted += u64::try_from(lifted)?;
+    }
+
+    Ok(deleted)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::postgres::{CensorshipDetails, CensorshipType};
+
+    #[::sqlx::test]
+    async fn test_censor_twice_updates(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let system =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        crate::postgres::censor_system(
+            &mut transaction,
+            CensorshipType::DoNotRecommend,
+            system.clone(),
+            &CensorshipDetails {
+                reason: Some(String::from("spam")),
+                censored_by: Some(String::from("alice")),
+                expires_at: None,
+            },
+        )
+        .await?;
+
+        crate::postgres::censor_system(
+            &mut transaction,
+            CensorshipType::RefuseStorage,
+            system.clone(),
+            &CensorshipDetails {
+                reason: Some(String::from("illegal")),
+                censored_by: Some(String::from("bob")),
+                expires_at: Some(4102444800000),
+            },
+        )
+        .await?;
+
+        let listed = crate::postgres::censorship::list_censored_systems(
+            &mut transaction,
+            0,
+            10,
+            None,
+        )
+        .await?;
+
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].system, system);
+        assert_eq!(listed[0].censorship_type, CensorshipType::RefuseStorage);
+        assert_eq!(listed[0].reason, Some(String::from("illegal")));
+        assert_eq!(listed[0].censored_by, Some(String::from("bob")));
+        assert_eq!(listed[0].expires_at, Some(4102444800000));
+
+        assert_eq!(
+            crate::postgres::censorship::load_censored_system(
+                &mut transaction,
+                listed[0].id
+            )
+            .await?,
+            listed.into_iter().next()
+        );
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_list_and_lift_censored_events(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let system =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        for logical_clock in 1..4 {
+            crate::postgres::censor_event(
+                &mut transaction,
+                if logical_clock == 2 {
+                    CensorshipType::RefuseStorage
+                } else {
+                    CensorshipType::DoNotRecommend
+                },
+                &system,
+                &process,
+                logical_clock,
+                &CensorshipDetails::default(),
+            )
+            .await?;
+        }
+
+        let first_page = crate::postgres::censorship::list_censored_events(
+            &mut transaction,
+            0,
+            2,
+            None,
+        )
+        .await?;
+
+        assert_eq!(
+            first_page
+                .iter()
+                .map(|censored| censored.logical_clock)
+                .collect::<::std::vec::Vec<u64>>(),
+            vec![1, 2]
+        );
+
+        let second_page = crate::postgres::censorship::list_censored_events(
+            &mut transaction,
+            first_page[1].id,
+            2,
+            None,
+        )
+        .await?;
+
+        assert_eq!(second_page.len(), 1);
+        assert_eq!(second_page[0].logical_clock, 3);
+        assert_eq!(second_page[0].process, process);
+
+        let refused = crate::postgres::censorship::list_censored_events(
+            &mut transaction,
+            0,
+            10,
+            Some(CensorshipType::RefuseStorage),
+        )
+        .await?;
+
+        assert_eq!(refused.len(), 1);
+        assert_eq!(refused[0].logical_clock, 2);
+
+        assert!(
+            crate::postgres::censorship::lift_censored_event(
+                &mut transaction,
+                refused[0].id
+            )
+            .await?
+        );
+        assert!(
+            !crate::postgres::censorship::lift_censored_event(
+                &mut transaction,
+                refused[0].id
+            )
+            .await?
+        );
+        assert!(crate::postgres::censorship::load_censored_event(
+            &mut transaction,
+            refused[0].id
+        )
+        .await?
+        .is_none());
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_delete_expired_censorship(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let expired =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+        let permanent =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        crate::postgres::censor_system(
+            &mut transaction,
+            CensorshipType::DoNotRecommend,
+            expired,
+            &CensorshipDetails {
+                expires_at: Some(1000),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        crate::postgres::censor_system(
+            &mut transaction,
+            CensorshipType::DoNotRecommend,
+            permanent.clone(),
+            &CensorshipDetails::default(),
+        )
+        .await?;
+
+        assert_eq!(
+            crate::postgres::censorship::delete_expired_censorship(
+                &mut transaction
+            )
+            .await?,
+            1
+        );
+
+        assert_eq!(
+            crate::postgres::censorship::list_censored_systems(
+                &mut transaction,
+                0,
+                10,
+                None,
+            )
+            .await?
+            .into_iter()
+            .map(|censored| censored.system)
+            .collect::<::std::vec::Vec<_>>(),
+            vec![permanent]
+        );
+
+        Ok(())
+    }
+}
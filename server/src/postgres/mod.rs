@@ -5,6 +5,8 @@ use ::std::convert::TryFrom;
 use crate::cursor::ExploreCursor;
 use crate::moderation::{ModerationFilters, ModerationOptions};
 
+pub(crate) mod admin;
+pub(crate) mod censorship;
 pub(crate) mod count_lww_element_references;
 pub(crate) mod count_references;
 pub(crate) mod purge;
@@ -20,13 +22,23 @@ pub(crate) mod update_counts;
 #[derive(::sqlx::Type)]
 #[sqlx(type_name = "censorship_type")]
 #[sqlx(rename_all = "snake_case")]
-#[derive(::serde::Deserialize)]
+#[derive(::serde::Deserialize, ::serde::Serialize)]
 #[serde(rename_all = "snake_case")]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub(crate) enum CensorshipType {
     DoNotRecommend,
     RefuseStorage,
 }
 
+// Who applied a censorship and why. An entry with `expires_at`, in unix
+// milliseconds, is removed by `crate::censorship::run_expiry_sweep`.
+#[derive(Default, Clone, Debug)]
+pub(crate) struct CensorshipDetails {
+    pub(crate) reason: Option<String>,
+    pub(crate) censored_by: Option<String>,
+    pub(crate) expires_at: Option<u64>,
+}
+
 #[derive(::sqlx::Type)]
 #[sqlx(type_name = "link_type")]
 #[sqlx(rename_all = "snake_case")]
@@ -98,7 +110,13 @@ pub(crate) struct EventsAndCursor {
 pub(crate) async fn prepare_database(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
 ) -> ::sqlx::Result<()> {
-    transaction.execute(include_str!("schema.sql")).await?;
+    // The moderated content types come from `moderation::extract`
+    let schema = include_str!("schema.sql").replace(
+        "{moderated_content_types}",
+        &crate::moderation::extract::moderated_content_types_sql(),
+    );
+
+    transaction.execute(schema.as_str()).await?;
     Ok(())
 }
 
@@ -125,7 +143,7 @@ pub(crate) async fn load_event(
         AND   system_key      = $2
         AND   process         = $3
         AND   logical_clock   = $4
-        AND   filter_events_by_moderation(events, $5::moderation_filter_type[], $6::moderation_mode)
+        AND   filter_events_by_moderation(events, $5::moderation_filter_type[], $6::moderation_mode, (SELECT moderation_policies()))
         LIMIT 1;
     ";
 
@@ -138,7 +156,7 @@ pub(crate) async fn load_event(
         ))
         .bind(process.bytes())
         .bind(i64::try_from(logical_clock)?)
-        .bind(moderation_options.get_filters_with_defaults())
+        .bind(moderation_options.get_filters())
         .bind(moderation_options.mode)
         .fetch_optional(&mut **transaction)
         .await?;
@@ -175,7 +193,7 @@ pub(crate) async fn load_events_after_id(
             ($1::BIGINT IS NULL OR unix_milliseconds >= $1) -- unix_milliseconds condition if $1 is not NULL
             AND
             ( ($1::BIGINT IS NOT NULL AND unix_milliseconds > $1) OR id > $2 ) -- Tie-breaking with id
-        AND filter_events_by_moderation(events, $4::moderation_filter_type[], $5::moderation_mode)
+        AND filter_events_by_moderation(events, $4::moderation_filter_type[], $5::moderation_mode, (SELECT moderation_policies()))
         ORDER BY unix_milliseconds ASC NULLS FIRST, id ASC
         LIMIT $3;
     ";
@@ -184,7 +202,7 @@ pub(crate) async fn load_events_after_id(
         .bind(start_timestamp)
         .bind(start_id)
         .bind(i64::try_from(limit)?)
-        .bind(moderation_options.get_filters_with_defaults())
+        .bind(moderation_options.get_filters())
         .bind(moderation_options.mode)
         .fetch_all(&mut **transaction)
         .await?;
@@ -210,6 +228,47 @@ pub(crate) async fn load_events_after_id(
     Ok(result)
 }
 
+// Pages through every stored event in insertion order, which unlike
+// `load_events_after_id` never skips events that arrive with an older
+// timestamp.
+// Ids are taken when events are inserted, so a transaction can commit after
+// one holding a later id. Events are only exported once they are `lag` old,
+// so that the cursor does not move past ones that are about to show up.
+pub(crate) async fn load_events_for_export(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    after_id: u64,
+    limit: u64,
+    lag: ::std::time::Duration,
+) -> ::anyhow::Result<EventsAndCursor> {
+    let query = "
+        SELECT id, raw_event FROM events
+        WHERE id > $1
+        AND server_time <= EXTRACT(EPOCH FROM NOW())::INT8 - $3
+        ORDER BY id ASC
+        LIMIT $2;
+    ";
+
+    let rows = ::sqlx::query_as::<_, (i64, ::std::vec::Vec<u8>)>(query)
+        .bind(i64::try_from(after_id)?)
+        .bind(i64::try_from(limit)?)
+        .bind(i64::try_from(lag.as_secs())?)
+        .fetch_all(&mut **transaction)
+        .await?;
+
+    let mut result_set = vec![];
+
+    for (_, raw_event) in rows.iter() {
+        result_set.push(polycentric_protocol::model::signed_event::from_vec(
+            raw_event,
+        )?);
+    }
+
+    Ok(EventsAndCursor {
+        events: result_set,
+        cursor: rows.last().map(|(id, _)| ExploreCursor::id_only(*id)),
+    })
+}
+
 pub(crate) async fn load_posts_before_id(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
     start_cursor: Option<ExploreCursor>,
@@ -225,7 +284,19 @@ pub(crate) async fn load_posts_before_id(
         SELECT id, raw_event, server_time, moderation_tags, unix_milliseconds FROM events
         WHERE ($1::BIGINT IS NULL OR unix_milliseconds <= $1) AND (unix_milliseconds < $1 OR id < $2)
         AND content_type = $3
-        AND filter_events_by_moderation(events, $5::moderation_filter_type[], $6::moderation_mode)
+        AND filter_events_by_moderation(events, $5::moderation_filter_type[], $6::moderation_mode, (SELECT moderation_policies()))
+        AND NOT EXISTS (
+            SELECT 1 FROM censored_systems
+            WHERE censored_systems.system_key_type = events.system_key_type
+            AND censored_systems.system_key = events.system_key
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM censored_events
+            WHERE censored_events.system_key_type = events.system_key_type
+            AND censored_events.system_key = events.system_key
+            AND censored_events.process = events.process
+            AND censored_events.logical_clock = events.logical_clock
+        )
         ORDER BY unix_milliseconds DESC NULLS LAST, id DESC
         LIMIT $4;
     ";
@@ -237,7 +308,7 @@ pub(crate) async fn load_posts_before_id(
             polycentric_protocol::model::known_message_types::POST,
         )?)
         .bind(i64::try_from(limit)?)
-        .bind(moderation_options.get_filters_with_defaults())
+        .bind(moderation_options.get_filters())
         .bind(moderation_options.mode)
         .fetch_all(&mut **transaction)
         .await?;
@@ -296,6 +367,7 @@ pub(crate) async fn load_latest_system_wide_lww_event_by_type(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
     system: &polycentric_protocol::model::public_key::PublicKey,
     content_type: u64,
+    moderation_options: &ModerationOptions,
 ) -> ::anyhow::Result<
     Option<polycentric_protocol::model::signed_event::SignedEvent>,
 > {
@@ -314,6 +386,8 @@ pub(crate) async fn load_latest_system_wide_lww_event_by_type(
             events.system_key = $2
         AND
             events.content_type = $3
+        AND
+            filter_events_by_moderation(events, $4::moderation_filter_type[], $5::moderation_mode, (SELECT moderation_policies()))
         ORDER BY
             lww_elements.unix_milliseconds DESC,
             events.process DESC
@@ -328,6 +402,8 @@ pub(crate) async fn load_latest_system_wide_lww_event_by_type(
             system,
         ))
         .bind(i64::try_from(content_type)?)
+        .bind(moderation_options.get_filters())
+        .bind(moderation_options.mode)
         .fetch_optional(&mut **transaction)
         .await?;
 
@@ -343,6 +419,80 @@ pub(crate) async fn load_latest_system_wide_lww_event_by_type(
     }
 }
 
+pub(crate) async fn load_system_events_by_content_type(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+    content_type: u64,
+) -> ::anyhow::Result<
+    ::std::vec::Vec<polycentric_protocol::model::signed_event::SignedEvent>,
+> {
+    let query = "
+        SELECT raw_event FROM events
+        WHERE system_key_type = $1
+        AND system_key = $2
+        AND content_type = $3
+        ORDER BY id ASC;
+    ";
+
+    ::sqlx::query_scalar::<_, ::std::vec::Vec<u8>>(query)
+        .bind(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(system),
+        )?)
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            system,
+        ))
+        .bind(i64::try_from(content_type)?)
+        .fetch_all(&mut **transaction)
+        .await?
+        .iter()
+        .map(|raw_event| {
+            polycentric_protocol::model::signed_event::from_vec(raw_event)
+        })
+        .collect()
+}
+
+// Pages through every known system in key order.
+pub(crate) async fn load_systems_after(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    after: Option<&polycentric_protocol::model::public_key::PublicKey>,
+    limit: u64,
+) -> ::anyhow::Result<
+    ::std::vec::Vec<polycentric_protocol::model::public_key::PublicKey>,
+> {
+    let query = "
+        SELECT DISTINCT system_key_type, system_key FROM events
+        WHERE (system_key_type, system_key) > ($1, $2)
+        ORDER BY system_key_type, system_key
+        LIMIT $3;
+    ";
+
+    let (key_type, key) = match after {
+        Some(system) => (
+            i64::try_from(
+                polycentric_protocol::model::public_key::get_key_type(system),
+            )?,
+            polycentric_protocol::model::public_key::get_key_bytes(system),
+        ),
+        None => (0, vec![]),
+    };
+
+    let rows = ::sqlx::query_as::<_, SystemRow>(query)
+        .bind(key_type)
+        .bind(key)
+        .bind(i64::try_from(limit)?)
+        .fetch_all(&mut **transaction)
+        .await?;
+
+    rows.iter()
+        .map(|row| {
+            polycentric_protocol::model::public_key::from_type_and_bytes(
+                row.system_key_type,
+                &row.system_key,
+            )
+        })
+        .collect()
+}
+
 pub(crate) async fn does_event_exist(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
     event: &polycentric_protocol::model::event::Event,
@@ -430,7 +580,8 @@ pub(crate) async fn delete_event(
         WHERE system_key_type = $1
         AND system_key = $2
         AND process = $3
-        AND logical_clock = $4;
+        AND logical_clock = $4
+        RETURNING raw_event;
     ";
 
     ::sqlx::query(query_insert_delete)
@@ -448,17 +599,20 @@ pub(crate) async fn delete_event(
         .execute(&mut **transaction)
         .await?;
 
-    ::sqlx::query(query_delete_event)
-        .bind(i64::try_from(
-            polycentric_protocol::model::public_key::get_key_type(system),
-        )?)
-        .bind(polycentric_protocol::model::public_key::get_key_bytes(
-            system,
-        ))
-        .bind(delete.process().bytes())
-        .bind(i64::try_from(*delete.logical_clock())?)
-        .execute(&mut **transaction)
-        .await?;
+    let deleted: ::std::vec::Vec<::std::vec::Vec<u8>> =
+        ::sqlx::query_scalar(query_delete_event)
+            .bind(i64::try_from(
+                polycentric_protocol::model::public_key::get_key_type(system),
+            )?)
+            .bind(polycentric_protocol::model::public_key::get_key_bytes(
+                system,
+            ))
+            .bind(delete.process().bytes())
+            .bind(i64::try_from(*delete.logical_clock())?)
+            .fetch_all(&mut **transaction)
+            .await?;
+
+    crate::search_indexer::remove_events(transaction, &deleted).await?;
 
     Ok(())
 }
@@ -523,6 +677,46 @@ pub(crate) async fn insert_event(
     Ok(u64::try_from(id)?)
 }
 
+// Notifications are only delivered once the transaction commits.
+pub(crate) const EVENTS_CHANNEL: &str = "events_available";
+
+pub(crate) async fn notify_event_available(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: u64,
+) -> ::anyhow::Result<()> {
+    ::sqlx::query("SELECT pg_notify($1, $2);")
+        .bind(EVENTS_CHANNEL)
+        .bind(event_id.to_string())
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn load_event_by_id(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: u64,
+) -> ::anyhow::Result<
+    Option<polycentric_protocol::model::signed_event::SignedEvent>,
+> {
+    let query = "
+        SELECT raw_event FROM events
+        WHERE id = $1;
+    ";
+
+    let potential_raw = ::sqlx::query_scalar::<_, ::std::vec::Vec<u8>>(query)
+        .bind(i64::try_from(event_id)?)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    match potential_raw {
+        Some(raw) => Ok(Some(
+            polycentric_protocol::model::signed_event::from_vec(&raw)?,
+        )),
+        None => Ok(None),
+    }
+}
+
 pub(crate) async fn insert_event_link(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
     event_id: u64,
@@ -828,12 +1022,15 @@ pub(crate) async fn known_ranges_for_system(
     Ok(result)
 }
 
+// Censoring something that is already censored replaces the previous type
+// and details.
 pub(crate) async fn censor_event(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
     censor_type: CensorshipType,
     system: &polycentric_protocol::model::public_key::PublicKey,
     process: &polycentric_protocol::model::process::Process,
     logical_clock: u64,
+    details: &CensorshipDetails,
 ) -> ::anyhow::Result<()> {
     let query = "
         INSERT INTO censored_events (
@@ -841,9 +1038,19 @@ pub(crate) async fn censor_event(
             system_key,
             process,
             logical_clock,
-            censorship_type
+            censorship_type,
+            reason,
+            censored_by,
+            expires_at
         )
-        VALUES ($1, $2, $3, $4, $5);
+        VALUES ($1, $2, $3, $4, $5, $6, $7, to_timestamp($8::INT8 / 1000.0))
+        ON CONFLICT (system_key_type, system_key, process, logical_clock)
+        DO UPDATE SET
+            censorship_type = EXCLUDED.censorship_type,
+            reason = EXCLUDED.reason,
+            censored_by = EXCLUDED.censored_by,
+            expires_at = EXCLUDED.expires_at,
+            updated_at = NOW();
         ";
     ::sqlx::query(query)
         .bind(i64::try_from(
@@ -855,23 +1062,63 @@ pub(crate) async fn censor_event(
         .bind(process.bytes())
         .bind(i64::try_from(logical_clock)?)
         .bind(censor_type)
+        .bind(&details.reason)
+        .bind(&details.censored_by)
+        .bind(details.expires_at.map(i64::try_from).transpose()?)
         .execute(&mut **transaction)
         .await?;
 
+    let query_raw_event = "
+        SELECT raw_event FROM events
+        WHERE system_key_type = $1
+        AND system_key = $2
+        AND process = $3
+        AND logical_clock = $4;
+    ";
+
+    let raw_events: ::std::vec::Vec<::std::vec::Vec<u8>> =
+        ::sqlx::query_scalar(query_raw_event)
+            .bind(i64::try_from(
+                polycentric_protocol::model::public_key::get_key_type(system),
+            )?)
+            .bind(polycentric_protocol::model::public_key::get_key_bytes(
+                system,
+            ))
+            .bind(process.bytes())
+            .bind(i64::try_from(logical_clock)?)
+            .fetch_all(&mut **transaction)
+            .await?;
+
+    crate::search_indexer::remove_events(transaction, &raw_events).await?;
+    crate::cache::purge_queue::enqueue_raw_events(transaction, &raw_events)
+        .await?;
+
     Ok(())
 }
+
 pub(crate) async fn censor_system(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
     censor_type: CensorshipType,
     system: polycentric_protocol::model::public_key::PublicKey,
+    details: &CensorshipDetails,
 ) -> ::anyhow::Result<()> {
     let query = "
         INSERT INTO censored_systems (
             system_key_type,
             system_key,
-            censorship_type
+            censorship_type,
+            reason,
+            censored_by,
+            expires_at
         )
-        VALUES ($1, $2, $3);
+        VALUES ($1, $2, $3, $4, $5, to_timestamp($6::INT8 / 1000.0))
+        ON CONFLICT (system_key_type, system_key)
+        DO UPDATE SET
+            censorship_type = EXCLUDED.censorship_type,
+            reason = EXCLUDED.reason,
+            censored_by = EXCLUDED.censored_by,
+            expires_at = EXCLUDED.expires_at,
+            updated_at = NOW();
         ";
     ::sqlx::query(query)
         .bind(i64::try_from(
@@ -881,113 +1128,483 @@ pub(crate) async fn censor_system(
             &system,
         ))
         .bind(censor_type)
+        .bind(&details.reason)
+        .bind(&details.censored_by)
+        .bind(details.expires_at.map(i64::try_from).transpose()?)
         .execute(&mut **transaction)
         .await?;
 
+    let query_posts = "
+        SELECT raw_event FROM events
+        WHERE system_key_type = $1
+        AND system_key = $2
+        AND content_type = $3;
+    ";
+
+    let posts: ::std::vec::Vec<::std::vec::Vec<u8>> =
+        ::sqlx::query_scalar(query_posts)
+            .bind(i64::try_from(
+                polycentric_protocol::model::public_key::get_key_type(&system),
+            )?)
+            .bind(polycentric_protocol::model::public_key::get_key_bytes(
+                &system,
+            ))
+            .bind(i64::try_from(
+                polycentric_protocol::model::known_message_types::POST,
+            )?)
+            .fetch_all(&mut **transaction)
+            .await?;
+
+    crate::search_indexer::remove_events(transaction, &posts).await?;
+    crate::search_indexer::remove_system(transaction, &system).await?;
+    crate::cache::purge_queue::enqueue_system(transaction, &system).await?;
+
     Ok(())
 }
 
-pub(crate) async fn claim_handle(
+// Without a censorship type any censorship of the event or its system counts.
+pub(crate) async fn is_event_censored(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
-    handle: String,
-    system: &polycentric_protocol::model::public_key::PublicKey,
-) -> ::anyhow::Result<()> {
-    let query_del = "
-        DELETE FROM identity_handles 
-        WHERE
-            system_key_type = $1
-            AND 
-            system_key = $2
-        ;
-        ";
+    event: &polycentric_protocol::model::event::Event,
+    censor_type: Option<CensorshipType>,
+) -> ::anyhow::Result<bool> {
+    let query = "
+        SELECT EXISTS (
+            SELECT 1 FROM censored_systems
+            WHERE system_key_type = $1
+            AND system_key = $2
+            AND ($5::censorship_type IS NULL OR censorship_type = $5)
+        ) OR EXISTS (
+            SELECT 1 FROM censored_events
+            WHERE system_key_type = $1
+            AND system_key = $2
+            AND process = $3
+            AND logical_clock = $4
+            AND ($5::censorship_type IS NULL OR censorship_type = $5)
+        );
+    ";
 
-    ::sqlx::query(query_del)
+    Ok(::sqlx::query_scalar::<_, bool>(query)
         .bind(i64::try_from(
-            polycentric_protocol::model::public_key::get_key_type(system),
+            polycentric_protocol::model::public_key::get_key_type(
+                event.system(),
+            ),
         )?)
         .bind(polycentric_protocol::model::public_key::get_key_bytes(
-            system,
+            event.system(),
         ))
-        .execute(&mut **transaction)
-        .await?;
+        .bind(event.process().bytes())
+        .bind(i64::try_from(*event.logical_clock())?)
+        .bind(censor_type)
+        .fetch_one(&mut **transaction)
+        .await?)
+}
+
+// Layers of an event already in the database. Content that no longer
+// decodes is kept as `Content::Unknown` rather than failing, events are only
+// decoded strictly when ingested.
+pub(crate) fn stored_event_layers(
+    signed_event: polycentric_protocol::model::signed_event::SignedEvent,
+) -> ::anyhow::Result<polycentric_protocol::model::EventLayers> {
+    match polycentric_protocol::model::EventLayers::new(signed_event.clone()) {
+        Ok(layers) => Ok(layers),
+        Err(err) => {
+            let layers =
+                polycentric_protocol::model::EventLayers::new_undecoded(
+                    signed_event,
+                )?;
+
+            ::log::warn!(
+                "stored event {} of type {} kept undecoded: {}",
+                *layers.event().logical_clock(),
+                *layers.event().content_type(),
+                err
+            );
+
+            Ok(layers)
+        }
+    }
+}
 
+// Whether each of `events` is censored, in order, with a single query.
+pub(crate) async fn are_events_censored(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    events: &[polycentric_protocol::model::event::Event],
+) -> ::anyhow::Result<::std::vec::Vec<bool>> {
     let query = "
-        INSERT INTO identity_handles (
+        SELECT
+            EXISTS (
+                SELECT 1 FROM censored_systems
+                WHERE censored_systems.system_key_type = p.system_key_type
+                AND censored_systems.system_key = p.system_key
+            ) OR EXISTS (
+                SELECT 1 FROM censored_events
+                WHERE censored_events.system_key_type = p.system_key_type
+                AND censored_events.system_key = p.system_key
+                AND censored_events.process = p.process
+                AND censored_events.logical_clock = p.logical_clock
+            )
+        FROM
+            UNNEST(
+                $1::bigint [],
+                $2::bytea [],
+                $3::bytea [],
+                $4::bigint []
+            ) WITH ORDINALITY AS p (
+                system_key_type,
+                system_key,
+                process,
+                logical_clock,
+                position
+            )
+        ORDER BY p.position;
+    ";
+
+    let mut p_system_key_type = vec![];
+    let mut p_system_key = vec![];
+    let mut p_process = vec![];
+    let mut p_logical_clock = vec![];
+
+    for event in events.iter() {
+        p_system_key_type.push(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(
+                event.system(),
+            ),
+        )?);
+        p_system_key.push(
+            polycentric_protocol::model::public_key::get_key_bytes(
+                event.system(),
+            ),
+        );
+        p_process.push(event.process().bytes().to_vec());
+        p_logical_clock.push(i64::try_from(*event.logical_clock())?);
+    }
+
+    Ok(::sqlx::query_scalar::<_, bool>(query)
+        .bind(p_system_key_type)
+        .bind(p_system_key)
+        .bind(p_process)
+        .bind(p_logical_clock)
+        .fetch_all(&mut **transaction)
+        .await?)
+}
+
+#[derive(PartialEq, Debug)]
+pub(crate) enum ClaimHandleResult {
+    Claimed,
+    AlreadyOwned,
+    Taken,
+}
+
+#[derive(::sqlx::FromRow)]
+struct HandleOwnerRow {
+    handle: String,
+    system_key: ::std::vec::Vec<u8>,
+    #[sqlx(try_from = "i64")]
+    system_key_type: u64,
+}
+
+async fn insert_handle_history(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    handle: &str,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+    action: &str,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO handle_history (
+            handle,
             system_key_type,
             system_key,
-            handle
+            action
         )
-        VALUES ($1, $2, $3);
-        ";
+        VALUES ($1, $2, $3, $4::handle_action);
+    ";
+
     ::sqlx::query(query)
+        .bind(handle)
         .bind(i64::try_from(
             polycentric_protocol::model::public_key::get_key_type(system),
         )?)
         .bind(polycentric_protocol::model::public_key::get_key_bytes(
             system,
         ))
-        .bind(handle)
+        .bind(action)
         .execute(&mut **transaction)
         .await?;
 
     Ok(())
 }
 
-pub(crate) async fn resolve_handle(
+// Handles are first come first served and compared case insensitively. A
+// system may only hold one handle, claiming a new one releases the old one.
+pub(crate) async fn claim_handle(
     transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
     handle: String,
-) -> ::anyhow::Result<polycentric_protocol::model::public_key::PublicKey> {
-    let query = "
+    system: &polycentric_protocol::model::public_key::PublicKey,
+) -> ::anyhow::Result<ClaimHandleResult> {
+    let query_owner = "
         SELECT
-            system_key,
-            system_key_type
+            handle,
+            system_key_type,
+            system_key
         FROM
             identity_handles
         WHERE
-            handle = $1;
+            LOWER(handle) = LOWER($1)
+        FOR UPDATE;
     ";
 
-    let sys_row = ::sqlx::query_as::<_, SystemRow>(query)
-        .bind(handle)
-        .fetch_one(&mut **transaction)
-        .await
-        .map_err(::anyhow::Error::new)?;
-
-    let sys = polycentric_protocol::model::public_key::from_type_and_bytes(
-        sys_row.system_key_type,
-        &sys_row.system_key,
-    )?;
+    let owner = ::sqlx::query_as::<_, HandleOwnerRow>(query_owner)
+        .bind(&handle)
+        .fetch_optional(&mut **transaction)
+        .await?;
 
-    Ok(sys)
-}
+    if let Some(owner) = owner {
+        let owner_system =
+            polycentric_protocol::model::public_key::from_type_and_bytes(
+                owner.system_key_type,
+                &owner.system_key,
+            )?;
 
-pub(crate) async fn load_random_profiles(
-    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
-    moderation_options: &ModerationOptions,
-) -> ::anyhow::Result<Vec<polycentric_protocol::model::public_key::PublicKey>> {
-    let query = "
-    SELECT 
-      system_key_type, 
-      system_key 
-    FROM 
-      (
-        SELECT 
-          DISTINCT events.system_key_type, 
-          events.system_key 
-        FROM 
-          events 
-          LEFT JOIN censored_systems
-          ON events.system_key_type = censored_systems.system_key_type 
-          AND events.system_key = censored_systems.system_key 
-        WHERE 
-          censored_systems.system_key IS NULL
-          AND filter_events_by_moderation(events, $1::moderation_filter_type[])
-      ) AS systems 
-    ORDER BY 
-      RANDOM() 
-    LIMIT 
-      10;
-    ";
+        if &owner_system != system {
+            return Ok(ClaimHandleResult::Taken);
+        }
+
+        if owner.handle == handle {
+            return Ok(ClaimHandleResult::AlreadyOwned);
+        }
+    }
+
+    let query_release = "
+        DELETE FROM identity_handles
+        WHERE
+            system_key_type = $1
+            AND
+            system_key = $2
+        RETURNING handle;
+    ";
+
+    let released = ::sqlx::query_scalar::<_, String>(query_release)
+        .bind(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(system),
+        )?)
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            system,
+        ))
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    if let Some(released) = released {
+        insert_handle_history(&mut *transaction, &released, system, "released")
+            .await?;
+    }
+
+    let query_claim = "
+        INSERT INTO identity_handles (
+            system_key_type,
+            system_key,
+            handle
+        )
+        VALUES ($1, $2, $3);
+    ";
+
+    let claimed = ::sqlx::query(query_claim)
+        .bind(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(system),
+        )?)
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            system,
+        ))
+        .bind(&handle)
+        .execute(&mut **transaction)
+        .await;
+
+    // Another request may have claimed the handle since we checked.
+    if let Err(::sqlx::Error::Database(err)) = &claimed {
+        if err.is_unique_violation() {
+            return Ok(ClaimHandleResult::Taken);
+        }
+    }
+
+    claimed?;
+
+    insert_handle_history(&mut *transaction, &handle, system, "claimed")
+        .await?;
+
+    Ok(ClaimHandleResult::Claimed)
+}
+
+// Frees the handle whatever its case. Returns the system that held it.
+pub(crate) async fn release_handle(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    handle: &str,
+) -> ::anyhow::Result<Option<polycentric_protocol::model::public_key::PublicKey>>
+{
+    let query = "
+        DELETE FROM identity_handles
+        WHERE LOWER(handle) = LOWER($1)
+        RETURNING handle, system_key_type, system_key;
+    ";
+
+    let owner = ::sqlx::query_as::<_, HandleOwnerRow>(query)
+        .bind(handle)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    match owner {
+        Some(owner) => {
+            let system =
+                polycentric_protocol::model::public_key::from_type_and_bytes(
+                    owner.system_key_type,
+                    &owner.system_key,
+                )?;
+
+            insert_handle_history(
+                &mut *transaction,
+                &owner.handle,
+                &system,
+                "released",
+            )
+            .await?;
+
+            Ok(Some(system))
+        }
+        None => Ok(None),
+    }
+}
+
+pub(crate) async fn load_remote_server_cursor(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    address: &str,
+) -> ::anyhow::Result<Option<u64>> {
+    let query = "
+        SELECT position FROM remote_server_cursors
+        WHERE address = $1;
+    ";
+
+    ::sqlx::query_scalar::<_, i64>(query)
+        .bind(address)
+        .fetch_optional(&mut **transaction)
+        .await?
+        .map(u64::try_from)
+        .transpose()
+        .map_err(::anyhow::Error::new)
+}
+
+pub(crate) async fn upsert_remote_server_cursor(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    address: &str,
+    position: u64,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO remote_server_cursors (address, position, updated_on)
+        VALUES ($1, $2, NOW())
+        ON CONFLICT (address) DO UPDATE
+        SET position = EXCLUDED.position, updated_on = NOW();
+    ";
+
+    ::sqlx::query(query)
+        .bind(address)
+        .bind(i64::try_from(position)?)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+// Returns false if the challenge has been used before.
+pub(crate) async fn insert_used_challenge(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    challenge: &[u8],
+    created_on: u64,
+) -> ::anyhow::Result<bool> {
+    let query = "
+        INSERT INTO used_challenges (challenge, created_on)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING;
+    ";
+
+    let result = ::sqlx::query(query)
+        .bind(challenge)
+        .bind(i64::try_from(created_on)?)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub(crate) async fn delete_used_challenges_before(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    created_on: u64,
+) -> ::anyhow::Result<u64> {
+    let query = "
+        DELETE FROM used_challenges
+        WHERE created_on < $1;
+    ";
+
+    let result = ::sqlx::query(query)
+        .bind(i64::try_from(created_on)?)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub(crate) async fn resolve_handle(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    handle: String,
+) -> ::anyhow::Result<polycentric_protocol::model::public_key::PublicKey> {
+    let query = "
+        SELECT
+            system_key,
+            system_key_type
+        FROM
+            identity_handles
+        WHERE
+            LOWER(handle) = LOWER($1);
+    ";
+
+    let sys_row = ::sqlx::query_as::<_, SystemRow>(query)
+        .bind(handle)
+        .fetch_one(&mut **transaction)
+        .await
+        .map_err(::anyhow::Error::new)?;
+
+    let sys = polycentric_protocol::model::public_key::from_type_and_bytes(
+        sys_row.system_key_type,
+        &sys_row.system_key,
+    )?;
+
+    Ok(sys)
+}
+
+pub(crate) async fn load_random_profiles(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    moderation_options: &ModerationOptions,
+) -> ::anyhow::Result<Vec<polycentric_protocol::model::public_key::PublicKey>> {
+    let query = "
+    SELECT 
+      system_key_type, 
+      system_key 
+    FROM 
+      (
+        SELECT 
+          DISTINCT events.system_key_type, 
+          events.system_key 
+        FROM 
+          events 
+          LEFT JOIN censored_systems
+          ON events.system_key_type = censored_systems.system_key_type 
+          AND events.system_key = censored_systems.system_key 
+        WHERE 
+          censored_systems.system_key IS NULL
+          AND filter_events_by_moderation(events, $1::moderation_filter_type[])
+      ) AS systems 
+    ORDER BY 
+      RANDOM() 
+    LIMIT 
+      10;
+    ";
 
     let sys_rows = ::sqlx::query_as::<_, SystemRow>(query)
         .bind(
@@ -1290,13 +1907,15 @@ pub mod tests {
         transaction.commit().await?;
 
         transaction = pool.begin().await?;
-        assert!(!crate::postgres::claim_handle(
-            &mut transaction,
-            String::from("osotnoc"),
-            &system2
-        )
-        .await
-        .is_ok());
+        assert_eq!(
+            crate::postgres::claim_handle(
+                &mut transaction,
+                String::from("osotnoc"),
+                &system2
+            )
+            .await?,
+            crate::postgres::ClaimHandleResult::Taken
+        );
 
         transaction = pool.begin().await?;
         crate::postgres::claim_handle(
@@ -1335,4 +1954,610 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[::sqlx::test]
+    async fn test_used_challenges(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        assert!(
+            crate::postgres::insert_used_challenge(&mut transaction, &[1], 10)
+                .await?
+        );
+        assert!(
+            !crate::postgres::insert_used_challenge(&mut transaction, &[1], 10)
+                .await?
+        );
+        assert!(
+            crate::postgres::insert_used_challenge(&mut transaction, &[2], 20)
+                .await?
+        );
+
+        assert_eq!(
+            crate::postgres::delete_used_challenges_before(
+                &mut transaction,
+                15
+            )
+            .await?,
+            1
+        );
+
+        assert!(
+            crate::postgres::insert_used_challenge(&mut transaction, &[1], 10)
+                .await?
+        );
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_load_systems_after(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        let mut systems = vec![];
+
+        for _ in 0..3 {
+            let keypair = polycentric_protocol::test_utils::make_test_keypair();
+
+            for logical_clock in 1..3 {
+                crate::ingest::ingest_event_postgres(
+                    &mut transaction,
+                    &polycentric_protocol::test_utils::make_test_event(
+                        &keypair,
+                        &process,
+                        logical_clock,
+                    ),
+                )
+                .await?;
+            }
+
+            systems.push(
+                polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                    keypair.verifying_key(),
+                ),
+            );
+        }
+
+        systems.sort_by_key(|system| {
+            polycentric_protocol::model::public_key::get_key_bytes(system)
+        });
+
+        let first_page =
+            crate::postgres::load_systems_after(&mut transaction, None, 2)
+                .await?;
+
+        assert!(first_page == systems[0..2]);
+
+        let second_page = crate::postgres::load_systems_after(
+            &mut transaction,
+            first_page.last(),
+            2,
+        )
+        .await?;
+
+        assert!(second_page == systems[2..]);
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_remote_server_cursor(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let address = "https://peer.example";
+
+        assert_eq!(
+            crate::postgres::load_remote_server_cursor(
+                &mut transaction,
+                address
+            )
+            .await?,
+            None
+        );
+
+        crate::postgres::upsert_remote_server_cursor(
+            &mut transaction,
+            address,
+            5,
+        )
+        .await?;
+
+        crate::postgres::upsert_remote_server_cursor(
+            &mut transaction,
+            address,
+            9,
+        )
+        .await?;
+
+        assert_eq!(
+            crate::postgres::load_remote_server_cursor(
+                &mut transaction,
+                address
+            )
+            .await?,
+            Some(9)
+        );
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_load_events_for_export(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let s1 = polycentric_protocol::test_utils::make_test_keypair();
+        let s1p1 = polycentric_protocol::test_utils::make_test_process();
+
+        // Inserted later but with an older timestamp than the first event
+        let e1 = polycentric_protocol::test_utils::make_test_event_with_time(
+            &s1, &s1p1, 1, 2000,
+        );
+        let e2 = polycentric_protocol::test_utils::make_test_event_with_time(
+            &s1, &s1p1, 2, 1000,
+        );
+
+        crate::ingest::ingest_event_postgres(&mut transaction, &e1).await?;
+        crate::ingest::ingest_event_postgres(&mut transaction, &e2).await?;
+
+        // Too recent to be exported yet
+        assert!(crate::postgres::load_events_for_export(
+            &mut transaction,
+            0,
+            10,
+            ::std::time::Duration::from_secs(60),
+        )
+        .await?
+        .events
+        .is_empty());
+
+        let first_page = crate::postgres::load_events_for_export(
+            &mut transaction,
+            0,
+            1,
+            ::std::time::Duration::ZERO,
+        )
+        .await?;
+
+        assert!(first_page.events == vec![e1]);
+
+        let cursor = first_page.cursor.expect("expected cursor");
+
+        let second_page = crate::postgres::load_events_for_export(
+            &mut transaction,
+            u64::try_from(cursor.id)?,
+            10,
+            ::std::time::Duration::ZERO,
+        )
+        .await?;
+
+        assert!(second_page.events == vec![e2]);
+
+        let last_page = crate::postgres::load_events_for_export(
+            &mut transaction,
+            u64::try_from(second_page.cursor.expect("expected cursor").id)?,
+            10,
+            ::std::time::Duration::ZERO,
+        )
+        .await?;
+
+        assert!(last_page.events.is_empty());
+        assert!(last_page.cursor.is_none());
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_handles_case_insensitive(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let system1 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        let system2 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        assert_eq!(
+            crate::postgres::claim_handle(
+                &mut transaction,
+                String::from("Osotnoc"),
+                &system1,
+            )
+            .await?,
+            crate::postgres::ClaimHandleResult::Claimed
+        );
+
+        assert_eq!(
+            crate::postgres::claim_handle(
+                &mut transaction,
+                String::from("Osotnoc"),
+                &system1,
+            )
+            .await?,
+            crate::postgres::ClaimHandleResult::AlreadyOwned
+        );
+
+        assert_eq!(
+            crate::postgres::claim_handle(
+                &mut transaction,
+                String::from("OSOTNOC"),
+                &system2,
+            )
+            .await?,
+            crate::postgres::ClaimHandleResult::Taken
+        );
+
+        assert!(
+            crate::postgres::resolve_handle(
+                &mut transaction,
+                String::from("osotnoc")
+            )
+            .await?
+                == system1
+        );
+
+        assert_eq!(
+            crate::postgres::claim_handle(
+                &mut transaction,
+                String::from("other"),
+                &system1,
+            )
+            .await?,
+            crate::postgres::ClaimHandleResult::Claimed
+        );
+
+        assert_eq!(
+            crate::postgres::claim_handle(
+                &mut transaction,
+                String::from("osotnoc"),
+                &system2,
+            )
+            .await?,
+            crate::postgres::ClaimHandleResult::Claimed
+        );
+
+        let history = ::sqlx::query_as::<_, (String, String)>(
+            "
+            SELECT handle, action::text
+            FROM handle_history
+            ORDER BY id ASC;
+            ",
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        assert_eq!(
+            history,
+            vec![
+                ("Osotnoc".to_string(), "claimed".to_string()),
+                ("Osotnoc".to_string(), "released".to_string()),
+                ("other".to_string(), "claimed".to_string()),
+                ("osotnoc".to_string(), "claimed".to_string()),
+            ]
+        );
+
+        // Enforced by the database too, not only by `claim_handle`
+        let system3 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        assert!(::sqlx::query(
+            "
+            INSERT INTO identity_handles (handle, system_key_type, system_key)
+            VALUES ('OSOTNOC', 1, $1);
+            ",
+        )
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            &system3
+        ))
+        .execute(&mut *transaction)
+        .await
+        .is_err());
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_release_handle(pool: ::sqlx::PgPool) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let system1 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        let system2 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            );
+
+        crate::postgres::claim_handle(
+            &mut transaction,
+            String::from("Osotnoc"),
+            &system1,
+        )
+        .await?;
+
+        assert_eq!(
+            crate::postgres::release_handle(&mut transaction, "osotnoc")
+                .await?,
+            Some(system1)
+        );
+        assert_eq!(
+            crate::postgres::release_handle(&mut transaction, "osotnoc")
+                .await?,
+            None
+        );
+
+        assert_eq!(
+            crate::postgres::claim_handle(
+                &mut transaction,
+                String::from("osotnoc"),
+                &system2,
+            )
+            .await?,
+            crate::postgres::ClaimHandleResult::Claimed
+        );
+
+        let actions = ::sqlx::query_scalar::<_, String>(
+            "SELECT action::text FROM handle_history ORDER BY id ASC;",
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        assert_eq!(actions, vec!["claimed", "released", "claimed"]);
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_explore_excludes_do_not_recommend(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let s1 = polycentric_protocol::test_utils::make_test_keypair();
+        let s2 = polycentric_protocol::test_utils::make_test_keypair();
+        let s1p1 = polycentric_protocol::test_utils::make_test_process();
+        let s2p1 = polycentric_protocol::test_utils::make_test_process();
+
+        let s1p1e1 =
+            polycentric_protocol::test_utils::make_test_event(&s1, &s1p1, 1);
+        let s1p1e2 =
+            polycentric_protocol::test_utils::make_test_event(&s1, &s1p1, 2);
+        let s2p1e1 =
+            polycentric_protocol::test_utils::make_test_event(&s2, &s2p1, 1);
+
+        crate::ingest::ingest_event_postgres(&mut transaction, &s1p1e1).await?;
+        crate::ingest::ingest_event_postgres(&mut transaction, &s1p1e2).await?;
+        crate::ingest::ingest_event_postgres(&mut transaction, &s2p1e1).await?;
+
+        let system1 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                s1.verifying_key(),
+            );
+        let system2 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                s2.verifying_key(),
+            );
+
+        crate::postgres::censor_event(
+            &mut transaction,
+            crate::postgres::CensorshipType::DoNotRecommend,
+            &system1,
+            &s1p1,
+            1,
+            &crate::postgres::CensorshipDetails::default(),
+        )
+        .await?;
+
+        crate::postgres::censor_system(
+            &mut transaction,
+            crate::postgres::CensorshipType::DoNotRecommend,
+            system2.clone(),
+            &crate::postgres::CensorshipDetails::default(),
+        )
+        .await?;
+
+        let moderation_options = crate::postgres::ModerationOptions {
+            filters: None,
+            mode: ModerationMode::Off,
+        };
+
+        let explore = crate::postgres::load_posts_before_id(
+            &mut transaction,
+            None,
+            10,
+            &moderation_options,
+        )
+        .await?;
+
+        assert!(explore.events == vec![s1p1e2]);
+
+        // Still available when fetched directly
+        assert!(
+            crate::postgres::load_event(
+                &mut transaction,
+                &system1,
+                &s1p1,
+                1,
+                &moderation_options,
+            )
+            .await?
+                == Some(s1p1e1.clone())
+        );
+
+        assert!(
+            crate::postgres::load_event(
+                &mut transaction,
+                &system2,
+                &s2p1,
+                1,
+                &moderation_options,
+            )
+            .await?
+                == Some(s2p1e1.clone())
+        );
+
+        for (signed_event, censored) in [
+            (&s1p1e1, true),
+            (&s2p1e1, true),
+            (&explore.events[0], false),
+        ] {
+            assert_eq!(
+                crate::postgres::is_event_censored(
+                    &mut transaction,
+                    &polycentric_protocol::model::event::from_vec(
+                        signed_event.event()
+                    )?,
+                    None,
+                )
+                .await?,
+                censored
+            );
+        }
+
+        assert!(
+            !crate::postgres::is_event_censored(
+                &mut transaction,
+                &polycentric_protocol::model::event::from_vec(s1p1e1.event())?,
+                Some(crate::postgres::CensorshipType::RefuseStorage),
+            )
+            .await?
+        );
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    #[::sqlx::test]
+    async fn test_refuse_storage(pool: ::sqlx::PgPool) -> ::anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+
+        let s1 = polycentric_protocol::test_utils::make_test_keypair();
+        let s2 = polycentric_protocol::test_utils::make_test_keypair();
+        let s1p1 = polycentric_protocol::test_utils::make_test_process();
+        let s2p1 = polycentric_protocol::test_utils::make_test_process();
+
+        let system1 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                s1.verifying_key(),
+            );
+        let system2 =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                s2.verifying_key(),
+            );
+
+        let moderation_options = crate::postgres::ModerationOptions {
+            filters: None,
+            mode: ModerationMode::Off,
+        };
+
+        crate::postgres::censor_system(
+            &mut transaction,
+            crate::postgres::CensorshipType::RefuseStorage,
+            system1.clone(),
+            &crate::postgres::CensorshipDetails::default(),
+        )
+        .await?;
+
+        crate::ingest::ingest_event_postgres(
+            &mut transaction,
+            &polycentric_protocol::test_utils::make_test_event(&s1, &s1p1, 1),
+        )
+        .await?;
+
+        assert!(crate::postgres::load_event(
+            &mut transaction,
+            &system1,
+            &s1p1,
+            1,
+            &moderation_options,
+        )
+        .await?
+        .is_none());
+
+        let s2p1e1 =
+            polycentric_protocol::test_utils::make_test_event(&s2, &s2p1, 1);
+        let s2p1e2 =
+            polycentric_protocol::test_utils::make_test_event(&s2, &s2p1, 2);
+
+        crate::ingest::ingest_event_postgres(&mut transaction, &s2p1e1).await?;
+
+        crate::postgres::censor_event(
+            &mut transaction,
+            crate::postgres::CensorshipType::RefuseStorage,
+            &system2,
+            &s2p1,
+            1,
+            &crate::postgres::CensorshipDetails::default(),
+        )
+        .await?;
+
+        crate::postgres::purge::purge_event(
+            &mut transaction,
+            &system2,
+            &s2p1,
+            1,
+        )
+        .await?;
+
+        crate::ingest::ingest_event_postgres(&mut transaction, &s2p1e1).await?;
+        crate::ingest::ingest_event_postgres(&mut transaction, &s2p1e2).await?;
+
+        assert!(crate::postgres::load_event(
+            &mut transaction,
+            &system2,
+            &s2p1,
+            1,
+            &moderation_options,
+        )
+        .await?
+        .is_none());
+
+        assert!(
+            crate::postgres::load_event(
+                &mut transaction,
+                &system2,
+                &s2p1,
+                2,
+                &moderation_options,
+            )
+            .await?
+                == Some(s2p1e2)
+        );
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
 }
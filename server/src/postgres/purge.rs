@@ -6,17 +6,58 @@ pub(crate) async fn purge(
         DELETE FROM events
         WHERE system_key_type = $1
         AND system_key = $2
+        RETURNING raw_event
     ";
 
-    ::sqlx::query(query)
-        .bind(i64::try_from(
-            polycentric_protocol::model::public_key::get_key_type(system),
-        )?)
-        .bind(polycentric_protocol::model::public_key::get_key_bytes(
-            system,
-        ))
-        .execute(&mut **transaction)
-        .await?;
+    let purged: ::std::vec::Vec<::std::vec::Vec<u8>> =
+        ::sqlx::query_scalar(query)
+            .bind(i64::try_from(
+                polycentric_protocol::model::public_key::get_key_type(system),
+            )?)
+            .bind(polycentric_protocol::model::public_key::get_key_bytes(
+                system,
+            ))
+            .fetch_all(&mut **transaction)
+            .await?;
+
+    crate::search_indexer::remove_events(transaction, &purged).await?;
+    crate::cache::purge_queue::enqueue_raw_events(transaction, &purged).await?;
+    crate::search_indexer::remove_system(transaction, system).await?;
+    crate::cache::purge_queue::enqueue_system(transaction, system).await?;
+
+    Ok(())
+}
+
+pub(crate) async fn purge_event(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+    process: &polycentric_protocol::model::process::Process,
+    logical_clock: u64,
+) -> ::anyhow::Result<()> {
+    let query = "
+        DELETE FROM events
+        WHERE system_key_type = $1
+        AND system_key = $2
+        AND process = $3
+        AND logical_clock = $4
+        RETURNING raw_event
+    ";
+
+    let purged: ::std::vec::Vec<::std::vec::Vec<u8>> =
+        ::sqlx::query_scalar(query)
+            .bind(i64::try_from(
+                polycentric_protocol::model::public_key::get_key_type(system),
+            )?)
+            .bind(polycentric_protocol::model::public_key::get_key_bytes(
+                system,
+            ))
+            .bind(process.bytes())
+            .bind(i64::try_from(logical_clock)?)
+            .fetch_all(&mut **transaction)
+            .await?;
+
+    crate::search_indexer::remove_events(transaction, &purged).await?;
+    crate::cache::purge_queue::enqueue_raw_events(transaction, &purged).await?;
 
     Ok(())
 }
@@ -120,7 +120,7 @@ pub(crate) async fn load_event_later_than(
             FROM
                 events
             WHERE
-                filter_events_by_moderation(events, $6::moderation_filter_type[], $7::moderation_mode)
+                filter_events_by_moderation(events, $6::moderation_filter_type[], $7::moderation_mode, (SELECT moderation_policies()))
             UNION
             SELECT
                 events.raw_event as raw_event,
@@ -167,7 +167,7 @@ pub(crate) async fn load_event_later_than(
             .bind(process.bytes())
             .bind(i64::try_from(content_type)?)
             .bind(i64::try_from(later_than_unix_milliseconds)?)
-            .bind(moderation_options.get_filters_with_defaults())
+            .bind(moderation_options.get_filters())
             .bind(moderation_options.mode)
             .fetch_optional(&mut **transaction)
             .await?;
@@ -211,7 +211,7 @@ pub(crate) async fn load_event_earlier_than(
             FROM
                 events
             WHERE
-                filter_events_by_moderation(events, $6::moderation_filter_type[], $7::moderation_mode)
+                filter_events_by_moderation(events, $6::moderation_filter_type[], $7::moderation_mode, (SELECT moderation_policies()))
             UNION
             SELECT
                 events.raw_event as raw_event,
@@ -258,7 +258,7 @@ pub(crate) async fn load_event_earlier_than(
             .bind(process.bytes())
             .bind(i64::try_from(content_type)?)
             .bind(i64::try_from(earlier_than_unix_milliseconds)?)
-            .bind(moderation_options.get_filters_with_defaults())
+            .bind(moderation_options.get_filters())
             .bind(moderation_options.mode)
             .fetch_optional(&mut **transaction)
             .await?;
@@ -301,7 +301,7 @@ pub(crate) async fn load_events_by_time(
             FROM
                 events
             WHERE
-                filter_events_by_moderation(events, $6::moderation_filter_type[], $7::moderation_mode)
+                filter_events_by_moderation(events, $6::moderation_filter_type[], $7::moderation_mode, (SELECT moderation_policies()))
             UNION
             SELECT
                 events.raw_event as raw_event,
@@ -349,7 +349,7 @@ pub(crate) async fn load_events_by_time(
         .bind(i64::try_from(limit)?)
         .bind(
             moderation_options
-                .get_filters_with_defaults()
+                .get_filters()
         )
         .bind(
             moderation_options
@@ -375,6 +375,42 @@ pub(crate) async fn load_events_by_time(
 pub mod tests {
     use crate::moderation::ModerationFilters;
 
+    async fn approve(
+        transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+        system: &polycentric_protocol::model::public_key::PublicKey,
+        process: &polycentric_protocol::model::process::Process,
+        logical_clock: u64,
+    ) -> ::anyhow::Result<()> {
+        let query = "
+            SELECT id FROM events
+            WHERE system_key_type = $1
+            AND system_key = $2
+            AND process = $3
+            AND logical_clock = $4;
+        ";
+
+        let id: i64 = ::sqlx::query_scalar(query)
+            .bind(i64::try_from(
+                polycentric_protocol::model::public_key::get_key_type(system),
+            )?)
+            .bind(polycentric_protocol::model::public_key::get_key_bytes(
+                system,
+            ))
+            .bind(process.bytes())
+            .bind(i64::try_from(logical_clock)?)
+            .fetch_one(&mut **transaction)
+            .await?;
+
+        crate::moderation::moderation_queue::approve_event(
+            transaction,
+            id,
+            None,
+            "test",
+            None,
+        )
+        .await
+    }
+
     #[::sqlx::test]
     async fn test_no_events(pool: ::sqlx::PgPool) -> ::anyhow::Result<()> {
         let mut transaction = pool.begin().await?;
@@ -453,41 +489,11 @@ pub mod tests {
                 s1.verifying_key(),
             );
 
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            1,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            2,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            3,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            4,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            5,
-        )
-        .await?;
+        approve(&mut transaction, &s1_key, &s1p1, 1).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 2).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 3).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 4).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 5).await?;
 
         let system =
             polycentric_protocol::model::public_key::PublicKey::Ed25519(
@@ -600,85 +606,19 @@ pub mod tests {
                 s1.verifying_key(),
             );
 
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            1,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            2,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            3,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            4,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            5,
-        )
-        .await?;
+        approve(&mut transaction, &s1_key, &s1p1, 1).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 2).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 3).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 4).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 5).await?;
 
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p2,
-            1,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p2,
-            2,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p2,
-            3,
-        )
-        .await?;
+        approve(&mut transaction, &s1_key, &s1p2, 1).await?;
+        approve(&mut transaction, &s1_key, &s1p2, 2).await?;
+        approve(&mut transaction, &s1_key, &s1p2, 3).await?;
 
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p3,
-            1,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p3,
-            2,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p3,
-            3,
-        )
-        .await?;
+        approve(&mut transaction, &s1_key, &s1p3, 1).await?;
+        approve(&mut transaction, &s1_key, &s1p3, 2).await?;
+        approve(&mut transaction, &s1_key, &s1p3, 3).await?;
 
         let system =
             polycentric_protocol::model::public_key::PublicKey::Ed25519(
@@ -764,49 +704,13 @@ pub mod tests {
             polycentric_protocol::model::public_key::PublicKey::Ed25519(
                 s1.verifying_key(),
             );
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            1,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            2,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            3,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            4,
-        )
-        .await?;
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            5,
-        )
-        .await?;
+        approve(&mut transaction, &s1_key, &s1p1, 1).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 2).await?;
+        // 3 is deleted
+        approve(&mut transaction, &s1_key, &s1p1, 4).await?;
+        approve(&mut transaction, &s1_key, &s1p1, 5).await?;
 
-        crate::moderation::moderation_queue::approve_event(
-            &mut transaction,
-            &s1_key,
-            &s1p1,
-            6,
-        )
-        .await?;
+        approve(&mut transaction, &s1_key, &s1p1, 6).await?;
 
         let system =
             polycentric_protocol::model::public_key::PublicKey::Ed25519(
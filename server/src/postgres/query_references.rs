@@ -161,7 +161,7 @@ pub(crate) async fn query_bytes(
         AND
             ($2 IS NULL OR events.content_type = $2)
         AND
-            filter_events_by_moderation(events, $5::moderation_filter_type[], $6::moderation_mode)
+            filter_events_by_moderation(events, $5::moderation_filter_type[], $6::moderation_mode, (SELECT moderation_policies()))
         GROUP BY
             events.id
         ORDER BY
@@ -189,7 +189,7 @@ pub(crate) async fn query_bytes(
         .bind(from_type_query)
         .bind(cursor_query)
         .bind(i64::try_from(limit)?)
-        .bind(moderation_options.get_filters_with_defaults())
+        .bind(moderation_options.get_filters())
         .bind(moderation_options.mode)
         .fetch_all(&mut **transaction)
         .await?;
@@ -49,6 +49,21 @@ EXCEPTION
     WHEN duplicate_object THEN null;
 END $$;
 
+DO $$ BEGIN
+    CREATE TYPE report_reason_enum AS ENUM (
+        'spam',
+        'harassment',
+        'hate',
+        'violence',
+        'sexual',
+        'csam',
+        'impersonation',
+        'other'
+    );
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
 DO $$ BEGIN
     CREATE TYPE moderation_mode AS ENUM (
         'off',
@@ -108,22 +123,143 @@ EXCEPTION
     WHEN duplicate_object THEN null;
 END $$;
 
+-- The moderation policy of each moderated content type, written by the
+-- STORE_MODERATION_POLICY mode from the policy file, see
+-- src/moderation/policy.rs. The rows inserted here are the built-in policy
+-- used by a database that has never been given one.
+CREATE TABLE IF NOT EXISTS moderation_policy (
+    content_type INT8 PRIMARY KEY,
+    -- Overrides the moderation mode of the server when set
+    moderation_mode moderation_mode,
+    -- Applied for tags the client did not send a filter for
+    filters moderation_filter_type[] NOT NULL DEFAULT '{}',
+    -- Tags hidden at or above the level regardless of client filters
+    hide moderation_tag_type[] NOT NULL DEFAULT '{}',
+    -- Tags rejecting the event at or above the level
+    reject moderation_tag_type[] NOT NULL DEFAULT '{}'
+);
+
+INSERT INTO moderation_policy (content_type, filters)
+SELECT
+    content_type,
+    ARRAY[
+        ('violence', 1, TRUE),
+        ('hate', 2, TRUE),
+        ('sexual', 1, TRUE)
+    ]::moderation_filter_type[]
+FROM unnest(ARRAY[{moderated_content_types}]::INT8[]) AS content_type
+WHERE NOT EXISTS (SELECT 1 FROM moderation_policy);
+
+-- Every policy file stored, the one with the highest version is in effect
+CREATE TABLE IF NOT EXISTS moderation_policy_versions (
+    version INT8 PRIMARY KEY,
+    document JSONB NOT NULL,
+    stored_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Read with `(SELECT moderation_policies())` so that the policy is loaded
+-- once per query rather than once per row passed to
+-- filter_events_by_moderation
+CREATE OR REPLACE FUNCTION moderation_policies()
+RETURNS moderation_policy[] AS $$
+    SELECT COALESCE(array_agg(moderation_policy), '{}')
+    FROM moderation_policy;
+$$ LANGUAGE sql STABLE;
+
+-- Whether any of the tags reaches a level listed in `rules`
+CREATE OR REPLACE FUNCTION moderation_tags_match(
+    tags moderation_tag_type[],
+    rules moderation_tag_type[]
+)
+RETURNS BOOLEAN AS $$
+    SELECT EXISTS (
+        SELECT 1
+        FROM unnest(COALESCE(tags, '{}')) AS tag,
+             unnest(COALESCE(rules, '{}')) AS rule
+        WHERE tag.name = rule.name
+        AND tag.level >= rule.level
+    );
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION moderation_policy_rejects(
+    event_content_type INT8,
+    tags moderation_tag_type[]
+)
+RETURNS BOOLEAN AS $$
+    SELECT COALESCE((
+        SELECT moderation_tags_match(tags, reject)
+        FROM moderation_policy
+        WHERE content_type = event_content_type
+    ), FALSE);
+$$ LANGUAGE sql STABLE;
+
+-- Tags given different levels in `a` and `b`, a missing tag being level 0
+CREATE OR REPLACE FUNCTION moderation_tag_disagreements(
+    a moderation_tag_type[],
+    b moderation_tag_type[]
+)
+RETURNS TABLE (name VARCHAR(20), a_level SMALLINT, b_level SMALLINT) AS $$
+    SELECT
+        COALESCE(x.name, y.name),
+        COALESCE(x.level, 0)::SMALLINT,
+        COALESCE(y.level, 0)::SMALLINT
+    FROM (
+        SELECT tag.name, MAX(tag.level) AS level
+        FROM unnest(COALESCE(a, '{}')) AS tag
+        GROUP BY tag.name
+    ) AS x
+    FULL OUTER JOIN (
+        SELECT tag.name, MAX(tag.level) AS level
+        FROM unnest(COALESCE(b, '{}')) AS tag
+        GROUP BY tag.name
+    ) AS y ON x.name = y.name
+    WHERE COALESCE(x.level, 0) <> COALESCE(y.level, 0);
+$$ LANGUAGE sql IMMUTABLE;
+
+DROP FUNCTION IF EXISTS filter_events_by_moderation(
+    events,
+    moderation_filter_type[],
+    moderation_mode
+);
+
 CREATE OR REPLACE FUNCTION filter_events_by_moderation(
     event_row events,
     filter_array moderation_filter_type[],
-    moderation_mode moderation_mode
+    moderation_mode moderation_mode,
+    policies moderation_policy[]
 )
 RETURNS BOOLEAN AS $$
 DECLARE
     filter_item moderation_filter_type;
     moderation_tag moderation_tag_type;
     tag_found BOOLEAN;
+    candidate moderation_policy;
+    policy moderation_policy;
+    filters moderation_filter_type[];
 BEGIN
-    -- We only care about posts, descriptions and avatars
-    IF event_row.content_type != 3 AND event_row.content_type != 6 AND event_row.content_type != 9 THEN
+    -- Only posts, usernames, descriptions, avatars, claims and banners are
+    -- moderated
+    IF event_row.content_type NOT IN ({moderated_content_types}) THEN
         RETURN TRUE;
     END IF;
 
+    -- Rejected events are kept for review but never served
+    IF event_row.moderation_status = 'flagged_and_rejected' THEN
+        RETURN FALSE;
+    END IF;
+
+    FOREACH candidate IN ARRAY COALESCE(policies, '{}')
+    LOOP
+        IF candidate.content_type = event_row.content_type THEN
+            policy := candidate;
+            EXIT;
+        END IF;
+    END LOOP;
+
+    IF policy.moderation_mode IS NOT NULL THEN
+        moderation_mode := policy.moderation_mode;
+    END IF;
+
     IF moderation_mode = 'off' THEN
         RETURN TRUE;
     END IF;
@@ -134,12 +270,28 @@ BEGIN
         END IF;
         RETURN TRUE;
     END IF;
-    
-    IF filter_array IS NULL OR array_length(filter_array, 1) = 0 THEN
+
+    IF moderation_tags_match(event_row.moderation_tags, policy.hide) THEN
+        RETURN FALSE;
+    END IF;
+
+    -- Filters sent by the client take precedence over the policy defaults
+    filters := COALESCE(filter_array, '{}');
+
+    FOREACH filter_item IN ARRAY COALESCE(policy.filters, '{}')
+    LOOP
+        IF NOT EXISTS (
+            SELECT 1 FROM unnest(filters) AS f WHERE f.name = filter_item.name
+        ) THEN
+            filters := array_append(filters, filter_item);
+        END IF;
+    END LOOP;
+
+    IF array_length(filters, 1) IS NULL THEN
         RETURN TRUE;
     END IF;
 
-    FOREACH filter_item IN ARRAY filter_array
+    FOREACH filter_item IN ARRAY filters
     LOOP
         tag_found := FALSE;
 
@@ -147,7 +299,7 @@ BEGIN
             CONTINUE;
         END IF;
         
-        FOREACH moderation_tag IN ARRAY event_row.moderation_tags
+        FOREACH moderation_tag IN ARRAY COALESCE(event_row.moderation_tags, '{}')
         LOOP
             IF moderation_tag.name = filter_item.name THEN
                 tag_found := TRUE;
@@ -167,10 +319,10 @@ BEGIN
 END;
 $$ LANGUAGE plpgsql;
 
-CREATE INDEX IF NOT EXISTS idx_moderation_queue_filter
+CREATE INDEX IF NOT EXISTS idx_moderation_queue_pending
 ON events (moderation_status, content_type, id)
 WHERE moderation_status IN ('unprocessed', 'error')
-  AND content_type IN (3, 6, 9);
+  AND content_type IN ({moderated_content_types});
 
 CREATE INDEX IF NOT EXISTS
 events_content_type_idx
@@ -182,7 +334,126 @@ CREATE TABLE IF NOT EXISTS event_processing_status (
     processing_started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
     failure_count INT NOT NULL DEFAULT 0,
     last_failure_at TIMESTAMP WITH TIME ZONE,
-    last_error_message TEXT
+    last_error_message TEXT,
+    lease_owner TEXT,
+    lease_expires_at TIMESTAMP WITH TIME ZONE,
+    dead_lettered_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Position of each MODE=REMODERATE job, the id of the last event handled
+CREATE TABLE IF NOT EXISTS remoderation_cursors (
+    job TEXT PRIMARY KEY,
+    position BIGINT NOT NULL,
+    updated_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Events a MODE=REMODERATE job failed to tag, retried by its next run
+CREATE TABLE IF NOT EXISTS remoderation_failures (
+    job TEXT NOT NULL,
+    event_id BIGINT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    failed_on TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (job, event_id)
+);
+
+-- Decisions made by hand in the review API. Automated moderation skips
+-- events that have one until they are requeued.
+CREATE TABLE IF NOT EXISTS moderation_reviews (
+    event_id BIGINT PRIMARY KEY REFERENCES events(id) ON DELETE CASCADE,
+    moderation_status moderation_status_enum NOT NULL,
+    moderation_tags moderation_tag_type[],
+    reviewer TEXT NOT NULL,
+    note TEXT,
+    reviewed_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Tags from SHADOW_TAG_INTERFACE next to those of the primary provider for
+-- the same event, for comparison only
+CREATE TABLE IF NOT EXISTS moderation_shadow_results (
+    event_id BIGINT PRIMARY KEY REFERENCES events(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    primary_error BOOLEAN NOT NULL,
+    primary_tags moderation_tag_type[] NOT NULL,
+    shadow_error BOOLEAN NOT NULL,
+    shadow_tags moderation_tag_type[] NOT NULL,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_moderation_shadow_results_created_on
+ON moderation_shadow_results (created_on);
+
+-- Reports sent by users, one per reporter and target. event_id is NULL
+-- when a whole system is reported.
+CREATE TABLE IF NOT EXISTS moderation_reports (
+    id BIGSERIAL PRIMARY KEY,
+    reporter_key_type INT8 NOT NULL,
+    reporter_key BYTEA NOT NULL,
+    target_key_type INT8 NOT NULL,
+    target_key BYTEA NOT NULL,
+    event_id BIGINT REFERENCES events(id) ON DELETE CASCADE,
+    reason report_reason_enum NOT NULL,
+    note TEXT,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS moderation_reports_reporter_idx
+ON moderation_reports (
+    reporter_key_type,
+    reporter_key,
+    target_key_type,
+    target_key,
+    COALESCE(event_id, 0)
+);
+
+CREATE INDEX IF NOT EXISTS moderation_reports_event_id_idx
+ON moderation_reports (event_id);
+
+CREATE INDEX IF NOT EXISTS moderation_reports_target_idx
+ON moderation_reports (target_key_type, target_key)
+WHERE event_id IS NULL;
+
+-- Number of reports of each event and of each system, kept by
+-- `reports::submit` so that the moderation queue can order by them without
+-- counting reports
+CREATE TABLE IF NOT EXISTS moderation_event_report_counts (
+    event_id BIGINT PRIMARY KEY REFERENCES events(id) ON DELETE CASCADE,
+    reports INT8 NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS moderation_system_report_counts (
+    system_key_type INT8 NOT NULL,
+    system_key BYTEA NOT NULL,
+    reports INT8 NOT NULL,
+
+    PRIMARY KEY (system_key_type, system_key)
+);
+
+-- Events reported often enough to need a reviewer, cleared by a review.
+-- recheck marks approved events the moderation queue tags again while they
+-- stay visible.
+CREATE TABLE IF NOT EXISTS moderation_escalations (
+    event_id BIGINT PRIMARY KEY REFERENCES events(id) ON DELETE CASCADE,
+    reports INT8 NOT NULL,
+    recheck BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Provider results by the SHA-256 of the content sent, see `result_cache`
+CREATE TABLE IF NOT EXISTS moderation_result_cache (
+    digest BYTEA NOT NULL,
+    kind TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    tags moderation_tag_type[] NOT NULL DEFAULT '{}',
+    is_csam BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (digest, kind)
+);
+
+-- SHA-256 of blobs and blob sections that are refused for good
+CREATE TABLE IF NOT EXISTS blocked_content_hashes (
+    digest BYTEA PRIMARY KEY,
+    reason TEXT NOT NULL,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
 CREATE INDEX IF NOT EXISTS idx_eps_failure_metrics
@@ -420,6 +691,11 @@ CREATE TABLE IF NOT EXISTS censored_events (
     process BYTEA NOT NULL,
     logical_clock INT8 NOT NULL,
     censorship_type CENSORSHIP_TYPE NOT NULL,
+    reason TEXT,
+    censored_by TEXT,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
 
     CHECK (system_key_type >= 0),
     CHECK (LENGTH(process) = 16),
@@ -433,6 +709,11 @@ CREATE TABLE IF NOT EXISTS censored_systems (
     system_key_type INT8 NOT NULL,
     system_key BYTEA NOT NULL,
     censorship_type CENSORSHIP_TYPE NOT NULL,
+    reason TEXT,
+    censored_by TEXT,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
 
     CHECK (system_key_type >= 0),
 
@@ -532,3 +813,144 @@ CREATE TABLE IF NOT EXISTS identity_handles (
         system_key
     )
 );
+
+-- Handles are unique regardless of case. Older databases can hold handles
+-- that only differ by case, `migrate` refuses to upgrade those until an
+-- operator resolved them.
+DO $$ BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM identity_handles
+        GROUP BY LOWER(handle)
+        HAVING COUNT(*) > 1
+    ) THEN
+        CREATE UNIQUE INDEX IF NOT EXISTS identity_handles_lower_handle_idx
+        ON identity_handles (LOWER(handle));
+    END IF;
+END $$;
+
+DO $$ BEGIN
+            CREATE TYPE handle_action AS ENUM (
+                'claimed',
+                'released'
+            );
+        EXCEPTION
+            WHEN duplicate_object THEN null;
+        END $$;
+
+CREATE TABLE IF NOT EXISTS handle_history (
+    id BIGSERIAL PRIMARY KEY,
+    handle VARCHAR(64) NOT NULL,
+    system_key_type INT8 NOT NULL,
+    system_key BYTEA NOT NULL,
+    action handle_action NOT NULL,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS handle_history_handle_idx
+ON handle_history (LOWER(handle));
+
+CREATE INDEX IF NOT EXISTS handle_history_system_idx
+ON handle_history (system_key_type, system_key);
+
+CREATE TABLE IF NOT EXISTS used_challenges (
+    challenge BYTEA PRIMARY KEY,
+    created_on INT8 NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS used_challenges_created_on_idx
+ON used_challenges (created_on);
+
+CREATE TABLE IF NOT EXISTS remote_server_cursors (
+    address TEXT PRIMARY KEY,
+    position INT8 NOT NULL,
+    updated_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Tokens are "<prefix>.<secret>", the prefix is not secret and is how a
+-- token is found. A name can be reused once its token is revoked.
+CREATE TABLE IF NOT EXISTS admin_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    token_prefix TEXT NOT NULL UNIQUE,
+    token_hash BYTEA NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_on TIMESTAMPTZ,
+
+    CHECK (LENGTH(token_hash) = 32)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_admin_tokens_active_name
+ON admin_tokens (name) WHERE revoked_on IS NULL;
+
+CREATE TABLE IF NOT EXISTS admin_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    admin_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS admin_audit_log_admin_name_idx
+ON admin_audit_log (admin_name, id);
+
+CREATE INDEX IF NOT EXISTS admin_audit_log_action_idx
+ON admin_audit_log (action, id);
+
+CREATE OR REPLACE FUNCTION admin_audit_log_append_only()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'admin_audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER admin_audit_log_append_only
+BEFORE UPDATE OR DELETE ON admin_audit_log
+FOR EACH ROW EXECUTE FUNCTION admin_audit_log_append_only();
+
+CREATE OR REPLACE TRIGGER admin_audit_log_no_truncate
+BEFORE TRUNCATE ON admin_audit_log
+FOR EACH STATEMENT EXECUTE FUNCTION admin_audit_log_append_only();
+
+CREATE TABLE IF NOT EXISTS search_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    -- Indexes the event
+    event_id BIGINT REFERENCES events(id) ON DELETE CASCADE,
+    -- Writes the document even if a newer version is indexed
+    overwrite BOOLEAN NOT NULL DEFAULT false,
+    -- Removes the document
+    search_index TEXT,
+    document_id TEXT,
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CHECK (
+        event_id IS NOT NULL
+        OR (search_index IS NOT NULL AND document_id IS NOT NULL)
+    )
+);
+
+CREATE INDEX IF NOT EXISTS search_outbox_next_attempt_at_idx
+ON search_outbox (next_attempt_at, id);
+
+CREATE INDEX IF NOT EXISTS search_outbox_event_id_idx
+ON search_outbox (event_id);
+
+-- Not unique on tag so that transactions queueing the same tag never wait on
+-- each other, `purge_queue::take` coalesces them
+CREATE TABLE IF NOT EXISTS cache_purges (
+    id BIGSERIAL PRIMARY KEY,
+    tag TEXT NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS cache_purges_next_attempt_at_idx
+ON cache_purges (next_attempt_at, id);
+
+CREATE INDEX IF NOT EXISTS cache_purges_tag_idx
+ON cache_purges (tag);
@@ -53,7 +53,7 @@ pub(crate) async fn select(
         AND
             events.logical_clock <= input_rows.high
         AND
-            filter_events_by_moderation(events, $6::moderation_filter_type[], $7::moderation_mode)
+            filter_events_by_moderation(events, $6::moderation_filter_type[], $7::moderation_mode, (SELECT moderation_policies()))
     ";
 
     let mut p_system_key_type = vec![];
@@ -46,7 +46,7 @@ pub(crate) async fn select(
         AND
             events.content_type = input_rows.content_type
         AND
-            filter_events_by_moderation(events, $4::moderation_filter_type[], $5::moderation_mode)
+            filter_events_by_moderation(events, $4::moderation_filter_type[], $5::moderation_mode, (SELECT moderation_policies()))
         ORDER BY
             events.system_key_type,
             events.system_key,
@@ -0,0 +1,736 @@
+use ::protobuf::Message;
+
+// Anti-entropy between servers. For each system the ranges of logical clocks
+// known here are compared with those of every peer through `/ranges`, events
+// only the peer has are fetched with `/events`, and events only we have are
+// pushed back to the peer's `/events`. Peers are the configured servers and,
+// as anyone can sign a SERVER event, only those servers listed by systems
+// that are on an allowed host with public addresses.
+
+const SYSTEMS_PAGE_SIZE: u64 = 100;
+const EVENTS_PER_REQUEST: u64 = 100;
+const PASS_INTERVAL: ::std::time::Duration =
+    ::std::time::Duration::from_secs(60);
+
+// A server listed by a system is only reached at the addresses checked when
+// it was allowed, the host is not resolved again for the requests themselves.
+#[derive(Debug)]
+struct Peer {
+    url: String,
+    // The host and its checked addresses, configured servers are not pinned
+    pinned: Option<(String, ::std::vec::Vec<::std::net::SocketAddr>)>,
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub(crate) struct ReconcileResult {
+    pub(crate) pulled: usize,
+    pub(crate) pushed: usize,
+}
+
+fn encode_url_proto(message: &impl Message) -> ::anyhow::Result<String> {
+    Ok(::base64::encode_config(
+        message.write_to_bytes()?,
+        ::base64::URL_SAFE,
+    ))
+}
+
+// Resolves a system's SERVER LWW element set to the servers currently in it.
+pub(crate) fn server_list(
+    events: &[polycentric_protocol::model::signed_event::SignedEvent],
+) -> ::anyhow::Result<::std::vec::Vec<String>> {
+    let mut latest = ::std::collections::HashMap::<String, (u64, bool)>::new();
+
+    for signed_event in events {
+        let layers =
+            crate::postgres::stored_event_layers(signed_event.clone())?;
+
+        let (server, lww_element_set) =
+            match (layers.content(), layers.event().lww_element_set()) {
+                (
+                    polycentric_protocol::model::content::Content::Server(
+                        server,
+                    ),
+                    Some(lww_element_set),
+                ) => (server, lww_element_set),
+                _ => continue,
+            };
+
+        let added = lww_element_set.operation.enum_value_or_default()
+            == polycentric_protocol::protocol::lwwelement_set::Operation::ADD;
+
+        let replaces = match latest.get(server) {
+            Some((unix_milliseconds, _)) => {
+                lww_element_set.unix_milliseconds > *unix_milliseconds
+            }
+            None => true,
+        };
+
+        if replaces {
+            latest.insert(
+                server.clone(),
+                (lww_element_set.unix_milliseconds, added),
+            );
+        }
+    }
+
+    let mut result: ::std::vec::Vec<String> = latest
+        .into_iter()
+        .filter(|(_, (_, added))| *added)
+        .map(|(server, _)| server)
+        .collect();
+
+    result.sort();
+
+    Ok(result)
+}
+
+async fn fetch_remote_ranges(
+    client: &::reqwest::Client,
+    peer: &str,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+) -> ::anyhow::Result<polycentric_protocol::protocol::RangesForSystem> {
+    let response = client
+        .get(format!("{}/ranges", peer))
+        .query(&[(
+            "system",
+            encode_url_proto(
+                &polycentric_protocol::model::public_key::to_proto(system),
+            )?,
+        )])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(
+        polycentric_protocol::protocol::RangesForSystem::parse_from_tokio_bytes(
+            &response.bytes().await?,
+        )?,
+    )
+}
+
+async fn fetch_remote_events(
+    client: &::reqwest::Client,
+    peer: &str,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+    ranges: &polycentric_protocol::protocol::RangesForSystem,
+) -> ::anyhow::Result<polycentric_protocol::protocol::Events> {
+    let response = client
+        .get(format!("{}/events", peer))
+        .query(&[
+            (
+                "system",
+                encode_url_proto(
+                    &polycentric_protocol::model::public_key::to_proto(system),
+                )?,
+            ),
+            ("ranges", encode_url_proto(ranges)?),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(
+        polycentric_protocol::protocol::Events::parse_from_tokio_bytes(
+            &response.bytes().await?,
+        )?,
+    )
+}
+
+async fn push_events(
+    client: &::reqwest::Client,
+    peer: &str,
+    events: &[polycentric_protocol::model::signed_event::SignedEvent],
+) -> ::anyhow::Result<()> {
+    let mut batch = polycentric_protocol::protocol::Events::new();
+
+    batch.events = events
+        .iter()
+        .map(polycentric_protocol::model::signed_event::to_proto)
+        .collect();
+
+    client
+        .post(format!("{}/events", peer))
+        .header("x-polycentric-user-agent", "reconcile")
+        .body(batch.write_to_bytes()?)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+pub(crate) async fn reconcile_system(
+    state: &::std::sync::Arc<crate::State>,
+    client: &::reqwest::Client,
+    peer: &str,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+) -> ::anyhow::Result<ReconcileResult> {
+    let local_ranges = {
+        let mut transaction = state.pool.begin().await?;
+        let ranges =
+            crate::postgres::known_ranges_for_system(&mut transaction, system)
+                .await?;
+        transaction.commit().await?;
+        ranges
+    };
+
+    let remote_ranges = fetch_remote_ranges(client, peer, system).await?;
+
+    let mut result = ReconcileResult::default();
+
+    let user_agent = Some(String::from("reconcile"));
+
+    let mut missing_locally =
+        polycentric_protocol::model::ranges::subtract_for_system(
+            &remote_ranges,
+            &local_ranges,
+        );
+
+    while !missing_locally.ranges_for_processes.is_empty() {
+        let request = polycentric_protocol::model::ranges::take_max_items(
+            &missing_locally,
+            EVENTS_PER_REQUEST,
+        );
+
+        let events = crate::ingest::verify_remote_events(
+            &fetch_remote_events(client, peer, system, &request)
+                .await?
+                .events,
+        )
+        .into_iter()
+        .filter(|signed_event| {
+            polycentric_protocol::model::event::from_vec(signed_event.event())
+                .map(|event| event.system() == system)
+                .unwrap_or(false)
+        })
+        .collect::<::std::vec::Vec<_>>();
+
+        // The advertised ranges are not trusted to be bounded, a peer that
+        // has nothing more to give for them is done
+        if events.is_empty() {
+            break;
+        }
+
+        result.pulled += events.len();
+
+        crate::ingest::ingest_event_batch(state, &user_agent, events).await?;
+
+        missing_locally =
+            polycentric_protocol::model::ranges::subtract_for_system(
+                &missing_locally,
+                &request,
+            );
+    }
+
+    let mut missing_remotely =
+        polycentric_protocol::model::ranges::subtract_for_system(
+            &local_ranges,
+            &remote_ranges,
+        );
+
+    while !missing_remotely.ranges_for_processes.is_empty() {
+        let request = polycentric_protocol::model::ranges::take_max_items(
+            &missing_remotely,
+            EVENTS_PER_REQUEST,
+        );
+
+        // Only what would be served to the peer's clients through our own
+        // read endpoints
+        let events = {
+            let mut transaction = state.pool.begin().await?;
+            let events = crate::postgres::select_events_by_ranges::select(
+                &mut transaction,
+                system,
+                &request,
+                &crate::moderation::ModerationOptions {
+                    filters: None,
+                    mode: state.moderation_mode,
+                },
+            )
+            .await?;
+            transaction.commit().await?;
+            events
+        };
+
+        if !events.is_empty() {
+            push_events(client, peer, &events).await?;
+        }
+
+        result.pushed += events.len();
+
+        missing_remotely =
+            polycentric_protocol::model::ranges::subtract_for_system(
+                &missing_remotely,
+                &request,
+            );
+    }
+
+    Ok(result)
+}
+
+fn is_public_address(address: &::std::net::IpAddr) -> bool {
+    match address {
+        ::std::net::IpAddr::V4(address) => {
+            let octets = address.octets();
+
+            !(address.is_private()
+                || address.is_loopback()
+                || address.is_link_local()
+                || address.is_unspecified()
+                || address.is_broadcast()
+                || address.is_documentation()
+                || address.is_multicast()
+                // 100.64.0.0/10, shared address space
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+                || octets[0] == 0)
+        }
+        ::std::net::IpAddr::V6(address) => {
+            if let Some(mapped) = address.to_ipv4_mapped() {
+                return is_public_address(&::std::net::IpAddr::V4(mapped));
+            }
+
+            let first = address.segments()[0];
+
+            !(address.is_loopback()
+                || address.is_unspecified()
+                || address.is_multicast()
+                // fc00::/7, unique local
+                || (first & 0xfe00) == 0xfc00
+                // fe80::/10, link local
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+// A server listed by a system is only contacted when its host is allowed and
+// every address it resolves to is public. Returns the host and the addresses
+// to pin it to.
+async fn resolve_allowed_peer(
+    peer: &str,
+    allowed_hosts: &[String],
+) -> Option<(String, ::std::vec::Vec<::std::net::SocketAddr>)> {
+    let url = ::url::Url::parse(peer).ok()?;
+
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return None;
+    }
+
+    let host = url.host_str()?.to_lowercase();
+
+    if !allowed_hosts.contains(&host) {
+        return None;
+    }
+
+    let port = url.port_or_known_default()?;
+
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addresses: ::std::vec::Vec<_> = ::tokio::net::lookup_host((host, port))
+        .await
+        .ok()?
+        .collect();
+
+    if addresses.is_empty()
+        || !addresses
+            .iter()
+            .all(|address| is_public_address(&address.ip()))
+    {
+        return None;
+    }
+
+    Some((host.to_string(), addresses))
+}
+
+fn make_client(
+    pinned: &Option<(String, ::std::vec::Vec<::std::net::SocketAddr>)>,
+) -> ::anyhow::Result<::reqwest::Client> {
+    // Redirects could lead anywhere, past the checks on listed servers
+    let builder = ::reqwest::Client::builder()
+        .redirect(::reqwest::redirect::Policy::none());
+
+    Ok(match pinned {
+        Some((host, addresses)) => {
+            builder.resolve_to_addrs(host, addresses).build()?
+        }
+        None => builder.build()?,
+    })
+}
+
+// Listed servers get a client of their own, pinned to the checked addresses.
+async fn reconcile_peer(
+    state: &::std::sync::Arc<crate::State>,
+    client: &::reqwest::Client,
+    peer: &Peer,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+) -> ::anyhow::Result<ReconcileResult> {
+    match &peer.pinned {
+        Some(_) => {
+            reconcile_system(
+                state,
+                &make_client(&peer.pinned)?,
+                &peer.url,
+                system,
+            )
+            .await
+        }
+        None => reconcile_system(state, client, &peer.url, system).await,
+    }
+}
+
+async fn peers_for_system(
+    state: &::std::sync::Arc<crate::State>,
+    servers: &[String],
+    allowed_hosts: &[String],
+    self_address: &Option<String>,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+) -> ::anyhow::Result<::std::vec::Vec<Peer>> {
+    let mut transaction = state.pool.begin().await?;
+
+    let server_events = crate::postgres::load_system_events_by_content_type(
+        &mut transaction,
+        system,
+        polycentric_protocol::model::known_message_types::SERVER,
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    let mut peers: ::std::vec::Vec<Peer> = servers
+        .iter()
+        .map(|peer| Peer {
+            url: peer.clone(),
+            pinned: None,
+        })
+        .collect();
+
+    for peer in server_list(&server_events)? {
+        if let Some(pinned) = resolve_allowed_peer(&peer, allowed_hosts).await {
+            peers.push(Peer {
+                url: peer,
+                pinned: Some(pinned),
+            });
+        }
+    }
+
+    for peer in peers.iter_mut() {
+        peer.url = peer.url.trim_end_matches('/').to_string();
+    }
+
+    peers.retain(|peer| {
+        self_address.as_deref().map(|own| own.trim_end_matches('/'))
+            != Some(peer.url.as_str())
+    });
+
+    // Stable, so a configured server is kept over the same server listed by
+    // the system
+    peers.sort_by(|a, b| a.url.cmp(&b.url));
+    peers.dedup_by(|a, b| a.url == b.url);
+
+    Ok(peers)
+}
+
+// Reconciles every known system with the configured servers and the servers
+// each system lists for itself, then starts another pass.
+pub(crate) async fn run(
+    state: ::std::sync::Arc<crate::State>,
+    servers: ::std::vec::Vec<String>,
+    allowed_hosts: ::std::vec::Vec<String>,
+    self_address: Option<String>,
+) -> ::anyhow::Result<()> {
+    let client = make_client(&None)?;
+
+    loop {
+        let mut after = None;
+
+        loop {
+            let systems = {
+                let mut transaction = state.pool.begin().await?;
+                let systems = crate::postgres::load_systems_after(
+                    &mut transaction,
+                    after.as_ref(),
+                    SYSTEMS_PAGE_SIZE,
+                )
+                .await?;
+                transaction.commit().await?;
+                systems
+            };
+
+            for system in systems.iter() {
+                let peers = peers_for_system(
+                    &state,
+                    &servers,
+                    &allowed_hosts,
+                    &self_address,
+                    system,
+                )
+                .await?;
+
+                for peer in peers.iter() {
+                    match reconcile_peer(&state, &client, peer, system).await {
+                        Ok(result) => {
+                            if result != ReconcileResult::default() {
+                                ::log::info!(
+                                    "reconciled {:?} with {}: {:?}",
+                                    polycentric_protocol::model::public_key::to_base64(system)?,
+                                    peer.url,
+                                    result,
+                                );
+                            }
+                        }
+                        Err(err) => {
+                            ::log::warn!(
+                                "failed to reconcile with {}: {}",
+                                peer.url,
+                                err
+                            );
+                        }
+                    }
+                }
+            }
+
+            match systems.last() {
+                Some(system) => after = Some(system.clone()),
+                None => break,
+            }
+        }
+
+        ::log::info!("reconciliation pass complete");
+
+        ::tokio::time::sleep(PASS_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use ::protobuf::Message;
+
+    fn make_server_event(
+        keypair: &::ed25519_dalek::SigningKey,
+        process: &polycentric_protocol::model::process::Process,
+        logical_clock: u64,
+        server: &str,
+        operation: polycentric_protocol::protocol::lwwelement_set::Operation,
+        unix_milliseconds: u64,
+    ) -> polycentric_protocol::model::signed_event::SignedEvent {
+        let mut lww_element_set =
+            polycentric_protocol::protocol::LWWElementSet::new();
+        lww_element_set.operation = operation.into();
+        lww_element_set.value = server.as_bytes().to_vec();
+        lww_element_set.unix_milliseconds = unix_milliseconds;
+
+        let event = polycentric_protocol::model::event::Event::new(
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                keypair.verifying_key(),
+            ),
+            process.clone(),
+            logical_clock,
+            polycentric_protocol::model::known_message_types::SERVER,
+            vec![],
+            polycentric_protocol::protocol::VectorClock::new(),
+            polycentric_protocol::protocol::Indices::new(),
+            vec![],
+            None,
+            Some(lww_element_set),
+            None,
+        );
+
+        polycentric_protocol::model::signed_event::SignedEvent::sign(
+            polycentric_protocol::model::event::to_proto(&event)
+                .unwrap()
+                .write_to_bytes()
+                .unwrap(),
+            keypair,
+        )
+    }
+
+    #[test]
+    fn test_server_list() {
+        use polycentric_protocol::protocol::lwwelement_set::Operation;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        let events = vec![
+            make_server_event(
+                &keypair,
+                &process,
+                1,
+                "https://a",
+                Operation::ADD,
+                1,
+            ),
+            make_server_event(
+                &keypair,
+                &process,
+                2,
+                "https://b",
+                Operation::ADD,
+                1,
+            ),
+            make_server_event(
+                &keypair,
+                &process,
+                3,
+                "https://a",
+                Operation::REMOVE,
+                2,
+            ),
+            make_server_event(
+                &keypair,
+                &process,
+                4,
+                "https://c",
+                Operation::REMOVE,
+                2,
+            ),
+            make_server_event(
+                &keypair,
+                &process,
+                5,
+                "https://c",
+                Operation::ADD,
+                3,
+            ),
+        ];
+
+        assert_eq!(
+            crate::reconcile::server_list(&events).unwrap(),
+            vec![String::from("https://b"), String::from("https://c")]
+        );
+    }
+
+    #[test]
+    fn test_is_public_address() {
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(
+                !crate::reconcile::is_public_address(&address.parse().unwrap()),
+                "{}",
+                address
+            );
+        }
+
+        for address in ["8.8.8.8", "2001:4860:4860::8888"] {
+            assert!(crate::reconcile::is_public_address(
+                &address.parse().unwrap()
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_allowed_peer() {
+        let allowed = vec![
+            String::from("8.8.8.8"),
+            String::from("127.0.0.1"),
+            String::from("[::1]"),
+        ];
+
+        assert_eq!(
+            crate::reconcile::resolve_allowed_peer("https://8.8.8.8", &allowed)
+                .await,
+            Some((
+                String::from("8.8.8.8"),
+                vec!["8.8.8.8:443".parse().unwrap()]
+            ))
+        );
+
+        for peer in [
+            "https://1.1.1.1",
+            "http://127.0.0.1:8081",
+            "http://[::1]",
+            "file:///etc/passwd",
+        ] {
+            assert!(
+                crate::reconcile::resolve_allowed_peer(peer, &allowed)
+                    .await
+                    .is_none(),
+                "{}",
+                peer
+            );
+        }
+    }
+
+    #[::sqlx::test]
+    async fn test_reconcile_stops_on_empty_response(
+        pool: ::sqlx::PgPool,
+    ) -> ::anyhow::Result<()> {
+        use ::warp::Filter;
+
+        let mut transaction = pool.begin().await?;
+        crate::postgres::prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+        let system =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                keypair.verifying_key(),
+            );
+
+        // Advertises every logical clock but never returns an event
+        let mut range = polycentric_protocol::protocol::Range::new();
+        range.low = 1;
+        range.high = u64::MAX;
+
+        let mut for_process =
+            polycentric_protocol::protocol::RangesForProcess::new();
+        for_process.process = ::protobuf::MessageField::some(
+            polycentric_protocol::model::process::to_proto(&process),
+        );
+        for_process.ranges.push(range);
+
+        let mut ranges = polycentric_protocol::protocol::RangesForSystem::new();
+        ranges.ranges_for_processes.push(for_process);
+
+        let ranges = ranges.write_to_bytes()?;
+        let events =
+            polycentric_protocol::protocol::Events::new().write_to_bytes()?;
+
+        let requests =
+            ::std::sync::Arc::new(::std::sync::atomic::AtomicUsize::new(0));
+
+        let route = ::warp::path("ranges").map(move || ranges.clone()).or(
+            ::warp::path("events").map({
+                let requests = requests.clone();
+                move || {
+                    requests
+                        .fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+                    events.clone()
+                }
+            }),
+        );
+
+        let (address, server) =
+            ::warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+
+        ::tokio::spawn(server);
+
+        let (search, _) =
+            crate::opensearch::test_utils::mock(::serde_json::json!({}))?;
+        let state = crate::make_test_state(&pool, search)?;
+
+        let result = crate::reconcile::reconcile_system(
+            &state,
+            &crate::reconcile::make_client(&None)?,
+            &format!("http://{}", address),
+            &system,
+        )
+        .await?;
+
+        assert_eq!(result, crate::reconcile::ReconcileResult::default());
+        assert_eq!(requests.load(::std::sync::atomic::Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1379 @@
+use crate::moderation::moderation_queue::ModerationStatus;
+use ::cadence::{Counted, Gauged};
+
+// Ingest does not talk to OpenSearch. Events that belong in a search index
+// are written to `search_outbox` in the same transaction that stores them,
+// and `run` drains the outbox in the background with the _bulk API. Events
+// that fail to index are retried with exponential backoff, so the index
+// catches up once OpenSearch is healthy again.
+//
+// Removals go through the same outbox, keyed by document id since the event
+// is usually gone by the time they are sent. `reconcile` cleans up documents
+// whose removal never made it to the outbox.
+//
+// Censored events are kept out of the indices the same way, so discovery
+// endpoints do not need to filter them at query time.
+//
+// Profile documents are per system and follow the latest event. When that
+// event is taken away the document is rewritten from the latest event still
+// visible, or removed if there is none.
+
+pub(crate) struct Options {
+    pub(crate) interval: ::std::time::Duration,
+    pub(crate) batch_size: u64,
+    pub(crate) max_backoff: ::std::time::Duration,
+}
+
+#[derive(::sqlx::FromRow)]
+struct OutboxRow {
+    id: i64,
+    // Set for removals
+    search_index: Option<String>,
+    document_id: Option<String>,
+    // Set for events still stored
+    raw_event: Option<::std::vec::Vec<u8>>,
+    overwrite: bool,
+    // Rejected by moderation or censored
+    hidden: bool,
+}
+
+enum Operation {
+    Index(crate::opensearch::SearchDocument),
+    Remove { index: String, id: String },
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct DrainResult {
+    // Entries taken from the outbox, including those with nothing to send
+    pulled: u64,
+    indexed: u64,
+    failed: u64,
+}
+
+#[derive(::sqlx::FromRow, Debug)]
+struct Backlog {
+    pending: i64,
+    // Seconds since the oldest pending entry was written
+    oldest: f64,
+}
+
+// Content types that have a document in one of the indices, see
+// `ingest::search_document`.
+pub(crate) fn searchable_content_types(
+) -> ::anyhow::Result<::std::vec::Vec<i64>> {
+    Ok(vec![
+        i64::try_from(polycentric_protocol::model::known_message_types::POST)?,
+        i64::try_from(
+            polycentric_protocol::model::known_message_types::USERNAME,
+        )?,
+        i64::try_from(
+            polycentric_protocol::model::known_message_types::DESCRIPTION,
+        )?,
+    ])
+}
+
+// Queues indexing of the event. Does nothing once the event is gone, rejected
+// by moderation or censored.
+pub(crate) async fn enqueue(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: u64,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO search_outbox (event_id) VALUES ($1);
+    ";
+
+    ::sqlx::query(query)
+        .bind(i64::try_from(event_id)?)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+async fn enqueue_removal(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    index: &str,
+    document_id: &str,
+) -> ::anyhow::Result<()> {
+    let query = "
+        INSERT INTO search_outbox (search_index, document_id)
+        VALUES ($1, $2);
+    ";
+
+    ::sqlx::query(query)
+        .bind(index)
+        .bind(document_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+// Rewrites the profile document from the latest visible event of the system.
+// The event being replaced is usually newer, so the version check is skipped.
+async fn refresh_profile(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+    content_type: u64,
+    index: &str,
+) -> ::anyhow::Result<()> {
+    let query = "
+        SELECT e.id
+        FROM events e
+        INNER JOIN lww_elements l ON l.event_id = e.id
+        WHERE e.system_key_type = $1
+        AND e.system_key = $2
+        AND e.content_type = $3
+        AND e.moderation_status <> $4
+        AND NOT EXISTS (
+            SELECT 1 FROM censored_systems cs
+            WHERE cs.system_key_type = e.system_key_type
+            AND cs.system_key = e.system_key
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM censored_events ce
+            WHERE ce.system_key_type = e.system_key_type
+            AND ce.system_key = e.system_key
+            AND ce.process = e.process
+            AND ce.logical_clock = e.logical_clock
+        )
+        ORDER BY l.unix_milliseconds DESC, e.process DESC
+        LIMIT 1;
+    ";
+
+    let latest: Option<i64> = ::sqlx::query_scalar(query)
+        .bind(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(system),
+        )?)
+        .bind(polycentric_protocol::model::public_key::get_key_bytes(
+            system,
+        ))
+        .bind(i64::try_from(content_type)?)
+        .bind(ModerationStatus::FlaggedAndRejected)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    match latest {
+        Some(event_id) => {
+            let query = "
+                INSERT INTO search_outbox (event_id, overwrite)
+                VALUES ($1, true);
+            ";
+
+            ::sqlx::query(query)
+                .bind(event_id)
+                .execute(&mut **transaction)
+                .await?;
+        }
+        None => {
+            enqueue_removal(
+                transaction,
+                index,
+                &polycentric_protocol::model::public_key::to_base64(system)?,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Queues removal of the documents of these events, profile documents are
+// refreshed instead. Takes raw events so that it can be given the rows
+// returned by a DELETE, call it once the events are gone or hidden.
+pub(crate) async fn remove_events(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    raw_events: &[::std::vec::Vec<u8>],
+) -> ::anyhow::Result<()> {
+    let mut profiles = ::std::collections::HashSet::new();
+
+    for raw_event in raw_events {
+        let layers = crate::postgres::stored_event_layers(
+            polycentric_protocol::model::signed_event::from_vec(raw_event)?,
+        )?;
+
+        if let Some(document) = crate::ingest::search_document(&layers)? {
+            if document.index == crate::opensearch::MESSAGES_INDEX {
+                enqueue_removal(transaction, document.index, &document.id)
+                    .await?;
+            } else if profiles.insert((document.index, document.id)) {
+                refresh_profile(
+                    transaction,
+                    layers.event().system(),
+                    *layers.event().content_type(),
+                    document.index,
+                )
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn remove_event(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    event_id: i64,
+) -> ::anyhow::Result<()> {
+    let query = "
+        SELECT raw_event FROM events WHERE id = $1;
+    ";
+
+    let raw_events: ::std::vec::Vec<::std::vec::Vec<u8>> =
+        ::sqlx::query_scalar(query)
+            .bind(event_id)
+            .fetch_all(&mut **transaction)
+            .await?;
+
+    remove_events(transaction, &raw_events).await
+}
+
+// Queues removal of the profile documents of a purged system.
+pub(crate) async fn remove_system(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    system: &polycentric_protocol::model::public_key::PublicKey,
+) -> ::anyhow::Result<()> {
+    let document_id =
+        polycentric_protocol::model::public_key::to_base64(system)?;
+
+    for index in [
+        crate::opensearch::PROFILE_NAMES_INDEX,
+        crate::opensearch::PROFILE_DESCRIPTIONS_INDEX,
+    ] {
+        enqueue_removal(transaction, index, &document_id).await?;
+    }
+
+    Ok(())
+}
+
+// Locks the entries due for an attempt so that several servers can drain the
+// same outbox.
+async fn pull(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    limit: u64,
+) -> ::anyhow::Result<::std::vec::Vec<OutboxRow>> {
+    let query = "
+        SELECT
+            o.id,
+            o.search_index,
+            o.document_id,
+            e.raw_event,
+            o.overwrite,
+            (
+                COALESCE(e.moderation_status = $2, false)
+                OR EXISTS (
+                    SELECT 1 FROM censored_systems cs
+                    WHERE cs.system_key_type = e.system_key_type
+                    AND cs.system_key = e.system_key
+                )
+                OR EXISTS (
+                    SELECT 1 FROM censored_events ce
+                    WHERE ce.system_key_type = e.system_key_type
+                    AND ce.system_key = e.system_key
+                    AND ce.process = e.process
+                    AND ce.logical_clock = e.logical_clock
+                )
+            ) AS hidden
+        FROM search_outbox o
+        LEFT JOIN events e ON e.id = o.event_id
+        WHERE o.next_attempt_at <= NOW()
+        ORDER BY o.next_attempt_at, o.id
+        LIMIT $1
+        FOR UPDATE OF o SKIP LOCKED;
+    ";
+
+    Ok(::sqlx::query_as::<_, OutboxRow>(query)
+        .bind(i64::try_from(limit)?)
+        .bind(ModerationStatus::FlaggedAndRejected)
+        .fetch_all(&mut **transaction)
+        .await?)
+}
+
+async fn complete(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    ids: &[i64],
+) -> ::anyhow::Result<()> {
+    let query = "
+        DELETE FROM search_outbox WHERE id = ANY($1);
+    ";
+
+    ::sqlx::query(query)
+        .bind(ids)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+// The next attempt is delayed by 2^attempts seconds, up to `max_backoff`.
+async fn retry(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    id: i64,
+    error: &str,
+    max_backoff: ::std::time::Duration,
+) -> ::anyhow::Result<()> {
+    let query = "
+        UPDATE search_outbox
+        SET attempts = attempts + 1,
+            last_error = $2,
+            next_attempt_at = NOW() + make_interval(
+                secs => LEAST(POWER(2, LEAST(attempts, 30)), $3)
+            )
+        WHERE id = $1;
+    ";
+
+    ::sqlx::query(query)
+        .bind(id)
+        .bind(error)
+        .bind(max_backoff.as_secs_f64())
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+async fn backlog(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+) -> ::anyhow::Result<Backlog> {
+    let query = "
+        SELECT
+            COUNT(*) AS pending,
+            COALESCE(
+                EXTRACT(EPOCH FROM NOW() - MIN(created_on)), 0
+            )::FLOAT8 AS oldest
+        FROM search_outbox;
+    ";
+
+    Ok(::sqlx::query_as::<_, Backlog>(query)
+        .fetch_one(&mut **transaction)
+        .await?)
+}
+
+// Sends the operations in one _bulk request. Returns the error for each
+// operation that failed, in the same order. A version conflict means a newer
+// document is already indexed and a missing document was already removed,
+// neither is an error.
+async fn send_bulk(
+    search: &::opensearch::OpenSearch,
+    operations: &[Operation],
+) -> ::anyhow::Result<::std::vec::Vec<Option<String>>> {
+    let mut bulk = ::opensearch::BulkOperations::new();
+
+    for operation in operations {
+        match operation {
+            Operation::Index(document) => {
+                let mut index =
+                    ::opensearch::BulkOperation::index(&document.body)
+                        .id(&document.id)
+                        .index(document.index);
+
+                if let Some(version) = document.version {
+                    index =
+                        index.version(i64::try_from(version)?).version_type(
+                            ::opensearch::params::VersionType::ExternalGte,
+                        );
+                }
+
+                bulk.push(index)?;
+            }
+            Operation::Remove { index, id } => {
+                bulk.push(
+                    ::opensearch::BulkOperation::<()>::delete(id).index(index),
+                )?;
+            }
+        }
+    }
+
+    let response = search
+        .bulk(::opensearch::BulkParts::None)
+        .body(vec![bulk])
+        .send()
+        .await?;
+
+    let status = response.status_code();
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+
+        ::anyhow::bail!("bulk request failed with status {}: {}", status, body);
+    }
+
+    let body = response.json::<::serde_json::Value>().await?;
+
+    let items = body["items"]
+        .as_array()
+        .ok_or_else(|| ::anyhow::anyhow!("bulk response missing items"))?;
+
+    if items.len() != operations.len() {
+        ::anyhow::bail!(
+            "bulk response has {} items for {} operations",
+            items.len(),
+            operations.len()
+        );
+    }
+
+    Ok(items
+        .iter()
+        .zip(operations)
+        .map(|(item, operation)| {
+            let (result, expected) = match operation {
+                Operation::Index(_) => (&item["index"], 409),
+                Operation::Remove { .. } => (&item["delete"], 404),
+            };
+
+            let status = result["status"].as_u64().unwrap_or(0);
+
+            if (200..300).contains(&status) || status == expected {
+                None
+            } else {
+                Some(format!("status {}: {}", status, result["error"]))
+            }
+        })
+        .collect())
+}
+
+fn prepare(row: OutboxRow) -> ::anyhow::Result<Option<Operation>> {
+    if let (Some(index), Some(id)) = (row.search_index, row.document_id) {
+        return Ok(Some(Operation::Remove { index, id }));
+    }
+
+    let raw_event = match row.raw_event {
+        Some(raw_event) if !row.hidden => raw_event,
+        _ => return Ok(None),
+    };
+
+    let layers = crate::postgres::stored_event_layers(
+        polycentric_protocol::model::signed_event::from_vec(&raw_event)?,
+    )?;
+
+    Ok(
+        crate::ingest::search_document(&layers)?.map(|mut document| {
+            if row.overwrite {
+                document.version = None;
+            }
+            Operation::Index(document)
+        }),
+    )
+}
+
+async fn drain(
+    state: &crate::State,
+    options: &Options,
+) -> ::anyhow::Result<DrainResult> {
+    let mut transaction = state.pool.begin().await?;
+
+    let rows = pull(&mut transaction, options.batch_size).await?;
+
+    let mut result = DrainResult {
+        pulled: u64::try_from(rows.len())?,
+        ..DrainResult::default()
+    };
+    let mut done = vec![];
+    let mut pending = vec![];
+    let mut operations = vec![];
+
+    for row in rows {
+        let id = row.id;
+
+        match prepare(row) {
+            Ok(Some(operation)) => {
+                pending.push(id);
+                operations.push(operation);
+            }
+            Ok(None) => {
+                done.push(id);
+            }
+            Err(err) => {
+                ::log::warn!("dropping search outbox entry {}: {}", id, err);
+                done.push(id);
+            }
+        }
+    }
+
+    if !operations.is_empty() {
+        match send_bulk(&state.search, &operations).await {
+            Ok(errors) => {
+                for (id, error) in pending.into_iter().zip(errors) {
+                    match error {
+                        Some(error) => {
+                            retry(
+                                &mut transaction,
+                                id,
+                                &error,
+                                options.max_backoff,
+                            )
+                            .await?;
+                            result.failed += 1;
+                        }
+                        None => {
+                            done.push(id);
+                            result.indexed += 1;
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                ::log::warn!("failed to send search documents: {}", err);
+
+                let error = err.to_string();
+
+                for id in pending {
+                    retry(&mut transaction, id, &error, options.max_backoff)
+                        .await?;
+                    result.failed += 1;
+                }
+            }
+        }
+    }
+
+    complete(&mut transaction, &done).await?;
+
+    transaction.commit().await?;
+
+    Ok(result)
+}
+
+async fn report(
+    state: &crate::State,
+    result: &DrainResult,
+) -> ::anyhow::Result<()> {
+    let mut transaction = state.pool.begin().await?;
+    let backlog = backlog(&mut transaction).await?;
+    transaction.commit().await?;
+
+    state
+        .statsd_client
+        .gauge_with_tags(
+            "search_outbox_backlog",
+            u64::try_from(backlog.pending)?,
+        )
+        .try_send()?;
+
+    state
+        .statsd_client
+        .gauge_with_tags("search_outbox_oldest_seconds", backlog.oldest)
+        .try_send()?;
+
+    state
+        .statsd_client
+        .count_with_tags("search_indexed", i64::try_from(result.indexed)?)
+        .try_send()?;
+
+    state
+        .statsd_client
+        .count_with_tags("search_index_failed", i64::try_from(result.failed)?)
+        .try_send()?;
+
+    Ok(())
+}
+
+pub(crate) async fn run(
+    state: ::std::sync::Arc<crate::State>,
+    options: Options,
+) {
+    let mut interval = ::tokio::time::interval(
+        options
+            .interval
+            .max(::std::time::Duration::from_millis(100)),
+    );
+
+    loop {
+        interval.tick().await;
+
+        // Keep going without waiting for the next tick while there is a full
+        // batch to drain. Entries that are skipped or removed count, so a run
+        // of hidden events does not stall the outbox.
+        loop {
+            let result = match drain(&state, &options).await {
+                Ok(result) => result,
+                Err(err) => {
+                    ::log::warn!("failed to drain search outbox: {}", err);
+                    break;
+                }
+            };
+
+            if let Err(err) = report(&state, &result).await {
+                ::log::warn!("failed to report search outbox: {}", err);
+            }
+
+            if result.pulled < options.batch_size || result.failed > 0 {
+                break;
+            }
+        }
+    }
+}
+
+// Positions of the pointers with no visible event. Censored events are not
+// visible.
+async fn find_orphan_pointers(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    pointers: &[polycentric_protocol::model::pointer::Pointer],
+) -> ::anyhow::Result<::std::vec::Vec<usize>> {
+    let query = "
+        SELECT (t.position - 1)::INT8
+        FROM UNNEST($1::INT8[], $2::BYTEA[], $3::BYTEA[], $4::INT8[])
+        WITH ORDINALITY AS t(
+            system_key_type,
+            system_key,
+            process,
+            logical_clock,
+            position
+        )
+        WHERE NOT EXISTS (
+            SELECT 1 FROM events e
+            WHERE e.system_key_type = t.system_key_type
+            AND e.system_key = t.system_key
+            AND e.process = t.process
+            AND e.logical_clock = t.logical_clock
+            AND e.moderation_status IS DISTINCT FROM $5
+            AND NOT EXISTS (
+                SELECT 1 FROM censored_events ce
+                WHERE ce.system_key_type = e.system_key_type
+                AND ce.system_key = e.system_key
+                AND ce.process = e.process
+                AND ce.logical_clock = e.logical_clock
+            )
+        )
+        OR EXISTS (
+            SELECT 1 FROM censored_systems cs
+            WHERE cs.system_key_type = t.system_key_type
+            AND cs.system_key = t.system_key
+        );
+    ";
+
+    let mut key_types = vec![];
+    let mut keys = vec![];
+    let mut processes = vec![];
+    let mut logical_clocks = vec![];
+
+    for pointer in pointers {
+        key_types.push(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(
+                pointer.system(),
+            ),
+        )?);
+        keys.push(polycentric_protocol::model::public_key::get_key_bytes(
+            pointer.system(),
+        ));
+        processes.push(pointer.process().bytes().to_vec());
+        logical_clocks.push(i64::try_from(*pointer.logical_clock())?);
+    }
+
+    let positions: ::std::vec::Vec<i64> = ::sqlx::query_scalar(query)
+        .bind(key_types)
+        .bind(keys)
+        .bind(processes)
+        .bind(logical_clocks)
+        .bind(ModerationStatus::FlaggedAndRejected)
+        .fetch_all(&mut **transaction)
+        .await?;
+
+    Ok(positions
+        .into_iter()
+        .map(usize::try_from)
+        .collect::<Result<_, _>>()?)
+}
+
+// Positions of the systems with no events left or that are censored.
+async fn find_orphan_systems(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    systems: &[polycentric_protocol::model::public_key::PublicKey],
+) -> ::anyhow::Result<::std::vec::Vec<usize>> {
+    let query = "
+        SELECT (t.position - 1)::INT8
+        FROM UNNEST($1::INT8[], $2::BYTEA[])
+        WITH ORDINALITY AS t(system_key_type, system_key, position)
+        WHERE NOT EXISTS (
+            SELECT 1 FROM events e
+            WHERE e.system_key_type = t.system_key_type
+            AND e.system_key = t.system_key
+        )
+        OR EXISTS (
+            SELECT 1 FROM censored_systems cs
+            WHERE cs.system_key_type = t.system_key_type
+            AND cs.system_key = t.system_key
+        );
+    ";
+
+    let mut key_types = vec![];
+    let mut keys = vec![];
+
+    for system in systems {
+        key_types.push(i64::try_from(
+            polycentric_protocol::model::public_key::get_key_type(system),
+        )?);
+        keys.push(polycentric_protocol::model::public_key::get_key_bytes(
+            system,
+        ));
+    }
+
+    let positions: ::std::vec::Vec<i64> = ::sqlx::query_scalar(query)
+        .bind(key_types)
+        .bind(keys)
+        .fetch_all(&mut **transaction)
+        .await?;
+
+    Ok(positions
+        .into_iter()
+        .map(usize::try_from)
+        .collect::<Result<_, _>>()?)
+}
+
+// Document ids from the page that no longer match anything in Postgres. Ids
+// that do not decode can never be served and count as orphans.
+async fn find_orphans(
+    transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+    index: &str,
+    ids: ::std::vec::Vec<String>,
+) -> ::anyhow::Result<::std::vec::Vec<String>> {
+    let mut orphans = vec![];
+    let mut decoded_ids = vec![];
+
+    let positions = if index == crate::opensearch::MESSAGES_INDEX {
+        let mut pointers = vec![];
+
+        for id in ids {
+            match polycentric_protocol::model::pointer::from_base64(&id) {
+                Ok(pointer) => {
+                    pointers.push(pointer);
+                    decoded_ids.push(id);
+                }
+                Err(_) => orphans.push(id),
+            }
+        }
+
+        find_orphan_pointers(transaction, &pointers).await?
+    } else {
+        let mut systems = vec![];
+
+        for id in ids {
+            match polycentric_protocol::model::public_key::from_base64(&id) {
+                Ok(system) => {
+                    systems.push(system);
+                    decoded_ids.push(id);
+                }
+                Err(_) => orphans.push(id),
+            }
+        }
+
+        find_orphan_systems(transaction, &systems).await?
+    };
+
+    for position in positions {
+        orphans.push(decoded_ids[position].clone());
+    }
+
+    Ok(orphans)
+}
+
+async fn reconcile_index(
+    pool: &::sqlx::PgPool,
+    search: &::opensearch::OpenSearch,
+    index: &str,
+    batch_size: u64,
+) -> ::anyhow::Result<()> {
+    let mut scanned = 0;
+    let mut removed = 0;
+
+    let mut response = search
+        .search(::opensearch::SearchParts::Index(&[index]))
+        .scroll("5m")
+        .size(i64::try_from(batch_size)?)
+        .body(::serde_json::json!({
+            "query": { "match_all": {} },
+            "_source": false
+        }))
+        .send()
+        .await?
+        .error_for_status_code()?
+        .json::<crate::opensearch::OpenSearchScrollL0>()
+        .await?;
+
+    loop {
+        let hits = response.hits.map(|hits| hits.hits).unwrap_or_default();
+
+        if hits.is_empty() {
+            break;
+        }
+
+        scanned += hits.len();
+
+        let ids = hits.into_iter().map(|hit| hit._id).collect();
+
+        let mut transaction = pool.begin().await?;
+        let orphans = find_orphans(&mut transaction, index, ids).await?;
+        transaction.commit().await?;
+
+        if !orphans.is_empty() {
+            let operations = orphans
+                .into_iter()
+                .map(|id| Operation::Remove {
+                    index: index.to_string(),
+                    id,
+                })
+                .collect::<::std::vec::Vec<_>>();
+
+            for (operation, error) in
+                operations.iter().zip(send_bulk(search, &operations).await?)
+            {
+                if let (Operation::Remove { id, .. }, Some(error)) =
+                    (operation, error)
+                {
+                    ::log::warn!(
+                        "failed to remove {} from {}: {}",
+                        id,
+                        index,
+                        error
+                    );
+                } else {
+                    removed += 1;
+                }
+            }
+        }
+
+        let scroll_id = response
+            ._scroll_id
+            .ok_or_else(|| ::anyhow::anyhow!("scroll id missing"))?;
+
+        response = search
+            .scroll(::opensearch::ScrollParts::None)
+            .body(::serde_json::json!({
+                "scroll": "5m",
+                "scroll_id": scroll_id
+            }))
+            .send()
+            .await?
+            .error_for_status_code()?
+            .json::<crate::opensearch::OpenSearchScrollL0>()
+            .await?;
+    }
+
+    if let Some(scroll_id) = response._scroll_id {
+        search
+            .clear_scroll(::opensearch::ClearScrollParts::None)
+            .body(::serde_json::json!({ "scroll_id": [scroll_id] }))
+            .send()
+            .await?;
+    }
+
+    ::log::info!(
+        "{}: scanned {} documents, removed {} orphans",
+        index,
+        scanned,
+        removed
+    );
+
+    Ok(())
+}
+
+// Removes documents whose event was deleted, purged, rejected or censored
+// without the removal reaching the index, for example before the outbox
+// existed.
+pub(crate) async fn reconcile(
+    pool: ::sqlx::PgPool,
+    search: ::opensearch::OpenSearch,
+    batch_size: u64,
+) -> ::anyhow::Result<()> {
+    for index in [
+        crate::opensearch::MESSAGES_INDEX,
+        crate::opensearch::PROFILE_NAMES_INDEX,
+        crate::opensearch::PROFILE_DESCRIPTIONS_INDEX,
+    ] {
+        reconcile_index(&pool, &search, index, batch_size).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::postgres::prepare_database;
+    use protobuf::Message;
+    use sqlx::PgPool;
+
+    async fn ingest(
+        transaction: &mut ::sqlx::Transaction<'_, ::sqlx::Postgres>,
+        content_type: u64,
+        content: &[u8],
+    ) -> anyhow::Result<()> {
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        crate::ingest::ingest_event_postgres(
+            transaction,
+            &polycentric_protocol::test_utils::make_test_event_with_content(
+                &keypair,
+                &process,
+                1,
+                content_type,
+                content,
+                vec![],
+            ),
+        )
+        .await
+    }
+
+    fn removal(row: OutboxRow) -> anyhow::Result<(String, String)> {
+        match prepare(row)? {
+            Some(Operation::Remove { index, id }) => Ok((index, id)),
+            _ => Err(anyhow::anyhow!("expected a removal")),
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_outbox(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        ingest(
+            &mut transaction,
+            polycentric_protocol::model::known_message_types::POST,
+            &polycentric_protocol::test_utils::make_test_post_content(),
+        )
+        .await?;
+
+        // Not searchable, never enqueued
+        ingest(
+            &mut transaction,
+            polycentric_protocol::model::known_message_types::BLOB_META,
+            &[1, 2, 3],
+        )
+        .await?;
+
+        let mut rows = pull(&mut transaction, 10).await?;
+        assert_eq!(rows.len(), 1);
+
+        let row = rows.remove(0);
+        let id = row.id;
+
+        match prepare(row)? {
+            Some(Operation::Index(document)) => {
+                assert_eq!(document.index, crate::opensearch::MESSAGES_INDEX)
+            }
+            _ => panic!("post should be indexed"),
+        }
+
+        retry(
+            &mut transaction,
+            id,
+            "unavailable",
+            ::std::time::Duration::from_secs(60),
+        )
+        .await?;
+
+        // Backing off
+        assert!(pull(&mut transaction, 10).await?.is_empty());
+        assert_eq!(backlog(&mut transaction).await?.pending, 1);
+
+        sqlx::query("UPDATE search_outbox SET next_attempt_at = NOW();")
+            .execute(&mut *transaction)
+            .await?;
+
+        let rows = pull(&mut transaction, 10).await?;
+        assert_eq!(rows.len(), 1);
+
+        complete(&mut transaction, &[rows[0].id]).await?;
+        assert_eq!(backlog(&mut transaction).await?.pending, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_event_not_indexed() -> anyhow::Result<()> {
+        // Stored before usernames had to be LWW elements
+        let legacy =
+            polycentric_protocol::test_utils::make_test_event_with_content(
+                &polycentric_protocol::test_utils::make_test_keypair(),
+                &polycentric_protocol::test_utils::make_test_process(),
+                1,
+                polycentric_protocol::model::known_message_types::USERNAME,
+                b"alice",
+                vec![],
+            );
+
+        let row = OutboxRow {
+            id: 1,
+            search_index: None,
+            document_id: None,
+            raw_event: Some(
+                polycentric_protocol::model::signed_event::to_proto(&legacy)
+                    .write_to_bytes()?,
+            ),
+            overwrite: false,
+            hidden: false,
+        };
+
+        assert!(prepare(row)?.is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_drain_counts_skipped(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        for _ in 0..2 {
+            ingest(
+                &mut transaction,
+                polycentric_protocol::model::known_message_types::POST,
+                &polycentric_protocol::test_utils::make_test_post_content(),
+            )
+            .await?;
+        }
+
+        sqlx::query(
+            "UPDATE events SET moderation_status = 'flagged_and_rejected';",
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        let (search, requests) =
+            crate::opensearch::test_utils::mock(::serde_json::json!({}))?;
+        let state = crate::make_test_state(&pool, search)?;
+
+        let options = Options {
+            interval: ::std::time::Duration::from_secs(1),
+            batch_size: 2,
+            max_backoff: ::std::time::Duration::from_secs(60),
+        };
+
+        // A full batch with nothing to index
+        assert_eq!(
+            drain(&state, &options).await?,
+            DrainResult {
+                pulled: 2,
+                indexed: 0,
+                failed: 0,
+            }
+        );
+        assert_eq!(drain(&state, &options).await?, DrainResult::default());
+        assert!(requests.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_removals(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        let post = polycentric_protocol::test_utils::make_test_event(
+            &keypair, &process, 1,
+        );
+        let pointer =
+            polycentric_protocol::model::pointer::from_signed_event(&post)?;
+        let document_id =
+            polycentric_protocol::model::pointer::to_base64(&pointer)?;
+
+        crate::ingest::ingest_event_postgres(&mut transaction, &post).await?;
+
+        // Rejected before it was indexed
+        sqlx::query("UPDATE events SET moderation_status = $1;")
+            .bind(ModerationStatus::FlaggedAndRejected)
+            .execute(&mut *transaction)
+            .await?;
+
+        let rows = pull(&mut transaction, 10).await?;
+        assert_eq!(rows.len(), 1);
+        assert!(prepare(rows.into_iter().next().unwrap())?.is_none());
+        sqlx::query("DELETE FROM search_outbox;")
+            .execute(&mut *transaction)
+            .await?;
+
+        let event_id = sqlx::query_scalar("SELECT id FROM events;")
+            .fetch_one(&mut *transaction)
+            .await?;
+        remove_event(&mut transaction, event_id).await?;
+
+        let mut rows = pull(&mut transaction, 10).await?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            removal(rows.remove(0))?,
+            (
+                crate::opensearch::MESSAGES_INDEX.to_string(),
+                document_id.clone()
+            )
+        );
+        sqlx::query("DELETE FROM search_outbox;")
+            .execute(&mut *transaction)
+            .await?;
+
+        // Deleting the post removes its document
+        crate::ingest::ingest_event_postgres(
+            &mut transaction,
+            &polycentric_protocol::test_utils::make_delete_event_from_event(
+                &keypair, &process, &post, 2, 0,
+            ),
+        )
+        .await?;
+
+        let rows = pull(&mut transaction, 10).await?;
+        assert_eq!(
+            rows.into_iter()
+                .map(removal)
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            vec![(crate::opensearch::MESSAGES_INDEX.to_string(), document_id)]
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_profile_removals(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+        let system =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                keypair.verifying_key(),
+            );
+        let document_id =
+            polycentric_protocol::model::public_key::to_base64(&system)?;
+
+        for (logical_clock, name) in [(1, "alice"), (2, "mallory")] {
+            crate::ingest::ingest_event_postgres(
+                &mut transaction,
+                &polycentric_protocol::test_utils::make_test_lww_event(
+                    &keypair,
+                    &process,
+                    logical_clock,
+                    polycentric_protocol::model::known_message_types::USERNAME,
+                    name.as_bytes(),
+                    logical_clock * 1000,
+                ),
+            )
+            .await?;
+        }
+
+        sqlx::query("DELETE FROM search_outbox;")
+            .execute(&mut *transaction)
+            .await?;
+
+        let rejected: i64 = sqlx::query_scalar(
+            "SELECT id FROM events WHERE logical_clock = 2;",
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        sqlx::query("UPDATE events SET moderation_status = $1 WHERE id = $2;")
+            .bind(ModerationStatus::FlaggedAndRejected)
+            .bind(rejected)
+            .execute(&mut *transaction)
+            .await?;
+
+        remove_event(&mut transaction, rejected).await?;
+
+        // Rewritten from the previous username over the newer document
+        let rows = pull(&mut transaction, 10).await?;
+        assert_eq!(rows.len(), 1);
+
+        match prepare(rows.into_iter().next().unwrap())? {
+            Some(Operation::Index(document)) => {
+                assert_eq!(
+                    document.index,
+                    crate::opensearch::PROFILE_NAMES_INDEX
+                );
+                assert_eq!(document.id, document_id);
+                assert_eq!(document.body["profile_name"], "alice");
+                assert_eq!(document.version, None);
+            }
+            _ => panic!("profile should be rewritten"),
+        }
+
+        sqlx::query("DELETE FROM search_outbox;")
+            .execute(&mut *transaction)
+            .await?;
+
+        // Nothing left to show
+        sqlx::query("UPDATE events SET moderation_status = $1;")
+            .bind(ModerationStatus::FlaggedAndRejected)
+            .execute(&mut *transaction)
+            .await?;
+
+        remove_event(&mut transaction, rejected).await?;
+
+        let rows = pull(&mut transaction, 10).await?;
+        assert_eq!(
+            rows.into_iter()
+                .map(removal)
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            vec![(
+                crate::opensearch::PROFILE_NAMES_INDEX.to_string(),
+                document_id
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_censorship(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+        let system =
+            polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                keypair.verifying_key(),
+            );
+
+        let post = polycentric_protocol::test_utils::make_test_event(
+            &keypair, &process, 1,
+        );
+        let document_id = polycentric_protocol::model::pointer::to_base64(
+            &polycentric_protocol::model::pointer::from_signed_event(&post)?,
+        )?;
+
+        crate::ingest::ingest_event_postgres(&mut transaction, &post).await?;
+
+        crate::postgres::censor_event(
+            &mut transaction,
+            crate::postgres::CensorshipType::DoNotRecommend,
+            &system,
+            &process,
+            1,
+            &crate::postgres::CensorshipDetails::default(),
+        )
+        .await?;
+
+        // Censored before it was indexed, and its document removed
+        let mut rows = pull(&mut transaction, 10).await?;
+        assert_eq!(rows.len(), 2);
+        assert!(prepare(rows.remove(0))?.is_none());
+        assert_eq!(
+            removal(rows.remove(0))?,
+            (crate::opensearch::MESSAGES_INDEX.to_string(), document_id)
+        );
+        sqlx::query("DELETE FROM search_outbox;")
+            .execute(&mut *transaction)
+            .await?;
+
+        let censored = crate::postgres::censorship::list_censored_events(
+            &mut transaction,
+            0,
+            10,
+            None,
+        )
+        .await?;
+        assert!(
+            crate::postgres::censorship::lift_censored_event(
+                &mut transaction,
+                censored[0].id,
+            )
+            .await?
+        );
+
+        // Indexed again once lifted
+        let mut rows = pull(&mut transaction, 10).await?;
+        assert_eq!(rows.len(), 1);
+        assert!(matches!(
+            prepare(rows.remove(0))?,
+            Some(Operation::Index(_))
+        ));
+        sqlx::query("DELETE FROM search_outbox;")
+            .execute(&mut *transaction)
+            .await?;
+
+        crate::postgres::censor_system(
+            &mut transaction,
+            crate::postgres::CensorshipType::DoNotRecommend,
+            system.clone(),
+            &crate::postgres::CensorshipDetails::default(),
+        )
+        .await?;
+
+        let rows = pull(&mut transaction, 10).await?;
+        assert_eq!(
+            rows.into_iter()
+                .map(removal)
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>(),
+            vec![
+                crate::opensearch::MESSAGES_INDEX.to_string(),
+                crate::opensearch::PROFILE_NAMES_INDEX.to_string(),
+                crate::opensearch::PROFILE_DESCRIPTIONS_INDEX.to_string(),
+            ]
+        );
+
+        // Still stored, but no longer visible to search
+        let orphans = find_orphans(
+            &mut transaction,
+            crate::opensearch::PROFILE_NAMES_INDEX,
+            vec![polycentric_protocol::model::public_key::to_base64(&system)?],
+        )
+        .await?;
+        assert_eq!(orphans.len(), 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_find_orphans(pool: PgPool) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        let stored = polycentric_protocol::test_utils::make_test_event(
+            &keypair, &process, 1,
+        );
+        let missing = polycentric_protocol::test_utils::make_test_event(
+            &keypair, &process, 2,
+        );
+
+        crate::ingest::ingest_event_postgres(&mut transaction, &stored).await?;
+
+        let to_id = |event: &polycentric_protocol::model::signed_event::SignedEvent| -> anyhow::Result<String> {
+            polycentric_protocol::model::pointer::to_base64(
+                &polycentric_protocol::model::pointer::from_signed_event(
+                    event,
+                )?,
+            )
+        };
+
+        let orphans = find_orphans(
+            &mut transaction,
+            crate::opensearch::MESSAGES_INDEX,
+            vec![to_id(&stored)?, to_id(&missing)?, "garbage".to_string()],
+        )
+        .await?;
+        assert_eq!(orphans, vec!["garbage".to_string(), to_id(&missing)?]);
+
+        let stranger = polycentric_protocol::model::public_key::to_base64(
+            &polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                polycentric_protocol::test_utils::make_test_keypair()
+                    .verifying_key(),
+            ),
+        )?;
+
+        let orphans = find_orphans(
+            &mut transaction,
+            crate::opensearch::PROFILE_NAMES_INDEX,
+            vec![
+                polycentric_protocol::model::public_key::to_base64(
+                    &polycentric_protocol::model::public_key::PublicKey::Ed25519(
+                        keypair.verifying_key(),
+                    ),
+                )?,
+                stranger.clone(),
+            ],
+        )
+        .await?;
+        assert_eq!(orphans, vec![stranger]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+// Newly ingested events are announced on a Postgres channel by ingest. Each
+// API replica listens on that channel and fans the events out to its own
+// stream subscribers, so a client sees every event regardless of which
+// replica ingested it.
+
+pub(crate) struct StreamedEvent {
+    pub(crate) event: polycentric_protocol::model::event::Event,
+}
+
+#[derive(Default)]
+pub(crate) struct Subscription {
+    systems:
+        ::std::vec::Vec<polycentric_protocol::model::public_key::PublicKey>,
+    references:
+        ::std::vec::Vec<polycentric_protocol::model::reference::Reference>,
+}
+
+impl Subscription {
+    pub(crate) fn from_proto(
+        proto: &polycentric_protocol::protocol::StreamSubscription,
+    ) -> ::anyhow::Result<Subscription> {
+        Ok(Subscription {
+            systems: proto
+                .systems
+                .iter()
+                .map(polycentric_protocol::model::public_key::from_proto)
+                .collect::<::anyhow::Result<_>>()?,
+            references: proto
+                .references
+                .iter()
+                .map(polycentric_protocol::model::reference::from_proto)
+                .collect::<::anyhow::Result<_>>()?,
+        })
+    }
+
+    pub(crate) fn matches(&self, event: &StreamedEvent) -> bool {
+        self.systems.contains(event.event.system())
+            || event
+                .event
+                .references()
+                .iter()
+                .any(|reference| self.references.contains(reference))
+    }
+}
+
+async fn load_streamed_event(
+    pool: &::sqlx::PgPool,
+    event_id: u64,
+) -> ::anyhow::Result<Option<StreamedEvent>> {
+    let mut transaction = pool.begin().await?;
+
+    let potential_signed_event =
+        crate::postgres::load_event_by_id(&mut transaction, event_id).await?;
+
+    transaction.commit().await?;
+
+    match potential_signed_event {
+        Some(signed_event) => {
+            let event = polycentric_protocol::model::event::from_vec(
+                signed_event.event(),
+            )?;
+
+            Ok(Some(StreamedEvent { event }))
+        }
+        None => Ok(None),
+    }
+}
+
+// Forwards notifications until the connection fails. The backoff is reset
+// once listening, so only repeated failures to connect back off further.
+async fn listen(
+    pool: &::sqlx::PgPool,
+    sender: &::tokio::sync::broadcast::Sender<::std::sync::Arc<StreamedEvent>>,
+    backoff: &mut ::backoff::ExponentialBackoff,
+) -> ::anyhow::Result<()> {
+    let mut listener = ::sqlx::postgres::PgListener::connect_with(pool).await?;
+
+    listener.listen(crate::postgres::EVENTS_CHANNEL).await?;
+
+    ::backoff::backoff::Backoff::reset(backoff);
+
+    loop {
+        let notification = listener.recv().await?;
+
+        let event_id = match notification.payload().parse::<u64>() {
+            Ok(event_id) => event_id,
+            Err(err) => {
+                ::log::warn!("invalid stream notification: {}", err);
+                continue;
+            }
+        };
+
+        // Nobody is subscribed on this replica
+        if sender.receiver_count() == 0 {
+            continue;
+        }
+
+        match load_streamed_event(pool, event_id).await {
+            Ok(Some(streamed_event)) => {
+                let _ = sender.send(::std::sync::Arc::new(streamed_event));
+            }
+            Ok(None) => {}
+            Err(err) => {
+                ::log::warn!("failed to load streamed event: {}", err);
+            }
+        }
+    }
+}
+
+// Notifications sent while disconnected are lost, clients recover them by
+// syncing.
+pub(crate) async fn run_listener(
+    pool: ::sqlx::PgPool,
+    sender: ::tokio::sync::broadcast::Sender<::std::sync::Arc<StreamedEvent>>,
+) {
+    let mut backoff = ::backoff::ExponentialBackoff {
+        max_elapsed_time: None,
+        ..Default::default()
+    };
+
+    loop {
+        if let Err(err) = listen(&pool, &sender, &mut backoff).await {
+            ::log::warn!("stream listener failed, reconnecting: {}", err);
+        }
+
+        let delay = ::backoff::backoff::Backoff::next_backoff(&mut backoff)
+            .unwrap_or(backoff.max_interval);
+
+        ::tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::postgres::prepare_database;
+    use sqlx::PgPool;
+
+    // Process ids of the connections listening for stream notifications.
+    async fn listeners(pool: &PgPool) -> anyhow::Result<Vec<i32>> {
+        Ok(sqlx::query_scalar(
+            "
+            SELECT pid FROM pg_stat_activity
+            WHERE datname = current_database()
+            AND query LIKE 'LISTEN%';
+            ",
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    async fn wait_for_listener(pool: &PgPool) -> anyhow::Result<i32> {
+        for _ in 0..100 {
+            if let Some(pid) = listeners(pool).await?.pop() {
+                return Ok(pid);
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        Err(anyhow::anyhow!("listener never connected"))
+    }
+
+    async fn ingest(
+        pool: &PgPool,
+        signed_event: &polycentric_protocol::model::signed_event::SignedEvent,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        crate::ingest::ingest_event_postgres(&mut transaction, signed_event)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_listener_delivers_after_reconnect(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let mut transaction = pool.begin().await?;
+        prepare_database(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let (sender, mut receiver) = tokio::sync::broadcast::channel(16);
+
+        tokio::spawn(super::run_listener(pool.clone(), sender));
+
+        let keypair = polycentric_protocol::test_utils::make_test_keypair();
+        let process = polycentric_protocol::test_utils::make_test_process();
+
+        let pid = wait_for_listener(&pool).await?;
+
+        ingest(
+            &pool,
+            &polycentric_protocol::test_utils::make_test_event(
+                &keypair, &process, 1,
+            ),
+        )
+        .await?;
+
+        let streamed = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            receiver.recv(),
+        )
+        .await??;
+        assert_eq!(*streamed.event.logical_clock(), 1);
+
+        sqlx::query("SELECT pg_terminate_backend($1);")
+            .bind(pid)
+            .execute(&pool)
+            .await?;
+
+        for _ in 0..100 {
+            if listeners(&pool).await?.iter().any(|other| *other != pid) {
+                break;
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        ingest(
+            &pool,
+            &polycentric_protocol::test_utils::make_test_event(
+                &keypair, &process, 2,
+            ),
+        )
+        .await?;
+
+        let streamed = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            receiver.recv(),
+        )
+        .await??;
+        assert_eq!(*streamed.event.logical_clock(), 2);
+
+        Ok(())
+    }
+}